@@ -0,0 +1,98 @@
+//! Throughput of the command hot path: an in-process server driven over a
+//! loopback connection, one client at a time.
+//!
+//! ```sh
+//! cargo run --release --example bench [-- --ops 100000 --pipeline 100]
+//! ```
+//!
+//! Each case runs `--ops` commands, 10000 by default so a run takes a few
+//! seconds, and the pipelined ones send them `--pipeline` at a time.
+
+use std::time::{Duration, Instant};
+
+use redis_starter_rust::{Client, Frame, Server};
+
+struct Options {
+    ops: usize,
+    pipeline: usize,
+}
+
+impl Options {
+    fn parse() -> Options {
+        let args: Vec<String> = std::env::args().collect();
+        let value = |name: &str, default: usize| {
+            args.iter()
+                .position(|arg| arg == name)
+                .and_then(|idx| args.get(idx + 1))
+                .map(|value| value.parse().unwrap_or_else(|_| panic!("Invalid value for {}", name)))
+                .unwrap_or(default)
+        };
+
+        Options { ops: value("--ops", 10000), pipeline: value("--pipeline", 100).max(1) }
+    }
+}
+
+/// Sends `commands` one at a time, or `batch` at a time in a pipeline,
+/// returning how long they took to be answered.
+async fn run(client: &mut Client, commands: &[Vec<String>], batch: usize) -> Duration {
+    let start = Instant::now();
+
+    for chunk in commands.chunks(batch) {
+        if batch == 1 {
+            client.command(&chunk[0]).await.expect("command failed");
+            continue;
+        }
+
+        let mut pipeline = client.pipeline();
+        for command in chunk {
+            pipeline.command(command);
+        }
+        for reply in pipeline.execute().await.expect("pipeline failed") {
+            reply.expect("command failed");
+        }
+    }
+
+    start.elapsed()
+}
+
+fn report(name: &str, ops: usize, elapsed: Duration) {
+    println!(
+        "{:<16} {:>10.0} ops/s {:>8.2} us/op",
+        name,
+        ops as f64 / elapsed.as_secs_f64(),
+        elapsed.as_secs_f64() * 1e6 / ops as f64,
+    );
+}
+
+#[tokio::main]
+async fn main() {
+    let options = Options::parse();
+    let server = Server::builder().port(0).spawn().await.expect("server failed to start");
+    let mut client = Client::connect(server.addr()).await.expect("failed to connect");
+
+    let command = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<String>>();
+    let key = |idx: usize| format!("key:{}", idx % 1000);
+
+    let ping: Vec<_> = (0..options.ops).map(|_| command(&["PING"])).collect();
+    let set: Vec<_> = (0..options.ops).map(|idx| command(&["SET", &key(idx), "value"])).collect();
+    let get_hit: Vec<_> = (0..options.ops).map(|idx| command(&["GET", &key(idx)])).collect();
+    let get_miss: Vec<_> = (0..options.ops).map(|idx| command(&["GET", &format!("missing:{}", idx)])).collect();
+
+    let cases: [(&str, &[Vec<String>], usize); 6] = [
+        ("PING", &ping, 1),
+        ("SET", &set, 1),
+        ("GET hit", &get_hit, 1),
+        ("GET miss", &get_miss, 1),
+        ("pipelined SET", &set, options.pipeline),
+        ("pipelined GET", &get_hit, options.pipeline),
+    ];
+    for (name, commands, batch) in cases {
+        let elapsed = run(&mut client, commands, batch).await;
+        report(name, commands.len(), elapsed);
+    }
+
+    // The hits above really were hits.
+    assert!(matches!(client.command(["GET", "key:0"]).await, Ok(Frame::Bulk(Some(_)))));
+
+    server.shutdown().await;
+}
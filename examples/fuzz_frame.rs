@@ -0,0 +1,83 @@
+//! Fuzzes the RESP parser: `Frame::check` followed by `Frame::parse`, the
+//! way a connection reads frames, with and without `expect_file`.
+//!
+//! ```sh
+//! cargo run --release --example fuzz_frame [-- --iterations 1000000 --seed 42]
+//! ```
+//!
+//! The corpus in `tests/frame_fuzz`, which `cargo test` replays, is
+//! replayed first, then mutated inputs, from the given seed or a random
+//! one. Every input must hold the invariants of `check_input`. A failing
+//! input is printed, to be added to the corpus once fixed.
+
+use std::panic;
+
+use redis_starter_rust::random;
+
+#[path = "../tests/frame_fuzz/mod.rs"]
+mod frame_fuzz;
+use frame_fuzz::{check_input, deeply_nested, escape, CORPUS};
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let value = |name: &str| args.iter().position(|arg| arg == name).and_then(|idx| args.get(idx + 1)).map(|value| value.parse::<u64>().expect("invalid number"));
+
+    let iterations = value("--iterations").unwrap_or(100000);
+    let seed = value("--seed").unwrap_or_else(random::entropy_seed);
+    random::seed(seed);
+    println!("Fuzzing {} inputs with seed {}", iterations, seed);
+
+    // Failures are reported along with their input instead.
+    panic::set_hook(Box::new(|_| {}));
+
+    let mut failures = 0;
+    let mut run = |input: &[u8]| {
+        if let Err(failure) = check_input(input) {
+            failures += 1;
+            println!("{}: {}", failure, escape(input));
+        }
+    };
+
+    for input in CORPUS {
+        run(input);
+    }
+    run(&deeply_nested());
+    for _ in 0..iterations {
+        run(&mutate(CORPUS[random::next_u64() as usize % CORPUS.len()]));
+    }
+
+    match failures {
+        0 => println!("No failures"),
+        failures => {
+            println!("{} failures", failures);
+            std::process::exit(1);
+        },
+    }
+}
+
+/// A few random edits of `input`: bytes flipped, inserted or removed,
+/// truncation, and parts of other corpus entries spliced in.
+fn mutate(input: &[u8]) -> Vec<u8> {
+    let mut input = input.to_vec();
+    let below = |n: usize| random::next_u64() as usize % n.max(1);
+
+    for _ in 0..=below(4) {
+        let idx = below(input.len() + 1);
+        match below(6) {
+            0 if idx < input.len() => input[idx] ^= 1 << below(8),
+            1 => input.insert(idx, b"\r\n*$+-:0123456789"[below(17)]),
+            2 if idx < input.len() => {
+                input.remove(idx);
+            },
+            3 => input.truncate(idx),
+            4 => {
+                let other = CORPUS[below(CORPUS.len())];
+                let from = below(other.len() + 1);
+                input.splice(idx..idx, other[from..].iter().copied());
+            },
+            _ => input.insert(idx, random::next_u64() as u8),
+        }
+    }
+
+    input
+}
@@ -0,0 +1,379 @@
+//! Users and what they may do, a subset of the ACL rules of redis-server:
+//!
+//! `user alice on >secret ~cache:* +@read -keys`
+//!
+//! Users can be enabled (`on`) or disabled (`off`), with passwords added
+//! with `>`, given as SHA-256 hashes with `#`, or none needed (`nopass`).
+//! Commands are allowed and denied by name (`+get`, `-get`) or by category
+//! (`+@read`, `-@all`), with later rules taking precedence over earlier
+//! ones. Keys are allowed by glob patterns (`~cache:*`, `allkeys`). Channel
+//! patterns (`&*`) are accepted and listed, but not enforced yet.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+
+use bytes::Bytes;
+
+use crate::command_table::{self, CommandSpec, COMMAND_TABLE};
+use crate::{glob, ServerConfig};
+
+pub const DEFAULT_USER: &str = "default";
+
+/// The users, by name. There's always a `default` user, which connections
+/// are logged in as until they authenticate, as long as it needs no
+/// password.
+pub struct Acl {
+    users: RwLock<BTreeMap<String, Arc<User>>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct User {
+    pub name: String,
+    enabled: bool,
+    nopass: bool,
+    /// SHA-256 hashes of the passwords, so they're never kept in plain text.
+    passwords: Vec<[u8; 32]>,
+    commands: Vec<CommandRule>,
+    keys: Vec<Bytes>,
+    channels: Vec<Bytes>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct CommandRule {
+    allow: bool,
+    target: RuleTarget,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum RuleTarget {
+    All,
+    Category(String),
+    Command(&'static str),
+}
+
+impl Acl {
+    /// Sets up the users of the `aclfile` and the `user` rules of the
+    /// config, after the default user allowed to do anything.
+    pub fn from_config(config: &ServerConfig) -> crate::Result<Acl> {
+        let mut users = BTreeMap::new();
+        let default = User::new(DEFAULT_USER).apply_rules(&["on", "nopass", "~*", "&*", "+@all"])?;
+        users.insert(DEFAULT_USER.to_string(), Arc::new(default));
+
+        let mut declarations = vec![];
+        if !config.aclfile.is_empty() {
+            let contents = std::fs::read_to_string(&config.aclfile)
+                .map_err(|err| format!("ERR Error loading ACL file {}: {}", config.aclfile, err))?;
+
+            for line in contents.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+                match line.strip_prefix("user ") {
+                    Some(declaration) => declarations.push(declaration.to_string()),
+                    None => return Err(format!("ERR Error in ACL file {}: lines must start with 'user', got '{}'", config.aclfile, line).into()),
+                }
+            }
+        }
+        declarations.extend(config.users.iter().cloned());
+
+        for declaration in declarations {
+            let user = User::parse(&declaration)?;
+            users.insert(user.name.clone(), Arc::new(user));
+        }
+
+        Ok(Acl { users: RwLock::new(users) })
+    }
+
+    pub fn get_user(&self, name: &str) -> Option<Arc<User>> {
+        self.users.read().unwrap_or_else(|err| err.into_inner()).get(name).cloned()
+    }
+
+    pub fn users(&self) -> Vec<Arc<User>> {
+        self.users.read().unwrap_or_else(|err| err.into_inner()).values().cloned().collect()
+    }
+
+    /// The user new connections are logged in as, if any.
+    pub fn initial_user(&self) -> Option<String> {
+        match self.get_user(DEFAULT_USER) {
+            Some(user) if user.enabled && user.nopass => Some(DEFAULT_USER.to_string()),
+            _ => None,
+        }
+    }
+
+    /// Checks a user's password, returning the user if it may log in.
+    pub fn authenticate(&self, name: &str, password: &[u8]) -> Option<Arc<User>> {
+        let user = self.get_user(name)?;
+
+        match user.enabled && (user.nopass || user.passwords.contains(&sha256(password))) {
+            true => Some(user),
+            false => None,
+        }
+    }
+}
+
+impl User {
+    fn new(name: &str) -> User {
+        User {
+            name: name.to_string(),
+            enabled: false,
+            nopass: false,
+            passwords: vec![],
+            commands: vec![],
+            keys: vec![],
+            channels: vec![],
+        }
+    }
+
+    /// Parses a user declaration: its name, followed by its rules.
+    pub fn parse(declaration: &str) -> crate::Result<User> {
+        let mut parts = declaration.split_whitespace();
+        let name = parts.next().ok_or("ERR Error in user declaration: missing user name")?;
+        let rules: Vec<&str> = parts.collect();
+
+        User::new(name)
+            .apply_rules(&rules)
+            .map_err(|err| format!("ERR Error in user declaration '{}': {}", name, err).into())
+    }
+
+    fn apply_rules(mut self, rules: &[&str]) -> Result<User, String> {
+        for rule in rules {
+            self.apply_rule(rule)?;
+        }
+
+        Ok(self)
+    }
+
+    fn apply_rule(&mut self, rule: &str) -> Result<(), String> {
+        match rule.to_lowercase().as_str() {
+            "on" => self.enabled = true,
+            "off" => self.enabled = false,
+            "nopass" => {
+                self.nopass = true;
+                self.passwords.clear();
+            },
+            "resetpass" => {
+                self.nopass = false;
+                self.passwords.clear();
+            },
+            "allkeys" => self.keys = vec![Bytes::from_static(b"*")],
+            "resetkeys" => self.keys.clear(),
+            "allchannels" => self.channels = vec![Bytes::from_static(b"*")],
+            "resetchannels" => self.channels.clear(),
+            "allcommands" => self.commands = vec![CommandRule { allow: true, target: RuleTarget::All }],
+            "nocommands" => self.commands.clear(),
+            "reset" => {
+                let name = std::mem::take(&mut self.name);
+                *self = User::new(&name);
+            },
+            _ => return self.apply_prefixed_rule(rule),
+        }
+
+        Ok(())
+    }
+
+    fn apply_prefixed_rule(&mut self, rule: &str) -> Result<(), String> {
+        let (prefix, arg) = rule.split_at(rule.chars().next().map(char::len_utf8).unwrap_or(0));
+
+        match prefix {
+            ">" => {
+                self.nopass = false;
+                let hash = sha256(arg.as_bytes());
+                if !self.passwords.contains(&hash) {
+                    self.passwords.push(hash);
+                }
+            },
+            "<" => {
+                let hash = sha256(arg.as_bytes());
+                self.passwords.retain(|password| *password != hash);
+            },
+            "#" => {
+                let hash = parse_hash(arg).ok_or("The password hash must be exactly 64 characters and contain only lowercase hexadecimal characters")?;
+                self.nopass = false;
+                if !self.passwords.contains(&hash) {
+                    self.passwords.push(hash);
+                }
+            },
+            "!" => {
+                let hash = parse_hash(arg).ok_or("The password hash must be exactly 64 characters and contain only lowercase hexadecimal characters")?;
+                self.passwords.retain(|password| *password != hash);
+            },
+            "~" => self.keys.push(Bytes::copy_from_slice(arg.as_bytes())),
+            "&" => self.channels.push(Bytes::copy_from_slice(arg.as_bytes())),
+            "+" | "-" => {
+                let target = match arg.strip_prefix('@') {
+                    Some("all") => RuleTarget::All,
+                    Some(category) if categories().iter().any(|known| known == category) => RuleTarget::Category(category.to_string()),
+                    Some(_) => return Err("Unknown command or category name in ACL".to_string()),
+                    None => match command_table::lookup(&arg.to_lowercase()) {
+                        Some(spec) => RuleTarget::Command(spec.name),
+                        None => return Err("Unknown command or category name in ACL".to_string()),
+                    },
+                };
+
+                let rule = CommandRule { allow: prefix == "+", target };
+                // Allowing or denying everything overrides what came before.
+                if rule.target == RuleTarget::All {
+                    self.commands.clear();
+                }
+                self.commands.push(rule);
+            },
+            _ => return Err("Syntax error".to_string()),
+        }
+
+        Ok(())
+    }
+
+    /// Whether the user may run the command, going by the last rule that
+    /// covers it.
+    pub fn can_run(&self, spec: &CommandSpec) -> bool {
+        self.commands
+            .iter()
+            .rev()
+            .find(|rule| match &rule.target {
+                RuleTarget::All => true,
+                RuleTarget::Category(category) => spec.categories().iter().any(|other| other[1..] == **category),
+                RuleTarget::Command(name) => *name == spec.name,
+            })
+            .map(|rule| rule.allow)
+            .unwrap_or(false)
+    }
+
+    pub fn can_access_key(&self, key: &[u8]) -> bool {
+        self.keys.iter().any(|pattern| glob::matches(pattern, key))
+    }
+
+    pub fn is_nopass(&self) -> bool {
+        self.nopass
+    }
+
+    /// Whether the user may run anything on any key, so checking each
+    /// command can be skipped.
+    pub fn is_unrestricted(&self) -> bool {
+        let all_keys = self.keys.iter().any(|pattern| &pattern[..] == b"*");
+        let all_commands = matches!(self.commands.as_slice(), [CommandRule { allow: true, target: RuleTarget::All }]);
+
+        all_keys && all_commands
+    }
+
+    /// The user's rules, as listed by `ACL LIST`.
+    pub fn describe(&self) -> String {
+        let mut parts = vec![format!("user {}", self.name), if self.enabled { "on" } else { "off" }.to_string()];
+
+        if self.nopass {
+            parts.push("nopass".to_string());
+        }
+        for password in self.passwords.iter() {
+            parts.push(format!("#{}", password.iter().map(|byte| format!("{:02x}", byte)).collect::<String>()));
+        }
+        for pattern in self.keys.iter() {
+            parts.push(format!("~{}", String::from_utf8_lossy(pattern)));
+        }
+        match self.channels.is_empty() {
+            true => parts.push("resetchannels".to_string()),
+            false => parts.extend(self.channels.iter().map(|pattern| format!("&{}", String::from_utf8_lossy(pattern)))),
+        }
+        if !matches!(self.commands.first(), Some(CommandRule { target: RuleTarget::All, .. })) {
+            parts.push("-@all".to_string());
+        }
+        for rule in self.commands.iter() {
+            let target = match &rule.target {
+                RuleTarget::All => "@all".to_string(),
+                RuleTarget::Category(category) => format!("@{}", category),
+                RuleTarget::Command(name) => name.to_string(),
+            };
+            parts.push(format!("{}{}", if rule.allow { "+" } else { "-" }, target));
+        }
+
+        parts.join(" ")
+    }
+}
+
+/// Every category some command belongs to, without the `@`.
+pub fn categories() -> Vec<String> {
+    let mut categories: Vec<String> = COMMAND_TABLE
+        .iter()
+        .flat_map(|spec| spec.categories())
+        .map(|category| category[1..].to_string())
+        .collect();
+    categories.sort();
+    categories.dedup();
+
+    categories
+}
+
+fn parse_hash(hex: &str) -> Option<[u8; 32]> {
+    if hex.len() != 64 || !hex.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b)) {
+        return None;
+    }
+
+    let mut hash = [0; 32];
+    for (idx, byte) in hash.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[idx * 2..idx * 2 + 2], 16).ok()?;
+    }
+
+    Some(hash)
+}
+
+const SHA256_K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// SHA-256 of `data`, which is how redis-server stores passwords too.
+fn sha256(data: &[u8]) -> [u8; 32] {
+    let mut state: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+    ];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend(((data.len() as u64) * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut w = [0u32; 64];
+        for (idx, word) in block.chunks(4).enumerate() {
+            w[idx] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for idx in 16..64 {
+            let s0 = w[idx - 15].rotate_right(7) ^ w[idx - 15].rotate_right(18) ^ (w[idx - 15] >> 3);
+            let s1 = w[idx - 2].rotate_right(17) ^ w[idx - 2].rotate_right(19) ^ (w[idx - 2] >> 10);
+            w[idx] = w[idx - 16].wrapping_add(s0).wrapping_add(w[idx - 7]).wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = state;
+        for idx in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = h.wrapping_add(s1).wrapping_add(ch).wrapping_add(SHA256_K[idx]).wrapping_add(w[idx]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+
+        for (word, add) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *word = word.wrapping_add(add);
+        }
+    }
+
+    let mut hash = [0; 32];
+    for (idx, word) in state.iter().enumerate() {
+        hash[idx * 4..idx * 4 + 4].copy_from_slice(&word.to_be_bytes());
+    }
+
+    hash
+}
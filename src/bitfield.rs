@@ -0,0 +1,185 @@
+//! Integer fields of 1 to 64 bits at arbitrary bit offsets of a string, as
+//! used by `BITFIELD`.
+
+use crate::{bitops, value};
+
+/// A signed or unsigned integer type, like `i5` or `u16`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FieldType {
+    pub signed: bool,
+    pub bits: u32,
+}
+
+impl FieldType {
+    /// Parses a type, which like in Redis can be up to 64 bits wide when
+    /// signed but only 63 bits when unsigned.
+    pub fn parse(arg: &str) -> Option<FieldType> {
+        let (signed, bits) = match arg.split_at(arg.len().min(1)) {
+            ("i", bits) | ("I", bits) => (true, bits),
+            ("u", bits) | ("U", bits) => (false, bits),
+            _ => return None,
+        };
+
+        let max_bits = if signed { 64 } else { 63 };
+        match bits.parse::<u32>() {
+            Ok(bits) if bits >= 1 && bits <= max_bits => Some(FieldType { signed, bits }),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        format!("{}{}", if self.signed { "i" } else { "u" }, self.bits)
+    }
+
+    fn min(&self) -> i128 {
+        if self.signed { -(1 << (self.bits - 1)) } else { 0 }
+    }
+
+    fn max(&self) -> i128 {
+        if self.signed { (1 << (self.bits - 1)) - 1 } else { (1 << self.bits) - 1 }
+    }
+}
+
+/// What to do when a write doesn't fit its field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    Wrap,
+    Sat,
+    Fail,
+}
+
+impl Overflow {
+    pub fn parse(arg: &str) -> Option<Overflow> {
+        match arg.to_uppercase().as_str() {
+            "WRAP" => Some(Overflow::Wrap),
+            "SAT" => Some(Overflow::Sat),
+            "FAIL" => Some(Overflow::Fail),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Overflow::Wrap => "WRAP",
+            Overflow::Sat => "SAT",
+            Overflow::Fail => "FAIL",
+        }
+    }
+
+    /// Fits `value` into a field of type `ty`, or returns `None` if it
+    /// doesn't fit and the behaviour is `Fail`.
+    pub fn apply(&self, ty: FieldType, value: i128) -> Option<i64> {
+        if value >= ty.min() && value <= ty.max() {
+            return Some(value as i64);
+        }
+
+        match self {
+            Overflow::Wrap => {
+                let wrapped = value.rem_euclid(1 << ty.bits);
+                if ty.signed && wrapped > ty.max() {
+                    Some((wrapped - (1 << ty.bits)) as i64)
+                } else {
+                    Some(wrapped as i64)
+                }
+            },
+            Overflow::Sat if value < ty.min() => Some(ty.min() as i64),
+            Overflow::Sat => Some(ty.max() as i64),
+            Overflow::Fail => None,
+        }
+    }
+}
+
+/// Parses a field offset, either a bit offset or `#N` for the `N`th field of
+/// type `ty`.
+pub fn parse_offset(arg: &str, ty: FieldType) -> Option<u64> {
+    let parse = |arg: &str| value::parse_integer(arg.as_bytes()).and_then(|int| u64::try_from(int).ok());
+    let offset = match arg.strip_prefix('#') {
+        Some(index) => parse(index)?.checked_mul(ty.bits as u64)?,
+        None => parse(arg)?,
+    };
+
+    if offset > bitops::MAX_BIT_OFFSET {
+        return None;
+    }
+
+    Some(offset)
+}
+
+/// Reads the field of type `ty` at bit `offset`, treating bits past the end
+/// of `buf` as 0.
+pub fn read(buf: &[u8], offset: u64, ty: FieldType) -> i64 {
+    let mut value: u64 = 0;
+    for idx in 0..ty.bits as u64 {
+        value = (value << 1) | bitops::get_bit(buf, offset + idx) as u64;
+    }
+
+    // Sign-extend negative values.
+    if ty.signed && ty.bits < 64 && (value >> (ty.bits - 1)) & 1 == 1 {
+        value |= u64::MAX << ty.bits;
+    }
+
+    value as i64
+}
+
+/// Writes the low bits of `value` to the field of type `ty` at bit `offset`,
+/// growing `buf` as needed.
+pub fn write(buf: &mut Vec<u8>, offset: u64, ty: FieldType, value: i64) {
+    let value = value as u64;
+
+    for idx in 0..ty.bits as u64 {
+        let bit = (value >> (ty.bits as u64 - 1 - idx)) & 1;
+        bitops::set_bit(buf, offset + idx, bit as u8);
+    }
+}
+
+/// A `BITFIELD` subcommand. Writes carry the overflow behaviour in effect
+/// when they were given.
+#[derive(Debug, Clone, Copy)]
+pub enum FieldOp {
+    Get(FieldType, u64),
+    Set(FieldType, u64, i64, Overflow),
+    IncrBy(FieldType, u64, i64, Overflow),
+}
+
+impl FieldOp {
+    pub fn is_write(&self) -> bool {
+        !matches!(self, FieldOp::Get(..))
+    }
+
+    /// Index of the byte just past the field.
+    pub fn end_byte(&self) -> usize {
+        let (ty, offset) = match self {
+            FieldOp::Get(ty, offset) | FieldOp::Set(ty, offset, ..) | FieldOp::IncrBy(ty, offset, ..) => (ty, offset),
+        };
+
+        // The byte holding the last bit, plus one.
+        ((offset + ty.bits as u64 - 1) / 8 + 1) as usize
+    }
+
+    /// Runs the subcommand against `buf`, returning its reply value, or
+    /// `None` if a write failed on overflow.
+    pub fn execute(&self, buf: &mut Vec<u8>) -> Option<i64> {
+        match *self {
+            FieldOp::Get(ty, offset) => Some(read(buf, offset, ty)),
+            FieldOp::Set(ty, offset, value, overflow) => {
+                let old = read(buf, offset, ty);
+
+                // Like Redis, unsigned fields take the value as unsigned.
+                let value = if ty.signed { value as i128 } else { value as u64 as i128 };
+                let value = overflow.apply(ty, value)?;
+
+                write(buf, offset, ty, value);
+
+                Some(old)
+            },
+            FieldOp::IncrBy(ty, offset, incr, overflow) => {
+                let old = read(buf, offset, ty);
+                let value = overflow.apply(ty, old as i128 + incr as i128)?;
+
+                write(buf, offset, ty, value);
+
+                Some(value)
+            },
+        }
+    }
+}
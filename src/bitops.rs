@@ -0,0 +1,186 @@
+//! Bit-level access to string values. Like Redis, bits are numbered from the
+//! most significant bit of the first byte.
+
+/// Largest bit offset accepted by commands, since strings are limited to
+/// 512MB.
+pub const MAX_BIT_OFFSET: u64 = (1 << 32) - 1;
+
+/// Returns the bit at `offset`, treating bits past the end as 0.
+pub fn get_bit(buf: &[u8], offset: u64) -> u8 {
+    let byte = (offset / 8) as usize;
+    let shift = 7 - (offset % 8) as u8;
+
+    buf.get(byte).map(|byte| (byte >> shift) & 1).unwrap_or(0)
+}
+
+/// Sets the bit at `offset` to `bit`, growing `buf` with zero bytes as needed,
+/// and returns its previous value.
+pub fn set_bit(buf: &mut Vec<u8>, offset: u64, bit: u8) -> u8 {
+    let byte = (offset / 8) as usize;
+    let shift = 7 - (offset % 8) as u8;
+
+    if byte >= buf.len() {
+        crate::value::pad_string(buf, byte + 1);
+    }
+
+    let old = (buf[byte] >> shift) & 1;
+    if bit == 1 {
+        buf[byte] |= 1 << shift;
+    } else {
+        buf[byte] &= !(1 << shift);
+    }
+
+    old
+}
+
+/// Unit of the ranges taken by `BITCOUNT` and `BITPOS`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeUnit {
+    Byte,
+    Bit,
+}
+
+impl RangeUnit {
+    pub fn parse(arg: &str) -> Option<RangeUnit> {
+        match arg.to_uppercase().as_str() {
+            "BYTE" => Some(RangeUnit::Byte),
+            "BIT" => Some(RangeUnit::Bit),
+            _ => None,
+        }
+    }
+}
+
+/// Resolves the inclusive range `start..=end` of a string of `byte_len`
+/// bytes, in `unit`s and with negative indexes counting from the end, to an
+/// inclusive range of bits. Returns `None` if the range is empty.
+pub fn resolve_range(start: i64, end: i64, unit: RangeUnit, byte_len: usize) -> Option<(u64, u64)> {
+    let len = match unit {
+        RangeUnit::Byte => byte_len as i64,
+        RangeUnit::Bit => byte_len as i64 * 8,
+    };
+
+    let start = if start < 0 { (len + start).max(0) } else { start };
+    let end = if end < 0 { (len + end).max(0) } else { end.min(len - 1) };
+
+    if start > end || len == 0 {
+        return None;
+    }
+
+    match unit {
+        RangeUnit::Byte => Some((start as u64 * 8, end as u64 * 8 + 7)),
+        RangeUnit::Bit => Some((start as u64, end as u64)),
+    }
+}
+
+/// Number of set bits in `buf`.
+pub fn popcount(buf: &[u8]) -> u64 {
+    let mut words = buf.chunks_exact(8);
+    let mut count: u64 = words.by_ref()
+        .map(|word| u64::from_ne_bytes(word.try_into().unwrap()).count_ones() as u64)
+        .sum();
+    count += words.remainder().iter().map(|byte| byte.count_ones() as u64).sum::<u64>();
+
+    count
+}
+
+/// Number of set bits in the inclusive bit range `start..=end` of `buf`.
+pub fn count_bits(buf: &[u8], start: u64, end: u64) -> u64 {
+    let first = (start / 8) as usize;
+    let last = (end / 8) as usize;
+
+    let mut count = popcount(&buf[first..=last]);
+
+    // Leave out the bits of the first and last bytes outside the range.
+    count -= (buf[first] as u32 >> (8 - start % 8)).count_ones() as u64;
+    count -= (buf[last] & (0x7f >> (end % 8))).count_ones() as u64;
+
+    count
+}
+
+/// Position of the first bit set to `bit` in the inclusive bit range
+/// `start..=end` of `buf`.
+pub fn bit_position(buf: &[u8], bit: u8, start: u64, end: u64) -> Option<u64> {
+    let first = (start / 8) as usize;
+    let last = (end / 8) as usize;
+
+    // Bytes in which there's nothing to find.
+    let skip: u8 = if bit == 1 { 0 } else { 0xff };
+    let skip_word = u64::from_ne_bytes([skip; 8]);
+
+    let mut idx = first;
+    while idx <= last {
+        // Skip over the middle of the range a word at a time.
+        if idx > first && idx + 8 <= last && u64::from_ne_bytes(buf[idx..idx + 8].try_into().unwrap()) == skip_word {
+            idx += 8;
+            continue;
+        }
+
+        // Bits of the first and last bytes outside the range mustn't match.
+        let mut outside = 0u8;
+        if idx == first {
+            outside |= !(0xff >> (start % 8));
+        }
+        if idx == last {
+            outside |= 0x7f >> (end % 8);
+        }
+
+        let byte = if bit == 1 { buf[idx] & !outside } else { buf[idx] | outside };
+        if byte != skip {
+            let matching = if bit == 1 { byte } else { !byte };
+            return Some(idx as u64 * 8 + matching.leading_zeros() as u64);
+        }
+
+        idx += 1;
+    }
+
+    None
+}
+
+/// A `BITOP` operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitOperation {
+    And,
+    Or,
+    Xor,
+    Not,
+}
+
+impl BitOperation {
+    pub fn parse(arg: &str) -> Option<BitOperation> {
+        match arg.to_uppercase().as_str() {
+            "AND" => Some(BitOperation::And),
+            "OR" => Some(BitOperation::Or),
+            "XOR" => Some(BitOperation::Xor),
+            "NOT" => Some(BitOperation::Not),
+            _ => None,
+        }
+    }
+}
+
+/// Combines `operands` bitwise, zero-padding the shorter ones to the length
+/// of the longest. `Not` only looks at the first operand.
+pub fn bit_operation(op: BitOperation, operands: &[&[u8]]) -> Vec<u8> {
+    let len = operands.iter().map(|operand| operand.len()).max().unwrap_or(0);
+
+    if op == BitOperation::Not {
+        return operands.first().map(|operand| operand.iter().map(|byte| !byte).collect()).unwrap_or_default();
+    }
+
+    let mut res = operands.first().map(|operand| operand.to_vec()).unwrap_or_default();
+    res.resize(len, 0);
+
+    for operand in operands.iter().skip(1) {
+        let padded = operand.iter().copied().chain(std::iter::repeat(0));
+
+        for (byte, other) in res.iter_mut().zip(padded) {
+            match op {
+                BitOperation::And => *byte &= other,
+                BitOperation::Or => *byte |= other,
+                BitOperation::Xor => *byte ^= other,
+                BitOperation::Not => unreachable!(),
+            }
+        }
+    }
+
+    res
+}
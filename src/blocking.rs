@@ -0,0 +1,244 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use bytes::Bytes;
+use tokio::sync::Notify;
+use tokio::time::Instant;
+
+/// Why a blocked client stopped waiting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Wakeup {
+    /// One of its keys was written to, so it should look at them again,
+    /// or it was served an element already, see `BlockedClient::served`.
+    Ready,
+    /// Its timeout passed.
+    Timeout,
+    /// It was unblocked from the outside, e.g. by `CLIENT KILL`.
+    Unblocked,
+}
+
+type WatchedKey = (usize, Bytes);
+
+/// The clients blocked on keys, like `BLPOP` does, by database and key.
+///
+/// A client blocks through `block` while still holding the locks of the
+/// shards of its keys, so no write can slip in between it finding nothing
+/// and being registered. Writes that may serve a waiter call `signal` for
+/// the keys they touched.
+///
+/// Pushes serve their waiters through `serve` instead, in the order they
+/// blocked: one element per client, as long as the list has any, so one
+/// pushed element serves the longest waiting client alone and the rest
+/// keep waiting. The elements are popped by the pushing command, with the
+/// shard of the key still locked, and handed to the waiters, so no other
+/// client can pop an element between it being pushed and the waiter it's
+/// promised to getting it. Clients unblocked from the outside, but not
+/// yet gone from the registry, are skipped.
+///
+/// Only writes that may leave a list under a key signal it: `RESTORE`,
+/// `SORT .. STORE` and `SWAPDB`, which may put one there whole, and pushes
+/// applied from the master, after which waiters on a replica pop for
+/// themselves.
+/// Deleting a key, be it by `DEL`-like commands, expiry, eviction or a
+/// flush, never does, so its waiters stay parked until a list shows up.
+/// Waiters may still be woken for a key holding something else, and then
+/// go back to waiting, as a signal only means "look again".
+///
+/// `block` hands out a `BlockedClient`, which takes the client out of the
+/// registry again when dropped. However the blocking command ends, be it
+/// served, timed out, killed or its task aborted, no waiter is left behind.
+#[derive(Default)]
+pub struct Blocking {
+    registry: Mutex<Registry>,
+}
+
+#[derive(Default)]
+struct Registry {
+    /// Ids of the clients waiting on each key, longest waiting first.
+    keys: HashMap<WatchedKey, VecDeque<u64>>,
+    clients: HashMap<u64, Waiter>,
+}
+
+struct Waiter {
+    command: String,
+    keys: Vec<WatchedKey>,
+    signal: Arc<Signal>,
+}
+
+#[derive(Default)]
+struct Signal {
+    notify: Notify,
+    unblocked: AtomicBool,
+    /// The key and element the client was served by `serve`.
+    served: Mutex<Option<(Bytes, Bytes)>>,
+}
+
+/// A client registered as blocked, until dropped.
+pub struct BlockedClient<'a> {
+    blocking: &'a Blocking,
+    id: u64,
+    signal: Arc<Signal>,
+}
+
+impl Blocking {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers client `id` as blocked by `command` on `keys` of database
+    /// `db_index`.
+    pub fn block(&self, id: u64, command: &str, db_index: usize, keys: &[Bytes]) -> BlockedClient<'_> {
+        let mut registry = self.registry.lock().unwrap_or_else(|err| err.into_inner());
+        let signal = Arc::new(Signal::default());
+
+        let keys: Vec<WatchedKey> = keys.iter().map(|key| (db_index, key.clone())).collect();
+        for key in keys.iter() {
+            registry.keys.entry(key.clone()).or_default().push_back(id);
+        }
+        registry.clients.insert(id, Waiter { command: command.to_string(), keys, signal: signal.clone() });
+
+        BlockedClient { blocking: self, id, signal }
+    }
+
+    /// Wakes the clients blocked on `key`, which was just written to. Each
+    /// looks at its keys again, and goes back to waiting if there's nothing
+    /// for it after all.
+    pub fn signal(&self, db_index: usize, key: &[u8]) {
+        let registry = self.registry.lock().unwrap_or_else(|err| err.into_inner());
+
+        if let Some(ids) = registry.keys.get(&(db_index, Bytes::copy_from_slice(key))) {
+            for id in ids {
+                registry.clients[id].signal.notify.notify_one();
+            }
+        }
+    }
+
+    /// Serves the clients blocked on `key`, which was just pushed to, longest
+    /// waiting first. `pop` takes an element off the list for a client
+    /// blocked by the given command, and serving stops once it has none.
+    /// Served clients are no longer blocked, and get the element through
+    /// `BlockedClient::served`.
+    pub fn serve(&self, db_index: usize, key: &[u8], mut pop: impl FnMut(&str) -> Option<Bytes>) {
+        let mut registry = self.registry.lock().unwrap_or_else(|err| err.into_inner());
+        let watched = (db_index, Bytes::copy_from_slice(key));
+        let ids: Vec<u64> = registry.keys.get(&watched).map(|ids| ids.iter().copied().collect()).unwrap_or_default();
+
+        for id in ids {
+            let waiter = &registry.clients[&id];
+            if waiter.signal.unblocked.load(Ordering::Relaxed) {
+                continue;
+            }
+
+            let value = match pop(&waiter.command) {
+                Some(value) => value,
+                None => break,
+            };
+            *waiter.signal.served.lock().unwrap_or_else(|err| err.into_inner()) = Some((watched.1.clone(), value));
+            waiter.signal.notify.notify_one();
+            registry.remove(id);
+        }
+    }
+
+    /// Wakes every client blocked on a key of database `db_index`, whose
+    /// keys were all replaced at once.
+    pub fn signal_db(&self, db_index: usize) {
+        let registry = self.registry.lock().unwrap_or_else(|err| err.into_inner());
+
+        for ((key_db, _), ids) in registry.keys.iter() {
+            if *key_db == db_index {
+                for id in ids {
+                    registry.clients[id].signal.notify.notify_one();
+                }
+            }
+        }
+    }
+
+    /// Makes client `id` stop waiting, returning whether it was blocked.
+    pub fn unblock(&self, id: u64) -> bool {
+        let registry = self.registry.lock().unwrap_or_else(|err| err.into_inner());
+
+        match registry.clients.get(&id) {
+            Some(waiter) => {
+                waiter.signal.unblocked.store(true, Ordering::Relaxed);
+                waiter.signal.notify.notify_one();
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// The command client `id` is blocked by, if any.
+    pub fn blocked_by(&self, id: u64) -> Option<String> {
+        self.registry.lock().unwrap_or_else(|err| err.into_inner()).clients.get(&id).map(|waiter| waiter.command.clone())
+    }
+
+    /// Number of blocked clients.
+    pub fn len(&self) -> usize {
+        self.registry.lock().unwrap_or_else(|err| err.into_inner()).clients.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn remove(&self, id: u64) {
+        self.registry.lock().unwrap_or_else(|err| err.into_inner()).remove(id);
+    }
+}
+
+impl Registry {
+    fn remove(&mut self, id: u64) {
+        let waiter = match self.clients.remove(&id) {
+            Some(waiter) => waiter,
+            None => return,
+        };
+
+        for key in waiter.keys.iter() {
+            if let Some(ids) = self.keys.get_mut(key) {
+                ids.retain(|other| *other != id);
+                if ids.is_empty() {
+                    self.keys.remove(key);
+                }
+            }
+        }
+    }
+}
+
+impl BlockedClient<'_> {
+    /// Waits until one of the client's keys is written to, it's unblocked,
+    /// or `deadline` passes, if it has one. A wakeup arriving before the
+    /// wait starts isn't lost. A client served an element is `Ready`, even
+    /// if it was unblocked or timed out at the same time, so the element
+    /// isn't lost.
+    pub async fn wait(&self, deadline: Option<Instant>) -> Wakeup {
+        let woken = match deadline {
+            Some(deadline) => tokio::time::timeout_at(deadline, self.signal.notify.notified()).await.is_ok(),
+            None => {
+                self.signal.notify.notified().await;
+                true
+            },
+        };
+
+        if self.signal.served.lock().unwrap_or_else(|err| err.into_inner()).is_some() {
+            Wakeup::Ready
+        } else if self.signal.unblocked.load(Ordering::Relaxed) {
+            Wakeup::Unblocked
+        } else if woken {
+            Wakeup::Ready
+        } else {
+            Wakeup::Timeout
+        }
+    }
+
+    /// The key and element the client was served while blocked, if any.
+    pub fn served(&self) -> Option<(Bytes, Bytes)> {
+        self.signal.served.lock().unwrap_or_else(|err| err.into_inner()).take()
+    }
+}
+
+impl Drop for BlockedClient<'_> {
+    fn drop(&mut self) {
+        self.blocking.remove(self.id);
+    }
+}
@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The long operations running, like `DEBUG SLEEP` or a big `SORT`, which
+/// may hold locks for long enough that other clients notice.
+///
+/// Once one has run for longer than `busy-reply-threshold`, other clients'
+/// commands get a `BUSY` error instead of silently queueing behind it, and
+/// `SCRIPT KILL` can stop it. Stopping is cooperative: the operation calls
+/// `LongOperation::checkpoint` every now and then, which fails once it's
+/// killed. Checkpoints only come before an operation writes anything, so a
+/// killed one leaves no half applied change behind.
+pub struct BusyOperations {
+    running: Mutex<HashMap<u64, Running>>,
+    next_id: AtomicU64,
+    /// `busy-reply-threshold` in milliseconds, zero or negative to never
+    /// reply `BUSY`.
+    threshold: AtomicI64,
+}
+
+struct Running {
+    client_id: u64,
+    started: Instant,
+    killed: Arc<AtomicBool>,
+}
+
+/// An operation registered as running, until dropped.
+pub struct LongOperation<'a> {
+    /// None for operations no one may kill, like those received from the
+    /// master, which the replica has to apply in full.
+    busy: Option<(&'a BusyOperations, u64)>,
+    killed: Arc<AtomicBool>,
+}
+
+impl BusyOperations {
+    pub fn new(threshold: i64) -> Self {
+        Self { running: Mutex::new(HashMap::new()), next_id: AtomicU64::new(0), threshold: AtomicI64::new(threshold) }
+    }
+
+    /// Applies the `busy-reply-threshold` setting.
+    pub fn configure(&self, threshold: i64) {
+        self.threshold.store(threshold, Ordering::Relaxed);
+    }
+
+    /// Registers a long operation started by client `client_id`.
+    pub fn start(&self, client_id: u64) -> LongOperation<'_> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let killed = Arc::new(AtomicBool::new(false));
+
+        self.running.lock().unwrap_or_else(|err| err.into_inner()).insert(id, Running { client_id, started: Instant::now(), killed: killed.clone() });
+
+        LongOperation { busy: Some((self, id)), killed }
+    }
+
+    /// Whether a long operation of another client than `client_id` has run
+    /// past the threshold, so the client should be told the server is busy.
+    pub fn is_busy(&self, client_id: u64) -> bool {
+        let threshold = match self.threshold.load(Ordering::Relaxed) {
+            threshold if threshold > 0 => Duration::from_millis(threshold as u64),
+            _ => return false,
+        };
+
+        self.running.lock().unwrap_or_else(|err| err.into_inner()).values()
+            .any(|running| running.client_id != client_id && running.started.elapsed() >= threshold)
+    }
+
+    /// Asks every long operation to stop at its next checkpoint, returning
+    /// whether there was any.
+    pub fn kill(&self) -> bool {
+        let running = self.running.lock().unwrap_or_else(|err| err.into_inner());
+
+        for operation in running.values() {
+            operation.killed.store(true, Ordering::Relaxed);
+        }
+
+        !running.is_empty()
+    }
+}
+
+impl LongOperation<'static> {
+    /// An operation which isn't registered, so it's never reported busy
+    /// and never killed.
+    pub fn unkillable() -> Self {
+        LongOperation { busy: None, killed: Arc::new(AtomicBool::new(false)) }
+    }
+}
+
+impl LongOperation<'_> {
+    /// Fails if the operation was killed, so it can stop where it is.
+    pub fn checkpoint(&self) -> crate::Result<()> {
+        match self.killed.load(Ordering::Relaxed) {
+            true => Err("ERR Operation killed by user with SCRIPT KILL".into()),
+            false => Ok(()),
+        }
+    }
+}
+
+impl Drop for LongOperation<'_> {
+    fn drop(&mut self) {
+        if let Some((busy, id)) = self.busy {
+            busy.running.lock().unwrap_or_else(|err| err.into_inner()).remove(&id);
+        }
+    }
+}
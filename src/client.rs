@@ -0,0 +1,166 @@
+//! A client for talking to a Redis server, this one or any other, over a
+//! `Connection`. Replies are returned as frames, apart from error replies,
+//! which become `RedisError`s.
+
+use std::io;
+use std::time::Duration;
+
+use bytes::Bytes;
+use tokio::net::{TcpStream, ToSocketAddrs};
+
+use crate::{Connection, Frame, RedisError};
+
+/// The expiry `SET` gives a key.
+#[derive(Debug, Clone, Copy)]
+pub enum Expiry {
+    /// Seconds from now.
+    Ex(u64),
+    /// Milliseconds from now.
+    Px(u64),
+}
+
+pub struct Client {
+    conn: Connection,
+}
+
+impl Client {
+    pub async fn connect(addr: impl ToSocketAddrs) -> crate::Result<Client> {
+        let stream = TcpStream::connect(addr).await?;
+
+        Ok(Client::new(Connection::new(stream)))
+    }
+
+    pub fn new(conn: Connection) -> Client {
+        Client { conn }
+    }
+
+    /// Gives back the connection, e.g. to keep reading what the server sends
+    /// on its own.
+    pub fn into_connection(self) -> Connection {
+        self.conn
+    }
+
+    pub async fn get(&mut self, key: &str) -> crate::Result<Option<Bytes>> {
+        match self.command(["GET", key]).await? {
+            Frame::Bulk(val) => Ok(val),
+            reply => Err(unexpected_reply(&reply)),
+        }
+    }
+
+    pub async fn set(&mut self, key: &str, val: &[u8], expiry: Option<Expiry>) -> crate::Result<()> {
+        let mut args = vec![Bytes::from_static(b"SET"), Bytes::copy_from_slice(key.as_bytes()), Bytes::copy_from_slice(val)];
+        match expiry {
+            Some(Expiry::Ex(secs)) => args.extend([Bytes::from_static(b"EX"), Bytes::from(secs.to_string())]),
+            Some(Expiry::Px(millis)) => args.extend([Bytes::from_static(b"PX"), Bytes::from(millis.to_string())]),
+            None => {},
+        }
+
+        self.raw_command(args).await?;
+
+        Ok(())
+    }
+
+    pub async fn incr(&mut self, key: &str) -> crate::Result<i64> {
+        match self.command(["INCR", key]).await? {
+            Frame::Integer(val) => Ok(val),
+            reply => Err(unexpected_reply(&reply)),
+        }
+    }
+
+    /// Sends any command, returning its reply.
+    pub async fn raw_command(&mut self, args: Vec<Bytes>) -> crate::Result<Frame> {
+        self.command(args).await
+    }
+
+    /// Sends a command given as strings or bytes, returning its reply.
+    pub async fn command<I>(&mut self, args: I) -> crate::Result<Frame>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        into_result(self.request(args).await?)
+    }
+
+    /// Sends a command, returning its reply as is, error replies included.
+    pub(crate) async fn request<I>(&mut self, args: I) -> crate::Result<Frame>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        self.conn.write_frame(&Frame::command(args)).await?;
+
+        self.read_reply().await
+    }
+
+    /// Starts a pipeline: commands sent together, with their replies read
+    /// once they've all been sent.
+    pub fn pipeline(&mut self) -> Pipeline<'_> {
+        Pipeline { client: self, commands: vec![] }
+    }
+
+    /// Reads an RDB file, sent without the trailing CRLF of bulk strings.
+    /// Fails with `TimedOut` once nothing arrived for `idle_timeout`.
+    pub(crate) async fn read_file(&mut self, idle_timeout: Option<Duration>) -> crate::Result<Bytes> {
+        self.conn.set_idle_timeout(idle_timeout);
+        let res = self.conn.read_frame(true).await;
+        self.conn.set_idle_timeout(None);
+
+        match res? {
+            Some(Frame::File(file)) => Ok(file),
+            Some(reply) => Err(unexpected_reply(&reply)),
+            None => Err(closed()),
+        }
+    }
+
+    async fn read_reply(&mut self) -> crate::Result<Frame> {
+        self.conn.read_frame(false).await?.ok_or_else(closed)
+    }
+}
+
+pub struct Pipeline<'a> {
+    client: &'a mut Client,
+    commands: Vec<Frame>,
+}
+
+impl<'a> Pipeline<'a> {
+    pub fn command<I>(&mut self, args: I) -> &mut Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        self.commands.push(Frame::command(args));
+        self
+    }
+
+    /// Sends the commands, returning their replies in order. Failing
+    /// commands don't stop the ones after them, so each reply is a result of
+    /// its own; only losing the connection fails the whole pipeline.
+    pub async fn execute(&mut self) -> crate::Result<Vec<crate::Result<Frame>>> {
+        for cmd in self.commands.iter() {
+            self.client.conn.queue_frame(cmd).await?;
+        }
+        self.client.conn.flush().await?;
+
+        let mut replies = Vec::with_capacity(self.commands.len());
+        for _ in self.commands.drain(..) {
+            replies.push(into_result(self.client.read_reply().await?));
+        }
+
+        Ok(replies)
+    }
+}
+
+fn into_result(reply: Frame) -> crate::Result<Frame> {
+    match reply {
+        Frame::Error(err) => Err(RedisError::from_reply(err)),
+        reply => Ok(reply),
+    }
+}
+
+fn unexpected_reply(reply: &Frame) -> RedisError {
+    RedisError::Reply(format!("ERR unexpected reply {:?}", reply))
+}
+
+fn closed() -> RedisError {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed by server").into()
+}
@@ -0,0 +1,62 @@
+use crate::{ChannelKind, Command};
+
+/// Per-connection state, owned by the task serving the connection.
+#[derive(Debug, Default)]
+pub struct ClientState {
+    /// Id of the client, unique for as long as the server runs.
+    pub id: u64,
+    /// Whether the client is a replica, which sent `PSYNC`.
+    pub replica: bool,
+    /// Index of the database selected with `SELECT`.
+    pub db_index: usize,
+    /// Number of channels and patterns the client is subscribed to. While
+    /// non-zero the connection is in subscriber mode.
+    pub subscriptions: usize,
+    /// Number of shard channels the client is subscribed to, which put it
+    /// in subscriber mode just the same.
+    pub shard_subscriptions: usize,
+    /// The ACL user the client is logged in as, `None` until it
+    /// authenticates if the default user needs a password.
+    pub user: Option<String>,
+    /// The name set with `CLIENT SETNAME` or `HELLO .. SETNAME`.
+    pub name: Option<String>,
+    /// The client library and its version, as the library reports them
+    /// with `CLIENT SETINFO`.
+    pub lib_name: Option<String>,
+    pub lib_ver: Option<String>,
+    /// The transaction started with `MULTI`, until `EXEC` or `DISCARD`.
+    pub transaction: Option<Transaction>,
+}
+
+/// Commands queued between `MULTI` and `EXEC`.
+#[derive(Debug, Default)]
+pub struct Transaction {
+    pub commands: Vec<Command>,
+    /// Whether a command was refused while queueing, which makes `EXEC`
+    /// discard the transaction.
+    pub failed: bool,
+}
+
+impl ClientState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_subscribed(&self) -> bool {
+        self.subscriptions > 0 || self.shard_subscriptions > 0
+    }
+
+    /// Makes the transaction fail on `EXEC`, if one is open.
+    pub fn fail_transaction(&mut self) {
+        if let Some(transaction) = self.transaction.as_mut() {
+            transaction.failed = true;
+        }
+    }
+
+    pub fn subscriptions_mut(&mut self, kind: ChannelKind) -> &mut usize {
+        match kind {
+            ChannelKind::Global => &mut self.subscriptions,
+            ChannelKind::Shard => &mut self.shard_subscriptions,
+        }
+    }
+}
@@ -0,0 +1,259 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use bytes::Bytes;
+use tokio::sync::watch;
+
+use crate::{Blocking, ClientState, Watches};
+
+/// The clients connected to this server, as listed by `CLIENT LIST`.
+///
+/// The task serving a connection registers it for as long as it runs, and
+/// keeps its entry up to date around every command. Other clients can only
+/// read the entries, or ask for a client to be killed, which the serving task
+/// notices and acts on.
+pub struct ClientRegistry {
+    clients: Mutex<BTreeMap<u64, ClientInfo>>,
+    next_id: AtomicU64,
+}
+
+struct ClientInfo {
+    addr: String,
+    connected_at: Instant,
+    last_interaction: Instant,
+    /// The last command the client sent, or the one it's running.
+    cmd: String,
+    db: usize,
+    sub: usize,
+    ssub: usize,
+    /// Commands queued in the client's transaction, -1 outside of one.
+    multi: i64,
+    user: String,
+    name: String,
+    lib_name: String,
+    lib_ver: String,
+    replica: bool,
+    kill: watch::Sender<bool>,
+}
+
+/// The kinds of clients `CLIENT KILL TYPE` tells apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientType {
+    Normal,
+    /// Our master's link. It isn't registered as a client, so never matches.
+    Master,
+    Replica,
+    /// Clients subscribed to at least one channel.
+    PubSub,
+}
+
+impl ClientType {
+    /// Parses a type as `CLIENT KILL` takes it, `slave` included.
+    pub fn parse(name: &str) -> Option<ClientType> {
+        match name.to_lowercase().as_str() {
+            "normal" => Some(ClientType::Normal),
+            "master" => Some(ClientType::Master),
+            "replica" | "slave" => Some(ClientType::Replica),
+            "pubsub" => Some(ClientType::PubSub),
+            _ => None,
+        }
+    }
+}
+
+impl ClientInfo {
+    /// Like redis-server, a replica is a replica first, and a subscriber
+    /// only if it isn't one.
+    fn client_type(&self) -> ClientType {
+        if self.replica {
+            ClientType::Replica
+        } else if self.sub > 0 || self.ssub > 0 {
+            ClientType::PubSub
+        } else {
+            ClientType::Normal
+        }
+    }
+
+    /// The client's line in `CLIENT LIST`.
+    fn describe(&self, id: u64, blocking: &Blocking) -> String {
+        let mut flags = String::new();
+        if self.replica {
+            flags.push('S');
+        }
+        if blocking.blocked_by(id).is_some() {
+            flags.push('b');
+        }
+        if self.client_type() == ClientType::PubSub {
+            flags.push('P');
+        }
+        if self.multi >= 0 {
+            flags.push('x');
+        }
+        if flags.is_empty() {
+            flags.push('N');
+        }
+
+        format!(
+            "id={} addr={} name={} age={} idle={} flags={} db={} sub={} psub=0 ssub={} multi={} cmd={} user={} resp=2 lib-name={} lib-ver={}\n",
+            id,
+            self.addr,
+            self.name,
+            self.connected_at.elapsed().as_secs(),
+            self.last_interaction.elapsed().as_secs(),
+            flags,
+            self.db,
+            self.sub,
+            self.ssub,
+            self.multi,
+            self.cmd,
+            self.user,
+            self.lib_name,
+            self.lib_ver,
+        )
+    }
+}
+
+/// A client's entry in the registry, removed when dropped.
+pub struct RegisteredClient<'a> {
+    registry: &'a ClientRegistry,
+    pub id: u64,
+    killed: watch::Receiver<bool>,
+}
+
+impl ClientRegistry {
+    pub fn new() -> Self {
+        // Like redis-server, ids start at 1 and are never reused.
+        Self { clients: Mutex::new(BTreeMap::new()), next_id: AtomicU64::new(1) }
+    }
+
+    pub fn register(&self, addr: &str) -> RegisteredClient<'_> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (kill, killed) = watch::channel(false);
+        let now = Instant::now();
+
+        self.clients.lock().unwrap_or_else(|err| err.into_inner()).insert(id, ClientInfo {
+            addr: addr.to_string(),
+            connected_at: now,
+            last_interaction: now,
+            cmd: "NULL".to_string(),
+            db: 0,
+            sub: 0,
+            ssub: 0,
+            multi: -1,
+            user: String::new(),
+            name: String::new(),
+            lib_name: String::new(),
+            lib_ver: String::new(),
+            replica: false,
+            kill,
+        });
+
+        RegisteredClient { registry: self, id, killed }
+    }
+
+    /// Records that the client started running `cmd`.
+    pub fn start_command(&self, id: u64, cmd: &str) {
+        if let Some(info) = self.clients.lock().unwrap_or_else(|err| err.into_inner()).get_mut(&id) {
+            info.cmd = cmd.to_string();
+            info.last_interaction = Instant::now();
+        }
+    }
+
+    /// Records the client's state once a command is done with it.
+    pub fn update(&self, id: u64, client: &ClientState) {
+        if let Some(info) = self.clients.lock().unwrap_or_else(|err| err.into_inner()).get_mut(&id) {
+            info.db = client.db_index;
+            info.sub = client.subscriptions;
+            info.ssub = client.shard_subscriptions;
+            info.multi = client.transaction.as_ref().map(|transaction| transaction.commands.len() as i64).unwrap_or(-1);
+            info.user = client.user.clone().unwrap_or_default();
+            info.name = client.name.clone().unwrap_or_default();
+            info.lib_name = client.lib_name.clone().unwrap_or_default();
+            info.lib_ver = client.lib_ver.clone().unwrap_or_default();
+            info.replica = client.replica;
+            info.last_interaction = Instant::now();
+        }
+    }
+
+    /// Asks the clients matching `id`, `addr` and `client_type`, where given,
+    /// to disconnect, returning the ids of those found. The client `skip` is
+    /// left alone.
+    pub fn kill(&self, id: Option<u64>, addr: Option<&str>, client_type: Option<ClientType>, skip: Option<u64>) -> Vec<u64> {
+        let clients = self.clients.lock().unwrap_or_else(|err| err.into_inner());
+
+        clients.iter()
+            .filter(|(other, _)| Some(**other) != skip)
+            .filter(|(other, _)| id.map(|id| id == **other).unwrap_or(true))
+            .filter(|(_, info)| addr.map(|addr| addr == info.addr).unwrap_or(true))
+            .filter(|(_, info)| client_type.map(|client_type| client_type == info.client_type()).unwrap_or(true))
+            .map(|(other, info)| {
+                let _ = info.kill.send(true);
+                *other
+            })
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.clients.lock().unwrap_or_else(|err| err.into_inner()).len()
+    }
+
+    /// Number of clients of the given type.
+    pub fn count(&self, client_type: ClientType) -> usize {
+        self.clients.lock().unwrap_or_else(|err| err.into_inner()).values().filter(|info| info.client_type() == client_type).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// One line per client, in the format of `CLIENT LIST`.
+    pub fn list(&self, blocking: &Blocking) -> String {
+        let clients = self.clients.lock().unwrap_or_else(|err| err.into_inner());
+
+        clients.iter().map(|(id, info)| info.describe(*id, blocking)).collect()
+    }
+
+    /// The line `CLIENT LIST` has for client `id`, as `CLIENT INFO` replies.
+    pub fn info(&self, id: u64, blocking: &Blocking) -> Option<String> {
+        self.clients.lock().unwrap_or_else(|err| err.into_inner()).get(&id).map(|info| info.describe(id, blocking))
+    }
+
+    pub fn get_info_bytes(&self, blocking: &Blocking, watches: &Watches) -> Bytes {
+        Bytes::from(format!(
+            "# Clients\nconnected_clients:{}\nblocked_clients:{}\npubsub_clients:{}\nwatching_clients:{}\ntotal_watched_keys:{}\n",
+            self.len(),
+            blocking.len(),
+            self.count(ClientType::PubSub),
+            watches.watching_clients(),
+            watches.watched_keys_count(),
+        ))
+    }
+}
+
+impl Default for ClientRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RegisteredClient<'_> {
+    pub fn is_killed(&self) -> bool {
+        *self.killed.borrow()
+    }
+
+    /// Waits until the client is killed.
+    pub async fn killed(&mut self) {
+        while !*self.killed.borrow_and_update() {
+            if self.killed.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+impl Drop for RegisteredClient<'_> {
+    fn drop(&mut self) {
+        self.registry.clients.lock().unwrap_or_else(|err| err.into_inner()).remove(&self.id);
+    }
+}
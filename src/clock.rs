@@ -0,0 +1,168 @@
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// How often the wall clock is read again to check for drift.
+const RESYNC_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Drift from the wall clock tolerated before re-anchoring.
+const MAX_DRIFT_MILLIS: u128 = 1000;
+
+/// Where the server reads the time from, in milliseconds since the Unix
+/// epoch. Every expiry is checked against it, so swapping it for a
+/// `ManualClock` with `install` makes expiry deterministic.
+pub trait Clock: Send + Sync {
+    fn now_millis(&self) -> u128;
+}
+
+/// A source of wall-clock time, in milliseconds since the Unix epoch.
+pub trait WallClock: Send + Sync {
+    fn now_millis(&self) -> u128;
+}
+
+/// The system's real-time clock.
+pub struct SystemClock;
+
+impl WallClock for SystemClock {
+    fn now_millis(&self) -> u128 {
+        // A clock set before 1970 is treated as being at the epoch instead of
+        // failing.
+        SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or(0)
+    }
+}
+
+struct Anchor {
+    instant: Instant,
+    wall_millis: u128,
+    last_resync: Instant,
+    last_millis: u128,
+}
+
+/// Unix timestamps that never go backwards.
+///
+/// A single wall-clock reading is anchored to an `Instant`, and timestamps
+/// are derived from the time elapsed since. The wall clock is checked again
+/// every `RESYNC_INTERVAL`: when it has drifted ahead by more than
+/// `MAX_DRIFT_MILLIS`, the clock jumps forward to it, but a wall clock stepped
+/// backwards is ignored and time keeps advancing from where it was.
+pub struct MonotonicClock<W: WallClock = SystemClock> {
+    wall: W,
+    anchor: Mutex<Anchor>,
+}
+
+impl<W: WallClock> MonotonicClock<W> {
+    pub fn new(wall: W) -> Self {
+        let now = Instant::now();
+        let wall_millis = wall.now_millis();
+
+        Self {
+            wall,
+            anchor: Mutex::new(Anchor { instant: now, wall_millis, last_resync: now, last_millis: wall_millis }),
+        }
+    }
+
+    pub fn now_millis(&self) -> u128 {
+        // Nothing below can panic while the lock is held, but don't let a
+        // poisoned lock take every caller down with it either.
+        let mut anchor = self.anchor.lock().unwrap_or_else(|err| err.into_inner());
+
+        let now = Instant::now();
+        let mut millis = anchor.wall_millis + now.duration_since(anchor.instant).as_millis();
+
+        if now.duration_since(anchor.last_resync) >= RESYNC_INTERVAL {
+            anchor.last_resync = now;
+
+            let wall_millis = self.wall.now_millis();
+            if wall_millis > millis + MAX_DRIFT_MILLIS {
+                anchor.instant = now;
+                anchor.wall_millis = wall_millis;
+                millis = wall_millis;
+            }
+        }
+
+        millis = millis.max(anchor.last_millis);
+        anchor.last_millis = millis;
+
+        millis
+    }
+}
+
+impl<W: WallClock> Clock for MonotonicClock<W> {
+    fn now_millis(&self) -> u128 {
+        MonotonicClock::now_millis(self)
+    }
+}
+
+/// A clock which only moves when told to.
+pub struct ManualClock {
+    millis: AtomicU64,
+}
+
+impl ManualClock {
+    pub fn new(millis: u64) -> Self {
+        Self { millis: AtomicU64::new(millis) }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.millis.fetch_add(by.as_millis() as u64, Ordering::SeqCst);
+    }
+
+    pub fn set(&self, millis: u64) {
+        self.millis.store(millis, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now_millis(&self) -> u128 {
+        self.millis.load(Ordering::SeqCst) as u128
+    }
+}
+
+static SYSTEM_CLOCK: AtomicPtr<MonotonicClock> = AtomicPtr::new(ptr::null_mut());
+
+/// The process-wide clock, anchored on first use.
+pub fn system() -> &'static MonotonicClock {
+    let clock = SYSTEM_CLOCK.load(Ordering::Acquire);
+    if !clock.is_null() {
+        // SAFETY: Once set, the pointer refers to a leaked box which is never
+        // freed.
+        return unsafe { &*clock };
+    }
+
+    let new = Box::into_raw(Box::new(MonotonicClock::new(SystemClock)));
+    match SYSTEM_CLOCK.compare_exchange(ptr::null_mut(), new, Ordering::AcqRel, Ordering::Acquire) {
+        // SAFETY: `new` was just leaked and is never freed.
+        Ok(_) => unsafe { &*new },
+        Err(existing) => {
+            // Another thread got there first.
+            // SAFETY: `new` was never shared.
+            drop(unsafe { Box::from_raw(new) });
+            // SAFETY: As above, `existing` is never freed.
+            unsafe { &*existing }
+        }
+    }
+}
+
+// A `&dyn Clock` is two words wide, so the atomic points at a leaked one.
+static INSTALLED_CLOCK: AtomicPtr<&'static dyn Clock> = AtomicPtr::new(ptr::null_mut());
+
+/// Makes `clock` the one the server reads the time from, instead of
+/// `system()`. Meant for tests, which install a `ManualClock` before
+/// starting the server.
+pub fn install(clock: &'static dyn Clock) {
+    // Whatever was installed before may still be in use, so it's leaked.
+    INSTALLED_CLOCK.store(Box::into_raw(Box::new(clock)), Ordering::Release);
+}
+
+/// The clock the server reads the time from: the installed one if any,
+/// otherwise `system()`.
+pub fn current() -> &'static dyn Clock {
+    let clock = INSTALLED_CLOCK.load(Ordering::Acquire);
+    if clock.is_null() {
+        return system();
+    }
+
+    // SAFETY: Installed clocks are leaked boxes which are never freed.
+    unsafe { *clock }
+}
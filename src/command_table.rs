@@ -0,0 +1,1146 @@
+use crate::commands::{bitmap, cluster, connection, generic, geo, hash, hyperloglog, list, parse, pubsub, replication, scripting, server, set, string, transactions, CommandArgs, CommandExec};
+use std::collections::HashMap;
+
+use bytes::Bytes;
+
+use crate::Frame;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandFlag {
+    Write,
+    Readonly,
+    Denyoom,
+    Admin,
+    Noscript,
+    Loading,
+    Stale,
+    Fast,
+    Blocking,
+    /// The keys can't all be found from `keys`, see `CommandSpec::extract_keys`.
+    MovableKeys,
+}
+
+impl CommandFlag {
+    pub fn name(&self) -> &'static str {
+        match self {
+            CommandFlag::Write => "write",
+            CommandFlag::Readonly => "readonly",
+            CommandFlag::Denyoom => "denyoom",
+            CommandFlag::Admin => "admin",
+            CommandFlag::Noscript => "noscript",
+            CommandFlag::Loading => "loading",
+            CommandFlag::Stale => "stale",
+            CommandFlag::Fast => "fast",
+            CommandFlag::Blocking => "blocking",
+            CommandFlag::MovableKeys => "movablekeys",
+        }
+    }
+}
+
+/// Where the keys of a command are found in its argument vector, counting
+/// the command name as position 0. A negative `last` counts from the end, and
+/// `first == 0` means the command takes no keys.
+#[derive(Debug, Clone, Copy)]
+pub struct KeyPositions {
+    pub first: i64,
+    pub last: i64,
+    pub step: i64,
+}
+
+impl KeyPositions {
+    /// The keys among a command's arguments.
+    pub fn extract<'a>(&self, args: &'a [Bytes]) -> Vec<&'a Bytes> {
+        if self.first <= 0 || self.step <= 0 {
+            return vec![];
+        }
+
+        let last = match self.last < 0 {
+            true => args.len() as i64 + self.last,
+            false => self.last.min(args.len() as i64 - 1),
+        };
+
+        (self.first..=last).step_by(self.step as usize).map(|idx| &args[idx as usize]).collect()
+    }
+}
+
+/// Keys of `SORT`: the key sorted and, with `STORE`, the destination. Like
+/// in redis-server, the patterns of `BY` and `GET` don't count, and only
+/// the last `STORE` does.
+fn sort_keys(args: &[Bytes]) -> Vec<&Bytes> {
+    let mut keys: Vec<&Bytes> = args.get(1).into_iter().collect();
+    let mut store = None;
+
+    let mut idx = 2;
+    while idx < args.len() {
+        let arg = &args[idx];
+        if arg.eq_ignore_ascii_case(b"limit") {
+            idx += 3;
+        } else if arg.eq_ignore_ascii_case(b"by") || arg.eq_ignore_ascii_case(b"get") {
+            idx += 2;
+        } else if arg.eq_ignore_ascii_case(b"store") {
+            store = args.get(idx + 1).or(store);
+            idx += 2;
+        } else {
+            idx += 1;
+        }
+    }
+    keys.extend(store);
+
+    keys
+}
+
+const NO_KEYS: KeyPositions = KeyPositions { first: 0, last: 0, step: 0 };
+const SINGLE_KEY: KeyPositions = KeyPositions { first: 1, last: 1, step: 1 };
+
+#[derive(Debug)]
+pub struct CommandSpec {
+    /// Lowercase command name.
+    pub name: &'static str,
+    /// Parses the command's arguments, already checked against `arity`.
+    pub parse: fn(&CommandArgs) -> crate::Result<Box<dyn CommandExec>>,
+    /// Number of arguments including the command name; a negative value
+    /// means "at least that many".
+    pub arity: i64,
+    pub flags: &'static [CommandFlag],
+    pub keys: KeyPositions,
+    pub group: &'static str,
+    pub since: &'static str,
+    pub summary: &'static str,
+}
+
+impl CommandSpec {
+    pub fn has_flag(&self, flag: CommandFlag) -> bool {
+        self.flags.contains(&flag)
+    }
+
+    /// The keys among `args`, the command's arguments including its name.
+    /// Those of commands with `movablekeys` depend on the other arguments,
+    /// which are parsed for them.
+    pub fn extract_keys<'a>(&self, args: &'a [Bytes]) -> Vec<&'a Bytes> {
+        match self.name {
+            "sort" => sort_keys(args),
+            _ => self.keys.extract(args),
+        }
+    }
+
+    /// Whether the arguments may hold a password, like those of `AUTH` and
+    /// `HELLO ... AUTH`, which `MONITOR` doesn't show and `SLOWLOG` redacts.
+    pub fn has_secret_args(&self) -> bool {
+        matches!(self.name, "auth" | "hello")
+    }
+
+    pub fn check_arity(&self, argc: usize) -> bool {
+        let argc = argc as i64;
+
+        if self.arity >= 0 {
+            argc == self.arity
+        } else {
+            argc >= -self.arity
+        }
+    }
+
+    /// ACL categories the command belongs to.
+    pub fn categories(&self) -> Vec<String> {
+        let mut categories = vec![];
+
+        if self.has_flag(CommandFlag::Write) {
+            categories.push("@write");
+        }
+        if self.has_flag(CommandFlag::Readonly) {
+            categories.push("@read");
+        }
+        if self.has_flag(CommandFlag::Fast) {
+            categories.push("@fast");
+        } else {
+            categories.push("@slow");
+        }
+        if self.has_flag(CommandFlag::Blocking) {
+            categories.push("@blocking");
+        }
+        if self.has_flag(CommandFlag::Admin) {
+            categories.push("@admin");
+            categories.push("@dangerous");
+        }
+
+        let mut categories: Vec<String> = categories.into_iter().map(String::from).collect();
+        categories.push(format!("@{}", self.group));
+
+        categories
+    }
+
+    /// The `COMMAND INFO` entry for the command, called `name` by clients.
+    pub fn info_frame(&self, name: &str) -> Frame {
+        Frame::Array(vec![
+            Frame::bulk(name.to_string()),
+            Frame::Integer(self.arity),
+            Frame::Array(self.flags.iter().map(|flag| Frame::Simple(flag.name().to_string())).collect()),
+            Frame::Integer(self.keys.first),
+            Frame::Integer(self.keys.last),
+            Frame::Integer(self.keys.step),
+            Frame::Array(self.categories().into_iter().map(Frame::Simple).collect()),
+            // Tips, key specifications and subcommands.
+            Frame::Array(vec![]),
+            Frame::Array(vec![]),
+            Frame::Array(vec![]),
+        ])
+    }
+
+    /// The `COMMAND DOCS` entry for the command.
+    pub fn docs_frame(&self) -> Frame {
+        Frame::Array(vec![
+            Frame::bulk("summary"),
+            Frame::bulk(self.summary),
+            Frame::bulk("since"),
+            Frame::bulk(self.since),
+            Frame::bulk("group"),
+            Frame::bulk(self.group),
+        ])
+    }
+}
+
+/// Every command the server implements, with its parser. A command missing
+/// from this table is treated as unknown. Kept sorted by name, which
+/// `lookup` relies on.
+pub const COMMAND_TABLE: &[CommandSpec] = &[
+    CommandSpec {
+        name: "acl",
+        parse: parse::<server::AclCommand>,
+        arity: -2,
+        flags: &[CommandFlag::Noscript, CommandFlag::Loading, CommandFlag::Stale],
+        keys: NO_KEYS,
+        group: "server",
+        since: "6.0.0",
+        summary: "A container for Access List Control commands.",
+    },
+    CommandSpec {
+        name: "append",
+        parse: parse::<string::Append>,
+        arity: 3,
+        flags: &[CommandFlag::Write, CommandFlag::Denyoom, CommandFlag::Fast],
+        keys: SINGLE_KEY,
+        group: "string",
+        since: "2.0.0",
+        summary: "Appends a string to the value of a key. Creates the key if it doesn't exist.",
+    },
+    CommandSpec {
+        name: "auth",
+        parse: parse::<connection::Auth>,
+        arity: -2,
+        flags: &[CommandFlag::Noscript, CommandFlag::Loading, CommandFlag::Stale, CommandFlag::Fast],
+        keys: NO_KEYS,
+        group: "connection",
+        since: "1.0.0",
+        summary: "Authenticates the connection.",
+    },
+    CommandSpec {
+        name: "bitcount",
+        parse: parse::<bitmap::BitCount>,
+        arity: -2,
+        flags: &[CommandFlag::Readonly],
+        keys: SINGLE_KEY,
+        group: "bitmap",
+        since: "2.6.0",
+        summary: "Counts the number of set bits (population counting) in a string.",
+    },
+    CommandSpec {
+        name: "bitfield",
+        parse: parse::<bitmap::BitField>,
+        arity: -2,
+        flags: &[CommandFlag::Write, CommandFlag::Denyoom],
+        keys: SINGLE_KEY,
+        group: "bitmap",
+        since: "3.2.0",
+        summary: "Performs arbitrary bitfield integer operations on strings.",
+    },
+    CommandSpec {
+        name: "bitop",
+        parse: parse::<bitmap::BitOp>,
+        arity: -4,
+        flags: &[CommandFlag::Write, CommandFlag::Denyoom],
+        keys: KeyPositions { first: 2, last: -1, step: 1 },
+        group: "bitmap",
+        since: "2.6.0",
+        summary: "Performs bitwise operations on multiple strings, and stores the result.",
+    },
+    CommandSpec {
+        name: "bitpos",
+        parse: parse::<bitmap::BitPos>,
+        arity: -3,
+        flags: &[CommandFlag::Readonly],
+        keys: SINGLE_KEY,
+        group: "bitmap",
+        since: "2.8.7",
+        summary: "Finds the first set (1) or clear (0) bit in a string.",
+    },
+    CommandSpec {
+        name: "blpop",
+        parse: parse::<list::BlockingPop>,
+        arity: -3,
+        flags: &[CommandFlag::Write, CommandFlag::Noscript, CommandFlag::Blocking],
+        keys: KeyPositions { first: 1, last: -2, step: 1 },
+        group: "list",
+        since: "2.0.0",
+        summary: "Removes and returns the first element in a list. Blocks until an element is available otherwise.",
+    },
+    CommandSpec {
+        name: "brpop",
+        parse: parse::<list::BlockingPop>,
+        arity: -3,
+        flags: &[CommandFlag::Write, CommandFlag::Noscript, CommandFlag::Blocking],
+        keys: KeyPositions { first: 1, last: -2, step: 1 },
+        group: "list",
+        since: "2.0.0",
+        summary: "Removes and returns the last element in a list. Blocks until an element is available otherwise.",
+    },
+    CommandSpec {
+        name: "client",
+        parse: parse::<connection::ClientCommand>,
+        arity: -2,
+        flags: &[CommandFlag::Admin, CommandFlag::Noscript, CommandFlag::Loading, CommandFlag::Stale],
+        keys: NO_KEYS,
+        group: "connection",
+        since: "2.4.0",
+        summary: "A container for client connection commands.",
+    },
+    CommandSpec {
+        name: "cluster",
+        parse: parse::<cluster::Cluster>,
+        arity: -2,
+        flags: &[CommandFlag::Loading, CommandFlag::Stale],
+        keys: NO_KEYS,
+        group: "cluster",
+        since: "3.0.0",
+        summary: "A container for Redis Cluster commands.",
+    },
+    CommandSpec {
+        name: "command",
+        parse: parse::<server::CommandList>,
+        arity: -1,
+        flags: &[CommandFlag::Loading, CommandFlag::Stale],
+        keys: NO_KEYS,
+        group: "server",
+        since: "2.8.13",
+        summary: "Returns detailed information about all commands.",
+    },
+    CommandSpec {
+        name: "config",
+        parse: parse::<server::Config>,
+        arity: -2,
+        flags: &[CommandFlag::Admin, CommandFlag::Noscript, CommandFlag::Loading, CommandFlag::Stale],
+        keys: NO_KEYS,
+        group: "server",
+        since: "2.0.0",
+        summary: "Gets or sets configuration parameters.",
+    },
+    CommandSpec {
+        name: "debug",
+        parse: parse::<server::DebugCommand>,
+        arity: -2,
+        flags: &[CommandFlag::Admin, CommandFlag::Noscript, CommandFlag::Loading, CommandFlag::Stale],
+        keys: NO_KEYS,
+        group: "server",
+        since: "1.0.0",
+        summary: "A container for debugging commands.",
+    },
+    CommandSpec {
+        name: "decr",
+        parse: parse::<string::IncrBy>,
+        arity: 2,
+        flags: &[CommandFlag::Write, CommandFlag::Denyoom, CommandFlag::Fast],
+        keys: SINGLE_KEY,
+        group: "string",
+        since: "1.0.0",
+        summary: "Decrements the integer value of a key by one. Uses 0 as initial value if the key doesn't exist.",
+    },
+    CommandSpec {
+        name: "decrby",
+        parse: parse::<string::IncrBy>,
+        arity: 3,
+        flags: &[CommandFlag::Write, CommandFlag::Denyoom, CommandFlag::Fast],
+        keys: SINGLE_KEY,
+        group: "string",
+        since: "1.0.0",
+        summary: "Decrements a number from the integer value of a key. Uses 0 as initial value if the key doesn't exist.",
+    },
+    CommandSpec {
+        name: "del",
+        parse: parse::<generic::Del>,
+        arity: -2,
+        flags: &[CommandFlag::Write],
+        keys: KeyPositions { first: 1, last: -1, step: 1 },
+        group: "generic",
+        since: "1.0.0",
+        summary: "Deletes one or more keys.",
+    },
+    CommandSpec {
+        name: "discard",
+        parse: parse::<transactions::Discard>,
+        arity: 1,
+        flags: &[CommandFlag::Noscript, CommandFlag::Loading, CommandFlag::Stale, CommandFlag::Fast],
+        keys: NO_KEYS,
+        group: "transactions",
+        since: "2.0.0",
+        summary: "Discards a transaction.",
+    },
+    CommandSpec {
+        name: "dump",
+        parse: parse::<generic::Dump>,
+        arity: 2,
+        flags: &[CommandFlag::Readonly],
+        keys: SINGLE_KEY,
+        group: "generic",
+        since: "2.6.0",
+        summary: "Returns a serialized representation of the value stored at a key.",
+    },
+    CommandSpec {
+        name: "echo",
+        parse: parse::<connection::Echo>,
+        arity: 2,
+        flags: &[CommandFlag::Fast],
+        keys: NO_KEYS,
+        group: "connection",
+        since: "1.0.0",
+        summary: "Returns the given string.",
+    },
+    CommandSpec {
+        name: "exec",
+        parse: parse::<transactions::Exec>,
+        arity: 1,
+        flags: &[CommandFlag::Noscript, CommandFlag::Loading, CommandFlag::Stale],
+        keys: NO_KEYS,
+        group: "transactions",
+        since: "1.2.0",
+        summary: "Executes all commands in a transaction.",
+    },
+    CommandSpec {
+        name: "exists",
+        parse: parse::<generic::Exists>,
+        arity: -2,
+        flags: &[CommandFlag::Readonly, CommandFlag::Fast],
+        keys: KeyPositions { first: 1, last: -1, step: 1 },
+        group: "generic",
+        since: "1.0.0",
+        summary: "Determines whether one or more keys exist.",
+    },
+    CommandSpec {
+        name: "flushall",
+        parse: parse::<server::FlushAll>,
+        arity: -1,
+        flags: &[CommandFlag::Write],
+        keys: NO_KEYS,
+        group: "server",
+        since: "1.0.0",
+        summary: "Removes all keys from all databases.",
+    },
+    CommandSpec {
+        name: "flushdb",
+        parse: parse::<server::FlushDb>,
+        arity: -1,
+        flags: &[CommandFlag::Write],
+        keys: NO_KEYS,
+        group: "server",
+        since: "1.0.0",
+        summary: "Remove all keys from the current database.",
+    },
+    CommandSpec {
+        name: "geoadd",
+        parse: parse::<geo::GeoAdd>,
+        arity: -5,
+        flags: &[CommandFlag::Write, CommandFlag::Denyoom],
+        keys: SINGLE_KEY,
+        group: "geo",
+        since: "3.2.0",
+        summary: "Adds one or more members to a geospatial index. The key is created if it doesn't exist.",
+    },
+    CommandSpec {
+        name: "geodist",
+        parse: parse::<geo::GeoDist>,
+        arity: -4,
+        flags: &[CommandFlag::Readonly],
+        keys: SINGLE_KEY,
+        group: "geo",
+        since: "3.2.0",
+        summary: "Returns the distance between two members of a geospatial index.",
+    },
+    CommandSpec {
+        name: "geopos",
+        parse: parse::<geo::GeoPos>,
+        arity: -2,
+        flags: &[CommandFlag::Readonly],
+        keys: SINGLE_KEY,
+        group: "geo",
+        since: "3.2.0",
+        summary: "Returns the longitude and latitude of members from a geospatial index.",
+    },
+    CommandSpec {
+        name: "geosearch",
+        parse: parse::<geo::GeoSearch>,
+        arity: -7,
+        flags: &[CommandFlag::Readonly],
+        keys: SINGLE_KEY,
+        group: "geo",
+        since: "6.2.0",
+        summary: "Queries a geospatial index for members inside an area of a box or a circle.",
+    },
+    CommandSpec {
+        name: "get",
+        parse: parse::<string::Get>,
+        arity: 2,
+        flags: &[CommandFlag::Readonly, CommandFlag::Fast],
+        keys: SINGLE_KEY,
+        group: "string",
+        since: "1.0.0",
+        summary: "Returns the string value of a key.",
+    },
+    CommandSpec {
+        name: "getbit",
+        parse: parse::<bitmap::GetBit>,
+        arity: 3,
+        flags: &[CommandFlag::Readonly, CommandFlag::Fast],
+        keys: SINGLE_KEY,
+        group: "bitmap",
+        since: "2.2.0",
+        summary: "Returns a bit value by offset.",
+    },
+    CommandSpec {
+        name: "getrange",
+        parse: parse::<string::GetRange>,
+        arity: 4,
+        flags: &[CommandFlag::Readonly],
+        keys: SINGLE_KEY,
+        group: "string",
+        since: "2.4.0",
+        summary: "Returns a substring of the string stored at a key.",
+    },
+    CommandSpec {
+        name: "hdel",
+        parse: parse::<hash::HDel>,
+        arity: -3,
+        flags: &[CommandFlag::Write, CommandFlag::Fast],
+        keys: SINGLE_KEY,
+        group: "hash",
+        since: "2.0.0",
+        summary: "Deletes one or more fields and their values from a hash. Deletes the hash if no fields remain.",
+    },
+    CommandSpec {
+        name: "hello",
+        parse: parse::<connection::Hello>,
+        arity: -1,
+        flags: &[CommandFlag::Noscript, CommandFlag::Loading, CommandFlag::Stale, CommandFlag::Fast],
+        keys: NO_KEYS,
+        group: "connection",
+        since: "6.0.0",
+        summary: "Handshakes with the Redis server.",
+    },
+    CommandSpec {
+        name: "hexpire",
+        parse: parse::<hash::HExpire>,
+        arity: -6,
+        flags: &[CommandFlag::Write, CommandFlag::Denyoom, CommandFlag::Fast],
+        keys: SINGLE_KEY,
+        group: "hash",
+        since: "7.4.0",
+        summary: "Set expiry for hash field using relative time to expire (seconds)",
+    },
+    CommandSpec {
+        name: "hexpireat",
+        parse: parse::<hash::HExpire>,
+        arity: -6,
+        flags: &[CommandFlag::Write, CommandFlag::Denyoom, CommandFlag::Fast],
+        keys: SINGLE_KEY,
+        group: "hash",
+        since: "7.4.0",
+        summary: "Set expiry for hash field using an absolute Unix timestamp (seconds)",
+    },
+    CommandSpec {
+        name: "hget",
+        parse: parse::<hash::HGet>,
+        arity: 3,
+        flags: &[CommandFlag::Readonly, CommandFlag::Fast],
+        keys: SINGLE_KEY,
+        group: "hash",
+        since: "2.0.0",
+        summary: "Returns the value of a field in a hash.",
+    },
+    CommandSpec {
+        name: "hgetall",
+        parse: parse::<hash::HGetAll>,
+        arity: 2,
+        flags: &[CommandFlag::Readonly],
+        keys: SINGLE_KEY,
+        group: "hash",
+        since: "2.0.0",
+        summary: "Returns all fields and values in a hash.",
+    },
+    CommandSpec {
+        name: "hlen",
+        parse: parse::<hash::HLen>,
+        arity: 2,
+        flags: &[CommandFlag::Readonly, CommandFlag::Fast],
+        keys: SINGLE_KEY,
+        group: "hash",
+        since: "2.0.0",
+        summary: "Returns the number of fields in a hash.",
+    },
+    CommandSpec {
+        name: "hpersist",
+        parse: parse::<hash::HPersist>,
+        arity: -5,
+        flags: &[CommandFlag::Write, CommandFlag::Fast],
+        keys: SINGLE_KEY,
+        group: "hash",
+        since: "7.4.0",
+        summary: "Removes the expiration time for each specified field",
+    },
+    CommandSpec {
+        name: "hpexpire",
+        parse: parse::<hash::HExpire>,
+        arity: -6,
+        flags: &[CommandFlag::Write, CommandFlag::Denyoom, CommandFlag::Fast],
+        keys: SINGLE_KEY,
+        group: "hash",
+        since: "7.4.0",
+        summary: "Set expiry for hash field using relative time to expire (milliseconds)",
+    },
+    CommandSpec {
+        name: "hpexpireat",
+        parse: parse::<hash::HExpire>,
+        arity: -6,
+        flags: &[CommandFlag::Write, CommandFlag::Denyoom, CommandFlag::Fast],
+        keys: SINGLE_KEY,
+        group: "hash",
+        since: "7.4.0",
+        summary: "Set expiry for hash field using an absolute Unix timestamp (milliseconds)",
+    },
+    CommandSpec {
+        name: "hpttl",
+        parse: parse::<hash::HTtl>,
+        arity: -5,
+        flags: &[CommandFlag::Readonly, CommandFlag::Fast],
+        keys: SINGLE_KEY,
+        group: "hash",
+        since: "7.4.0",
+        summary: "Returns the TTL in milliseconds of a hash field.",
+    },
+    CommandSpec {
+        name: "hset",
+        parse: parse::<hash::HSet>,
+        arity: -4,
+        flags: &[CommandFlag::Write, CommandFlag::Denyoom, CommandFlag::Fast],
+        keys: SINGLE_KEY,
+        group: "hash",
+        since: "2.0.0",
+        summary: "Creates or modifies the value of a field in a hash.",
+    },
+    CommandSpec {
+        name: "httl",
+        parse: parse::<hash::HTtl>,
+        arity: -5,
+        flags: &[CommandFlag::Readonly, CommandFlag::Fast],
+        keys: SINGLE_KEY,
+        group: "hash",
+        since: "7.4.0",
+        summary: "Returns the TTL in seconds of a hash field.",
+    },
+    CommandSpec {
+        name: "incr",
+        parse: parse::<string::IncrBy>,
+        arity: 2,
+        flags: &[CommandFlag::Write, CommandFlag::Denyoom, CommandFlag::Fast],
+        keys: SINGLE_KEY,
+        group: "string",
+        since: "1.0.0",
+        summary: "Increments the integer value of a key by one. Uses 0 as initial value if the key doesn't exist.",
+    },
+    CommandSpec {
+        name: "incrby",
+        parse: parse::<string::IncrBy>,
+        arity: 3,
+        flags: &[CommandFlag::Write, CommandFlag::Denyoom, CommandFlag::Fast],
+        keys: SINGLE_KEY,
+        group: "string",
+        since: "1.0.0",
+        summary: "Increments the integer value of a key by a number. Uses 0 as initial value if the key doesn't exist.",
+    },
+    CommandSpec {
+        name: "incrbyfloat",
+        parse: parse::<string::IncrByFloat>,
+        arity: 3,
+        flags: &[CommandFlag::Write, CommandFlag::Denyoom, CommandFlag::Fast],
+        keys: SINGLE_KEY,
+        group: "string",
+        since: "2.6.0",
+        summary: "Increment the floating point value of a key by a number. Uses 0 as initial value if the key doesn't exist.",
+    },
+    CommandSpec {
+        name: "info",
+        parse: parse::<server::Info>,
+        arity: -1,
+        flags: &[CommandFlag::Loading, CommandFlag::Stale],
+        keys: NO_KEYS,
+        group: "server",
+        since: "1.0.0",
+        summary: "Returns information and statistics about the server.",
+    },
+    CommandSpec {
+        name: "keys",
+        parse: parse::<generic::Keys>,
+        arity: 2,
+        flags: &[CommandFlag::Readonly],
+        keys: NO_KEYS,
+        group: "generic",
+        since: "1.0.0",
+        summary: "Returns all key names that match a pattern.",
+    },
+    CommandSpec {
+        name: "latency",
+        parse: parse::<server::Latency>,
+        arity: -2,
+        flags: &[CommandFlag::Admin, CommandFlag::Noscript, CommandFlag::Loading, CommandFlag::Stale],
+        keys: NO_KEYS,
+        group: "server",
+        since: "2.8.13",
+        summary: "A container for latency diagnostics commands.",
+    },
+    CommandSpec {
+        name: "lcs",
+        parse: parse::<string::Lcs>,
+        arity: -3,
+        flags: &[CommandFlag::Readonly],
+        keys: KeyPositions { first: 1, last: 2, step: 1 },
+        group: "string",
+        since: "7.0.0",
+        summary: "Finds the longest common substring.",
+    },
+    CommandSpec {
+        name: "lpop",
+        parse: parse::<list::Pop>,
+        arity: -2,
+        flags: &[CommandFlag::Write, CommandFlag::Fast],
+        keys: SINGLE_KEY,
+        group: "list",
+        since: "1.0.0",
+        summary: "Returns the first elements in a list after removing it.",
+    },
+    CommandSpec {
+        name: "lpush",
+        parse: parse::<list::Push>,
+        arity: -3,
+        flags: &[CommandFlag::Write, CommandFlag::Denyoom, CommandFlag::Fast],
+        keys: SINGLE_KEY,
+        group: "list",
+        since: "1.0.0",
+        summary: "Prepends one or more elements to a list.",
+    },
+    CommandSpec {
+        name: "lrange",
+        parse: parse::<list::LRange>,
+        arity: 4,
+        flags: &[CommandFlag::Readonly],
+        keys: SINGLE_KEY,
+        group: "list",
+        since: "1.0.0",
+        summary: "Returns a range of elements from a list.",
+    },
+    CommandSpec {
+        name: "memory",
+        parse: parse::<server::MemoryCommand>,
+        arity: -2,
+        flags: &[CommandFlag::Readonly],
+        keys: KeyPositions { first: 2, last: 2, step: 1 },
+        group: "server",
+        since: "4.0.0",
+        summary: "A container for memory diagnostics commands.",
+    },
+    CommandSpec {
+        name: "monitor",
+        parse: parse::<server::Monitor>,
+        arity: 1,
+        flags: &[CommandFlag::Admin, CommandFlag::Noscript, CommandFlag::Loading, CommandFlag::Stale],
+        keys: NO_KEYS,
+        group: "server",
+        since: "1.0.0",
+        summary: "Listens for all requests received by the server in real-time.",
+    },
+    CommandSpec {
+        name: "multi",
+        parse: parse::<transactions::Multi>,
+        arity: 1,
+        flags: &[CommandFlag::Noscript, CommandFlag::Loading, CommandFlag::Stale, CommandFlag::Fast],
+        keys: NO_KEYS,
+        group: "transactions",
+        since: "1.2.0",
+        summary: "Starts a transaction.",
+    },
+    CommandSpec {
+        name: "object",
+        parse: parse::<generic::Object>,
+        arity: -2,
+        flags: &[CommandFlag::Readonly],
+        keys: KeyPositions { first: 2, last: 2, step: 1 },
+        group: "generic",
+        since: "2.2.3",
+        summary: "A container for object introspection commands.",
+    },
+    CommandSpec {
+        name: "pfadd",
+        parse: parse::<hyperloglog::PfAdd>,
+        arity: -2,
+        flags: &[CommandFlag::Write, CommandFlag::Denyoom, CommandFlag::Fast],
+        keys: SINGLE_KEY,
+        group: "hyperloglog",
+        since: "2.8.9",
+        summary: "Adds elements to a HyperLogLog key. Creates the key if it doesn't exist.",
+    },
+    CommandSpec {
+        name: "pfcount",
+        parse: parse::<hyperloglog::PfCount>,
+        arity: -2,
+        flags: &[CommandFlag::Readonly],
+        keys: KeyPositions { first: 1, last: -1, step: 1 },
+        group: "hyperloglog",
+        since: "2.8.9",
+        summary: "Returns the approximated cardinality of the set(s) observed by the HyperLogLog key(s).",
+    },
+    CommandSpec {
+        name: "pfmerge",
+        parse: parse::<hyperloglog::PfMerge>,
+        arity: -2,
+        flags: &[CommandFlag::Write, CommandFlag::Denyoom],
+        keys: KeyPositions { first: 1, last: -1, step: 1 },
+        group: "hyperloglog",
+        since: "2.8.9",
+        summary: "Merges one or more HyperLogLog values into a single key.",
+    },
+    CommandSpec {
+        name: "ping",
+        parse: parse::<connection::Ping>,
+        arity: -1,
+        flags: &[CommandFlag::Loading, CommandFlag::Fast],
+        keys: NO_KEYS,
+        group: "connection",
+        since: "1.0.0",
+        summary: "Returns the server's liveliness response.",
+    },
+    CommandSpec {
+        name: "psync",
+        parse: parse::<replication::Psync>,
+        arity: -3,
+        flags: &[CommandFlag::Admin, CommandFlag::Noscript],
+        keys: NO_KEYS,
+        group: "server",
+        since: "2.8.0",
+        summary: "An internal command used in replication.",
+    },
+    CommandSpec {
+        name: "publish",
+        parse: parse::<pubsub::Publish>,
+        arity: 3,
+        flags: &[CommandFlag::Loading, CommandFlag::Stale, CommandFlag::Fast],
+        keys: NO_KEYS,
+        group: "pubsub",
+        since: "2.0.0",
+        summary: "Posts a message to a channel.",
+    },
+    CommandSpec {
+        name: "pubsub",
+        parse: parse::<pubsub::PubSubCommand>,
+        arity: -2,
+        flags: &[CommandFlag::Loading, CommandFlag::Stale],
+        keys: NO_KEYS,
+        group: "pubsub",
+        since: "2.8.0",
+        summary: "A container for Pub/Sub commands.",
+    },
+    CommandSpec {
+        name: "replconf",
+        parse: parse::<replication::ReplConf>,
+        arity: -1,
+        flags: &[CommandFlag::Admin, CommandFlag::Noscript, CommandFlag::Loading, CommandFlag::Stale],
+        keys: NO_KEYS,
+        group: "server",
+        since: "3.0.0",
+        summary: "An internal command for configuring the replication stream.",
+    },
+    CommandSpec {
+        name: "restore",
+        parse: parse::<generic::Restore>,
+        arity: -4,
+        flags: &[CommandFlag::Write, CommandFlag::Denyoom],
+        keys: SINGLE_KEY,
+        group: "generic",
+        since: "2.6.0",
+        summary: "Creates a key from the serialized representation of a value.",
+    },
+    CommandSpec {
+        name: "rpop",
+        parse: parse::<list::Pop>,
+        arity: -2,
+        flags: &[CommandFlag::Write, CommandFlag::Fast],
+        keys: SINGLE_KEY,
+        group: "list",
+        since: "1.0.0",
+        summary: "Returns and removes the last elements of a list.",
+    },
+    CommandSpec {
+        name: "rpush",
+        parse: parse::<list::Push>,
+        arity: -3,
+        flags: &[CommandFlag::Write, CommandFlag::Denyoom, CommandFlag::Fast],
+        keys: SINGLE_KEY,
+        group: "list",
+        since: "1.0.0",
+        summary: "Appends one or more elements to a list.",
+    },
+    CommandSpec {
+        name: "scan",
+        parse: parse::<generic::Scan>,
+        arity: -2,
+        flags: &[CommandFlag::Readonly],
+        keys: NO_KEYS,
+        group: "generic",
+        since: "2.8.0",
+        summary: "Iterates over the key names in the database.",
+    },
+    CommandSpec {
+        name: "script",
+        parse: parse::<scripting::ScriptCommand>,
+        arity: -2,
+        flags: &[CommandFlag::Noscript],
+        keys: NO_KEYS,
+        group: "scripting",
+        since: "2.6.0",
+        summary: "A container for Lua scripts management commands.",
+    },
+    CommandSpec {
+        name: "select",
+        parse: parse::<connection::Select>,
+        arity: 2,
+        flags: &[CommandFlag::Loading, CommandFlag::Stale, CommandFlag::Fast],
+        keys: NO_KEYS,
+        group: "connection",
+        since: "1.0.0",
+        summary: "Changes the selected database.",
+    },
+    CommandSpec {
+        name: "set",
+        parse: parse::<string::Set>,
+        arity: -3,
+        flags: &[CommandFlag::Write, CommandFlag::Denyoom],
+        keys: SINGLE_KEY,
+        group: "string",
+        since: "1.0.0",
+        summary: "Sets the string value of a key, ignoring its type. The key is created if it doesn't exist.",
+    },
+    CommandSpec {
+        name: "setbit",
+        parse: parse::<bitmap::SetBit>,
+        arity: 4,
+        flags: &[CommandFlag::Write, CommandFlag::Denyoom],
+        keys: SINGLE_KEY,
+        group: "bitmap",
+        since: "2.2.0",
+        summary: "Sets or clears the bit at offset of the string value. Creates the key if it doesn't exist.",
+    },
+    CommandSpec {
+        name: "setrange",
+        parse: parse::<string::SetRange>,
+        arity: 4,
+        flags: &[CommandFlag::Write, CommandFlag::Denyoom],
+        keys: SINGLE_KEY,
+        group: "string",
+        since: "2.2.0",
+        summary: "Overwrites a part of a string value with another by an offset. Creates the key if it doesn't exist.",
+    },
+    CommandSpec {
+        name: "slowlog",
+        parse: parse::<server::Slowlog>,
+        arity: -2,
+        flags: &[CommandFlag::Admin, CommandFlag::Loading, CommandFlag::Stale],
+        keys: NO_KEYS,
+        group: "server",
+        since: "2.2.12",
+        summary: "A container for slow log commands.",
+    },
+    CommandSpec {
+        name: "smembers",
+        parse: parse::<set::SMembers>,
+        arity: 2,
+        flags: &[CommandFlag::Readonly],
+        keys: SINGLE_KEY,
+        group: "set",
+        since: "1.0.0",
+        summary: "Returns all members of a set.",
+    },
+    CommandSpec {
+        name: "sort",
+        parse: parse::<generic::Sort>,
+        arity: -2,
+        flags: &[CommandFlag::Write, CommandFlag::Denyoom, CommandFlag::MovableKeys],
+        keys: SINGLE_KEY,
+        group: "generic",
+        since: "1.0.0",
+        summary: "Sorts the elements in a list, a set, or a sorted set, optionally storing the result.",
+    },
+    CommandSpec {
+        name: "spublish",
+        parse: parse::<pubsub::Publish>,
+        arity: 3,
+        flags: &[CommandFlag::Loading, CommandFlag::Stale, CommandFlag::Fast],
+        keys: NO_KEYS,
+        group: "pubsub",
+        since: "7.0.0",
+        summary: "Post a message to a shard channel.",
+    },
+    CommandSpec {
+        name: "ssubscribe",
+        parse: parse::<pubsub::Subscribe>,
+        arity: -2,
+        flags: &[CommandFlag::Noscript, CommandFlag::Loading, CommandFlag::Stale],
+        keys: NO_KEYS,
+        group: "pubsub",
+        since: "7.0.0",
+        summary: "Listens for messages published to shard channels.",
+    },
+    CommandSpec {
+        name: "strlen",
+        parse: parse::<string::Strlen>,
+        arity: 2,
+        flags: &[CommandFlag::Readonly, CommandFlag::Fast],
+        keys: SINGLE_KEY,
+        group: "string",
+        since: "2.2.0",
+        summary: "Returns the length of a string value.",
+    },
+    CommandSpec {
+        name: "subscribe",
+        parse: parse::<pubsub::Subscribe>,
+        arity: -2,
+        flags: &[CommandFlag::Noscript, CommandFlag::Loading, CommandFlag::Stale],
+        keys: NO_KEYS,
+        group: "pubsub",
+        since: "2.0.0",
+        summary: "Listens for messages published to channels.",
+    },
+    CommandSpec {
+        name: "sunsubscribe",
+        parse: parse::<pubsub::Unsubscribe>,
+        arity: -1,
+        flags: &[CommandFlag::Noscript, CommandFlag::Loading, CommandFlag::Stale],
+        keys: NO_KEYS,
+        group: "pubsub",
+        since: "7.0.0",
+        summary: "Stops listening to messages posted to shard channels.",
+    },
+    CommandSpec {
+        name: "swapdb",
+        parse: parse::<server::SwapDb>,
+        arity: 3,
+        flags: &[CommandFlag::Write, CommandFlag::Fast],
+        keys: NO_KEYS,
+        group: "server",
+        since: "4.0.0",
+        summary: "Swaps two Redis databases.",
+    },
+    CommandSpec {
+        name: "unsubscribe",
+        parse: parse::<pubsub::Unsubscribe>,
+        arity: -1,
+        flags: &[CommandFlag::Noscript, CommandFlag::Loading, CommandFlag::Stale],
+        keys: NO_KEYS,
+        group: "pubsub",
+        since: "2.0.0",
+        summary: "Stops listening to messages posted to channels.",
+    },
+    CommandSpec {
+        name: "unwatch",
+        parse: parse::<transactions::Unwatch>,
+        arity: 1,
+        flags: &[CommandFlag::Noscript, CommandFlag::Loading, CommandFlag::Stale, CommandFlag::Fast],
+        keys: NO_KEYS,
+        group: "transactions",
+        since: "2.2.0",
+        summary: "Forgets about watched keys of a transaction.",
+    },
+    CommandSpec {
+        name: "wait",
+        parse: parse::<replication::Wait>,
+        arity: 3,
+        flags: &[CommandFlag::Noscript, CommandFlag::Blocking],
+        keys: NO_KEYS,
+        group: "generic",
+        since: "3.0.0",
+        summary: "Blocks until the asynchronous replication of all preceding write commands sent by the connection is completed.",
+    },
+    CommandSpec {
+        name: "waitaof",
+        parse: parse::<replication::WaitAof>,
+        arity: 4,
+        flags: &[CommandFlag::Noscript, CommandFlag::Blocking],
+        keys: NO_KEYS,
+        group: "generic",
+        since: "7.2.0",
+        summary: "Blocks until all of the preceding write commands sent by the connection are written to the append-only file of the master and/or replicas.",
+    },
+    CommandSpec {
+        name: "watch",
+        parse: parse::<transactions::Watch>,
+        arity: -2,
+        flags: &[CommandFlag::Noscript, CommandFlag::Loading, CommandFlag::Stale, CommandFlag::Fast],
+        keys: KeyPositions { first: 1, last: -1, step: 1 },
+        group: "transactions",
+        since: "2.2.0",
+        summary: "Monitors changes to keys to determine the execution of a transaction.",
+    },
+];
+
+/// Looks up a command by its lowercase name.
+pub fn lookup(name: &str) -> Option<&'static CommandSpec> {
+    COMMAND_TABLE.binary_search_by(|spec| spec.name.cmp(name)).ok().map(|idx| &COMMAND_TABLE[idx])
+}
+
+/// The names clients call commands by, which `rename-command` may change or
+/// take away. Everything else, replication included, goes by the names in
+/// `COMMAND_TABLE`, so replicas apply writes whatever their master calls the
+/// commands.
+#[derive(Debug, Default)]
+pub struct CommandNames {
+    /// The new name of each renamed command, None for those disabled.
+    renamed: HashMap<&'static str, Option<String>>,
+}
+
+impl CommandNames {
+    /// Applies `rename-command` pairs, which were validated when set.
+    pub fn new(renames: &[(String, String)]) -> Self {
+        let renamed = renames.iter()
+            .filter_map(|(name, new_name)| {
+                let spec = lookup(&name.to_lowercase())?;
+                Some((spec.name, Some(new_name.to_lowercase()).filter(|new_name| !new_name.is_empty())))
+            })
+            .collect();
+
+        Self { renamed }
+    }
+
+    /// Looks up a command by the lowercase name clients call it.
+    pub fn lookup(&self, name: &str) -> Option<&'static CommandSpec> {
+        if let Some((original, _)) = self.renamed.iter().find(|(_, new_name)| new_name.as_deref() == Some(name)) {
+            return lookup(original);
+        }
+
+        lookup(name).filter(|spec| !self.renamed.contains_key(spec.name))
+    }
+
+    /// The name clients call `spec` by, None if it's disabled.
+    pub fn name_of(&self, spec: &'static CommandSpec) -> Option<&str> {
+        match self.renamed.get(spec.name) {
+            Some(new_name) => new_name.as_deref(),
+            None => Some(spec.name),
+        }
+    }
+
+    /// Every command clients can call, with the name they call it by.
+    pub fn commands(&self) -> impl Iterator<Item = (&str, &'static CommandSpec)> {
+        COMMAND_TABLE.iter().filter_map(move |spec| Some((self.name_of(spec)?, spec)))
+    }
+}
@@ -0,0 +1,432 @@
+use std::borrow::Cow;
+
+use bytes::Bytes;
+
+use crate::bitfield::{FieldOp, FieldType, Overflow};
+use crate::bitops::{self, BitOperation, RangeUnit};
+use crate::db::{Shard, ShardGuards};
+use crate::value::pad_string;
+use crate::{EventClass, Frame, RedisError, Value};
+use super::string::check_string_length;
+use super::{store_result_or_delete, BoxFuture, CommandArgs, CommandContext, CommandExec, Propagate, ReplicaContext, StoreOutcome};
+
+#[derive(Debug)]
+pub struct SetBit {
+    key: Bytes,
+    offset: u64,
+    bit: u8,
+}
+
+impl SetBit {
+    pub fn new(key: Bytes, offset: u64, bit: u8) -> SetBit {
+        SetBit { key, offset, bit }
+    }
+
+    fn execute(&self, shard: &mut Shard) -> crate::Result<u8> {
+        Ok(shard.modify_string(&self.key, |buf| bitops::set_bit(buf, self.offset, self.bit))?)
+    }
+}
+
+impl CommandExec for SetBit {
+    fn parse(args: &CommandArgs) -> crate::Result<SetBit> {
+        let offset = parse_bit_offset(args, 2)?;
+        let bit = match args.string(3)?.as_str() {
+            "0" => 0,
+            "1" => 1,
+            _ => return Err("ERR bit is not an integer or out of range".into()),
+        };
+
+        Ok(SetBit::new(args.key(1)?, offset, bit))
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            check_string_length(self.offset / 8 + 1)?;
+
+            let mut shard = ctx.db.get_db(ctx.client.db_index).lock(&self.key).await;
+            let old = self.execute(&mut shard)?;
+
+            ctx.propagate(Propagate::Verbatim).await?;
+            drop(shard);
+
+            ctx.reply(&Frame::Integer(old as i64)).await?;
+
+            Ok(())
+        })
+    }
+
+    fn apply_replica(self: Box<Self>, ctx: &mut ReplicaContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let mut shard = ctx.db.get_db(ctx.db_index).lock(&self.key).await;
+            self.execute(&mut shard)?;
+
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct GetBit {
+    key: Bytes,
+    offset: u64,
+}
+
+impl GetBit {
+    pub fn new(key: Bytes, offset: u64) -> GetBit {
+        GetBit { key, offset }
+    }
+}
+
+impl CommandExec for GetBit {
+    fn parse(args: &CommandArgs) -> crate::Result<GetBit> {
+        Ok(GetBit::new(args.key(1)?, parse_bit_offset(args, 2)?))
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let bit = ctx.db.get_db(ctx.client.db_index).read_string(&self.key, |val| {
+                match val {
+                    Some(val) => bitops::get_bit(val, self.offset),
+                    None => 0,
+                }
+            }).await?;
+
+            ctx.reply(&Frame::Integer(bit as i64)).await?;
+
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct BitCount {
+    key: Bytes,
+    range: Option<(i64, i64, RangeUnit)>,
+}
+
+impl BitCount {
+    pub fn new(key: Bytes, range: Option<(i64, i64, RangeUnit)>) -> BitCount {
+        BitCount { key, range }
+    }
+}
+
+impl CommandExec for BitCount {
+    fn parse(args: &CommandArgs) -> crate::Result<BitCount> {
+        let range = match args.len() {
+            2 => None,
+            4 | 5 => {
+                let unit = if args.len() == 5 { parse_range_unit(&args.string(4)?)? } else { RangeUnit::Byte };
+                Some((args.integer(2)?, args.integer(3)?, unit))
+            },
+            _ => return Err(RedisError::Syntax),
+        };
+
+        Ok(BitCount::new(args.key(1)?, range))
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let count = ctx.db.get_db(ctx.client.db_index).read_string(&self.key, |val| {
+                match val {
+                    Some(val) => {
+                        let (start, end, unit) = self.range.unwrap_or((0, -1, RangeUnit::Byte));
+
+                        match bitops::resolve_range(start, end, unit, val.len()) {
+                            Some((start, end)) => bitops::count_bits(val, start, end),
+                            None => 0,
+                        }
+                    },
+                    None => 0,
+                }
+            }).await?;
+
+            ctx.reply(&Frame::Integer(count as i64)).await?;
+
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct BitPos {
+    key: Bytes,
+    bit: u8,
+    start: i64,
+    end: Option<i64>,
+    unit: RangeUnit,
+}
+
+impl BitPos {
+    pub fn new(key: Bytes, bit: u8, start: i64, end: Option<i64>, unit: RangeUnit) -> BitPos {
+        BitPos { key, bit, start, end, unit }
+    }
+}
+
+impl CommandExec for BitPos {
+    fn parse(args: &CommandArgs) -> crate::Result<BitPos> {
+        if args.len() > 6 {
+            return Err(RedisError::Syntax);
+        }
+
+        let bit = match args.string(2)?.as_str() {
+            "0" => 0,
+            "1" => 1,
+            _ => return Err("ERR The bit argument must be 1 or 0.".into()),
+        };
+
+        let start = if args.len() > 3 { args.integer(3)? } else { 0 };
+        let end = if args.len() > 4 { Some(args.integer(4)?) } else { None };
+        let unit = if args.len() > 5 { parse_range_unit(&args.string(5)?)? } else { RangeUnit::Byte };
+
+        Ok(BitPos::new(args.key(1)?, bit, start, end, unit))
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let pos = ctx.db.get_db(ctx.client.db_index).read_string(&self.key, |val| {
+                match val {
+                    Some(val) => {
+                        let range = bitops::resolve_range(self.start, self.end.unwrap_or(-1), self.unit, val.len());
+
+                        match range.and_then(|(start, end)| bitops::bit_position(val, self.bit, start, end)) {
+                            Some(pos) => pos as i64,
+                            // Without an explicit end, a string is considered to
+                            // be padded with zeros on the right.
+                            None if self.bit == 0 && self.end.is_none() && range.is_some() => val.len() as i64 * 8,
+                            None => -1,
+                        }
+                    },
+                    None if self.bit == 0 => 0,
+                    None => -1,
+                }
+            }).await?;
+
+            ctx.reply(&Frame::Integer(pos)).await?;
+
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct BitOp {
+    op: BitOperation,
+    dest: Bytes,
+    keys: Vec<Bytes>,
+}
+
+impl BitOp {
+    pub fn new(op: BitOperation, dest: Bytes, keys: Vec<Bytes>) -> BitOp {
+        BitOp { op, dest, keys }
+    }
+
+    fn locked_keys(&self) -> Vec<&[u8]> {
+        std::iter::once(&self.dest).chain(self.keys.iter()).map(|key| &key[..]).collect()
+    }
+
+    /// Stores the result at the destination, deleting it if the result is
+    /// empty, and returns the result's length.
+    fn execute(&self, guards: &mut ShardGuards) -> crate::Result<(usize, StoreOutcome)> {
+        let mut operands = vec![];
+        for key in self.keys.iter() {
+            let operand = guards.get_mut(key).get_string(key)?.map(Cow::into_owned).unwrap_or_default();
+            operands.push(operand);
+        }
+
+        let operands: Vec<&[u8]> = operands.iter().map(|operand| &operand[..]).collect();
+        let res = bitops::bit_operation(self.op, &operands);
+        let len = res.len();
+
+        let res = (!res.is_empty()).then(|| Value::String(Bytes::from(res)));
+        let outcome = store_result_or_delete(guards.get_mut(&self.dest), &self.dest, res);
+
+        Ok((len, outcome))
+    }
+}
+
+impl CommandExec for BitOp {
+    fn parse(args: &CommandArgs) -> crate::Result<BitOp> {
+        let op = BitOperation::parse(&args.string(1)?).ok_or(RedisError::Syntax)?;
+        let keys = args.keys_from(3)?;
+
+        if op == BitOperation::Not && keys.len() != 1 {
+            return Err("ERR BITOP NOT must be called with a single source key.".into());
+        }
+
+        Ok(BitOp::new(op, args.key(2)?, keys))
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let mut guards = ctx.db.get_db(ctx.client.db_index).lock_keys(&self.locked_keys()).await;
+            let (len, outcome) = self.execute(&mut guards)?;
+
+            ctx.stored(&self.dest, outcome, EventClass::String, "set").await?;
+            drop(guards);
+
+            ctx.reply(&Frame::Integer(len as i64)).await?;
+
+            Ok(())
+        })
+    }
+
+    fn apply_replica(self: Box<Self>, ctx: &mut ReplicaContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let mut guards = ctx.db.get_db(ctx.db_index).lock_keys(&self.locked_keys()).await;
+            let (_, outcome) = self.execute(&mut guards)?;
+            ctx.stored(&self.dest, outcome, EventClass::String, "set");
+
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct BitField {
+    key: Bytes,
+    ops: Vec<FieldOp>,
+}
+
+impl BitField {
+    pub fn new(key: Bytes, ops: Vec<FieldOp>) -> BitField {
+        BitField { key, ops }
+    }
+
+    /// Length the writes grow the string to.
+    fn end_byte(&self) -> usize {
+        self.ops.iter().filter(|op| op.is_write()).map(FieldOp::end_byte).max().unwrap_or(0)
+    }
+
+    /// Runs the subcommands, returning their replies.
+    fn execute(&self, shard: &mut Shard) -> crate::Result<Vec<Option<i64>>> {
+        Ok(shard.modify_string(&self.key, |buf| {
+            // Like Redis, grow the string to fit every write up front, even
+            // the ones which end up failing on overflow.
+            pad_string(buf, self.end_byte());
+
+            self.ops.iter().map(|op| op.execute(buf)).collect()
+        })?)
+    }
+
+    /// The command sent to replicas, which only needs the writes.
+    fn to_frame(&self) -> Frame {
+        let mut args = vec![Bytes::from_static(b"BITFIELD"), self.key.clone()];
+
+        for op in self.ops.iter() {
+            let (name, ty, offset, value, overflow) = match *op {
+                FieldOp::Get(..) => continue,
+                FieldOp::Set(ty, offset, value, overflow) => ("SET", ty, offset, value, overflow),
+                FieldOp::IncrBy(ty, offset, incr, overflow) => ("INCRBY", ty, offset, incr, overflow),
+            };
+
+            args.extend(["OVERFLOW".to_string(), overflow.name().to_string(), name.to_string(), ty.name(), offset.to_string(), value.to_string()].map(Bytes::from));
+        }
+
+        Frame::command(args)
+    }
+}
+
+impl CommandExec for BitField {
+    fn parse(args: &CommandArgs) -> crate::Result<BitField> {
+        let mut ops = vec![];
+        let mut overflow = Overflow::Wrap;
+
+        let mut idx = 2;
+        while idx < args.len() {
+            let subcommand = args.string(idx)?.to_uppercase();
+
+            match subcommand.as_str() {
+                "GET" if idx + 2 < args.len() => {
+                    let ty = parse_field_type(&args.string(idx + 1)?)?;
+                    let offset = parse_field_offset(&args.string(idx + 2)?, ty)?;
+
+                    ops.push(FieldOp::Get(ty, offset));
+                    idx += 3;
+                },
+                "SET" | "INCRBY" if idx + 3 < args.len() => {
+                    let ty = parse_field_type(&args.string(idx + 1)?)?;
+                    let offset = parse_field_offset(&args.string(idx + 2)?, ty)?;
+                    let value = args.integer(idx + 3)?;
+
+                    if subcommand == "SET" {
+                        ops.push(FieldOp::Set(ty, offset, value, overflow));
+                    } else {
+                        ops.push(FieldOp::IncrBy(ty, offset, value, overflow));
+                    }
+                    idx += 4;
+                },
+                "OVERFLOW" if idx + 1 < args.len() => {
+                    overflow = match Overflow::parse(&args.string(idx + 1)?) {
+                        Some(overflow) => overflow,
+                        None => return Err("ERR Invalid OVERFLOW type specified".into()),
+                    };
+                    idx += 2;
+                },
+                _ => return Err(RedisError::Syntax),
+            }
+        }
+
+        Ok(BitField::new(args.key(1)?, ops))
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let replies = if self.ops.iter().any(FieldOp::is_write) {
+                check_string_length(self.end_byte() as u64)?;
+
+                // The string is created even if every write fails, so replicas
+                // get the command either way.
+                let mut shard = ctx.db.get_db(ctx.client.db_index).lock(&self.key).await;
+                let replies = self.execute(&mut shard)?;
+
+                ctx.propagate(Propagate::Rewrite(self.to_frame())).await?;
+                drop(shard);
+
+                replies
+            } else {
+                let shard = ctx.db.get_db(ctx.client.db_index).read(&self.key).await;
+                let val = shard.get_string(&self.key)?.map(Cow::into_owned).unwrap_or_default();
+
+                self.ops.iter().map(|op| match *op {
+                    FieldOp::Get(ty, offset) => Some(crate::bitfield::read(&val, offset, ty)),
+                    _ => unreachable!(),
+                }).collect()
+            };
+
+            let frame = Frame::Array(replies.into_iter().map(|reply| match reply {
+                Some(value) => Frame::Integer(value),
+                None => Frame::Bulk(None),
+            }).collect());
+            ctx.reply(&frame).await?;
+
+            Ok(())
+        })
+    }
+
+    fn apply_replica(self: Box<Self>, ctx: &mut ReplicaContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let mut shard = ctx.db.get_db(ctx.db_index).lock(&self.key).await;
+            self.execute(&mut shard)?;
+
+            Ok(())
+        })
+    }
+}
+
+fn parse_bit_offset(args: &CommandArgs, idx: usize) -> crate::Result<u64> {
+    let offset = args.integer_in(idx, 0..=bitops::MAX_BIT_OFFSET as i64, "ERR bit offset is not an integer or out of range")?;
+
+    Ok(offset as u64)
+}
+
+fn parse_field_type(arg: &str) -> crate::Result<FieldType> {
+    FieldType::parse(arg).ok_or_else(|| "ERR Invalid bitfield type. Use something like i16 u8. Note that u64 is not supported but i64 is.".into())
+}
+
+fn parse_field_offset(arg: &str, ty: FieldType) -> crate::Result<u64> {
+    crate::bitfield::parse_offset(arg, ty).ok_or_else(|| "ERR bit offset is not an integer or out of range".into())
+}
+
+fn parse_range_unit(arg: &str) -> crate::Result<RangeUnit> {
+    RangeUnit::parse(arg).ok_or(RedisError::Syntax)
+}
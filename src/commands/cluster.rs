@@ -0,0 +1,75 @@
+use crate::Frame;
+use super::{BoxFuture, CommandArgs, CommandContext, CommandExec};
+
+#[derive(Debug)]
+pub enum ClusterOption {
+    Info,
+    MyId,
+    Slots,
+    Shards,
+}
+
+/// `CLUSTER` subcommands clients probe on connect. Cluster mode isn't
+/// supported, so these describe a standalone instance.
+#[derive(Debug)]
+pub struct Cluster {
+    option: ClusterOption,
+}
+
+impl Cluster {
+    pub fn new(option: ClusterOption) -> Cluster {
+        Cluster { option }
+    }
+}
+
+impl CommandExec for Cluster {
+    fn parse(args: &CommandArgs) -> crate::Result<Cluster> {
+        let subcommand = args.string(1)?.to_lowercase();
+
+        let option = match (subcommand.as_str(), args.len()) {
+            ("info", 2) => ClusterOption::Info,
+            ("myid", 2) => ClusterOption::MyId,
+            ("slots", 2) => ClusterOption::Slots,
+            ("shards", 2) => ClusterOption::Shards,
+            _ => return Err("ERR This instance has cluster support disabled".into()),
+        };
+
+        Ok(Cluster::new(option))
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let frame = match self.option {
+                ClusterOption::Info => Frame::bulk(cluster_info()),
+                ClusterOption::MyId => Frame::bulk(ctx.db.get_identity().run_id.clone()),
+                ClusterOption::Slots | ClusterOption::Shards => Frame::Array(vec![]),
+            };
+
+            ctx.reply(&frame).await?;
+
+            Ok(())
+        })
+    }
+}
+
+/// `CLUSTER INFO` of an instance with cluster support disabled, with the
+/// fields of Redis and CRLF line endings like it.
+fn cluster_info() -> String {
+    let fields = [
+        ("cluster_enabled", "0"),
+        ("cluster_state", "ok"),
+        ("cluster_slots_assigned", "0"),
+        ("cluster_slots_ok", "0"),
+        ("cluster_slots_pfail", "0"),
+        ("cluster_slots_fail", "0"),
+        ("cluster_known_nodes", "1"),
+        ("cluster_size", "0"),
+        ("cluster_current_epoch", "0"),
+        ("cluster_my_epoch", "0"),
+        ("cluster_stats_messages_sent", "0"),
+        ("cluster_stats_messages_received", "0"),
+        ("total_cluster_links_buffer_limit_exceeded", "0"),
+    ];
+
+    fields.iter().map(|(name, value)| format!("{}:{}\r\n", name, value)).collect()
+}
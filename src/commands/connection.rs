@@ -0,0 +1,460 @@
+use bytes::Bytes;
+
+use crate::acl::DEFAULT_USER;
+use crate::identity::REDIS_VERSION;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::{ClientType, Frame, PauseMode, RedisError};
+use super::{BoxFuture, CommandArgs, CommandContext, CommandExec, ReplicaContext};
+
+#[derive(Debug)]
+pub struct Ping {
+    message: Option<Bytes>,
+}
+
+impl Ping {
+    pub fn new(message: Option<Bytes>) -> Ping {
+        Ping { message }
+    }
+}
+
+impl CommandExec for Ping {
+    fn parse(args: &CommandArgs) -> crate::Result<Ping> {
+        if args.len() > 2 {
+            return Err(args.wrong_arity());
+        }
+
+        let message = if args.len() == 2 { Some(args.bytes(1)?.clone()) } else { None };
+        Ok(Ping::new(message))
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            // Subscribed clients only expect pushes, so the reply takes the same
+            // array shape.
+            let frame = if ctx.client.is_subscribed() {
+                Frame::Array(vec![
+                    Frame::bulk("pong"),
+                    Frame::Bulk(Some(self.message.unwrap_or_default())),
+                ])
+            } else {
+                match self.message {
+                    Some(message) => Frame::Bulk(Some(message)),
+                    None => Frame::simple("PONG"),
+                }
+            };
+
+            ctx.reply(&frame).await?;
+            Ok(())
+        })
+    }
+
+    // The master pings its replicas periodically. These are only counted
+    // toward the offset, never answered.
+    fn apply_replica(self: Box<Self>, _ctx: &mut ReplicaContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+#[derive(Debug)]
+pub struct Echo {
+    arg: Bytes,
+}
+
+impl Echo {
+    pub fn new(arg: Bytes) -> Echo {
+        Echo { arg }
+    }
+}
+
+impl CommandExec for Echo {
+    fn parse(args: &CommandArgs) -> crate::Result<Echo> {
+        Ok(Echo::new(args.bytes(1)?.clone()))
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            ctx.reply(&Frame::Bulk(Some(self.arg))).await?;
+
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Select {
+    db_index: usize,
+}
+
+impl Select {
+    pub fn new(db_index: usize) -> Select {
+        Select { db_index }
+    }
+}
+
+impl CommandExec for Select {
+    fn parse(args: &CommandArgs) -> crate::Result<Select> {
+        match usize::try_from(args.integer(1)?) {
+            Ok(db_index) => Ok(Select::new(db_index)),
+            Err(_) => Err("ERR DB index is out of range".into()),
+        }
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let num_dbs = ctx.db.num_dbs();
+
+            if self.db_index >= num_dbs {
+                ctx.reply(&Frame::Error("ERR DB index is out of range".to_string())).await?;
+                return Ok(());
+            }
+
+            ctx.client.db_index = self.db_index;
+            ctx.reply(&Frame::simple("OK")).await?;
+
+            Ok(())
+        })
+    }
+
+    /// Changes the database the following replicated commands apply to.
+    fn apply_replica(self: Box<Self>, ctx: &mut ReplicaContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let num_dbs = ctx.db.num_dbs();
+
+            if self.db_index >= num_dbs {
+                return Err(format!("Master selected DB {} but only {} are configured", self.db_index, num_dbs).into());
+            }
+
+            ctx.db_index = self.db_index;
+
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Auth {
+    /// None for the single argument form, which logs in as the default user.
+    user: Option<String>,
+    password: Bytes,
+}
+
+impl Auth {
+    pub fn new(user: Option<String>, password: Bytes) -> Auth {
+        Auth { user, password }
+    }
+}
+
+impl CommandExec for Auth {
+    fn parse(args: &CommandArgs) -> crate::Result<Auth> {
+        match args.len() {
+            2 => Ok(Auth::new(None, args.bytes(1)?.clone())),
+            3 => Ok(Auth::new(Some(args.string(1)?), args.bytes(2)?.clone())),
+            _ => Err(RedisError::Syntax),
+        }
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            if self.user.is_none() && matches!(ctx.db.get_acl().get_user(DEFAULT_USER), Some(user) if user.is_nopass()) {
+                return Err("ERR AUTH <password> called without any password configured for the default user. Are you sure your configuration is correct?".into());
+            }
+
+            let user = self.user.unwrap_or_else(|| DEFAULT_USER.to_string());
+            authenticate(ctx, &user, &self.password)?;
+            ctx.reply(&Frame::simple("OK")).await?;
+
+            Ok(())
+        })
+    }
+}
+
+/// Logs the client in as `user`, if the password is right.
+fn authenticate(ctx: &mut CommandContext, user: &str, password: &[u8]) -> crate::Result<()> {
+    match ctx.db.get_acl().authenticate(user, password) {
+        Some(user) => {
+            ctx.client.user = Some(user.name.clone());
+            Ok(())
+        },
+        None => Err(RedisError::WrongPass),
+    }
+}
+
+/// Parses a client name, or a library name or version, which like in
+/// redis-server may only hold printable characters other than spaces, as
+/// `CLIENT LIST` separates its fields with them. `what` names it in the
+/// error.
+fn parse_client_attribute(what: &str, value: &[u8]) -> crate::Result<String> {
+    match value.iter().all(|byte| (b'!'..=b'~').contains(byte)) {
+        true => Ok(String::from_utf8_lossy(value).to_string()),
+        false => Err(format!("ERR {} cannot contain spaces, newlines or special characters.", what).into()),
+    }
+}
+
+/// An empty name unsets it.
+fn non_empty(value: String) -> Option<String> {
+    Some(value).filter(|value| !value.is_empty())
+}
+
+#[derive(Debug)]
+pub struct Hello {
+    protover: Option<i64>,
+    auth: Option<(String, Bytes)>,
+    name: Option<String>,
+}
+
+impl Hello {
+    pub fn new(protover: Option<i64>, auth: Option<(String, Bytes)>, name: Option<String>) -> Hello {
+        Hello { protover, auth, name }
+    }
+}
+
+impl CommandExec for Hello {
+    fn parse(args: &CommandArgs) -> crate::Result<Hello> {
+        if args.len() == 1 {
+            return Ok(Hello::new(None, None, None));
+        }
+
+        let protover = args.integer_in(1, .., "ERR Protocol version is not an integer or out of range")?;
+
+        let (mut auth, mut name) = (None, None);
+        let mut idx = 2;
+        while idx < args.len() {
+            let option = args.string(idx)?;
+
+            match option.to_lowercase().as_str() {
+                "auth" if idx + 2 < args.len() => {
+                    auth = Some((args.string(idx + 1)?, args.bytes(idx + 2)?.clone()));
+                    idx += 3;
+                },
+                "setname" if idx + 1 < args.len() => {
+                    name = Some(parse_client_attribute("Client names", args.bytes(idx + 1)?)?);
+                    idx += 2;
+                },
+                _ => return Err(format!("ERR Syntax error in HELLO option '{}'", option).into()),
+            }
+        }
+
+        Ok(Hello::new(Some(protover), auth, name))
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            // Only RESP2 is spoken, so RESP3 is refused like an unknown
+            // version, leaving the client on RESP2.
+            if matches!(self.protover, Some(protover) if protover != 2) {
+                return Err("NOPROTO sorry, this protocol version is not supported.".into());
+            }
+
+            match self.auth {
+                Some((user, password)) => authenticate(ctx, &user, &password)?,
+                None if ctx.client.user.is_none() => return Err("NOAUTH HELLO must be called with the client already authenticated, otherwise the HELLO <proto> AUTH <user> <pass> option can be used to authenticate the client and select the RESP protocol version at the same time".into()),
+                None => {},
+            }
+            if let Some(name) = self.name {
+                ctx.client.name = non_empty(name);
+            }
+
+            let role = match ctx.db.get_replication_state().get_role() {
+                "master" => "master",
+                _ => "replica",
+            };
+
+            ctx.reply(&Frame::Array(vec![
+                Frame::bulk("server"),
+                Frame::bulk("redis"),
+                Frame::bulk("version"),
+                Frame::bulk(REDIS_VERSION),
+                Frame::bulk("proto"),
+                Frame::Integer(2),
+                Frame::bulk("id"),
+                Frame::Integer(ctx.client.id as i64),
+                Frame::bulk("mode"),
+                Frame::bulk("standalone"),
+                Frame::bulk("role"),
+                Frame::bulk(role),
+                Frame::bulk("modules"),
+                Frame::Array(vec![]),
+            ])).await?;
+
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum ClientOption {
+    Id,
+    List,
+    /// The `CLIENT LIST` line of the client itself.
+    Info,
+    SetName(String),
+    GetName,
+    SetLibName(String),
+    SetLibVer(String),
+    /// The old `CLIENT KILL addr` form, replying OK rather than a count.
+    KillAddr(String),
+    Kill {
+        id: Option<u64>,
+        addr: Option<String>,
+        client_type: Option<ClientType>,
+        skip_me: bool,
+    },
+    Pause(Duration, PauseMode),
+    Unpause,
+}
+
+#[derive(Debug)]
+pub struct ClientCommand {
+    option: ClientOption,
+}
+
+impl ClientCommand {
+    pub fn new(option: ClientOption) -> ClientCommand {
+        ClientCommand { option }
+    }
+
+    fn parse_pause(args: &CommandArgs) -> crate::Result<ClientOption> {
+        let timeout = match args.integer_in(2, .., "ERR timeout is not an integer or out of range")? {
+            timeout if timeout < 0 => return Err("ERR timeout is negative".into()),
+            timeout => Duration::from_millis(timeout as u64),
+        };
+
+        let mode = match args.len() {
+            3 => PauseMode::All,
+            _ => match args.string(3)?.to_lowercase().as_str() {
+                "write" => PauseMode::Write,
+                "all" => PauseMode::All,
+                _ => return Err(RedisError::Syntax),
+            },
+        };
+
+        Ok(ClientOption::Pause(timeout, mode))
+    }
+
+    fn parse_kill(args: &CommandArgs) -> crate::Result<ClientOption> {
+        if args.len() == 3 {
+            return Ok(ClientOption::KillAddr(args.string(2)?));
+        }
+
+        let (mut id, mut addr, mut client_type, mut skip_me) = (None, None, None, true);
+
+        let mut idx = 2;
+        while idx < args.len() {
+            let value = match args.len() > idx + 1 {
+                true => args.string(idx + 1)?,
+                false => return Err(RedisError::Syntax),
+            };
+
+            match args.string(idx)?.to_lowercase().as_str() {
+                "id" => id = Some(args.integer_in(idx + 1, 1.., "ERR client-id should be greater than 0")? as u64),
+                "addr" => addr = Some(value),
+                "type" => match ClientType::parse(&value) {
+                    Some(value) => client_type = Some(value),
+                    None => return Err(format!("ERR Unknown client type '{}'", value).into()),
+                },
+                "skipme" => match value.to_lowercase().as_str() {
+                    "yes" => skip_me = true,
+                    "no" => skip_me = false,
+                    _ => return Err(RedisError::Syntax),
+                },
+                _ => return Err(RedisError::Syntax),
+            }
+            idx += 2;
+        }
+
+        Ok(ClientOption::Kill { id, addr, client_type, skip_me })
+    }
+
+    fn parse_setinfo(args: &CommandArgs) -> crate::Result<ClientOption> {
+        let attribute = args.string(2)?.to_lowercase();
+        let value = parse_client_attribute(&attribute, args.bytes(3)?);
+
+        match attribute.as_str() {
+            "lib-name" => Ok(ClientOption::SetLibName(value?)),
+            "lib-ver" => Ok(ClientOption::SetLibVer(value?)),
+            _ => Err(format!("ERR Unrecognized option '{}'", args.string(2)?).into()),
+        }
+    }
+}
+
+impl CommandExec for ClientCommand {
+    fn parse(args: &CommandArgs) -> crate::Result<ClientCommand> {
+        let subcommand = args.string(1)?.to_lowercase();
+
+        match (subcommand.as_str(), args.len()) {
+            ("id", 2) => Ok(ClientCommand::new(ClientOption::Id)),
+            ("list", 2) => Ok(ClientCommand::new(ClientOption::List)),
+            ("info", 2) => Ok(ClientCommand::new(ClientOption::Info)),
+            ("setname", 3) => Ok(ClientCommand::new(ClientOption::SetName(parse_client_attribute("Client names", args.bytes(2)?)?))),
+            ("getname", 2) => Ok(ClientCommand::new(ClientOption::GetName)),
+            ("setinfo", 4) => Ok(ClientCommand::new(ClientCommand::parse_setinfo(args)?)),
+            ("kill", len) if len >= 3 => Ok(ClientCommand::new(ClientCommand::parse_kill(args)?)),
+            ("pause", 3) | ("pause", 4) => Ok(ClientCommand::new(ClientCommand::parse_pause(args)?)),
+            ("unpause", 2) => Ok(ClientCommand::new(ClientOption::Unpause)),
+            (subcommand, _) => Err(RedisError::unknown_subcommand("CLIENT", subcommand)),
+        }
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let clients = ctx.db.get_clients();
+            let blocking = ctx.db.get_blocking();
+
+            // Killed clients that are blocked stop waiting right away, and
+            // disconnect once their command returns.
+            let frame = match self.option {
+                ClientOption::Id => Frame::Integer(ctx.client.id as i64),
+                ClientOption::List => Frame::bulk(clients.list(blocking)),
+                ClientOption::Info => Frame::bulk(clients.info(ctx.client.id, blocking).unwrap_or_default()),
+                ClientOption::SetName(name) => {
+                    ctx.client.name = non_empty(name);
+                    Frame::simple("OK")
+                },
+                ClientOption::GetName => Frame::Bulk(ctx.client.name.clone().map(Bytes::from)),
+                ClientOption::SetLibName(lib_name) => {
+                    ctx.client.lib_name = non_empty(lib_name);
+                    Frame::simple("OK")
+                },
+                ClientOption::SetLibVer(lib_ver) => {
+                    ctx.client.lib_ver = non_empty(lib_ver);
+                    Frame::simple("OK")
+                },
+                ClientOption::KillAddr(addr) => {
+                    let killed = clients.kill(None, Some(&addr), None, None);
+                    if killed.is_empty() {
+                        return Err("ERR No such client".into());
+                    }
+
+                    for id in killed {
+                        blocking.unblock(id);
+                    }
+                    Frame::simple("OK")
+                },
+                ClientOption::Kill { id, addr, client_type, skip_me } => {
+                    let skip = if skip_me { Some(ctx.client.id) } else { None };
+                    let killed = clients.kill(id, addr.as_deref(), client_type, skip);
+
+                    for id in killed.iter() {
+                        blocking.unblock(*id);
+                    }
+                    Frame::Integer(killed.len() as i64)
+                },
+                ClientOption::Pause(timeout, mode) => {
+                    ctx.db.get_pause().pause(mode, Instant::now() + timeout);
+                    Frame::simple("OK")
+                },
+                ClientOption::Unpause => {
+                    ctx.db.get_pause().unpause();
+                    Frame::simple("OK")
+                },
+            };
+
+            ctx.reply(&frame).await?;
+
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,718 @@
+use bytes::Bytes;
+
+use crate::db::{Keyspace, Shard, ShardGuards};
+use crate::{glob, rdb, value};
+use crate::{evict, get_unix_ts_millis, EventClass, Frame, LongOperation, RedisError, Value};
+use super::{store_result_or_delete, BoxFuture, CommandArgs, CommandContext, CommandExec, Propagate, ReplicaContext, StoreOutcome};
+
+/// Number of elements `SORT` goes through between checkpoints.
+const SORT_CHECKPOINT_INTERVAL: usize = 1024;
+
+#[derive(Debug)]
+pub struct Keys {
+    pattern: Bytes,
+}
+
+impl Keys {
+    pub fn new(pattern: Bytes) -> Keys {
+        Keys { pattern }
+    }
+}
+
+impl CommandExec for Keys {
+    fn parse(args: &CommandArgs) -> crate::Result<Keys> {
+        Ok(Keys::new(args.bytes(1)?.clone()))
+    }
+
+    /// Streams the keys matching the pattern, with every shard locked for
+    /// reading so the reply is a consistent view of the database.
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let shards = ctx.db.get_db(ctx.client.db_index).read_all().await;
+            let now = get_unix_ts_millis();
+
+            let keys: Vec<&Bytes> = shards.iter()
+                .flat_map(|shard| shard.keys(now))
+                .filter(|key| glob::matches(&self.pattern, key))
+                .collect();
+
+            let mut reply = ctx.reply_stream(keys.len()).await?;
+            for key in keys {
+                reply.push(Frame::bulk(key.clone())).await?;
+            }
+            reply.finish().await?;
+
+            Ok(())
+        })
+    }
+}
+
+/// Number of keys `SCAN` goes through per call without a `COUNT`.
+const SCAN_DEFAULT_COUNT: usize = 10;
+
+/// The type names `TYPE` replies with, which `SCAN .. TYPE` takes.
+const TYPE_NAMES: &[&str] = &["string", "list", "hash", "set", "zset", "stream"];
+
+#[derive(Debug)]
+pub struct Scan {
+    cursor: u64,
+    pattern: Option<Bytes>,
+    count: usize,
+    type_name: Option<String>,
+}
+
+impl Scan {
+    pub fn new(cursor: u64, pattern: Option<Bytes>, count: usize, type_name: Option<String>) -> Scan {
+        Scan { cursor, pattern, count, type_name }
+    }
+}
+
+impl CommandExec for Scan {
+    fn parse(args: &CommandArgs) -> crate::Result<Scan> {
+        let cursor = match value::parse_unsigned(args.bytes(1)?) {
+            Some(cursor) => cursor,
+            None => return Err("ERR invalid cursor".into()),
+        };
+
+        let (mut pattern, mut count, mut type_name) = (None, SCAN_DEFAULT_COUNT, None);
+        let mut idx = 2;
+        while idx < args.len() {
+            if idx + 1 >= args.len() {
+                return Err(RedisError::Syntax);
+            }
+
+            match args.string(idx)?.to_uppercase().as_str() {
+                "MATCH" => pattern = Some(args.bytes(idx + 1)?.clone()),
+                "COUNT" => count = match args.integer(idx + 1)? {
+                    count if count >= 1 => count as usize,
+                    _ => return Err(RedisError::Syntax),
+                },
+                "TYPE" => {
+                    let name = args.string(idx + 1)?;
+                    match TYPE_NAMES.iter().find(|known| known.eq_ignore_ascii_case(&name)) {
+                        Some(known) => type_name = Some(known.to_string()),
+                        None => return Err(format!("ERR unknown type name '{}'", name).into()),
+                    }
+                },
+                _ => return Err(RedisError::Syntax),
+            }
+            idx += 2;
+        }
+
+        Ok(Scan::new(cursor, pattern, count, type_name))
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let (cursor, keys) = ctx.db.get_db(ctx.client.db_index).scan(self.cursor, self.count, |key, value| {
+                self.pattern.as_ref().map_or(true, |pattern| glob::matches(pattern, key))
+                    && self.type_name.as_deref().map_or(true, |type_name| value.type_name() == type_name)
+            }).await;
+
+            ctx.reply(&Frame::Array(vec![
+                Frame::bulk(cursor.to_string()),
+                Frame::Array(keys.into_iter().map(|key| Frame::Bulk(Some(key))).collect()),
+            ])).await?;
+
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Del {
+    keys: Vec<Bytes>,
+}
+
+impl Del {
+    pub fn new(keys: Vec<Bytes>) -> Del {
+        Del { keys }
+    }
+
+    fn locked_keys(&self) -> Vec<&[u8]> {
+        self.keys.iter().map(|key| &key[..]).collect()
+    }
+
+    /// Deletes the keys, returning those which existed. Expired keys are
+    /// removed as well, but don't count, as they were gone already.
+    fn execute(&self, guards: &mut ShardGuards) -> Vec<&Bytes> {
+        let mut deleted = vec![];
+        for key in self.keys.iter() {
+            let shard = guards.get_mut(key);
+            if shard.peek(key).is_some() {
+                deleted.push(key);
+            }
+            shard.remove(key);
+        }
+
+        deleted
+    }
+}
+
+impl CommandExec for Del {
+    fn parse(args: &CommandArgs) -> crate::Result<Del> {
+        Ok(Del::new(args.keys_from(1)?))
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let mut guards = ctx.db.get_db(ctx.client.db_index).lock_keys(&self.locked_keys()).await;
+            let deleted = self.execute(&mut guards);
+            for key in deleted.iter() {
+                ctx.notify(EventClass::Generic, "del", key);
+            }
+
+            ctx.propagate(Propagate::Verbatim).await?;
+            drop(guards);
+
+            ctx.reply(&Frame::Integer(deleted.len() as i64)).await?;
+
+            Ok(())
+        })
+    }
+
+    fn apply_replica(self: Box<Self>, ctx: &mut ReplicaContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let mut guards = ctx.db.get_db(ctx.db_index).lock_keys(&self.locked_keys()).await;
+            for key in self.execute(&mut guards) {
+                ctx.notify(EventClass::Generic, "del", key);
+            }
+
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Exists {
+    keys: Vec<Bytes>,
+}
+
+impl Exists {
+    pub fn new(keys: Vec<Bytes>) -> Exists {
+        Exists { keys }
+    }
+}
+
+impl CommandExec for Exists {
+    fn parse(args: &CommandArgs) -> crate::Result<Exists> {
+        Ok(Exists::new(args.keys_from(1)?))
+    }
+
+    /// Counts the keys which exist, a key named twice counting twice, like
+    /// redis-server. Checking doesn't count as an access, and expired keys
+    /// found along the way are deleted, like `GET` does.
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let keyspace = ctx.db.get_db(ctx.client.db_index);
+            let now = get_unix_ts_millis();
+
+            let mut count = 0;
+            for key in self.keys.iter() {
+                let (exists, expired) = {
+                    let shard = keyspace.read(key).await;
+                    (shard.peek(key).is_some(), shard.is_expired(key, now))
+                };
+
+                if exists {
+                    count += 1;
+                }
+                if expired {
+                    keyspace.remove_if_expired(key).await;
+                }
+            }
+
+            ctx.reply(&Frame::Integer(count)).await?;
+
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Sort {
+    key: Bytes,
+    by: Option<Bytes>,
+    // Offset and count.
+    limit: Option<(i64, i64)>,
+    get: Vec<Bytes>,
+    descending: bool,
+    alpha: bool,
+    store: Option<Bytes>,
+}
+
+impl Sort {
+    pub fn new(key: Bytes, by: Option<Bytes>, limit: Option<(i64, i64)>, get: Vec<Bytes>, descending: bool, alpha: bool, store: Option<Bytes>) -> Sort {
+        Sort { key, by, limit, get, descending, alpha, store }
+    }
+
+    /// Patterns can name any key, so they need every shard locked.
+    async fn lock<'a>(&self, keyspace: &'a Keyspace) -> ShardGuards<'a> {
+        if self.by.is_some() || !self.get.is_empty() {
+            return keyspace.lock_all_keys().await;
+        }
+
+        let keys: Vec<&[u8]> = std::iter::once(&self.key).chain(self.store.iter()).map(|key| &key[..]).collect();
+        keyspace.lock_keys(&keys).await
+    }
+
+    /// Sorts the elements and, with `STORE`, saves them as a list, deleting
+    /// the destination if there are none. Returns the sorted elements, or
+    /// the values the `GET` patterns project them to, along with what was
+    /// done to the destination.
+    fn execute(&self, guards: &mut ShardGuards, operation: &LongOperation) -> crate::Result<(Vec<Option<Bytes>>, Option<StoreOutcome>)> {
+        let mut elements: Vec<Bytes> = match guards.get_mut(&self.key).get_value(&self.key) {
+            None => vec![],
+            Some(Value::List(list)) => list.iter().cloned().collect(),
+            Some(Value::Set(set)) => {
+                // Sets have no order of their own. Start from a sorted one,
+                // so the result is the same on replicas.
+                let mut members: Vec<Bytes> = set.iter().collect();
+                members.sort();
+                members
+            },
+            Some(Value::ZSet(zset)) => zset.iter().map(|(member, _)| member.clone()).collect(),
+            Some(_) => return Err(RedisError::WrongType),
+        };
+
+        // Like Redis, a BY pattern that can't match anything skips sorting.
+        let dont_sort = matches!(&self.by, Some(by) if !by.contains(&b'*'));
+        if !dont_sort {
+            self.sort(guards, &mut elements, operation)?;
+        }
+
+        let (start, end) = match self.limit {
+            Some((offset, count)) => {
+                let start = (offset.max(0) as usize).min(elements.len());
+                let end = if count < 0 { elements.len() } else { start.saturating_add(count as usize).min(elements.len()) };
+                (start, end)
+            },
+            None => (0, elements.len()),
+        };
+
+        let mut res = vec![];
+        for (idx, element) in elements[start..end].iter().enumerate() {
+            if idx % SORT_CHECKPOINT_INTERVAL == 0 {
+                operation.checkpoint()?;
+            }
+            if self.get.is_empty() {
+                res.push(Some(element.clone()));
+            }
+            for pattern in self.get.iter() {
+                res.push(lookup_by_pattern(guards, pattern, element));
+            }
+        }
+
+        let outcome = match &self.store {
+            Some(store) => {
+                operation.checkpoint()?;
+                let list = (!res.is_empty()).then(|| Value::List(res.iter().map(|val| val.clone().unwrap_or_default()).collect()));
+                Some(store_result_or_delete(guards.get_mut(store), store, list))
+            },
+            None => None,
+        };
+
+        Ok((res, outcome))
+    }
+
+    fn sort(&self, guards: &mut ShardGuards, elements: &mut Vec<Bytes>, operation: &LongOperation) -> crate::Result<()> {
+        let mut weights: Vec<Option<Bytes>> = Vec::with_capacity(elements.len());
+        for (idx, element) in elements.iter().enumerate() {
+            if idx % SORT_CHECKPOINT_INTERVAL == 0 {
+                operation.checkpoint()?;
+            }
+            weights.push(match &self.by {
+                Some(by) => lookup_by_pattern(guards, by, element),
+                None => Some(element.clone()),
+            });
+        }
+
+        let mut sorted: Vec<(Bytes, Option<Bytes>, f64)> = Vec::with_capacity(elements.len());
+        for (element, weight) in elements.drain(..).zip(weights) {
+            let score = match &weight {
+                Some(weight) if !self.alpha => parse_sort_score(weight)?,
+                _ => 0.0,
+            };
+            sorted.push((element, weight, score));
+        }
+
+        operation.checkpoint()?;
+        sorted.sort_by(|a, b| {
+            let ordering = if self.alpha {
+                // Missing weights sort first.
+                a.1.cmp(&b.1)
+            } else {
+                // Equal scores fall back to the elements, so the order is
+                // well defined.
+                a.2.total_cmp(&b.2).then_with(|| a.0.cmp(&b.0))
+            };
+
+            if self.descending { ordering.reverse() } else { ordering }
+        });
+
+        elements.extend(sorted.into_iter().map(|(element, _, _)| element));
+
+        Ok(())
+    }
+}
+
+impl CommandExec for Sort {
+    fn parse(args: &CommandArgs) -> crate::Result<Sort> {
+        let (mut by, mut limit, mut get, mut store) = (None, None, vec![], None);
+        let (mut descending, mut alpha) = (false, false);
+
+        let mut idx = 2;
+        while idx < args.len() {
+            match args.string(idx)?.to_uppercase().as_str() {
+                "BY" if idx + 1 < args.len() => {
+                    by = Some(args.bytes(idx + 1)?.clone());
+                    idx += 2;
+                },
+                "LIMIT" if idx + 2 < args.len() => {
+                    limit = Some((args.integer(idx + 1)?, args.integer(idx + 2)?));
+                    idx += 3;
+                },
+                "GET" if idx + 1 < args.len() => {
+                    get.push(args.bytes(idx + 1)?.clone());
+                    idx += 2;
+                },
+                "STORE" if idx + 1 < args.len() => {
+                    store = Some(args.key(idx + 1)?);
+                    idx += 2;
+                },
+                "ASC" => {
+                    descending = false;
+                    idx += 1;
+                },
+                "DESC" => {
+                    descending = true;
+                    idx += 1;
+                },
+                "ALPHA" => {
+                    alpha = true;
+                    idx += 1;
+                },
+                _ => return Err(RedisError::Syntax),
+            }
+        }
+
+        Ok(Sort::new(args.key(1)?, by, limit, get, descending, alpha, store))
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let operation = ctx.long_operation();
+            let mut guards = self.lock(ctx.db.get_db(ctx.client.db_index)).await;
+            let (res, outcome) = self.execute(&mut guards, &operation)?;
+
+            let frame = match (&self.store, outcome) {
+                (Some(store), Some(outcome)) => {
+                    ctx.stored(store, outcome, EventClass::List, "sortstore").await?;
+                    ctx.db.get_blocking().signal(ctx.client.db_index, store);
+                    Frame::Integer(res.len() as i64)
+                },
+                _ => Frame::Array(res.into_iter().map(Frame::Bulk).collect()),
+            };
+            drop(guards);
+
+            ctx.reply(&frame).await?;
+
+            Ok(())
+        })
+    }
+
+    fn apply_replica(self: Box<Self>, ctx: &mut ReplicaContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let mut guards = self.lock(ctx.db.get_db(ctx.db_index)).await;
+            let (_, outcome) = self.execute(&mut guards, &LongOperation::unkillable())?;
+            if let (Some(store), Some(outcome)) = (&self.store, outcome) {
+                ctx.stored(store, outcome, EventClass::List, "sortstore");
+                ctx.db.get_blocking().signal(ctx.db_index, store);
+            }
+
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Dump {
+    key: Bytes,
+}
+
+impl Dump {
+    pub fn new(key: Bytes) -> Dump {
+        Dump { key }
+    }
+}
+
+impl CommandExec for Dump {
+    fn parse(args: &CommandArgs) -> crate::Result<Dump> {
+        Ok(Dump::new(args.key(1)?))
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let payload = {
+                let shard = ctx.db.get_db(ctx.client.db_index).read(&self.key).await;
+
+                match shard.get_value(&self.key) {
+                    Some(value) => Some(rdb::dump(value).map_err(|err| format!("ERR {}", err))?),
+                    None => None,
+                }
+            };
+
+            ctx.reply(&Frame::Bulk(payload.map(Bytes::from))).await?;
+
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Restore {
+    key: Bytes,
+    // Milliseconds, or 0 for no expiry.
+    ttl: u128,
+    payload: Bytes,
+    replace: bool,
+    // Whether the TTL is a Unix timestamp rather than a duration.
+    absttl: bool,
+}
+
+impl Restore {
+    pub fn new(key: Bytes, ttl: u128, payload: Bytes, replace: bool, absttl: bool) -> Restore {
+        Restore { key, ttl, payload, replace, absttl }
+    }
+
+    /// The Unix timestamp in milliseconds the key expires at.
+    fn expiry(&self) -> Option<u128> {
+        match self.ttl {
+            0 => None,
+            ttl if self.absttl => Some(ttl),
+            ttl => Some(get_unix_ts_millis() + ttl),
+        }
+    }
+
+    fn execute(&self, shard: &mut Shard, expiry: Option<u128>) -> crate::Result<()> {
+        if !self.replace && shard.peek(&self.key).is_some() {
+            return Err("BUSYKEY Target key name already exists.".into());
+        }
+
+        let value = rdb::restore(&self.payload).map_err(|err| match err {
+            rdb::Error::Checksum => RedisError::from("ERR DUMP payload version or checksum are wrong"),
+            _ => RedisError::from("ERR Bad data format"),
+        })?;
+
+        // A key restored with an expiry in the past is gone right away.
+        if matches!(expiry, Some(ts) if ts <= get_unix_ts_millis()) {
+            shard.remove(&self.key);
+            return Ok(());
+        }
+
+        shard.insert(self.key.clone(), value, expiry);
+
+        Ok(())
+    }
+}
+
+impl CommandExec for Restore {
+    fn parse(args: &CommandArgs) -> crate::Result<Restore> {
+        let ttl = args.integer(2)?;
+        if ttl < 0 {
+            return Err("ERR Invalid TTL value, must be >= 0".into());
+        }
+
+        let (mut replace, mut absttl) = (false, false);
+        for idx in 4..args.len() {
+            match args.string(idx)?.to_uppercase().as_str() {
+                "REPLACE" => replace = true,
+                "ABSTTL" => absttl = true,
+                _ => return Err(RedisError::Syntax),
+            }
+        }
+
+        Ok(Restore::new(args.key(1)?, ttl as u128, args.bytes(3)?.clone(), replace, absttl))
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let mut shard = ctx.db.get_db(ctx.client.db_index).lock(&self.key).await;
+            let expiry = self.expiry();
+            self.execute(&mut shard, expiry)?;
+            ctx.db.get_blocking().signal(ctx.client.db_index, &self.key);
+
+            // Replicas get the expiry as a timestamp, so the key expires at the
+            // same time everywhere. The restore already succeeded here, so
+            // replace whatever the replica has.
+            ctx.propagate(Propagate::Rewrite(Frame::Array(vec![
+                Frame::bulk("RESTORE"),
+                Frame::bulk(self.key.clone()),
+                Frame::bulk(expiry.unwrap_or(0).to_string()),
+                Frame::Bulk(Some(self.payload.clone())),
+                Frame::bulk("REPLACE"),
+                Frame::bulk("ABSTTL"),
+            ]))).await?;
+            drop(shard);
+
+            ctx.reply(&Frame::simple("OK")).await?;
+
+            Ok(())
+        })
+    }
+
+    fn apply_replica(self: Box<Self>, ctx: &mut ReplicaContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let mut shard = ctx.db.get_db(ctx.db_index).lock(&self.key).await;
+            self.execute(&mut shard, self.expiry())?;
+            ctx.db.get_blocking().signal(ctx.db_index, &self.key);
+
+            Ok(())
+        })
+    }
+}
+
+/// Parses a `SORT` weight, which like in Redis may have leading whitespace.
+fn parse_sort_score(weight: &[u8]) -> crate::Result<f64> {
+    let score = std::str::from_utf8(weight).ok().and_then(|weight| weight.trim_start().parse::<f64>().ok());
+
+    match score {
+        Some(score) if !score.is_nan() => Ok(score),
+        _ => Err("ERR One or more scores can't be converted into double".into()),
+    }
+}
+
+/// Looks up the value a `SORT` pattern gives for `element`: the first `*` is
+/// replaced by the element to get a key, holding either a string or, with a
+/// trailing `->field`, a hash. `#` stands for the element itself.
+fn lookup_by_pattern(guards: &mut ShardGuards, pattern: &[u8], element: &Bytes) -> Option<Bytes> {
+    if pattern == b"#" {
+        return Some(element.clone());
+    }
+
+    let star = pattern.iter().position(|&byte| byte == b'*')?;
+    let (key_pattern, field) = match pattern[star + 1..].windows(2).position(|window| window == b"->") {
+        Some(idx) if star + 1 + idx + 2 < pattern.len() => {
+            let arrow = star + 1 + idx;
+            (&pattern[..arrow], Some(&pattern[arrow + 2..]))
+        },
+        _ => (pattern, None),
+    };
+
+    let key = [&key_pattern[..star], &element[..], &key_pattern[star + 1..]].concat();
+
+    match (guards.get_mut(&key).get_value(&key)?, field) {
+        (Value::String(string), None) => Some(string.clone()),
+        (Value::Appended(string), None) => Some(Bytes::copy_from_slice(string)),
+        (Value::Hash(hash), Some(field)) => hash.get(field, get_unix_ts_millis()).cloned(),
+        _ => None,
+    }
+}
+
+#[derive(Debug)]
+pub enum ObjectOption {
+    Encoding(Bytes),
+    IdleTime(Bytes),
+    Freq(Bytes),
+    RefCount(Bytes),
+}
+
+#[derive(Debug)]
+pub struct Object {
+    option: ObjectOption,
+}
+
+impl Object {
+    pub fn new(option: ObjectOption) -> Object {
+        Object { option }
+    }
+}
+
+impl CommandExec for Object {
+    fn parse(args: &CommandArgs) -> crate::Result<Object> {
+        let subcommand = args.string(1)?.to_lowercase();
+        let key = match args.len() {
+            3 => Some(args.key(2)?),
+            _ => None,
+        };
+
+        match (subcommand.as_str(), key) {
+            ("encoding", Some(key)) => Ok(Object::new(ObjectOption::Encoding(key))),
+            ("idletime", Some(key)) => Ok(Object::new(ObjectOption::IdleTime(key))),
+            ("freq", Some(key)) => Ok(Object::new(ObjectOption::Freq(key))),
+            ("refcount", Some(key)) => Ok(Object::new(ObjectOption::RefCount(key))),
+            (subcommand, _) => Err(RedisError::unknown_subcommand("OBJECT", subcommand)),
+        }
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let key = match &self.option {
+                ObjectOption::Encoding(key) | ObjectOption::IdleTime(key) | ObjectOption::Freq(key) | ObjectOption::RefCount(key) => key,
+            };
+
+            let frame = {
+                // Looking a key up here mustn't count as an access to it.
+                let shard = ctx.db.get_db(ctx.client.db_index).read(key).await;
+
+                match (shard.peek(key), &self.option) {
+                    (None, _) => Frame::Bulk(None),
+                    (Some(entry), ObjectOption::Encoding(_)) => Frame::bulk(value_encoding(&entry.value)),
+                    (Some(_), ObjectOption::IdleTime(_)) if evict::lfu_enabled() => Frame::Error(
+                        "ERR An LFU maxmemory policy is selected, idle time not tracked. Please note that when switching between policies at runtime LRU and LFU data will take some time to adjust.".to_string()
+                    ),
+                    (Some(entry), ObjectOption::IdleTime(_)) => Frame::Integer((entry.access.idle_time() / 1000) as i64),
+                    (Some(_), ObjectOption::Freq(_)) if !evict::lfu_enabled() => Frame::Error(
+                        "ERR An LFU maxmemory policy is not selected, access frequency not tracked. Please note that when switching between policies at runtime LRU and LFU data will take some time to adjust.".to_string()
+                    ),
+                    (Some(entry), ObjectOption::Freq(_)) => Frame::Integer(entry.access.frequency() as i64),
+                    // Shared objects are never freed, which redis-server
+                    // reports as the largest refcount there is.
+                    (Some(entry), ObjectOption::RefCount(_)) if entry.value.is_shared() => Frame::Integer(i32::MAX as i64),
+                    (Some(_), ObjectOption::RefCount(_)) => Frame::Integer(1),
+                }
+            };
+
+            ctx.reply(&frame).await?;
+
+            Ok(())
+        })
+    }
+}
+
+/// The encoding redis-server would pick for a value.
+pub(super) fn value_encoding(value: &Value) -> &'static str {
+    match value {
+        Value::String(val) => string_encoding(val),
+        // Like strings redis-server has appended to.
+        Value::Appended(_) => "raw",
+        Value::List(_) => "quicklist",
+        Value::Hash(hash) => hash.encoding(),
+        Value::Set(set) => set.encoding(),
+        Value::ZSet(zset) => zset.encoding(),
+        Value::Stream(_) => "stream",
+    }
+}
+
+/// Length of the value once written to an RDB file, without its type.
+pub(super) fn value_serialized_len(value: &Value) -> usize {
+    let mut buf = vec![];
+    match rdb::write_object(&mut buf, value) {
+        Ok(()) => buf.len() - 1,
+        // Streams can't be encoded yet.
+        Err(_) => 0,
+    }
+}
+
+/// The encoding redis-server would pick for a string value.
+fn string_encoding(val: &[u8]) -> &'static str {
+    let is_int = val.len() <= 20 && value::parse_integer(val).is_some();
+
+    if is_int {
+        "int"
+    } else if val.len() <= 44 {
+        "embstr"
+    } else {
+        "raw"
+    }
+}
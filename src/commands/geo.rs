@@ -0,0 +1,430 @@
+use bytes::Bytes;
+
+use crate::db::Shard;
+use crate::geohash;
+use crate::{Frame, RedisError, SortedSet, Value};
+use super::{BoxFuture, CommandArgs, CommandContext, CommandExec, Propagate, ReplicaContext};
+
+#[derive(Debug)]
+pub struct GeoAdd {
+    key: Bytes,
+    nx: bool,
+    xx: bool,
+    ch: bool,
+    // Longitude, latitude and member.
+    members: Vec<(f64, f64, Bytes)>,
+}
+
+impl GeoAdd {
+    pub fn new(key: Bytes, nx: bool, xx: bool, ch: bool, members: Vec<(f64, f64, Bytes)>) -> GeoAdd {
+        GeoAdd { key, nx, xx, ch, members }
+    }
+
+    /// Returns the number of members added, or changed too with `CH`.
+    fn execute(&self, shard: &mut Shard) -> crate::Result<usize> {
+        if shard.get_zset_mut(&self.key)?.is_none() {
+            if self.xx {
+                return Ok(0);
+            }
+            shard.insert(self.key.clone(), Value::ZSet(SortedSet::new()), None);
+        }
+
+        let zset = shard.get_zset_mut(&self.key)?.expect("key exists");
+
+        let mut count = 0;
+        for (lon, lat, member) in self.members.iter() {
+            let exists = zset.score(member).is_some();
+            if (exists && self.nx) || (!exists && self.xx) {
+                continue;
+            }
+
+            let score = geohash::score(*lon, *lat);
+            match zset.insert(member.clone(), score) {
+                None => count += 1,
+                Some(old) if self.ch && old != score => count += 1,
+                Some(_) => {},
+            }
+        }
+
+        Ok(count)
+    }
+}
+
+impl CommandExec for GeoAdd {
+    fn parse(args: &CommandArgs) -> crate::Result<GeoAdd> {
+        let (mut nx, mut xx, mut ch) = (false, false, false);
+
+        let mut idx = 2;
+        while idx < args.len() {
+            match args.string(idx)?.to_uppercase().as_str() {
+                "NX" => nx = true,
+                "XX" => xx = true,
+                "CH" => ch = true,
+                _ => break,
+            }
+            idx += 1;
+        }
+
+        if nx && xx {
+            return Err("ERR XX and NX options at the same time are not compatible".into());
+        }
+
+        let mut members = vec![];
+        while idx + 3 <= args.len() {
+            let lon: f64 = args.string(idx)?.parse()?;
+            let lat: f64 = args.string(idx + 1)?.parse()?;
+
+            if !geohash::is_valid(lon, lat) {
+                return Err(format!("ERR invalid longitude,latitude pair {:.6},{:.6}", lon, lat).into());
+            }
+
+            members.push((lon, lat, args.bytes(idx + 2)?.clone()));
+            idx += 3;
+        }
+
+        if members.is_empty() || idx != args.len() {
+            return Err(RedisError::Syntax);
+        }
+
+        Ok(GeoAdd::new(args.key(1)?, nx, xx, ch, members))
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let mut shard = ctx.db.get_db(ctx.client.db_index).lock(&self.key).await;
+            let count = self.execute(&mut shard)?;
+
+            ctx.propagate(Propagate::Verbatim).await?;
+            drop(shard);
+
+            ctx.reply(&Frame::Integer(count as i64)).await?;
+
+            Ok(())
+        })
+    }
+
+    fn apply_replica(self: Box<Self>, ctx: &mut ReplicaContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let mut shard = ctx.db.get_db(ctx.db_index).lock(&self.key).await;
+            self.execute(&mut shard)?;
+
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct GeoPos {
+    key: Bytes,
+    members: Vec<Bytes>,
+}
+
+impl GeoPos {
+    pub fn new(key: Bytes, members: Vec<Bytes>) -> GeoPos {
+        GeoPos { key, members }
+    }
+}
+
+impl CommandExec for GeoPos {
+    fn parse(args: &CommandArgs) -> crate::Result<GeoPos> {
+        let members = (2..args.len()).map(|idx| args.bytes(idx).cloned()).collect::<crate::Result<_>>()?;
+
+        Ok(GeoPos::new(args.key(1)?, members))
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let frame = {
+                let shard = ctx.db.get_db(ctx.client.db_index).read(&self.key).await;
+                let zset = shard.get_zset(&self.key)?;
+
+                Frame::Array(self.members.iter().map(|member| {
+                    match zset.and_then(|zset| zset.score(member)) {
+                        Some(score) => {
+                            let (lon, lat) = geohash::position(score);
+
+                            Frame::Array(vec![
+                                Frame::bulk(lon.to_string()),
+                                Frame::bulk(lat.to_string()),
+                            ])
+                        },
+                        None => Frame::Bulk(None),
+                    }
+                }).collect())
+            };
+
+            ctx.reply(&frame).await?;
+
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct GeoDist {
+    key: Bytes,
+    member1: Bytes,
+    member2: Bytes,
+    // Meters per unit the distance is reported in.
+    unit: f64,
+}
+
+impl GeoDist {
+    pub fn new(key: Bytes, member1: Bytes, member2: Bytes, unit: f64) -> GeoDist {
+        GeoDist { key, member1, member2, unit }
+    }
+}
+
+impl CommandExec for GeoDist {
+    fn parse(args: &CommandArgs) -> crate::Result<GeoDist> {
+        let unit = match args.len() {
+            4 => 1.0,
+            5 => parse_distance_unit(&args.string(4)?)?,
+            _ => return Err(RedisError::Syntax),
+        };
+
+        Ok(GeoDist::new(args.key(1)?, args.bytes(2)?.clone(), args.bytes(3)?.clone(), unit))
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let scores = {
+                let shard = ctx.db.get_db(ctx.client.db_index).read(&self.key).await;
+
+                shard.get_zset(&self.key)?.and_then(|zset| Some((zset.score(&self.member1)?, zset.score(&self.member2)?)))
+            };
+
+            let frame = match scores {
+                Some((score1, score2)) => {
+                    let (lon1, lat1) = geohash::position(score1);
+                    let (lon2, lat2) = geohash::position(score2);
+                    let distance = geohash::distance(lon1, lat1, lon2, lat2) / self.unit;
+
+                    Frame::bulk(format!("{:.4}", distance))
+                },
+                None => Frame::Bulk(None),
+            };
+
+            ctx.reply(&frame).await?;
+
+            Ok(())
+        })
+    }
+}
+
+/// Where a `GEOSEARCH` is centered.
+#[derive(Debug)]
+pub enum GeoCenter {
+    Member(Bytes),
+    LonLat(f64, f64),
+}
+
+#[derive(Debug)]
+pub struct GeoSearch {
+    key: Bytes,
+    center: GeoCenter,
+    shape: geohash::Shape,
+    // Meters per unit distances are given and reported in.
+    unit: f64,
+    // Sort by distance, descending if `Some(true)`.
+    descending: Option<bool>,
+    count: Option<usize>,
+    any: bool,
+    with_coord: bool,
+    with_dist: bool,
+    with_hash: bool,
+}
+
+impl GeoSearch {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(key: Bytes, center: GeoCenter, shape: geohash::Shape, unit: f64, descending: Option<bool>, count: Option<usize>, any: bool, with_coord: bool, with_dist: bool, with_hash: bool) -> GeoSearch {
+        GeoSearch { key, center, shape, unit, descending, count, any, with_coord, with_dist, with_hash }
+    }
+
+    /// The members inside the search area, with their distance from its
+    /// center in meters and their score. Only the geohash cells covering the
+    /// area are scanned.
+    fn search(&self, zset: &SortedSet) -> crate::Result<Vec<(Bytes, f64, f64)>> {
+        let (lon, lat) = match &self.center {
+            GeoCenter::Member(member) => match zset.score(member) {
+                Some(score) => geohash::position(score),
+                None => return Err("ERR could not decode requested zset member".into()),
+            },
+            GeoCenter::LonLat(lon, lat) => (*lon, *lat),
+        };
+
+        let mut matches = vec![];
+        for (min, max) in geohash::search_ranges(lon, lat, self.shape) {
+            for (member, score) in zset.range_by_score(min as f64, max as f64) {
+                // The upper bound of a cell belongs to the next one.
+                if score >= max as f64 {
+                    break;
+                }
+
+                let (member_lon, member_lat) = geohash::position(score);
+                if let Some(distance) = self.shape.distance_if_within((lon, lat), member_lon, member_lat) {
+                    matches.push((member.clone(), distance, score));
+
+                    if self.any && Some(matches.len()) == self.count {
+                        return Ok(matches);
+                    }
+                }
+            }
+        }
+
+        Ok(matches)
+    }
+}
+
+impl CommandExec for GeoSearch {
+    fn parse(args: &CommandArgs) -> crate::Result<GeoSearch> {
+        let (mut center, mut shape, mut unit) = (None, None, 1.0);
+        let (mut descending, mut count, mut any) = (None, None, false);
+        let (mut with_coord, mut with_dist, mut with_hash) = (false, false, false);
+
+        let mut idx = 2;
+        while idx < args.len() {
+            match args.string(idx)?.to_uppercase().as_str() {
+                "FROMMEMBER" if idx + 1 < args.len() && center.is_none() => {
+                    center = Some(GeoCenter::Member(args.bytes(idx + 1)?.clone()));
+                    idx += 2;
+                },
+                "FROMLONLAT" if idx + 2 < args.len() && center.is_none() => {
+                    let lon: f64 = args.string(idx + 1)?.parse()?;
+                    let lat: f64 = args.string(idx + 2)?.parse()?;
+
+                    if !geohash::is_valid(lon, lat) {
+                        return Err(format!("ERR invalid longitude,latitude pair {:.6},{:.6}", lon, lat).into());
+                    }
+
+                    center = Some(GeoCenter::LonLat(lon, lat));
+                    idx += 3;
+                },
+                "FROMMEMBER" | "FROMLONLAT" if center.is_some() => {
+                    return Err("ERR exactly one of FROMMEMBER or FROMLONLAT can be specified for GEOSEARCH".into());
+                },
+                "BYRADIUS" if idx + 2 < args.len() && shape.is_none() => {
+                    let radius: f64 = args.string(idx + 1)?.parse()?;
+                    if radius < 0.0 {
+                        return Err("ERR radius cannot be negative".into());
+                    }
+
+                    unit = parse_distance_unit(&args.string(idx + 2)?)?;
+                    shape = Some(geohash::Shape::Radius(radius * unit));
+                    idx += 3;
+                },
+                "BYBOX" if idx + 3 < args.len() && shape.is_none() => {
+                    let width: f64 = args.string(idx + 1)?.parse()?;
+                    let height: f64 = args.string(idx + 2)?.parse()?;
+                    if width < 0.0 || height < 0.0 {
+                        return Err("ERR height or width cannot be negative".into());
+                    }
+
+                    unit = parse_distance_unit(&args.string(idx + 3)?)?;
+                    shape = Some(geohash::Shape::Box { width: width * unit, height: height * unit });
+                    idx += 4;
+                },
+                "BYRADIUS" | "BYBOX" if shape.is_some() => {
+                    return Err("ERR exactly one of BYRADIUS and BYBOX can be specified for GEOSEARCH".into());
+                },
+                "ASC" => {
+                    descending = Some(false);
+                    idx += 1;
+                },
+                "DESC" => {
+                    descending = Some(true);
+                    idx += 1;
+                },
+                "COUNT" if idx + 1 < args.len() => {
+                    let n = args.integer(idx + 1)?;
+                    if n <= 0 {
+                        return Err("ERR COUNT must be > 0".into());
+                    }
+
+                    count = Some(n as usize);
+                    idx += 2;
+
+                    if idx < args.len() && args.string(idx)?.eq_ignore_ascii_case("ANY") {
+                        any = true;
+                        idx += 1;
+                    }
+                },
+                "ANY" => return Err("ERR the ANY argument requires COUNT argument".into()),
+                "WITHCOORD" => {
+                    with_coord = true;
+                    idx += 1;
+                },
+                "WITHDIST" => {
+                    with_dist = true;
+                    idx += 1;
+                },
+                "WITHHASH" => {
+                    with_hash = true;
+                    idx += 1;
+                },
+                _ => return Err(RedisError::Syntax),
+            }
+        }
+
+        let center = center.ok_or("ERR exactly one of FROMMEMBER or FROMLONLAT can be specified for GEOSEARCH")?;
+        let shape = shape.ok_or("ERR exactly one of BYRADIUS and BYBOX can be specified for GEOSEARCH")?;
+
+        Ok(GeoSearch::new(args.key(1)?, center, shape, unit, descending, count, any, with_coord, with_dist, with_hash))
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let mut matches = {
+                let shard = ctx.db.get_db(ctx.client.db_index).read(&self.key).await;
+
+                match shard.get_zset(&self.key)? {
+                    Some(zset) => self.search(zset)?,
+                    None => vec![],
+                }
+            };
+
+            // Without ANY, COUNT keeps the closest matches.
+            let descending = match (self.descending, self.count) {
+                (None, Some(_)) if !self.any => Some(false),
+                (descending, _) => descending,
+            };
+            if let Some(descending) = descending {
+                matches.sort_by(|a, b| if descending { b.1.total_cmp(&a.1) } else { a.1.total_cmp(&b.1) });
+            }
+            if let Some(count) = self.count {
+                matches.truncate(count);
+            }
+
+            let frames = matches.into_iter().map(|(member, distance, score)| {
+                if !self.with_dist && !self.with_hash && !self.with_coord {
+                    return Frame::Bulk(Some(member));
+                }
+
+                let mut fields = vec![Frame::Bulk(Some(member))];
+                if self.with_dist {
+                    fields.push(Frame::bulk(format!("{:.4}", distance / self.unit)));
+                }
+                if self.with_hash {
+                    fields.push(Frame::Integer(score as i64));
+                }
+                if self.with_coord {
+                    let (lon, lat) = geohash::position(score);
+                    fields.push(Frame::Array(vec![
+                        Frame::bulk(lon.to_string()),
+                        Frame::bulk(lat.to_string()),
+                    ]));
+                }
+
+                Frame::Array(fields)
+            }).collect();
+
+            ctx.reply(&Frame::Array(frames)).await?;
+
+            Ok(())
+        })
+    }
+}
+
+fn parse_distance_unit(arg: &str) -> crate::Result<f64> {
+    geohash::unit_to_meters(arg).ok_or_else(|| "ERR unsupported unit provided. please use M, KM, FT, MI".into())
+}
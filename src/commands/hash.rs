@@ -0,0 +1,537 @@
+use bytes::Bytes;
+
+use crate::db::Shard;
+use crate::{get_unix_ts_millis, Frame, Hash, Value};
+use super::{BoxFuture, CommandArgs, CommandContext, CommandExec, Propagate, ReplicaContext};
+
+/// Latest field expiry Redis accepts, in milliseconds.
+const MAX_FIELD_EXPIRY: u128 = (1 << 48) - 1;
+
+/// Replies for fields a command found missing, `-2` in every reply of the
+/// field expiry commands.
+const NO_FIELD: i64 = -2;
+
+/// Parses the `FIELDS numfields field [field ...]` ending the arguments of
+/// the field expiry commands, starting at `idx`.
+fn parse_fields(args: &CommandArgs, idx: usize) -> crate::Result<Vec<Bytes>> {
+    if idx + 1 >= args.len() || !args.string(idx)?.eq_ignore_ascii_case("FIELDS") {
+        return Err("ERR Mandatory argument FIELDS is missing or not at the right position".into());
+    }
+
+    let num_fields = args.integer_in(idx + 1, 1.., "ERR Number of fields must be a positive integer")?;
+    if num_fields as usize != args.len() - idx - 2 {
+        return Err("ERR The `numfields` parameter must match the number of arguments".into());
+    }
+
+    (idx + 2..args.len()).map(|idx| args.bytes(idx).cloned()).collect()
+}
+
+/// Deletes the hash at `key` once its last field is gone.
+fn remove_if_empty(shard: &mut Shard, key: &[u8]) {
+    if matches!(shard.get_value(key), Some(Value::Hash(hash)) if hash.is_empty()) {
+        shard.remove(key);
+    }
+}
+
+#[derive(Debug)]
+pub struct HSet {
+    key: Bytes,
+    fields: Vec<(Bytes, Bytes)>,
+}
+
+impl HSet {
+    pub fn new(key: Bytes, fields: Vec<(Bytes, Bytes)>) -> HSet {
+        HSet { key, fields }
+    }
+
+    fn execute(&self, shard: &mut Shard) -> crate::Result<usize> {
+        if shard.get_hash_mut(&self.key)?.is_none() {
+            shard.insert(self.key.clone(), Value::Hash(Hash::new()), None);
+        }
+
+        let hash = shard.get_hash_mut(&self.key)?.expect("key exists");
+        let added = self.fields.iter().filter(|(field, val)| hash.insert(field.clone(), val.clone())).count();
+
+        Ok(added)
+    }
+}
+
+impl CommandExec for HSet {
+    fn parse(args: &CommandArgs) -> crate::Result<HSet> {
+        if args.len() % 2 != 0 {
+            return Err(args.wrong_arity());
+        }
+
+        let fields = (2..args.len()).step_by(2)
+            .map(|idx| Ok((args.bytes(idx)?.clone(), args.bytes(idx + 1)?.clone())))
+            .collect::<crate::Result<_>>()?;
+
+        Ok(HSet::new(args.key(1)?, fields))
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let mut shard = ctx.db.get_db(ctx.client.db_index).lock(&self.key).await;
+            let added = self.execute(&mut shard)?;
+
+            ctx.propagate(Propagate::Verbatim).await?;
+            drop(shard);
+
+            ctx.reply(&Frame::Integer(added as i64)).await?;
+
+            Ok(())
+        })
+    }
+
+    fn apply_replica(self: Box<Self>, ctx: &mut ReplicaContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let mut shard = ctx.db.get_db(ctx.db_index).lock(&self.key).await;
+            self.execute(&mut shard)?;
+
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct HGet {
+    key: Bytes,
+    field: Bytes,
+}
+
+impl HGet {
+    pub fn new(key: Bytes, field: Bytes) -> HGet {
+        HGet { key, field }
+    }
+}
+
+impl CommandExec for HGet {
+    fn parse(args: &CommandArgs) -> crate::Result<HGet> {
+        Ok(HGet::new(args.key(1)?, args.bytes(2)?.clone()))
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let frame = {
+                let shard = ctx.db.get_db(ctx.client.db_index).read(&self.key).await;
+                let hash = shard.get_hash(&self.key)?;
+
+                Frame::Bulk(hash.and_then(|hash| hash.get(&self.field, get_unix_ts_millis())).cloned())
+            };
+
+            ctx.reply(&frame).await?;
+
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct HDel {
+    key: Bytes,
+    fields: Vec<Bytes>,
+}
+
+impl HDel {
+    pub fn new(key: Bytes, fields: Vec<Bytes>) -> HDel {
+        HDel { key, fields }
+    }
+
+    fn execute(&self, shard: &mut Shard) -> crate::Result<usize> {
+        let removed = match shard.get_hash_mut(&self.key)? {
+            Some(hash) => self.fields.iter().filter(|field| hash.remove(field)).count(),
+            None => 0,
+        };
+        remove_if_empty(shard, &self.key);
+
+        Ok(removed)
+    }
+}
+
+impl CommandExec for HDel {
+    fn parse(args: &CommandArgs) -> crate::Result<HDel> {
+        let fields = (2..args.len()).map(|idx| args.bytes(idx).cloned()).collect::<crate::Result<_>>()?;
+
+        Ok(HDel::new(args.key(1)?, fields))
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let mut shard = ctx.db.get_db(ctx.client.db_index).lock(&self.key).await;
+            let removed = self.execute(&mut shard)?;
+
+            ctx.propagate(match removed {
+                0 => Propagate::None,
+                _ => Propagate::Verbatim,
+            }).await?;
+            drop(shard);
+
+            ctx.reply(&Frame::Integer(removed as i64)).await?;
+
+            Ok(())
+        })
+    }
+
+    fn apply_replica(self: Box<Self>, ctx: &mut ReplicaContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let mut shard = ctx.db.get_db(ctx.db_index).lock(&self.key).await;
+            self.execute(&mut shard)?;
+
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct HLen {
+    key: Bytes,
+}
+
+impl HLen {
+    pub fn new(key: Bytes) -> HLen {
+        HLen { key }
+    }
+}
+
+impl CommandExec for HLen {
+    fn parse(args: &CommandArgs) -> crate::Result<HLen> {
+        Ok(HLen::new(args.key(1)?))
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let len = {
+                let shard = ctx.db.get_db(ctx.client.db_index).read(&self.key).await;
+                shard.get_hash(&self.key)?.map(|hash| hash.len(get_unix_ts_millis())).unwrap_or(0)
+            };
+
+            ctx.reply(&Frame::Integer(len as i64)).await?;
+
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct HGetAll {
+    key: Bytes,
+}
+
+impl HGetAll {
+    pub fn new(key: Bytes) -> HGetAll {
+        HGetAll { key }
+    }
+}
+
+impl CommandExec for HGetAll {
+    fn parse(args: &CommandArgs) -> crate::Result<HGetAll> {
+        Ok(HGetAll::new(args.key(1)?))
+    }
+
+    /// Streams the fields and values, as hashes can be big.
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let shard = ctx.db.get_db(ctx.client.db_index).read(&self.key).await;
+            let hash = shard.get_hash(&self.key)?;
+            let now = get_unix_ts_millis();
+
+            let mut reply = ctx.reply_stream(hash.map_or(0, |hash| hash.len(now)) * 2).await?;
+            for (field, val) in hash.iter().flat_map(|hash| hash.iter(now)) {
+                reply.push(Frame::Bulk(Some(field.clone()))).await?;
+                reply.push(Frame::Bulk(Some(val.clone()))).await?;
+            }
+            reply.finish().await?;
+
+            Ok(())
+        })
+    }
+}
+
+/// When the fields given to `HEXPIRE` and the like should expire.
+#[derive(Debug, Clone, Copy)]
+enum FieldExpiry {
+    /// Milliseconds from when the command runs.
+    After(u128),
+    /// A Unix timestamp in milliseconds.
+    At(u128),
+}
+
+impl FieldExpiry {
+    fn timestamp(self) -> u128 {
+        match self {
+            FieldExpiry::After(duration) => get_unix_ts_millis() + duration,
+            FieldExpiry::At(ts) => ts,
+        }
+    }
+}
+
+/// Which fields `HEXPIRE` and the like set the expiry of, by their current
+/// one. Fields without an expiry count as never expiring.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExpireCondition {
+    /// Only fields without an expiry.
+    Nx,
+    /// Only fields with an expiry.
+    Xx,
+    /// Only if the new expiry is later.
+    Gt,
+    /// Only if the new expiry is earlier.
+    Lt,
+}
+
+impl ExpireCondition {
+    fn allows(self, current: Option<u128>, new: u128) -> bool {
+        match (self, current) {
+            (ExpireCondition::Nx, current) => current.is_none(),
+            (ExpireCondition::Xx, current) => current.is_some(),
+            (ExpireCondition::Gt, Some(current)) => new > current,
+            (ExpireCondition::Gt, None) => false,
+            (ExpireCondition::Lt, Some(current)) => new < current,
+            (ExpireCondition::Lt, None) => true,
+        }
+    }
+}
+
+/// What `HEXPIRE` and the like did to a field, the codes of their reply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FieldExpired {
+    NoField = -2,
+    ConditionNotMet = 0,
+    Set = 1,
+    /// The expiry was already in the past, so the field was deleted.
+    Deleted = 2,
+}
+
+/// `HEXPIRE`, `HPEXPIRE`, `HEXPIREAT` and `HPEXPIREAT`.
+#[derive(Debug)]
+pub struct HExpire {
+    key: Bytes,
+    expiry: FieldExpiry,
+    condition: Option<ExpireCondition>,
+    fields: Vec<Bytes>,
+}
+
+impl HExpire {
+    fn new(key: Bytes, expiry: FieldExpiry, condition: Option<ExpireCondition>, fields: Vec<Bytes>) -> HExpire {
+        HExpire { key, expiry, condition, fields }
+    }
+
+    fn execute(&self, shard: &mut Shard, ts: u128) -> crate::Result<Vec<FieldExpired>> {
+        let hash = match shard.get_hash_mut(&self.key)? {
+            Some(hash) => hash,
+            None => return Ok(vec![FieldExpired::NoField; self.fields.len()]),
+        };
+
+        let now = get_unix_ts_millis();
+        let res = self.fields.iter().map(|field| {
+            let current = match hash.expiry(field, now) {
+                Some(current) => current,
+                None => return FieldExpired::NoField,
+            };
+
+            if matches!(self.condition, Some(condition) if !condition.allows(current, ts)) {
+                FieldExpired::ConditionNotMet
+            } else if ts <= now {
+                hash.remove(field);
+                FieldExpired::Deleted
+            } else {
+                hash.set_expiry(field, ts);
+                FieldExpired::Set
+            }
+        }).collect();
+        remove_if_empty(shard, &self.key);
+
+        Ok(res)
+    }
+
+    /// The fields for which the command got `outcome`.
+    fn fields_with(&self, res: &[FieldExpired], outcome: FieldExpired) -> Vec<Bytes> {
+        self.fields.iter().zip(res).filter(|(_, res)| **res == outcome).map(|(field, _)| field.clone()).collect()
+    }
+}
+
+impl CommandExec for HExpire {
+    fn parse(args: &CommandArgs) -> crate::Result<HExpire> {
+        let name = args.name();
+        let multiplier = match name {
+            "hexpire" | "hexpireat" => 1000,
+            _ => 1,
+        };
+
+        let millis = match args.integer(2)? {
+            time if time < 0 => return Err("ERR invalid expire time, must be >= 0".into()),
+            time => time.checked_mul(multiplier).map(|millis| millis as u128),
+        };
+        let expiry = match (millis, name.ends_with("at")) {
+            (Some(millis), true) if millis <= MAX_FIELD_EXPIRY => FieldExpiry::At(millis),
+            (Some(millis), false) if get_unix_ts_millis() + millis <= MAX_FIELD_EXPIRY => FieldExpiry::After(millis),
+            _ => return Err(format!("ERR invalid expire time in '{}' command", name).into()),
+        };
+
+        let condition = match args.string(3)?.to_uppercase().as_str() {
+            "NX" => Some(ExpireCondition::Nx),
+            "XX" => Some(ExpireCondition::Xx),
+            "GT" => Some(ExpireCondition::Gt),
+            "LT" => Some(ExpireCondition::Lt),
+            _ => None,
+        };
+        let fields = parse_fields(args, if condition.is_some() { 4 } else { 3 })?;
+
+        Ok(HExpire::new(args.key(1)?, expiry, condition, fields))
+    }
+
+    /// Replicas get the fields whose expiry was set as an `HPEXPIREAT` with
+    /// the resulting timestamp, and those deleted as an `HDEL`. A command
+    /// only ever does one of the two, all its fields sharing one expiry.
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let ts = self.expiry.timestamp();
+
+            let mut shard = ctx.db.get_db(ctx.client.db_index).lock(&self.key).await;
+            let res = self.execute(&mut shard, ts)?;
+
+            let (set, deleted) = (self.fields_with(&res, FieldExpired::Set), self.fields_with(&res, FieldExpired::Deleted));
+            let propagate = if !set.is_empty() {
+                let mut args = vec![Bytes::from("HPEXPIREAT"), self.key.clone(), Bytes::from(ts.to_string())];
+                args.extend([Bytes::from("FIELDS"), Bytes::from(set.len().to_string())]);
+                args.extend(set);
+                Propagate::Rewrite(Frame::command(args))
+            } else if !deleted.is_empty() {
+                let mut args = vec![Bytes::from("HDEL"), self.key.clone()];
+                args.extend(deleted);
+                Propagate::Rewrite(Frame::command(args))
+            } else {
+                Propagate::None
+            };
+            ctx.propagate(propagate).await?;
+            drop(shard);
+
+            ctx.reply(&Frame::Array(res.into_iter().map(|res| Frame::Integer(res as i64)).collect())).await?;
+
+            Ok(())
+        })
+    }
+
+    fn apply_replica(self: Box<Self>, ctx: &mut ReplicaContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let mut shard = ctx.db.get_db(ctx.db_index).lock(&self.key).await;
+            self.execute(&mut shard, self.expiry.timestamp())?;
+
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct HPersist {
+    key: Bytes,
+    fields: Vec<Bytes>,
+}
+
+impl HPersist {
+    pub fn new(key: Bytes, fields: Vec<Bytes>) -> HPersist {
+        HPersist { key, fields }
+    }
+
+    /// Removes the fields' expiries, replying `1` for each field which had
+    /// one, `-1` for those which didn't and `-2` for missing ones.
+    fn execute(&self, shard: &mut Shard) -> crate::Result<Vec<i64>> {
+        let hash = match shard.get_hash_mut(&self.key)? {
+            Some(hash) => hash,
+            None => return Ok(vec![NO_FIELD; self.fields.len()]),
+        };
+
+        let now = get_unix_ts_millis();
+        Ok(self.fields.iter().map(|field| match hash.expiry(field, now) {
+            None => NO_FIELD,
+            Some(None) => -1,
+            Some(Some(_)) => {
+                hash.persist(field);
+                1
+            },
+        }).collect())
+    }
+}
+
+impl CommandExec for HPersist {
+    fn parse(args: &CommandArgs) -> crate::Result<HPersist> {
+        Ok(HPersist::new(args.key(1)?, parse_fields(args, 2)?))
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let mut shard = ctx.db.get_db(ctx.client.db_index).lock(&self.key).await;
+            let res = self.execute(&mut shard)?;
+
+            ctx.propagate(match res.contains(&1) {
+                true => Propagate::Verbatim,
+                false => Propagate::None,
+            }).await?;
+            drop(shard);
+
+            ctx.reply(&Frame::Array(res.into_iter().map(Frame::Integer).collect())).await?;
+
+            Ok(())
+        })
+    }
+
+    fn apply_replica(self: Box<Self>, ctx: &mut ReplicaContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let mut shard = ctx.db.get_db(ctx.db_index).lock(&self.key).await;
+            self.execute(&mut shard)?;
+
+            Ok(())
+        })
+    }
+}
+
+/// `HTTL` and `HPTTL`.
+#[derive(Debug)]
+pub struct HTtl {
+    key: Bytes,
+    fields: Vec<Bytes>,
+    /// Reply in milliseconds rather than seconds.
+    millis: bool,
+}
+
+impl HTtl {
+    pub fn new(key: Bytes, fields: Vec<Bytes>, millis: bool) -> HTtl {
+        HTtl { key, fields, millis }
+    }
+}
+
+impl CommandExec for HTtl {
+    fn parse(args: &CommandArgs) -> crate::Result<HTtl> {
+        let millis = args.name() == "hpttl";
+
+        Ok(HTtl::new(args.key(1)?, parse_fields(args, 2)?, millis))
+    }
+
+    /// Replies with the time to live of each field, `-1` for fields which
+    /// don't expire and `-2` for missing ones. Seconds are rounded up, like
+    /// Redis does.
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let frame = {
+                let shard = ctx.db.get_db(ctx.client.db_index).read(&self.key).await;
+                let hash = shard.get_hash(&self.key)?;
+                let now = get_unix_ts_millis();
+
+                Frame::Array(self.fields.iter().map(|field| {
+                    let ttl = match hash.and_then(|hash| hash.expiry(field, now)) {
+                        None => NO_FIELD,
+                        Some(None) => -1,
+                        Some(Some(ts)) if self.millis => (ts - now) as i64,
+                        Some(Some(ts)) => ((ts - now + 999) / 1000) as i64,
+                    };
+
+                    Frame::Integer(ttl)
+                }).collect())
+            };
+
+            ctx.reply(&frame).await?;
+
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,196 @@
+use std::borrow::Cow;
+
+use bytes::Bytes;
+
+use crate::Frame;
+use crate::db::{Shard, ShardGuards};
+use crate::hyperloglog;
+use super::{BoxFuture, CommandArgs, CommandContext, CommandExec, Propagate, ReplicaContext};
+
+/// Returns the HyperLogLog at `key`, if any, checking it is one.
+fn get_hyperloglog<'a>(shard: &'a Shard, key: &[u8]) -> crate::Result<Option<Cow<'a, Bytes>>> {
+    match shard.get_string(key)? {
+        Some(val) if !hyperloglog::is_valid(&val) => Err("WRONGTYPE Key is not a valid HyperLogLog string value.".into()),
+        val => Ok(val),
+    }
+}
+
+#[derive(Debug)]
+pub struct PfAdd {
+    key: Bytes,
+    elements: Vec<Bytes>,
+}
+
+impl PfAdd {
+    pub fn new(key: Bytes, elements: Vec<Bytes>) -> PfAdd {
+        PfAdd { key, elements }
+    }
+
+    /// Returns whether the key was created or any register changed.
+    fn execute(&self, shard: &mut Shard) -> crate::Result<bool> {
+        let created = get_hyperloglog(shard, &self.key)?.is_none();
+
+        let changed = shard.modify_string(&self.key, |buf| {
+            if buf.is_empty() {
+                *buf = hyperloglog::new_dense();
+            }
+
+            let mut changed = false;
+            for element in self.elements.iter() {
+                changed |= hyperloglog::add(buf, element);
+            }
+
+            changed
+        })?;
+
+        Ok(created || changed)
+    }
+}
+
+impl CommandExec for PfAdd {
+    fn parse(args: &CommandArgs) -> crate::Result<PfAdd> {
+        let elements = (2..args.len()).map(|idx| args.bytes(idx).cloned()).collect::<crate::Result<_>>()?;
+
+        Ok(PfAdd::new(args.key(1)?, elements))
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let mut shard = ctx.db.get_db(ctx.client.db_index).lock(&self.key).await;
+            let updated = self.execute(&mut shard)?;
+
+            ctx.propagate(if updated { Propagate::Verbatim } else { Propagate::None }).await?;
+            drop(shard);
+
+            ctx.reply(&Frame::Integer(updated as i64)).await?;
+
+            Ok(())
+        })
+    }
+
+    fn apply_replica(self: Box<Self>, ctx: &mut ReplicaContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let mut shard = ctx.db.get_db(ctx.db_index).lock(&self.key).await;
+            self.execute(&mut shard)?;
+
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct PfCount {
+    keys: Vec<Bytes>,
+}
+
+impl PfCount {
+    pub fn new(keys: Vec<Bytes>) -> PfCount {
+        PfCount { keys }
+    }
+}
+
+impl CommandExec for PfCount {
+    fn parse(args: &CommandArgs) -> crate::Result<PfCount> {
+        Ok(PfCount::new(args.keys_from(1)?))
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let keys: Vec<&[u8]> = self.keys.iter().map(|key| &key[..]).collect();
+            let mut guards = ctx.db.get_db(ctx.client.db_index).lock_keys(&keys).await;
+
+            let count = if let [key] = keys[..] {
+                let shard = guards.get_mut(key);
+
+                match get_hyperloglog(shard, key)?.map(|val| hyperloglog::cached_count(&val)) {
+                    None => 0,
+                    Some(Some(count)) => count,
+                    Some(None) => {
+                        // Cache the count in the value, like Redis. Replicas work
+                        // it out again themselves when asked.
+                        shard.modify_string(key, |buf| {
+                            let count = hyperloglog::estimate(&hyperloglog::registers(buf));
+                            hyperloglog::set_cached_count(buf, count);
+
+                            count
+                        })?
+                    },
+                }
+            } else {
+                let mut max = vec![0; hyperloglog::REGISTERS];
+                for key in keys.iter() {
+                    if let Some(val) = get_hyperloglog(guards.get_mut(key), key)? {
+                        hyperloglog::merge(&mut max, &val);
+                    }
+                }
+
+                hyperloglog::estimate(&max)
+            };
+            drop(guards);
+
+            ctx.reply(&Frame::Integer(count as i64)).await?;
+
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct PfMerge {
+    dest: Bytes,
+    keys: Vec<Bytes>,
+}
+
+impl PfMerge {
+    pub fn new(dest: Bytes, keys: Vec<Bytes>) -> PfMerge {
+        PfMerge { dest, keys }
+    }
+
+    fn locked_keys(&self) -> Vec<&[u8]> {
+        std::iter::once(&self.dest).chain(self.keys.iter()).map(|key| &key[..]).collect()
+    }
+
+    fn execute(&self, guards: &mut ShardGuards) -> crate::Result<()> {
+        // The destination is merged in too, so its elements are kept.
+        let mut max = vec![0; hyperloglog::REGISTERS];
+        for key in self.locked_keys() {
+            if let Some(val) = get_hyperloglog(guards.get_mut(key), key)? {
+                hyperloglog::merge(&mut max, &val);
+            }
+        }
+
+        let merged = hyperloglog::from_registers(&max);
+        guards.get_mut(&self.dest).modify_string(&self.dest, |buf| *buf = merged)?;
+
+        Ok(())
+    }
+}
+
+impl CommandExec for PfMerge {
+    fn parse(args: &CommandArgs) -> crate::Result<PfMerge> {
+        Ok(PfMerge::new(args.key(1)?, args.keys_from(2)?))
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let mut guards = ctx.db.get_db(ctx.client.db_index).lock_keys(&self.locked_keys()).await;
+            self.execute(&mut guards)?;
+
+            ctx.propagate(Propagate::Verbatim).await?;
+            drop(guards);
+
+            ctx.reply(&Frame::simple("OK")).await?;
+
+            Ok(())
+        })
+    }
+
+    fn apply_replica(self: Box<Self>, ctx: &mut ReplicaContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let mut guards = ctx.db.get_db(ctx.db_index).lock_keys(&self.locked_keys()).await;
+            self.execute(&mut guards)?;
+
+            Ok(())
+        })
+    }
+}
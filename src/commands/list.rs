@@ -0,0 +1,413 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use bytes::Bytes;
+use tokio::time::Instant;
+
+use crate::db::{Shard, ShardGuards};
+use crate::{BlockedClient, EventClass, Frame, RedisError, Value, Wakeup};
+use super::{BoxFuture, CommandArgs, CommandContext, CommandExec, Propagate, ReplicaContext};
+
+/// The end of a list a command pushes to or pops from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListEnd {
+    Left,
+    Right,
+}
+
+impl ListEnd {
+    /// The end named by the first letter of commands like `LPUSH` and
+    /// `BRPOP`, after the `B` of blocking ones.
+    fn of_command(args: &CommandArgs) -> crate::Result<ListEnd> {
+        Ok(ListEnd::of_name(args.name()))
+    }
+
+    fn of_name(name: &str) -> ListEnd {
+        match name.to_lowercase().trim_start_matches('b').starts_with('l') {
+            true => ListEnd::Left,
+            false => ListEnd::Right,
+        }
+    }
+
+    fn pop(self, list: &mut VecDeque<Bytes>) -> Option<Bytes> {
+        match self {
+            ListEnd::Left => list.pop_front(),
+            ListEnd::Right => list.pop_back(),
+        }
+    }
+
+    fn pop_command(self) -> &'static str {
+        match self {
+            ListEnd::Left => "LPOP",
+            ListEnd::Right => "RPOP",
+        }
+    }
+
+    /// Keyspace events of pushing and popping at this end.
+    fn push_event(self) -> &'static str {
+        match self {
+            ListEnd::Left => "lpush",
+            ListEnd::Right => "rpush",
+        }
+    }
+
+    fn pop_event(self) -> &'static str {
+        match self {
+            ListEnd::Left => "lpop",
+            ListEnd::Right => "rpop",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Push {
+    key: Bytes,
+    end: ListEnd,
+    values: Vec<Bytes>,
+}
+
+impl Push {
+    pub fn new(key: Bytes, end: ListEnd, values: Vec<Bytes>) -> Push {
+        Push { key, end, values }
+    }
+
+    fn execute(&self, shard: &mut Shard) -> crate::Result<usize> {
+        if shard.get_list_mut(&self.key)?.is_none() {
+            shard.insert(self.key.clone(), Value::List(VecDeque::new()), None);
+        }
+
+        let list = shard.get_list_mut(&self.key)?.expect("key exists");
+        for value in self.values.iter() {
+            match self.end {
+                ListEnd::Left => list.push_front(value.clone()),
+                ListEnd::Right => list.push_back(value.clone()),
+            }
+        }
+
+        Ok(list.len())
+    }
+}
+
+impl CommandExec for Push {
+    fn parse(args: &CommandArgs) -> crate::Result<Push> {
+        let values = (2..args.len()).map(|idx| args.bytes(idx).cloned()).collect::<crate::Result<_>>()?;
+
+        Ok(Push::new(args.key(1)?, ListEnd::of_command(args)?, values))
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let mut shard = ctx.db.get_db(ctx.client.db_index).lock(&self.key).await;
+            let len = self.execute(&mut shard)?;
+            ctx.notify(EventClass::List, self.end.push_event(), &self.key);
+
+            // Clients blocked on the key are served right away, with the
+            // shard still locked, and replicas get their pops after the push.
+            let mut served = Vec::new();
+            ctx.db.get_blocking().serve(ctx.client.db_index, &self.key, |command| {
+                let end = ListEnd::of_name(command);
+                let value = pop(&mut shard, &self.key, end, 1).ok().flatten()?.pop()?;
+                notify_pop(&shard, &self.key, end, |class, event| ctx.notify(class, event, &self.key));
+                served.push(end);
+                Some(value)
+            });
+
+            ctx.propagate(Propagate::Verbatim).await?;
+            for end in served {
+                ctx.propagate(Propagate::Rewrite(Frame::command([end.pop_command().as_bytes(), &self.key]))).await?;
+            }
+            drop(shard);
+
+            ctx.reply(&Frame::Integer(len as i64)).await?;
+
+            Ok(())
+        })
+    }
+
+    fn apply_replica(self: Box<Self>, ctx: &mut ReplicaContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let mut shard = ctx.db.get_db(ctx.db_index).lock(&self.key).await;
+            self.execute(&mut shard)?;
+            ctx.notify(EventClass::List, self.end.push_event(), &self.key);
+            ctx.db.get_blocking().signal(ctx.db_index, &self.key);
+
+            Ok(())
+        })
+    }
+}
+
+/// Pops up to `count` elements off the list at `key`, deleting it once
+/// it's empty.
+fn pop(shard: &mut Shard, key: &[u8], end: ListEnd, count: usize) -> crate::Result<Option<Vec<Bytes>>> {
+    let list = match shard.get_list_mut(key)? {
+        Some(list) => list,
+        None => return Ok(None),
+    };
+
+    let popped = (0..count).map_while(|_| end.pop(list)).collect();
+    if list.is_empty() {
+        shard.remove(key);
+    }
+
+    Ok(Some(popped))
+}
+
+#[derive(Debug)]
+pub struct LRange {
+    key: Bytes,
+    start: i64,
+    stop: i64,
+}
+
+impl LRange {
+    pub fn new(key: Bytes, start: i64, stop: i64) -> LRange {
+        LRange { key, start, stop }
+    }
+}
+
+impl CommandExec for LRange {
+    fn parse(args: &CommandArgs) -> crate::Result<LRange> {
+        Ok(LRange::new(args.key(1)?, args.integer(2)?, args.integer(3)?))
+    }
+
+    /// Streams the elements from `start` to `stop`, both included, counting
+    /// from the end for negative indexes.
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let shard = ctx.db.get_db(ctx.client.db_index).read(&self.key).await;
+            let list = shard.get_list(&self.key)?;
+            let len = list.map_or(0, VecDeque::len) as i64;
+
+            let start = match self.start {
+                start if start < 0 => (start + len).max(0),
+                start => start,
+            };
+            let stop = match self.stop {
+                stop if stop < 0 => stop + len,
+                stop => stop.min(len - 1),
+            };
+            let count = match start <= stop {
+                true => (stop - start + 1) as usize,
+                false => 0,
+            };
+
+            let mut reply = ctx.reply_stream(count).await?;
+            for value in list.iter().flat_map(|list| list.iter().skip(start as usize).take(count)) {
+                reply.push(Frame::Bulk(Some(value.clone()))).await?;
+            }
+            reply.finish().await?;
+
+            Ok(())
+        })
+    }
+}
+
+/// Publishes the keyspace events of popping off the list at `key`, which
+/// is deleted once it's empty.
+fn notify_pop(shard: &Shard, key: &[u8], end: ListEnd, notify: impl Fn(EventClass, &str)) {
+    notify(EventClass::List, end.pop_event());
+    if shard.get(key).is_none() {
+        notify(EventClass::Generic, "del");
+    }
+}
+
+#[derive(Debug)]
+pub struct Pop {
+    key: Bytes,
+    end: ListEnd,
+    /// None without the count argument, which replies with a single element
+    /// rather than an array.
+    count: Option<usize>,
+}
+
+impl Pop {
+    pub fn new(key: Bytes, end: ListEnd, count: Option<usize>) -> Pop {
+        Pop { key, end, count }
+    }
+}
+
+impl CommandExec for Pop {
+    fn parse(args: &CommandArgs) -> crate::Result<Pop> {
+        let count = match args.len() {
+            2 => None,
+            3 => Some(args.integer_in(2, 0.., "ERR value is out of range, must be positive")? as usize),
+            _ => return Err(RedisError::Syntax),
+        };
+
+        Ok(Pop::new(args.key(1)?, ListEnd::of_command(args)?, count))
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let mut shard = ctx.db.get_db(ctx.client.db_index).lock(&self.key).await;
+            let popped = pop(&mut shard, &self.key, self.end, self.count.unwrap_or(1))?;
+
+            if matches!(&popped, Some(popped) if !popped.is_empty()) {
+                notify_pop(&shard, &self.key, self.end, |class, event| ctx.notify(class, event, &self.key));
+            }
+            ctx.propagate(match &popped {
+                Some(popped) if !popped.is_empty() => Propagate::Verbatim,
+                _ => Propagate::None,
+            }).await?;
+            drop(shard);
+
+            let frame = match (popped, self.count) {
+                (None, _) => Frame::Null,
+                (Some(mut popped), None) => Frame::Bulk(popped.pop()),
+                (Some(popped), Some(_)) => Frame::Array(popped.into_iter().map(|value| Frame::Bulk(Some(value))).collect()),
+            };
+            ctx.reply(&frame).await?;
+
+            Ok(())
+        })
+    }
+
+    fn apply_replica(self: Box<Self>, ctx: &mut ReplicaContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let mut shard = ctx.db.get_db(ctx.db_index).lock(&self.key).await;
+            let popped = pop(&mut shard, &self.key, self.end, self.count.unwrap_or(1))?;
+
+            if matches!(&popped, Some(popped) if !popped.is_empty()) {
+                notify_pop(&shard, &self.key, self.end, |class, event| ctx.notify(class, event, &self.key));
+            }
+
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct BlockingPop {
+    keys: Vec<Bytes>,
+    end: ListEnd,
+    /// None to wait for as long as it takes.
+    timeout: Option<Duration>,
+}
+
+impl BlockingPop {
+    pub fn new(keys: Vec<Bytes>, end: ListEnd, timeout: Option<Duration>) -> BlockingPop {
+        BlockingPop { keys, end, timeout }
+    }
+
+    /// Pops an element off the first non-empty list among the keys.
+    /// Pops off the first of the keys holding a list. A key holding
+    /// anything else fails the command, unless the client was `woken`:
+    /// it's only served by lists, so it keeps waiting instead.
+    fn pop_first(&self, shards: &mut ShardGuards, woken: bool) -> crate::Result<Option<(Bytes, Bytes)>> {
+        for key in self.keys.iter() {
+            let popped = match pop(shards.get_mut(key), key, self.end, 1) {
+                Err(RedisError::WrongType) if woken => continue,
+                res => res?,
+            };
+            if let Some(mut popped) = popped {
+                if let Some(value) = popped.pop() {
+                    return Ok(Some((key.clone(), value)));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/// Parses the timeout of a blocking command, in seconds. Zero means no
+/// timeout.
+pub(crate) fn parse_timeout(arg: &str) -> crate::Result<Option<Duration>> {
+    let timeout = match arg.parse::<f64>() {
+        Ok(timeout) if timeout.is_finite() => timeout,
+        _ => return Err("ERR timeout is not a float or out of range".into()),
+    };
+
+    if timeout < 0.0 {
+        return Err("ERR timeout is negative".into());
+    }
+    // Like Redis, the timeout must fit a 64-bit count of milliseconds.
+    if timeout * 1000.0 >= i64::MAX as f64 {
+        return Err("ERR timeout is out of range".into());
+    }
+
+    match timeout == 0.0 {
+        true => Ok(None),
+        false => Ok(Some(Duration::from_secs_f64(timeout))),
+    }
+}
+
+impl CommandExec for BlockingPop {
+    fn parse(args: &CommandArgs) -> crate::Result<BlockingPop> {
+        let keys = args.keys_from(1)?[..args.len() - 2].to_vec();
+        let timeout = parse_timeout(&args.string(args.len() - 1)?)?;
+
+        Ok(BlockingPop::new(keys, ListEnd::of_command(args)?, timeout))
+    }
+
+    /// Pops right away if one of the lists has an element. Otherwise the
+    /// client blocks until one of the keys is pushed to, or the timeout
+    /// passes. Replicas get the pop as a plain `LPOP` or `RPOP`.
+    ///
+    /// A push serves blocked clients itself, popping for them in the order
+    /// they blocked, see `Blocking`, and the client just replies with what
+    /// it was served. Other writes only wake it up to look again.
+    ///
+    /// The deadline is set once, so waking up to find the lists emptied by
+    /// another client doesn't push it back. Once it passes, the lists are
+    /// looked at one last time under their locks: an element that arrived
+    /// just as the client timed out is still served, like redis-server,
+    /// which serves blocked clients before handling timeouts.
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+            let db = ctx.db.clone();
+            let db_index = ctx.client.db_index;
+            let mut blocked = None;
+            let mut timed_out = false;
+
+            loop {
+                {
+                    // Inside `EXEC` the transaction lock is held already.
+                    let _guard = match ctx.in_transaction() {
+                        true => None,
+                        false => Some(db.lock_for_command().await),
+                    };
+                    let keys: Vec<&[u8]> = self.keys.iter().map(|key| &key[..]).collect();
+                    let mut shards = db.get_db(db_index).lock_keys(&keys).await;
+
+                    // Served by a push, which popped and propagated already.
+                    if let Some((key, value)) = blocked.as_ref().and_then(BlockedClient::served) {
+                        drop(shards);
+                        ctx.reply(&Frame::Array(vec![Frame::bulk(key), Frame::Bulk(Some(value))])).await?;
+                        return Ok(());
+                    }
+
+                    if let Some((key, value)) = self.pop_first(&mut shards, blocked.is_some())? {
+                        notify_pop(shards.get_mut(&key), &key, self.end, |class, event| ctx.notify(class, event, &key));
+                        ctx.propagate(Propagate::Rewrite(Frame::command([self.end.pop_command().as_bytes(), &key]))).await?;
+                        drop(shards);
+
+                        ctx.reply(&Frame::Array(vec![Frame::bulk(key), Frame::Bulk(Some(value))])).await?;
+                        return Ok(());
+                    }
+
+                    // Transactions never block, they just find nothing.
+                    if ctx.in_transaction() || timed_out {
+                        drop(shards);
+                        ctx.reply(&Frame::Null).await?;
+                        return Ok(());
+                    }
+
+                    // Registered while the keys are still locked, so a push
+                    // right after can't go unnoticed.
+                    if blocked.is_none() {
+                        let command = String::from_utf8_lossy(&ctx.args[0]).to_lowercase();
+                        blocked = Some(db.get_blocking().block(ctx.client.id, &command, db_index, &self.keys));
+                    }
+                }
+
+                match blocked.as_ref().expect("client is blocked").wait(deadline).await {
+                    Wakeup::Ready => continue,
+                    Wakeup::Timeout => timed_out = true,
+                    Wakeup::Unblocked => return Ok(()),
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,654 @@
+//! Commands, one `CommandExec` implementation each, grouped in modules like
+//! the command groups of `COMMAND DOCS`. A command is added by implementing
+//! the trait and registering it in `command_table::COMMAND_TABLE`, which
+//! also holds its arity, flags and key positions.
+
+use std::borrow::Cow;
+use std::future::Future;
+use std::ops::RangeBounds;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex as StdMutex;
+
+use bytes::Bytes;
+
+use crate::command_table::{CommandFlag, CommandNames, CommandSpec};
+use crate::db::Shard;
+use crate::notify;
+use crate::value;
+use crate::{debug, ClientState, Connection, ConnectionManager, EventClass, Frame, LongOperation, RedisError, SharedRedisState, Value};
+
+pub(crate) mod bitmap;
+pub(crate) mod cluster;
+pub(crate) mod connection;
+pub(crate) mod generic;
+pub(crate) mod geo;
+pub(crate) mod hash;
+pub(crate) mod hyperloglog;
+pub(crate) mod list;
+pub(crate) mod pubsub;
+pub(crate) mod replication;
+pub(crate) mod scripting;
+pub(crate) mod server;
+pub(crate) mod set;
+pub(crate) mod string;
+pub(crate) mod transactions;
+
+static REPLY_CHUNK_SIZE: AtomicUsize = AtomicUsize::new(1024);
+
+/// Applies the `reply-stream-chunk-size` setting to every `ReplyStream`.
+pub fn configure(reply_chunk_size: usize) {
+    REPLY_CHUNK_SIZE.store(reply_chunk_size.max(1), Ordering::Relaxed);
+}
+
+/// A future returned by a command, borrowing the context it runs in.
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// What a write sends to its replicas.
+#[derive(Debug)]
+pub enum Propagate {
+    /// Nothing, for writes that turned out not to change anything.
+    None,
+    /// The command as the client sent it.
+    Verbatim,
+    /// An equivalent command with the same effect wherever it runs, for
+    /// commands that depend on the current time or on randomness, or that
+    /// can leave out parts not writing anything.
+    Rewrite(Frame),
+}
+
+/// What a command storing its result at a destination key did to it, like
+/// `BITOP` or `SORT ... STORE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StoreOutcome {
+    Stored,
+    /// The result was empty, so the destination was deleted, if it existed.
+    Deleted { existed: bool },
+}
+
+/// Stores the result of a command at `dest`, or, as Redis does, deletes
+/// `dest` if there's no result, e.g. an empty list. Either way the write
+/// goes through the shard, so clients watching `dest` see it modified.
+///
+/// Commands then pass what happened to `CommandContext::stored`, or to
+/// `ReplicaContext::stored` when applying it from the master.
+pub(super) fn store_result_or_delete(shard: &mut Shard, dest: &Bytes, result: Option<Value>) -> StoreOutcome {
+    match result {
+        Some(value) => {
+            shard.insert(dest.clone(), value, None);
+            StoreOutcome::Stored
+        },
+        None => {
+            let existed = shard.peek(dest).is_some();
+            shard.remove(dest);
+            StoreOutcome::Deleted { existed }
+        },
+    }
+}
+
+/// What the commands of a transaction reply and propagate, collected while
+/// `EXEC` runs them.
+#[derive(Debug, Default)]
+pub struct TransactionOutput {
+    pub replies: Vec<Frame>,
+    /// Writes to send to the replicas, with the database of each.
+    pub writes: Vec<(usize, Frame)>,
+}
+
+/// Everything a client's command runs against: its connection, the server
+/// state and the client's own state. Lives as long as the connection.
+///
+/// A write lets everyone else know about it in a fixed order. With the keys
+/// it wrote still locked, it first calls `notify` for its keyspace events
+/// and signals blocked clients, then `propagate`s itself. Only then does it
+/// release the keys and `reply`. An observer therefore never sees a state
+/// older than what the client's reply implies, and replicas get writes to
+/// a key in the order they were applied.
+pub struct CommandContext {
+    pub addr: String,
+    pub db: SharedRedisState,
+    pub conn_manager: ConnectionManager,
+    pub client: ClientState,
+    /// The arguments of the command running, name included.
+    pub args: Vec<Bytes>,
+    /// Set while `EXEC` runs a transaction, taking the replies and writes
+    /// of its commands instead of the client and the replicas.
+    pub transaction_output: StdMutex<Option<TransactionOutput>>,
+}
+
+impl CommandContext {
+    pub fn new(addr: String, db: SharedRedisState, conn_manager: ConnectionManager) -> Self {
+        let mut client = ClientState::new();
+        client.user = db.get_acl().initial_user();
+
+        Self { addr, db, conn_manager, client, args: vec![], transaction_output: StdMutex::new(None) }
+    }
+
+    /// Queues a reply to the client, sent once the client has no more
+    /// commands waiting to run.
+    pub async fn reply(&self, frame: &Frame) -> crate::Result<()> {
+        if let Some(output) = self.transaction_output.lock().unwrap_or_else(|err| err.into_inner()).as_mut() {
+            output.replies.push(frame.clone());
+            return Ok(());
+        }
+
+        self.conn_manager.queue_frame(&self.addr, frame).await?;
+
+        Ok(())
+    }
+
+    /// Starts replying with an array of `len` elements, which are then
+    /// pushed one by one. See `ReplyStream`.
+    pub async fn reply_stream(&self, len: usize) -> crate::Result<ReplyStream<'_>> {
+        let buffered = self.in_transaction();
+        if !buffered {
+            self.conn_manager.queue_array_header(&self.addr, len).await?;
+        }
+
+        Ok(ReplyStream { ctx: self, chunk: Vec::with_capacity(len.min(REPLY_CHUNK_SIZE.load(Ordering::Relaxed))), buffered, remaining: len })
+    }
+
+    /// Whether the command runs as part of a transaction, where blocking
+    /// commands don't block.
+    pub fn in_transaction(&self) -> bool {
+        self.transaction_output.lock().unwrap_or_else(|err| err.into_inner()).is_some()
+    }
+
+    /// Registers the command as a long operation, for as long as the result
+    /// lives. Commands that may run for a while call this before starting,
+    /// and `checkpoint` on the result every now and then, returning its
+    /// error as it is once `SCRIPT KILL` stopped them. They should only have
+    /// written anything after their last checkpoint.
+    pub fn long_operation(&self) -> LongOperation<'_> {
+        self.db.get_busy().start(self.client.id)
+    }
+
+    /// Publishes a keyspace notification about `key` of the client's
+    /// database, if `notify-keyspace-events` enables it. Subscribers get it
+    /// queued right away, before the keys are released.
+    pub fn notify(&self, class: EventClass, event: &str, key: &[u8]) {
+        notify::keyspace_event(self.db.get_pubsub(), class, event, key, self.client.db_index);
+    }
+
+    /// Sends a write to the replicas, as applied to the client's database.
+    /// Called with the written keys still locked, so replicas get writes in
+    /// the order they were applied.
+    pub async fn propagate(&self, propagate: Propagate) -> crate::Result<()> {
+        let frame = match propagate {
+            Propagate::None => return Ok(()),
+            Propagate::Verbatim => Frame::command(&self.args),
+            Propagate::Rewrite(frame) => frame,
+        };
+
+        if let Some(output) = self.transaction_output.lock().unwrap_or_else(|err| err.into_inner()).as_mut() {
+            output.writes.push((self.client.db_index, frame));
+            return Ok(());
+        }
+
+        self.db.get_replication_state().propagate(&self.conn_manager, self.client.db_index, &frame).await
+    }
+
+    /// Publishes the keyspace event of a command which stored its result
+    /// at `dest`, `event` of `class` or `del` if it deleted it, and sends it
+    /// to the replicas.
+    ///
+    /// A stored result goes out as the command itself. An empty one goes out
+    /// as a `DEL` of the destination, whether or not it existed here, so
+    /// replicas drop it even if their sources differ.
+    pub async fn stored(&self, dest: &[u8], outcome: StoreOutcome, class: EventClass, event: &str) -> crate::Result<()> {
+        match outcome {
+            StoreOutcome::Stored => {
+                self.notify(class, event, dest);
+                self.propagate(Propagate::Verbatim).await
+            },
+            StoreOutcome::Deleted { existed } => {
+                if existed {
+                    self.notify(EventClass::Generic, "del", dest);
+                }
+                self.propagate(Propagate::Rewrite(Frame::command(["DEL".as_bytes(), dest]))).await
+            },
+        }
+    }
+}
+
+/// An array reply written a chunk of elements at a time, for replies as big
+/// as the collection they list. Only a chunk of elements is ever held on top
+/// of the data itself, rather than the whole reply before the first byte of
+/// it is written. What goes out is the same as replying with the array.
+///
+/// The length is sent first, so the command must know it, and must have
+/// checked everything that could fail before starting the reply. Commands
+/// stream with their keys still locked, the elements being borrowed from
+/// them. Inside a transaction the elements are collected into a regular
+/// reply instead.
+pub struct ReplyStream<'a> {
+    ctx: &'a CommandContext,
+    chunk: Vec<Frame>,
+    buffered: bool,
+    remaining: usize,
+}
+
+impl ReplyStream<'_> {
+    /// Adds the next element of the reply.
+    pub async fn push(&mut self, frame: Frame) -> crate::Result<()> {
+        debug_assert!(self.remaining > 0, "more elements than announced");
+        self.remaining -= 1;
+        self.chunk.push(frame);
+
+        if !self.buffered && self.chunk.len() >= REPLY_CHUNK_SIZE.load(Ordering::Relaxed) {
+            self.ctx.conn_manager.queue_frames(&self.ctx.addr, &self.chunk).await?;
+            self.chunk.clear();
+        }
+
+        Ok(())
+    }
+
+    /// Writes what's left of the reply, which must have had every element
+    /// announced pushed by now.
+    pub async fn finish(self) -> crate::Result<()> {
+        debug_assert!(self.remaining == 0, "fewer elements than announced");
+
+        match self.buffered {
+            true => self.ctx.reply(&Frame::Array(self.chunk)).await,
+            false => Ok(self.ctx.conn_manager.queue_frames(&self.ctx.addr, &self.chunk).await?),
+        }
+    }
+}
+
+/// What commands received from the master run against on a replica.
+pub struct ReplicaContext {
+    pub db: SharedRedisState,
+    /// Database the master's commands apply to, changed by `SELECT`.
+    pub db_index: usize,
+    /// Connection to the master, for the commands it expects an answer to.
+    pub master: Connection,
+    /// Replication offset before the command being applied, which is what
+    /// `REPLCONF GETACK` reports.
+    pub offset: u64,
+    /// Commands received after `MULTI`, applied together on `EXEC`. Lost
+    /// along with the context if the link drops before then.
+    pub transaction: Option<Vec<Command>>,
+}
+
+impl ReplicaContext {
+    pub fn new(db: SharedRedisState, master: Connection) -> Self {
+        Self { db, db_index: 0, master, offset: 0, transaction: None }
+    }
+
+    /// See `CommandContext::notify`. Replicas publish the events of the
+    /// writes they apply too.
+    pub fn notify(&self, class: EventClass, event: &str, key: &[u8]) {
+        notify::keyspace_event(self.db.get_pubsub(), class, event, key, self.db_index);
+    }
+
+    /// See `CommandContext::stored`, without the propagation.
+    pub fn stored(&self, dest: &[u8], outcome: StoreOutcome, class: EventClass, event: &str) {
+        match outcome {
+            StoreOutcome::Stored => self.notify(class, event, dest),
+            StoreOutcome::Deleted { existed: true } => self.notify(EventClass::Generic, "del", dest),
+            StoreOutcome::Deleted { existed: false } => {},
+        }
+    }
+}
+
+pub trait CommandExec: std::fmt::Debug + Send + Sync + 'static {
+    /// Parses the command from its arguments, whose number already matches
+    /// the arity in the command table.
+    fn parse(args: &CommandArgs) -> crate::Result<Self> where Self: Sized;
+
+    /// Runs the command and writes its reply. An error that isn't an I/O
+    /// error means the command failed, and is sent to the client as an error
+    /// reply with the connection staying open. I/O errors mean the
+    /// connection itself is gone.
+    ///
+    /// A command that fails must not have changed anything. Writes check the
+    /// arguments and the types of every key they involve, sources included,
+    /// before touching any of them, so an error never follows a partial
+    /// write that replicas wouldn't get.
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>>;
+
+    /// Runs the command as received from the master, without replying.
+    /// Only commands the master propagates need to implement this.
+    fn apply_replica(self: Box<Self>, _ctx: &mut ReplicaContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            debug!("Ignoring command from master: {:?}", self);
+
+            Ok(())
+        })
+    }
+}
+
+/// Parses a command into its implementation. Used for the `parse` entries
+/// of the command table.
+pub fn parse<C: CommandExec>(args: &CommandArgs) -> crate::Result<Box<dyn CommandExec>> {
+    Ok(Box::new(C::parse(args)?))
+}
+
+#[derive(Debug)]
+pub struct Unknown {
+    name: String,
+    args: Vec<String>,
+}
+
+impl Unknown {
+    pub fn new(name: String, args: Vec<String>) -> Unknown {
+        Unknown { name, args }
+    }
+}
+
+impl CommandExec for Unknown {
+    fn parse(args: &CommandArgs) -> crate::Result<Unknown> {
+        let name = String::from_utf8_lossy(args.bytes(0)?).to_string();
+        let args = args.array.iter().skip(1).map(|arg| match arg {
+            Frame::Bulk(Some(bytes)) => String::from_utf8_lossy(bytes).to_string(),
+            frame => format!("{:?}", frame),
+        }).collect();
+
+        Ok(Unknown::new(name, args))
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            // Same format as redis-server, quoting the first 128 characters of
+            // arguments.
+            let mut args = String::new();
+            for arg in self.args.iter() {
+                if args.len() >= 128 {
+                    break;
+                }
+                let arg: String = arg.chars().take(128 - args.len()).collect();
+                args.push_str(&format!("'{}' ", arg));
+            }
+
+            let name: String = self.name.chars().take(128).collect();
+            let message = format!("ERR unknown command '{}', with args beginning with: {}", name, args);
+            ctx.reply(&RedisError::Reply(message).to_frame()).await?;
+
+            Ok(())
+        })
+    }
+}
+
+/// Checked access to the arguments of a command, so parsers never index past
+/// the end of the frame or assume an argument is a bulk string.
+pub struct CommandArgs<'a> {
+    name: &'a str,
+    array: &'a [Frame],
+}
+
+impl<'a> CommandArgs<'a> {
+    fn new(name: &'a str, array: &'a [Frame]) -> Self {
+        Self { name, array }
+    }
+
+    /// The lowercase name of the command, as in the command table. Parsers
+    /// shared by several commands go by this rather than the first argument,
+    /// which is whatever the client called the command, renamed or not.
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// Number of arguments, including the command name.
+    pub fn len(&self) -> usize {
+        self.array.len()
+    }
+
+    /// The wrong number of arguments error, for parsers checking more than
+    /// the arity in the command table does.
+    pub fn wrong_arity(&self) -> RedisError {
+        RedisError::wrong_arity(self.name)
+    }
+
+    pub fn bytes(&self, idx: usize) -> crate::Result<&'a Bytes> {
+        match self.array.get(idx) {
+            Some(Frame::Bulk(Some(bytes))) => Ok(bytes),
+            Some(frame) => Err(RedisError::Protocol(format!("expected bulk string argument for '{}', got {:?}", self.name, frame))),
+            None => Err(self.wrong_arity()),
+        }
+    }
+
+    pub fn string(&self, idx: usize) -> crate::Result<String> {
+        Ok(String::from_utf8(self.bytes(idx)?.to_vec())?)
+    }
+
+    /// The integer at `idx`. Like Redis, only integers written the canonical
+    /// way are taken, without a `+` sign or leading zeros.
+    pub fn integer(&self, idx: usize) -> crate::Result<i64> {
+        value::parse_integer(self.bytes(idx)?).ok_or(RedisError::NotAnInteger)
+    }
+
+    /// The integer at `idx`, failing with `err` if it isn't one or is out
+    /// of `range`, for arguments Redis gives their own error.
+    pub fn integer_in(&self, idx: usize, range: impl RangeBounds<i64>, err: &str) -> crate::Result<i64> {
+        match value::parse_integer(self.bytes(idx)?) {
+            Some(int) if range.contains(&int) => Ok(int),
+            _ => Err(err.into()),
+        }
+    }
+
+    /// The key at `idx`. Keys are binary safe, so they're kept as bytes.
+    pub fn key(&self, idx: usize) -> crate::Result<Bytes> {
+        Ok(self.bytes(idx)?.clone())
+    }
+
+    /// All arguments starting at `idx` as keys.
+    pub fn keys_from(&self, idx: usize) -> crate::Result<Vec<Bytes>> {
+        (idx..self.len()).map(|idx| self.key(idx)).collect()
+    }
+
+    /// All arguments starting at `idx` as strings.
+    pub fn strings_from(&self, idx: usize) -> crate::Result<Vec<String>> {
+        (idx..self.len()).map(|idx| self.string(idx)).collect()
+    }
+}
+
+/// The commands a client may still send once it subscribed to a channel.
+const SUBSCRIBER_COMMANDS: &[&str] = &["subscribe", "unsubscribe", "psubscribe", "punsubscribe", "ssubscribe", "sunsubscribe", "ping", "quit", "reset"];
+
+/// The commands run right away rather than queued inside a transaction.
+const TRANSACTION_COMMANDS: &[&str] = &["multi", "exec", "discard", "watch", "quit", "reset"];
+
+/// The commands a client may send before it authenticated, which no user
+/// can be denied either.
+const NOAUTH_COMMANDS: &[&str] = &["auth", "hello"];
+
+/// The commands still run while another client's long operation keeps the
+/// server busy.
+const BUSY_COMMANDS: &[&str] = &["auth", "hello", "replconf", "multi", "discard", "reset", "script"];
+
+/// A parsed command, ready to run.
+#[derive(Debug)]
+pub struct Command {
+    args: Vec<Bytes>,
+    spec: Option<&'static CommandSpec>,
+    exec: Box<dyn CommandExec>,
+}
+
+impl Command {
+    /// Parses a command, looking it up by the names clients call commands;
+    /// see `CommandNames`. A renamed command's arguments are given its
+    /// original name, which is what it's checked, logged and propagated as.
+    pub fn from_frame(frame: Frame, names: &CommandNames) -> crate::Result<Command> {
+        let mut args = frame.to_args();
+        let array = match frame {
+            Frame::Array(array) => array,
+            frame => return Err(RedisError::Protocol(format!("expected an array as command, got {:?}", frame))),
+        };
+
+        let command_name = match array.first() {
+            Some(Frame::Bulk(Some(bytes))) => String::from_utf8_lossy(bytes),
+            Some(frame) => return Err(RedisError::Protocol(format!("expected bulk string command name, got {:?}", frame))),
+            None => return Err("ERR unknown command ''".into()),
+        };
+
+        let spec = match names.lookup(&command_name.to_ascii_lowercase()) {
+            Some(spec) => spec,
+            None => return Ok(Command { args, spec: None, exec: Box::new(Unknown::parse(&CommandArgs::new(&command_name, &array))?) }),
+        };
+        if !command_name.eq_ignore_ascii_case(spec.name) {
+            args[0] = Bytes::from_static(spec.name.as_bytes());
+        }
+
+        if !spec.check_arity(array.len()) {
+            return Err(RedisError::wrong_arity(spec.name));
+        }
+
+        let exec = (spec.parse)(&CommandArgs::new(spec.name, &array))?;
+
+        Ok(Command { args, spec: Some(spec), exec })
+    }
+
+    /// Runs the command for a client; see `CommandExec::apply`.
+    pub async fn apply(self, ctx: &mut CommandContext) -> crate::Result<()> {
+        let name = self.name();
+
+        // A command refused while queueing fails the transaction, like one
+        // that couldn't be parsed.
+        if let Err(err) = self.check(ctx, &name) {
+            ctx.client.fail_transaction();
+            return Err(err);
+        }
+
+        if ctx.client.transaction.is_some() && !TRANSACTION_COMMANDS.contains(&name.as_ref()) {
+            return self.queue(ctx).await;
+        }
+
+        // Replicas are never paused, and neither is CLIENT, so a pause can be
+        // lifted early.
+        if !ctx.client.replica && name != "client" {
+            ctx.db.get_pause().wait(self.may_write(ctx)).await;
+        }
+
+        let db = ctx.db.clone();
+        let _guard = match self.accesses_keys() {
+            true => Some(db.lock_for_command().await),
+            false => None,
+        };
+
+        self.run(ctx).await
+    }
+
+    /// The lowercase name of the command, borrowed from its spec unless it's
+    /// unknown.
+    fn name(&self) -> Cow<'static, str> {
+        match self.spec {
+            Some(spec) => Cow::Borrowed(spec.name),
+            None => Cow::Owned(String::from_utf8_lossy(&self.args[0]).to_lowercase()),
+        }
+    }
+
+    /// The keys the command names, none if it's unknown.
+    pub fn keys(&self) -> Vec<&[u8]> {
+        match self.spec {
+            Some(spec) => spec.extract_keys(&self.args).into_iter().map(|key| &key[..]).collect(),
+            None => vec![],
+        }
+    }
+
+    /// Runs the command without any of the checks of `apply`, e.g. as part of
+    /// a transaction.
+    pub(crate) async fn run(self, ctx: &mut CommandContext) -> crate::Result<()> {
+        ctx.args = self.args;
+        self.exec.apply(ctx).await
+    }
+
+    /// Adds the command to the client's transaction. Unknown commands reply
+    /// with their error right away, and fail the transaction.
+    async fn queue(self, ctx: &mut CommandContext) -> crate::Result<()> {
+        if self.spec.is_none() {
+            ctx.client.fail_transaction();
+            return self.run(ctx).await;
+        }
+
+        if let Some(transaction) = ctx.client.transaction.as_mut() {
+            transaction.commands.push(self);
+        }
+        ctx.reply(&Frame::simple("QUEUED")).await
+    }
+
+    /// Whether the command may write, for `CLIENT PAUSE WRITE`. `EXEC` does
+    /// if any of the commands it runs does.
+    fn may_write(&self, ctx: &CommandContext) -> bool {
+        let writes = |command: &Command| matches!(command.spec, Some(spec) if spec.has_flag(CommandFlag::Write));
+
+        match &ctx.client.transaction {
+            Some(transaction) if matches!(self.spec, Some(spec) if spec.name == "exec") => transaction.commands.iter().any(writes),
+            _ => writes(self),
+        }
+    }
+
+    /// Whether the command holds the transaction lock while it runs.
+    /// Blocking commands take it themselves, only while they look at their
+    /// keys, as holding it while they wait would hold up every transaction.
+    fn accesses_keys(&self) -> bool {
+        matches!(self.spec, Some(spec) if (spec.has_flag(CommandFlag::Write) || spec.has_flag(CommandFlag::Readonly)) && !spec.has_flag(CommandFlag::Blocking))
+    }
+
+    fn check(&self, ctx: &CommandContext, name: &str) -> crate::Result<()> {
+        self.check_permissions(ctx, name)?;
+
+        // A replica only takes writes from its master, whose commands are
+        // applied through `apply_replica` rather than checked here, so that
+        // it never drifts away from it.
+        if matches!(self.spec, Some(spec) if spec.has_flag(CommandFlag::Write)) && ctx.db.get_replication_state().get_role() == "slave" {
+            return Err(RedisError::ReadOnly);
+        }
+
+        if ctx.client.is_subscribed() && !SUBSCRIBER_COMMANDS.contains(&name) {
+            return Err(format!(
+                "ERR Can't execute '{}': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT / RESET are allowed in this context",
+                name,
+            ).into());
+        }
+
+        if ctx.db.get_loading().is_loading() && !matches!(self.spec, Some(spec) if spec.has_flag(CommandFlag::Loading)) {
+            return Err(RedisError::Loading);
+        }
+
+        if !BUSY_COMMANDS.contains(&name) && ctx.db.get_busy().is_busy(ctx.client.id) {
+            return Err(RedisError::Busy);
+        }
+
+        Ok(())
+    }
+
+    /// Checks the client authenticated, and that its user may run the
+    /// command on the keys it names.
+    fn check_permissions(&self, ctx: &CommandContext, name: &str) -> crate::Result<()> {
+        if NOAUTH_COMMANDS.contains(&name) {
+            return Ok(());
+        }
+
+        let user = match &ctx.client.user {
+            Some(user) => ctx.db.get_acl().get_user(user),
+            None => return Err(RedisError::NoAuth),
+        };
+
+        let (user, spec) = match (user, self.spec) {
+            (Some(user), Some(spec)) if !user.is_unrestricted() => (user, spec),
+            _ => return Ok(()),
+        };
+
+        if !user.can_run(spec) {
+            return Err(format!("NOPERM User {} has no permissions to run the '{}' command", user.name, spec.name).into());
+        }
+        if !self.keys().iter().all(|key| user.can_access_key(key)) {
+            return Err("NOPERM No permissions to access a key".into());
+        }
+
+        Ok(())
+    }
+
+    /// Runs a command received from the master, or queues it if it's part
+    /// of a transaction.
+    pub async fn apply_replica(self, ctx: &mut ReplicaContext) -> crate::Result<()> {
+        if let Some(transaction) = ctx.transaction.as_mut() {
+            if !TRANSACTION_COMMANDS.contains(&self.name().as_ref()) {
+                transaction.push(self);
+                return Ok(());
+            }
+        }
+
+        self.exec.apply_replica(ctx).await
+    }
+}
@@ -0,0 +1,219 @@
+use bytes::Bytes;
+
+use crate::{glob, ChannelKind, Frame, RedisError};
+use super::{BoxFuture, CommandArgs, CommandContext, CommandExec, Propagate, ReplicaContext};
+
+#[derive(Debug)]
+pub struct Subscribe {
+    kind: ChannelKind,
+    channels: Vec<Bytes>,
+}
+
+impl Subscribe {
+    pub fn new(kind: ChannelKind, channels: Vec<Bytes>) -> Subscribe {
+        Subscribe { kind, channels }
+    }
+}
+
+impl CommandExec for Subscribe {
+    fn parse(args: &CommandArgs) -> crate::Result<Subscribe> {
+        let channels = (1..args.len()).map(|idx| args.bytes(idx).cloned()).collect::<crate::Result<_>>()?;
+
+        Ok(Subscribe::new(parse_kind(args)?, channels))
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let pubsub = ctx.db.get_pubsub();
+            let verb = format!("{}subscribe", self.kind.prefix());
+
+            for channel in self.channels {
+                // Confirm before subscribing, so the confirmation is written
+                // ahead of any message on the channel.
+                let subscriptions = *ctx.client.subscriptions_mut(self.kind);
+                let count = match pubsub.is_subscribed(&ctx.addr, self.kind, &channel) {
+                    true => subscriptions,
+                    false => subscriptions + 1,
+                };
+                ctx.reply(&subscription_reply(&verb, Some(channel.clone()), count)).await?;
+
+                *ctx.client.subscriptions_mut(self.kind) = pubsub.subscribe(&ctx.conn_manager, &ctx.addr, self.kind, channel);
+            }
+
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Unsubscribe {
+    kind: ChannelKind,
+    channels: Vec<Bytes>,
+}
+
+impl Unsubscribe {
+    pub fn new(kind: ChannelKind, channels: Vec<Bytes>) -> Unsubscribe {
+        Unsubscribe { kind, channels }
+    }
+}
+
+impl CommandExec for Unsubscribe {
+    fn parse(args: &CommandArgs) -> crate::Result<Unsubscribe> {
+        let channels = (1..args.len()).map(|idx| args.bytes(idx).cloned()).collect::<crate::Result<_>>()?;
+
+        Ok(Unsubscribe::new(parse_kind(args)?, channels))
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let pubsub = ctx.db.get_pubsub();
+            let verb = format!("{}unsubscribe", self.kind.prefix());
+
+            // Without channels, unsubscribes from all of them.
+            let channels = match self.channels.is_empty() {
+                true => pubsub.channels_of(&ctx.addr, self.kind),
+                false => self.channels,
+            };
+
+            if channels.is_empty() {
+                ctx.reply(&subscription_reply(&verb, None, 0)).await?;
+            }
+            for channel in channels {
+                let count = pubsub.unsubscribe(&ctx.addr, self.kind, channel.clone());
+                *ctx.client.subscriptions_mut(self.kind) = count;
+                ctx.reply(&subscription_reply(&verb, Some(channel), count)).await?;
+            }
+
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Publish {
+    kind: ChannelKind,
+    channel: Bytes,
+    message: Bytes,
+}
+
+impl Publish {
+    pub fn new(kind: ChannelKind, channel: Bytes, message: Bytes) -> Publish {
+        Publish { kind, channel, message }
+    }
+}
+
+impl CommandExec for Publish {
+    fn parse(args: &CommandArgs) -> crate::Result<Publish> {
+        Ok(Publish::new(parse_kind(args)?, args.bytes(1)?.clone(), args.bytes(2)?.clone()))
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let receivers = ctx.db.get_pubsub().publish(self.kind, &self.channel, &self.message);
+
+            // Clients subscribed to replicas get the message too.
+            ctx.propagate(Propagate::Verbatim).await?;
+
+            ctx.reply(&Frame::Integer(receivers as i64)).await?;
+
+            Ok(())
+        })
+    }
+
+    fn apply_replica(self: Box<Self>, ctx: &mut ReplicaContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            ctx.db.get_pubsub().publish(self.kind, &self.channel, &self.message);
+
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum PubSubOption {
+    Channels(ChannelKind, Option<Bytes>),
+    NumSub(ChannelKind, Vec<Bytes>),
+    NumPat,
+}
+
+#[derive(Debug)]
+pub struct PubSubCommand {
+    option: PubSubOption,
+}
+
+impl PubSubCommand {
+    pub fn new(option: PubSubOption) -> PubSubCommand {
+        PubSubCommand { option }
+    }
+}
+
+impl CommandExec for PubSubCommand {
+    fn parse(args: &CommandArgs) -> crate::Result<PubSubCommand> {
+        let subcommand = args.string(1)?.to_lowercase();
+        let rest = (2..args.len()).map(|idx| args.bytes(idx).cloned()).collect::<crate::Result<Vec<_>>>()?;
+
+        let option = match (subcommand.as_str(), rest.len()) {
+            ("channels", 0 | 1) => PubSubOption::Channels(ChannelKind::Global, rest.into_iter().next()),
+            ("shardchannels", 0 | 1) => PubSubOption::Channels(ChannelKind::Shard, rest.into_iter().next()),
+            ("numsub", _) => PubSubOption::NumSub(ChannelKind::Global, rest),
+            ("shardnumsub", _) => PubSubOption::NumSub(ChannelKind::Shard, rest),
+            ("numpat", 0) => PubSubOption::NumPat,
+            (subcommand, _) => return Err(RedisError::unknown_subcommand("PUBSUB", subcommand)),
+        };
+
+        Ok(PubSubCommand::new(option))
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let pubsub = ctx.db.get_pubsub();
+
+            let frame = match self.option {
+                PubSubOption::Channels(kind, pattern) => {
+                    let channels = pubsub.channels(kind).into_iter()
+                        .filter(|channel| match &pattern {
+                            Some(pattern) => glob::matches(pattern, channel),
+                            None => true,
+                        })
+                        .map(|channel| Frame::Bulk(Some(channel)))
+                        .collect();
+
+                    Frame::Array(channels)
+                },
+                PubSubOption::NumSub(kind, channels) => {
+                    let mut res = Vec::with_capacity(channels.len() * 2);
+                    for channel in channels {
+                        let count = pubsub.num_subscribers(kind, &channel);
+                        res.push(Frame::Bulk(Some(channel)));
+                        res.push(Frame::Integer(count as i64));
+                    }
+
+                    Frame::Array(res)
+                },
+                // There are no pattern subscriptions yet.
+                PubSubOption::NumPat => Frame::Integer(0),
+            };
+            ctx.reply(&frame).await?;
+
+            Ok(())
+        })
+    }
+}
+
+/// Whether the command is the shard channel variant of its regular one.
+fn parse_kind(args: &CommandArgs) -> crate::Result<ChannelKind> {
+    match args.name() {
+        "ssubscribe" | "sunsubscribe" | "spublish" => Ok(ChannelKind::Shard),
+        _ => Ok(ChannelKind::Global),
+    }
+}
+
+/// The reply to `SUBSCRIBE` and `UNSUBSCRIBE`, one per channel, with the
+/// number of channels of the kind the client is subscribed to afterwards.
+fn subscription_reply(kind: &str, channel: Option<Bytes>, count: usize) -> Frame {
+    Frame::Array(vec![
+        Frame::bulk(kind.to_string()),
+        Frame::Bulk(channel),
+        Frame::Integer(count as i64),
+    ])
+}
@@ -0,0 +1,312 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use tokio::time::Instant;
+
+use crate::{debug, ConnectionClass, ConnectionManager, Frame, SharedReplicationState, Wakeup};
+use super::{BoxFuture, CommandArgs, CommandContext, CommandExec, ReplicaContext};
+
+// The replica's port and capabilities are parsed but not tracked yet.
+#[allow(dead_code)]
+#[derive(Debug)]
+pub enum ReplConfOption {
+    ListeningPort(String),
+    Capabilities(Vec<String>),
+    GetAck(String),
+    // The offset a replica applied and, if it runs an AOF, the one it
+    // fsynced. Either is None when the replica sent something unreadable.
+    Ack { offset: Option<u64>, aof_offset: Option<u64> },
+}
+
+#[derive(Debug)]
+pub struct ReplConf {
+    pub option: ReplConfOption,
+}
+
+impl ReplConf {
+    pub fn new(option: ReplConfOption) -> ReplConf {
+        ReplConf { option }
+    }
+}
+
+impl CommandExec for ReplConf {
+    fn parse(args: &CommandArgs) -> crate::Result<ReplConf> {
+        if args.len() < 3 {
+            return Err(args.wrong_arity());
+        }
+
+        let arg = args.string(1)?;
+
+        if arg == "listening-port" {
+            Ok(ReplConf::new(ReplConfOption::ListeningPort(args.string(2)?)))
+        } else if arg == "capa" {
+            Ok(ReplConf::new(ReplConfOption::Capabilities(args.strings_from(2)?)))
+        } else if arg.eq_ignore_ascii_case("getack") {
+            Ok(ReplConf::new(ReplConfOption::GetAck(args.string(2)?)))
+        } else if arg.eq_ignore_ascii_case("ack") {
+            let offset = args.string(2)?.parse::<u64>().ok();
+            let aof_offset = match args.len() {
+                5 if args.string(3)?.eq_ignore_ascii_case("fack") => args.string(4)?.parse::<u64>().ok(),
+                _ => None,
+            };
+            Ok(ReplConf::new(ReplConfOption::Ack { offset, aof_offset }))
+        } else {
+            Err(format!("ERR Unrecognized REPLCONF option: {}", arg).into())
+        }
+    }
+
+    /// Acknowledgements from synced replicas never get here, see
+    /// `handle_replica_frame`, and are ignored from anyone else. Like
+    /// redis-server, they are never replied to.
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            if let ReplConfOption::Ack { .. } = self.option {
+                return Ok(());
+            }
+
+            ctx.reply(&Frame::simple("OK")).await?;
+
+            Ok(())
+        })
+    }
+
+    fn apply_replica(self: Box<Self>, ctx: &mut ReplicaContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            match self.option {
+                // The offset doesn't include the GETACK itself, only what
+                // came before it.
+                ReplConfOption::GetAck(_) => {
+                    ctx.master.write_frame(&Frame::command(["REPLCONF", "ACK", &ctx.offset.to_string()])).await?;
+
+                    Ok(())
+                },
+                _ => Err("ERR Only REPLCONF GETACK is expected from the master".into()),
+            }
+        })
+    }
+}
+
+/// Handles a frame a replica sent after `PSYNC`, on the link it reads its
+/// replication stream from. Nothing is ever written back to it from here,
+/// so only acknowledgements are of use: they are recorded, and anything
+/// else is dropped.
+pub fn handle_replica_frame(ctx: &CommandContext, frame: &Frame) {
+    let array = match frame {
+        Frame::Array(array) => array,
+        _ => return debug!("Ignoring {:?} from replica {}", frame, ctx.addr),
+    };
+
+    let is_replconf = matches!(array.first(), Some(Frame::Bulk(Some(name))) if name.eq_ignore_ascii_case(b"replconf"));
+    let option = match is_replconf {
+        true => ReplConf::parse(&CommandArgs::new("replconf", array)).map(|replconf| replconf.option),
+        false => Err("not REPLCONF".into()),
+    };
+
+    match option {
+        Ok(ReplConfOption::Ack { offset: Some(offset), aof_offset }) => {
+            ctx.db.get_replication_state().record_ack(&ctx.addr, offset, aof_offset);
+        },
+        _ => debug!("Ignoring {:?} from replica {}", frame, ctx.addr),
+    }
+}
+
+#[derive(Debug)]
+pub struct Psync {
+    replication_id: String,
+    _replication_offset: i64,
+}
+
+impl Psync {
+    pub fn new(replication_id: String, _replication_offset: i64) -> Psync {
+        Psync {
+            replication_id,
+            _replication_offset,
+        }
+    }
+
+    async fn send_full_resync(dst_addr: &str, conn_manager: &ConnectionManager, repl_info: &SharedReplicationState, offset: u64, snapshot: Bytes) -> crate::Result<()> {
+        conn_manager.queue_frame(dst_addr,
+            &Frame::Simple(format!("FULLRESYNC {} {}", repl_info.get_replication_id(), offset))).await?;
+        conn_manager.write_frame(dst_addr, &Frame::File(snapshot)).await?;
+
+        repl_info.finish_full_resync(conn_manager, dst_addr).await
+    }
+}
+
+impl CommandExec for Psync {
+    fn parse(args: &CommandArgs) -> crate::Result<Psync> {
+        if args.len() != 3 {
+            return Err(args.wrong_arity());
+        }
+
+        let replication_id = args.string(1)?;
+        let replication_offset = args.integer(2)?;
+
+        Ok(Psync::new(replication_id, replication_offset))
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let repl_info = ctx.db.get_replication_state();
+            ctx.conn_manager.set_class(&ctx.addr, ConnectionClass::Replica).await;
+            ctx.client.replica = true;
+
+            if repl_info.get_replication_id() != self.replication_id {
+                // Full resync. The snapshot is taken and the replica registered
+                // with every shard locked, so each write is either part of the
+                // snapshot or propagated after it.
+                let (offset, snapshot) = {
+                    let guards = ctx.db.lock_all().await;
+
+                    let snapshot = Bytes::from(ctx.db.save_snapshot(&guards));
+                    let offset = repl_info.add_replica(ctx.addr.clone()).await;
+
+                    (offset, snapshot)
+                };
+
+                let res = Self::send_full_resync(&ctx.addr, &ctx.conn_manager, &repl_info, offset, snapshot).await;
+                if res.is_err() {
+                    repl_info.remove_replica(&ctx.addr).await;
+                }
+                res?;
+            } else {
+                // Partial sync
+                // ...
+            }
+
+            Ok(())
+        })
+    }
+}
+
+/// Parses the timeout of `WAIT` and `WAITAOF`, in milliseconds. Zero means
+/// no timeout.
+fn parse_wait_timeout(args: &CommandArgs, idx: usize) -> crate::Result<Option<Duration>> {
+    match args.integer_in(idx, .., "ERR timeout is not an integer or out of range")? {
+        timeout if timeout < 0 => Err("ERR timeout is negative".into()),
+        0 => Ok(None),
+        timeout => Ok(Some(Duration::from_millis(timeout as u64))),
+    }
+}
+
+/// Waits until `numreplicas` of the replicas acknowledged the offsets they
+/// have been sent so far, which cover every write of the client, or until
+/// `timeout` passes. With `aof`, replicas must have fsynced those writes to
+/// their AOF. Returns how many did, or None if the client was killed.
+///
+/// Replicas are asked for an acknowledgement once the client blocks.
+/// Transactions never block, they just count what was acknowledged already.
+async fn wait_for_replicas(ctx: &CommandContext, numreplicas: i64, timeout: Option<Duration>, aof: bool) -> Option<usize> {
+    let replication = ctx.db.get_replication_state();
+    let targets = replication.get_replica_offsets();
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+    let acked = replication.count_acked(&targets, aof);
+    if acked as i64 >= numreplicas || ctx.in_transaction() {
+        return Some(acked);
+    }
+
+    let command = String::from_utf8_lossy(&ctx.args[0]).to_lowercase();
+    let blocked = ctx.db.get_blocking().block(ctx.client.id, &command, ctx.client.db_index, &[]);
+    replication.request_acks(&ctx.conn_manager).await;
+
+    loop {
+        let next_ack = replication.acked();
+
+        let acked = replication.count_acked(&targets, aof);
+        if acked as i64 >= numreplicas {
+            return Some(acked);
+        }
+
+        tokio::select! {
+            wakeup = blocked.wait(deadline) => match wakeup {
+                Wakeup::Ready => continue,
+                Wakeup::Timeout => return Some(replication.count_acked(&targets, aof)),
+                Wakeup::Unblocked => return None,
+            },
+            _ = next_ack => continue,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Wait {
+    numreplicas: i64,
+    timeout: Option<Duration>,
+}
+
+impl Wait {
+    pub fn new(numreplicas: i64, timeout: Option<Duration>) -> Wait {
+        Wait { numreplicas, timeout }
+    }
+}
+
+impl CommandExec for Wait {
+    fn parse(args: &CommandArgs) -> crate::Result<Wait> {
+        let numreplicas = args.integer(1)?;
+        let timeout = parse_wait_timeout(args, 2)?;
+
+        Ok(Wait::new(numreplicas, timeout))
+    }
+
+    /// Replies with the number of replicas that acknowledged the client's
+    /// writes. Replicas refuse it, like Redis 7 does, as their writes are
+    /// never propagated anywhere.
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            if ctx.db.get_replication_state().get_role() != "master" {
+                return Err("ERR WAIT cannot be used with replica instances. Please also note that since Redis 4.0 if a replica is configured to be writable (which is not the default) writes to replicas are just local and are not propagated.".into());
+            }
+
+            if let Some(acked) = wait_for_replicas(ctx, self.numreplicas, self.timeout, false).await {
+                ctx.reply(&Frame::Integer(acked as i64)).await?;
+            }
+
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct WaitAof {
+    numlocal: i64,
+    numreplicas: i64,
+    timeout: Option<Duration>,
+}
+
+impl WaitAof {
+    pub fn new(numlocal: i64, numreplicas: i64, timeout: Option<Duration>) -> WaitAof {
+        WaitAof { numlocal, numreplicas, timeout }
+    }
+}
+
+impl CommandExec for WaitAof {
+    fn parse(args: &CommandArgs) -> crate::Result<WaitAof> {
+        let numlocal = args.integer(1)?;
+        let numreplicas = args.integer(2)?;
+        let timeout = parse_wait_timeout(args, 3)?;
+
+        Ok(WaitAof::new(numlocal, numreplicas, timeout))
+    }
+
+    /// Replies with the number of local AOFs and of replicas that fsynced
+    /// the client's writes. This server has no AOF of its own, so only
+    /// `numlocal` 0 is accepted and the local count is always 0; replicas
+    /// count once they report an fsynced offset with `REPLCONF ACK .. FACK`.
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            if ctx.db.get_replication_state().get_role() != "master" {
+                return Err("ERR WAITAOF cannot be used with replica instances. Please also note that writes to replicas are just local and are not propagated.".into());
+            }
+            if self.numlocal > 0 {
+                return Err("ERR WAITAOF cannot be used when numlocal is set but appendonly is disabled.".into());
+            }
+
+            if let Some(acked) = wait_for_replicas(ctx, self.numreplicas, self.timeout, true).await {
+                ctx.reply(&Frame::Array(vec![Frame::Integer(0), Frame::Integer(acked as i64)])).await?;
+            }
+
+            Ok(())
+        })
+    }
+}
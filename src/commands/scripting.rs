@@ -0,0 +1,46 @@
+use crate::{Frame, RedisError};
+use super::{BoxFuture, CommandArgs, CommandContext, CommandExec};
+
+#[derive(Debug)]
+pub enum ScriptOption {
+    Kill,
+}
+
+/// `SCRIPT`. There are no scripts, but `KILL` stops the long operations
+/// scripts would be, see `BusyOperations`.
+#[derive(Debug)]
+pub struct ScriptCommand {
+    option: ScriptOption,
+}
+
+impl ScriptCommand {
+    pub fn new(option: ScriptOption) -> ScriptCommand {
+        ScriptCommand { option }
+    }
+}
+
+impl CommandExec for ScriptCommand {
+    fn parse(args: &CommandArgs) -> crate::Result<ScriptCommand> {
+        let args = args.strings_from(1)?;
+        let subcommand = args[0].to_lowercase();
+
+        match (subcommand.as_str(), &args[1..]) {
+            ("kill", []) => Ok(ScriptCommand::new(ScriptOption::Kill)),
+            (subcommand, _) => Err(RedisError::unknown_subcommand("SCRIPT", subcommand)),
+        }
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            match self.option {
+                ScriptOption::Kill => if !ctx.db.get_busy().kill() {
+                    return Err("NOTBUSY No long operation in execution right now.".into());
+                },
+            }
+
+            ctx.reply(&Frame::simple("OK")).await?;
+
+            Ok(())
+        })
+    }
+}
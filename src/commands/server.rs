@@ -0,0 +1,859 @@
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::{acl, digest, glob, random, rdb};
+use crate::command_table::CommandSpec;
+use crate::{get_unix_ts_millis, warn, Frame, RedisError, ServerConfig};
+use super::generic::{value_encoding, value_serialized_len};
+use super::{BoxFuture, CommandArgs, CommandContext, CommandExec, Propagate, ReplicaContext};
+
+#[derive(Debug)]
+pub enum CommandListOption {
+    All,
+    Count,
+    Info(Vec<String>),
+    Docs(Vec<String>),
+    /// The command to find the keys of, with its arguments.
+    GetKeys(Vec<Bytes>),
+}
+
+#[derive(Debug)]
+pub struct CommandList {
+    option: CommandListOption,
+}
+
+impl CommandList {
+    pub fn new(option: CommandListOption) -> CommandList {
+        CommandList { option }
+    }
+}
+
+impl CommandExec for CommandList {
+    fn parse(args: &CommandArgs) -> crate::Result<CommandList> {
+        // The command given to GETKEYS may have binary arguments.
+        if args.len() > 1 && args.string(1)?.eq_ignore_ascii_case("getkeys") {
+            if args.len() < 3 {
+                return Err(args.wrong_arity());
+            }
+            let command = (2..args.len()).map(|idx| args.bytes(idx).cloned()).collect::<crate::Result<_>>()?;
+
+            return Ok(CommandList::new(CommandListOption::GetKeys(command)));
+        }
+
+        let mut args = args.strings_from(1)?;
+
+        if args.is_empty() {
+            return Ok(CommandList::new(CommandListOption::All));
+        }
+
+        let subcommand = args.remove(0).to_lowercase();
+
+        match subcommand.as_str() {
+            "count" if args.is_empty() => Ok(CommandList::new(CommandListOption::Count)),
+            "info" => Ok(CommandList::new(CommandListOption::Info(args))),
+            "docs" => Ok(CommandList::new(CommandListOption::Docs(args))),
+            subcommand => Err(RedisError::unknown_subcommand("COMMAND", subcommand)),
+        }
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            // Renamed commands are listed by their new names, and disabled ones
+            // not at all.
+            let names = ctx.db.get_command_names();
+
+            let frame = match self.option {
+                CommandListOption::All => Frame::Array(names.commands().map(|(name, spec)| spec.info_frame(name)).collect()),
+                CommandListOption::Count => Frame::Integer(names.commands().count() as i64),
+                CommandListOption::Info(requested) => Frame::Array(requested.iter().map(|name| {
+                    let name = name.to_lowercase();
+                    match names.lookup(&name) {
+                        Some(spec) => spec.info_frame(&name),
+                        None => Frame::Null,
+                    }
+                }).collect()),
+                CommandListOption::Docs(requested) => {
+                    let specs: Vec<(String, &CommandSpec)> = if requested.is_empty() {
+                        names.commands().map(|(name, spec)| (name.to_string(), spec)).collect()
+                    } else {
+                        requested.iter()
+                            .map(|name| name.to_lowercase())
+                            .filter_map(|name| names.lookup(&name).map(|spec| (name, spec)))
+                            .collect()
+                    };
+
+                    let mut res = vec![];
+                    for (name, spec) in specs {
+                        res.push(Frame::bulk(name));
+                        res.push(spec.docs_frame());
+                    }
+
+                    Frame::Array(res)
+                },
+                CommandListOption::GetKeys(command) => {
+                    let spec = match names.lookup(&String::from_utf8_lossy(&command[0]).to_lowercase()) {
+                        Some(spec) => spec,
+                        None => return Err("ERR Invalid command specified".into()),
+                    };
+                    if !spec.check_arity(command.len()) {
+                        return Err("ERR Invalid number of arguments specified for command".into());
+                    }
+
+                    let keys = spec.extract_keys(&command);
+                    if keys.is_empty() {
+                        return Err("ERR The command has no key arguments".into());
+                    }
+                    Frame::Array(keys.into_iter().map(|key| Frame::Bulk(Some(key.clone()))).collect())
+                },
+            };
+
+            ctx.reply(&frame).await?;
+
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Info {
+    section: Option<String>,
+}
+
+impl Info {
+    pub fn new(section: Option<String>) -> Info {
+        Info { section }
+    }
+}
+
+impl CommandExec for Info {
+    fn parse(args: &CommandArgs) -> crate::Result<Info> {
+        if args.len() > 2 {
+            return Err(RedisError::Syntax);
+        }
+
+        let section = if args.len() == 2 { Some(args.string(1)?) } else { None };
+
+        Ok(Info::new(section))
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let section = self.section.unwrap_or_else(|| "default".to_string()).to_lowercase();
+
+            let names: &[&str] = match section.as_str() {
+                "default" => &["server", "clients", "persistence", "stats", "replication", "keyspace"],
+                "all" | "everything" => &["server", "clients", "persistence", "stats", "replication", "commandstats", "keyspace"],
+                "server" => &["server"],
+                "clients" => &["clients"],
+                "persistence" => &["persistence"],
+                "stats" => &["stats"],
+                "replication" => &["replication"],
+                "commandstats" => &["commandstats"],
+                "keyspace" => &["keyspace"],
+                _ => {
+                    ctx.reply(&Frame::Error("ERR: Invalid section".to_string())).await?;
+                    return Ok(());
+                }
+            };
+
+            let mut sections = vec![];
+            for name in names {
+                sections.push(match *name {
+                    "server" => ctx.db.get_identity().get_info_bytes(),
+                    "clients" => ctx.db.get_clients().get_info_bytes(ctx.db.get_blocking(), ctx.db.get_watches()),
+                    "persistence" => ctx.db.get_loading().get_info_bytes(),
+                    "stats" => ctx.db.get_stats_info_bytes(),
+                    "replication" => ctx.db.get_replication_state().get_info_bytes(),
+                    "commandstats" => ctx.db.get_command_stats().get_info_bytes(),
+                    _ => ctx.db.get_keyspace_info_bytes().await,
+                });
+            }
+
+            ctx.reply(&Frame::bulk(sections.join(&b"\n"[..]))).await?;
+
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum SlowlogOption {
+    Get(Option<usize>),
+    Len,
+    Reset,
+}
+
+#[derive(Debug)]
+pub struct Slowlog {
+    option: SlowlogOption,
+}
+
+impl Slowlog {
+    pub fn new(option: SlowlogOption) -> Slowlog {
+        Slowlog { option }
+    }
+}
+
+impl CommandExec for Slowlog {
+    fn parse(args: &CommandArgs) -> crate::Result<Slowlog> {
+        let subcommand = args.string(1)?.to_lowercase();
+
+        match (subcommand.as_str(), args.len()) {
+            ("get", 2) => Ok(Slowlog::new(SlowlogOption::Get(Some(10)))),
+            ("get", 3) => {
+                let count = args.integer(2).ok();
+
+                match count {
+                    Some(-1) => Ok(Slowlog::new(SlowlogOption::Get(None))),
+                    Some(count) if count >= 0 => Ok(Slowlog::new(SlowlogOption::Get(Some(count as usize)))),
+                    _ => Err("ERR count should be greater than or equal to -1".into()),
+                }
+            },
+            ("len", 2) => Ok(Slowlog::new(SlowlogOption::Len)),
+            ("reset", 2) => Ok(Slowlog::new(SlowlogOption::Reset)),
+            (subcommand, _) => Err(RedisError::unknown_subcommand("SLOWLOG", subcommand)),
+        }
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let frame = match self.option {
+                SlowlogOption::Get(count) => ctx.db.get_slowlog().get(count),
+                SlowlogOption::Len => Frame::Integer(ctx.db.get_slowlog().len() as i64),
+                SlowlogOption::Reset => {
+                    ctx.db.get_slowlog().reset();
+                    Frame::simple("OK")
+                }
+            };
+
+            ctx.reply(&frame).await?;
+
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigOption {
+    Get(Vec<String>),
+    Set(Vec<(String, String)>),
+}
+
+#[derive(Debug)]
+pub struct Config {
+    option: ConfigOption,
+}
+
+impl Config {
+    pub fn new(option: ConfigOption) -> Config {
+        Config { option }
+    }
+}
+
+impl CommandExec for Config {
+    fn parse(args: &CommandArgs) -> crate::Result<Config> {
+        if args.len() < 3 {
+            return Err(args.wrong_arity());
+        }
+
+        let mut args = args.strings_from(1)?;
+
+        let subcommand = args.remove(0).to_lowercase();
+
+        match subcommand.as_str() {
+            "get" => Ok(Config::new(ConfigOption::Get(args))),
+            "set" if args.len() % 2 == 0 => {
+                let params = args.chunks(2).map(|pair| (pair[0].clone(), pair[1].clone())).collect();
+                Ok(Config::new(ConfigOption::Set(params)))
+            },
+            subcommand => Err(RedisError::unknown_subcommand("CONFIG", subcommand)),
+        }
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let frame = match self.option {
+                ConfigOption::Get(patterns) => {
+                    let mut res = vec![];
+
+                    for pattern in patterns {
+                        for (name, value) in ctx.db.get_config().get_matching(&pattern) {
+                            res.push(Frame::bulk(name));
+                            res.push(Frame::bulk(value));
+                        }
+                    }
+
+                    Frame::Array(res)
+                },
+                ConfigOption::Set(params) => {
+                    // Apply to a copy first, so that a bad parameter leaves the
+                    // configuration untouched.
+                    let mut config = ctx.db.get_config();
+                    let mut res = Frame::simple("OK");
+
+                    for (name, value) in params {
+                        if ServerConfig::IMMUTABLE_PARAMETERS.contains(&name.to_lowercase().as_str()) {
+                            res = Frame::Error(format!("ERR CONFIG SET failed (possibly related to argument '{}') - can't set immutable config", name));
+                            break;
+                        }
+
+                        if let Err(err) = config.set(&name, &value) {
+                            res = Frame::Error(err.to_string());
+                            break;
+                        }
+                    }
+
+                    if let Frame::Simple(_) = res {
+                        ctx.db.set_config(config);
+                    }
+
+                    res
+                },
+            };
+
+            ctx.reply(&frame).await?;
+
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Monitor {}
+
+impl Monitor {
+    pub fn new() -> Monitor {
+        Monitor {}
+    }
+}
+
+impl CommandExec for Monitor {
+    fn parse(_args: &CommandArgs) -> crate::Result<Monitor> {
+        Ok(Monitor::new())
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let mut feed = ctx.db.get_monitor_feed().subscribe();
+
+            ctx.reply(&Frame::simple("OK")).await?;
+
+            let (dst_addr, conn_manager) = (ctx.addr.clone(), ctx.conn_manager.clone());
+            tokio::spawn(async move {
+                loop {
+                    match feed.recv().await {
+                        Ok(line) => {
+                            if conn_manager.write_frame(&dst_addr, &Frame::Simple(line)).await.is_err() {
+                                conn_manager.remove(&dst_addr).await;
+                                break;
+                            }
+                        },
+                        Err(RecvError::Lagged(skipped)) => {
+                            // Never let a slow monitor hold up the rest of the server.
+                            warn!("Disconnecting monitor {} which fell {} lines behind", dst_addr, skipped);
+                            conn_manager.remove(&dst_addr).await;
+                            break;
+                        },
+                        Err(RecvError::Closed) => break,
+                    }
+                }
+            });
+
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum LatencyOption {
+    History(String),
+    Histogram(Vec<String>),
+    Reset(Vec<String>),
+}
+
+#[derive(Debug)]
+pub struct Latency {
+    option: LatencyOption,
+}
+
+impl Latency {
+    pub fn new(option: LatencyOption) -> Latency {
+        Latency { option }
+    }
+}
+
+impl CommandExec for Latency {
+    fn parse(args: &CommandArgs) -> crate::Result<Latency> {
+        let mut args = args.strings_from(1)?;
+
+        let subcommand = args.remove(0).to_lowercase();
+
+        match subcommand.as_str() {
+            "history" if args.len() == 1 => Ok(Latency::new(LatencyOption::History(args.remove(0)))),
+            "histogram" => Ok(Latency::new(LatencyOption::Histogram(args))),
+            "reset" => Ok(Latency::new(LatencyOption::Reset(args))),
+            subcommand => Err(RedisError::unknown_subcommand("LATENCY", subcommand)),
+        }
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let command_stats = ctx.db.get_command_stats();
+
+            let frame = match self.option {
+                LatencyOption::History(event) => command_stats.history(&event),
+                LatencyOption::Histogram(names) => command_stats.histogram(&names),
+                LatencyOption::Reset(names) => Frame::Integer(command_stats.reset(&names) as i64),
+            };
+
+            ctx.reply(&frame).await?;
+
+            Ok(())
+        })
+    }
+}
+
+/// How often `DEBUG SLEEP` checks whether it was killed.
+const DEBUG_SLEEP_SLICE: Duration = Duration::from_millis(10);
+
+#[derive(Debug)]
+pub enum MemoryOption {
+    Usage(Bytes),
+}
+
+#[derive(Debug)]
+pub struct MemoryCommand {
+    option: MemoryOption,
+}
+
+impl MemoryCommand {
+    pub fn new(option: MemoryOption) -> MemoryCommand {
+        MemoryCommand { option }
+    }
+}
+
+impl CommandExec for MemoryCommand {
+    fn parse(args: &CommandArgs) -> crate::Result<MemoryCommand> {
+        let subcommand = args.string(1)?.to_lowercase();
+
+        match (subcommand.as_str(), args.len()) {
+            ("usage", 3) => Ok(MemoryCommand::new(MemoryOption::Usage(args.key(2)?))),
+            // Sizes are added up exactly, so there's nothing to sample.
+            ("usage", 5) if args.bytes(3)?.eq_ignore_ascii_case(b"samples") => match args.integer(4)? {
+                samples if samples >= 0 => Ok(MemoryCommand::new(MemoryOption::Usage(args.key(2)?))),
+                _ => Err(RedisError::NotAnInteger),
+            },
+            ("usage", 4..) => Err(RedisError::Syntax),
+            (subcommand, _) => Err(RedisError::unknown_subcommand("MEMORY", subcommand)),
+        }
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let frame = match self.option {
+                MemoryOption::Usage(key) => match ctx.db.get_db(ctx.client.db_index).read(&key).await.peek(&key) {
+                    // The key and the entry it maps to, then whatever the
+                    // value points to.
+                    Some(entry) => Frame::Integer((std::mem::size_of::<Bytes>() + key.len() + std::mem::size_of_val(entry) + entry.value.memory_usage()) as i64),
+                    None => Frame::Bulk(None),
+                },
+            };
+
+            ctx.reply(&frame).await?;
+
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum DebugOption {
+    Sleep(Duration),
+    Object(Bytes),
+    SetActiveExpire(bool),
+    ReplTrace(bool),
+    /// Saves the dataset and loads it back, unless `NOSAVE` asked to only
+    /// load the dump file, which doesn't exist as nothing is written to disk.
+    Reload { save: bool },
+    /// The seed of the server's random numbers, to run it again with.
+    RandomSeed,
+    Digest,
+    DigestValue(Vec<Bytes>),
+    /// Matches a string against a glob-style pattern, as `KEYS` and `SCAN`
+    /// match keys.
+    StringMatchLen { pattern: Bytes, string: Bytes },
+    /// Panics like a bug in a command would. Unlike in redis-server, only the
+    /// client's connection goes down.
+    Panic,
+}
+
+#[derive(Debug)]
+pub struct DebugCommand {
+    option: DebugOption,
+}
+
+impl DebugCommand {
+    pub fn new(option: DebugOption) -> DebugCommand {
+        DebugCommand { option }
+    }
+}
+
+impl CommandExec for DebugCommand {
+    fn parse(args: &CommandArgs) -> crate::Result<DebugCommand> {
+        // Keys and patterns needn't be UTF-8, unlike the other arguments.
+        match (args.string(1)?.to_lowercase().as_str(), args.len()) {
+            ("object", 3) => return Ok(DebugCommand::new(DebugOption::Object(args.key(2)?))),
+            ("digest-value", _) => return Ok(DebugCommand::new(DebugOption::DigestValue(args.keys_from(2)?))),
+            ("stringmatch-len", 4) => return Ok(DebugCommand::new(DebugOption::StringMatchLen {
+                pattern: args.bytes(2)?.clone(),
+                string: args.bytes(3)?.clone(),
+            })),
+            _ => {},
+        }
+
+        let mut args = args.strings_from(1)?;
+
+        let subcommand = args.remove(0).to_lowercase();
+
+        match (subcommand.as_str(), args.as_slice()) {
+            ("sleep", [seconds]) => {
+                // Duration::from_secs_f64 panics on NaN, negative and huge
+                // values, none of which this range holds.
+                let duration = match seconds.parse::<f64>() {
+                    Ok(seconds) if (0.0..u64::MAX as f64).contains(&seconds) => Duration::from_secs_f64(seconds),
+                    _ => return Err(RedisError::NotAFloat),
+                };
+
+                Ok(DebugCommand::new(DebugOption::Sleep(duration)))
+            },
+            ("set-active-expire", [enabled]) => match enabled.as_str() {
+                "0" => Ok(DebugCommand::new(DebugOption::SetActiveExpire(false))),
+                "1" => Ok(DebugCommand::new(DebugOption::SetActiveExpire(true))),
+                _ => Err(RedisError::NotAnInteger),
+            },
+            ("repl-trace", [enabled]) => match enabled.as_str() {
+                "0" => Ok(DebugCommand::new(DebugOption::ReplTrace(false))),
+                "1" => Ok(DebugCommand::new(DebugOption::ReplTrace(true))),
+                _ => Err(RedisError::NotAnInteger),
+            },
+            ("random-seed", []) => Ok(DebugCommand::new(DebugOption::RandomSeed)),
+            ("digest", []) => Ok(DebugCommand::new(DebugOption::Digest)),
+            ("panic", []) => Ok(DebugCommand::new(DebugOption::Panic)),
+            ("reload", options) => {
+                let mut save = true;
+                for option in options {
+                    match option.to_lowercase().as_str() {
+                        "nosave" => save = false,
+                        _ => return Err(RedisError::Syntax),
+                    }
+                }
+
+                Ok(DebugCommand::new(DebugOption::Reload { save }))
+            },
+            (subcommand, _) => Err(RedisError::unknown_subcommand("DEBUG", subcommand)),
+        }
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let frame = match self.option {
+                DebugOption::Sleep(duration) => {
+                    // Deliberately hold every lock, so the whole server stalls,
+                    // but sleep in slices so SCRIPT KILL can cut it short.
+                    let operation = ctx.long_operation();
+                    let _guards = ctx.db.lock_all().await;
+
+                    let started = Instant::now();
+                    while started.elapsed() < duration {
+                        tokio::time::sleep(duration.saturating_sub(started.elapsed()).min(DEBUG_SLEEP_SLICE)).await;
+                        operation.checkpoint()?;
+                    }
+                    Frame::simple("OK")
+                },
+                DebugOption::Object(key) => match ctx.db.get_db(ctx.client.db_index).read(&key).await.peek(&key) {
+                    Some(entry) => Frame::Simple(format!(
+                        "Value at:{:p} refcount:1 encoding:{} serializedlength:{} lru_seconds_idle:{}",
+                        &entry.value,
+                        value_encoding(&entry.value),
+                        value_serialized_len(&entry.value),
+                        entry.access.idle_time() / 1000,
+                    )),
+                    None => RedisError::NoSuchKey.to_frame(),
+                },
+                DebugOption::SetActiveExpire(enabled) => {
+                    ctx.db.set_active_expire(enabled);
+                    Frame::simple("OK")
+                },
+                DebugOption::ReplTrace(enabled) => {
+                    ctx.db.get_replication_state().get_trace().set_enabled(enabled)?;
+                    Frame::simple("OK")
+                },
+                DebugOption::Reload { save } => debug_reload(ctx, save).await?,
+                DebugOption::RandomSeed => Frame::bulk(random::current_seed().to_string()),
+                DebugOption::Digest => Frame::bulk(digest::to_hex(&ctx.db.digest().await)),
+                DebugOption::DigestValue(keys) => {
+                    let now = get_unix_ts_millis();
+                    let db = ctx.db.get_db(ctx.client.db_index);
+
+                    let mut digests = Vec::with_capacity(keys.len());
+                    for key in keys {
+                        // Missing keys digest as all zeros.
+                        let value_digest = db.read(&key).await.peek(&key)
+                            .map(|entry| digest::value_digest(&entry.value, entry.expiry.is_some(), now))
+                            .unwrap_or_default();
+                        digests.push(Frame::bulk(digest::to_hex(&value_digest)));
+                    }
+                    Frame::Array(digests)
+                },
+                DebugOption::StringMatchLen { pattern, string } => Frame::Integer(glob::matches(&pattern, &string) as i64),
+                DebugOption::Panic => {
+                    // With locks other clients share held, so they're poisoned.
+                    let _slowlog = ctx.db.get_slowlog();
+                    let _output = ctx.transaction_output.lock().unwrap_or_else(|err| err.into_inner());
+                    panic!("DEBUG PANIC");
+                },
+            };
+
+            ctx.reply(&frame).await?;
+
+            Ok(())
+        })
+    }
+}
+
+/// Round trips the whole dataset through the RDB format, with every shard
+/// locked throughout, so what doesn't survive a save and load shows. Blocked
+/// clients stay blocked, and replicas aren't told anything: the dataset is
+/// meant to be the same.
+async fn debug_reload(ctx: &CommandContext, save: bool) -> crate::Result<Frame> {
+    const LOAD_ERROR: &str = "ERR Error trying to load the RDB dump, check server logs.";
+
+    if !save {
+        warn!("DEBUG RELOAD NOSAVE: there's no dump file to load");
+        return Ok(RedisError::Reply(LOAD_ERROR.to_string()).to_frame());
+    }
+
+    let mut guards = ctx.db.lock_all().await;
+    let snapshot = ctx.db.save_snapshot(&guards);
+
+    let _loading = ctx.db.get_loading().start(snapshot.len());
+    let keys = match rdb::load(&snapshot, |loaded| ctx.db.get_loading().progress(loaded)) {
+        Ok(keys) => keys,
+        Err(err) => {
+            warn!("DEBUG RELOAD: failed loading the dataset just saved: {}", err);
+            return Ok(RedisError::Reply(LOAD_ERROR.to_string()).to_frame());
+        },
+    };
+    ctx.db.replace_snapshot(&mut guards, keys, false)?;
+
+    Ok(Frame::simple("OK"))
+}
+
+#[derive(Debug)]
+pub struct SwapDb {
+    first: usize,
+    second: usize,
+}
+
+impl SwapDb {
+    pub fn new(first: usize, second: usize) -> SwapDb {
+        SwapDb { first, second }
+    }
+}
+
+impl CommandExec for SwapDb {
+    fn parse(args: &CommandArgs) -> crate::Result<SwapDb> {
+        let first = args.integer_in(1, 0.., "ERR invalid first DB index")? as usize;
+        let second = args.integer_in(2, 0.., "ERR invalid second DB index")? as usize;
+
+        Ok(SwapDb::new(first, second))
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let frame = if self.first >= ctx.db.num_dbs() {
+                Frame::Error("ERR invalid first DB index".to_string())
+            } else if self.second >= ctx.db.num_dbs() {
+                Frame::Error("ERR invalid second DB index".to_string())
+            } else {
+                let _guards = ctx.db.swap_dbs(self.first, self.second).await;
+
+                ctx.propagate(Propagate::Verbatim).await?;
+                ctx.db.get_blocking().signal_db(self.first);
+                ctx.db.get_blocking().signal_db(self.second);
+
+                Frame::simple("OK")
+            };
+
+            ctx.reply(&frame).await?;
+
+            Ok(())
+        })
+    }
+
+    fn apply_replica(self: Box<Self>, ctx: &mut ReplicaContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            if self.first >= ctx.db.num_dbs() || self.second >= ctx.db.num_dbs() {
+                return Err("Master swapped a DB which isn't configured".into());
+            }
+
+            let _guards = ctx.db.swap_dbs(self.first, self.second).await;
+            ctx.db.get_blocking().signal_db(self.first);
+            ctx.db.get_blocking().signal_db(self.second);
+
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct FlushDb {}
+
+impl FlushDb {
+    pub fn new() -> FlushDb {
+        FlushDb {}
+    }
+}
+
+impl CommandExec for FlushDb {
+    fn parse(args: &CommandArgs) -> crate::Result<FlushDb> {
+        parse_flush_mode(args)?;
+
+        Ok(FlushDb::new())
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let guards = ctx.db.get_db(ctx.client.db_index).flush().await;
+
+            ctx.propagate(Propagate::Verbatim).await?;
+            drop(guards);
+
+            ctx.reply(&Frame::simple("OK")).await?;
+
+            Ok(())
+        })
+    }
+
+    fn apply_replica(self: Box<Self>, ctx: &mut ReplicaContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            ctx.db.get_db(ctx.db_index).flush().await;
+
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct FlushAll {}
+
+impl FlushAll {
+    pub fn new() -> FlushAll {
+        FlushAll {}
+    }
+}
+
+impl CommandExec for FlushAll {
+    fn parse(args: &CommandArgs) -> crate::Result<FlushAll> {
+        parse_flush_mode(args)?;
+
+        Ok(FlushAll::new())
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let guards = ctx.db.flush_all().await;
+
+            ctx.propagate(Propagate::Verbatim).await?;
+            drop(guards);
+
+            ctx.reply(&Frame::simple("OK")).await?;
+
+            Ok(())
+        })
+    }
+
+    fn apply_replica(self: Box<Self>, ctx: &mut ReplicaContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            ctx.db.flush_all().await;
+
+            Ok(())
+        })
+    }
+}
+
+/// Checks the optional `SYNC` or `ASYNC` argument of `FLUSHDB` and
+/// `FLUSHALL`. Flushing is always synchronous, so both modes behave the same.
+fn parse_flush_mode(args: &CommandArgs) -> crate::Result<()> {
+    if args.len() > 2 {
+        return Err(RedisError::Syntax);
+    }
+
+    if args.len() == 2 {
+        let mode = args.bytes(1)?;
+        if !mode.eq_ignore_ascii_case(b"sync") && !mode.eq_ignore_ascii_case(b"async") {
+            return Err(RedisError::Syntax);
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum AclOption {
+    WhoAmI,
+    List,
+    Cat(Option<String>),
+}
+
+#[derive(Debug)]
+pub struct AclCommand {
+    option: AclOption,
+}
+
+impl AclCommand {
+    pub fn new(option: AclOption) -> AclCommand {
+        AclCommand { option }
+    }
+}
+
+impl CommandExec for AclCommand {
+    fn parse(args: &CommandArgs) -> crate::Result<AclCommand> {
+        let subcommand = args.string(1)?.to_lowercase();
+
+        match (subcommand.as_str(), args.len()) {
+            ("whoami", 2) => Ok(AclCommand::new(AclOption::WhoAmI)),
+            ("list", 2) => Ok(AclCommand::new(AclOption::List)),
+            ("cat", 2) => Ok(AclCommand::new(AclOption::Cat(None))),
+            ("cat", 3) => Ok(AclCommand::new(AclOption::Cat(Some(args.string(2)?.to_lowercase())))),
+            (subcommand, _) => Err(RedisError::unknown_subcommand("ACL", subcommand)),
+        }
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let frame = match self.option {
+                AclOption::WhoAmI => Frame::bulk(ctx.client.user.clone().unwrap_or_default()),
+                AclOption::List => Frame::Array(ctx.db.get_acl().users().iter().map(|user| Frame::bulk(user.describe())).collect()),
+                AclOption::Cat(None) => Frame::Array(acl::categories().into_iter().map(Frame::bulk).collect()),
+                AclOption::Cat(Some(category)) => {
+                    if !acl::categories().contains(&category) {
+                        return Err(format!("ERR Unknown category '{}'", category).into());
+                    }
+
+                    let category = format!("@{}", category);
+                    Frame::Array(ctx.db.get_command_names().commands()
+                        .filter(|(_, spec)| spec.categories().contains(&category))
+                        .map(|(name, _)| Frame::bulk(name.to_string()))
+                        .collect())
+                },
+            };
+
+            ctx.reply(&frame).await?;
+
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,37 @@
+use bytes::Bytes;
+
+use crate::Frame;
+use super::{BoxFuture, CommandArgs, CommandContext, CommandExec};
+
+#[derive(Debug)]
+pub struct SMembers {
+    key: Bytes,
+}
+
+impl SMembers {
+    pub fn new(key: Bytes) -> SMembers {
+        SMembers { key }
+    }
+}
+
+impl CommandExec for SMembers {
+    fn parse(args: &CommandArgs) -> crate::Result<SMembers> {
+        Ok(SMembers::new(args.key(1)?))
+    }
+
+    /// Streams the members, as sets can be big.
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let shard = ctx.db.get_db(ctx.client.db_index).read(&self.key).await;
+            let set = shard.get_set(&self.key)?;
+
+            let mut reply = ctx.reply_stream(set.map_or(0, |set| set.len())).await?;
+            for member in set.iter().flat_map(|set| set.iter()) {
+                reply.push(Frame::Bulk(Some(member))).await?;
+            }
+            reply.finish().await?;
+
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,667 @@
+use std::borrow::Cow;
+
+use bytes::Bytes;
+
+use crate::db::Shard;
+use crate::lcs;
+use crate::value::{self, pad_string};
+use crate::{debug, get_unix_ts_millis, ConnectionClass, EventClass, Frame, RedisError, Value};
+use super::{BoxFuture, CommandArgs, CommandContext, CommandExec, Propagate, ReplicaContext};
+
+/// When a key set with `SET` expires.
+#[derive(Debug, Clone, Copy)]
+enum SetExpiry {
+    /// Milliseconds from when the command runs, from `EX` and `PX`.
+    After(u128),
+    /// A Unix timestamp in milliseconds, from `EXAT` and `PXAT`.
+    At(u128),
+}
+
+impl SetExpiry {
+    /// The expiry of `EX`, `PX`, `EXAT` or `PXAT`, uppercase, followed by
+    /// `duration`.
+    fn parse(option: &str, duration: i64) -> crate::Result<SetExpiry> {
+        let multiplier: i64 = match option {
+            "EX" | "EXAT" => 1000,
+            _ => 1,
+        };
+
+        // Like Redis, reject anything that would overflow a signed
+        // 64-bit millisecond timestamp once added to the current time.
+        let max_duration = match option {
+            "EX" | "PX" => i64::MAX - get_unix_ts_millis() as i64,
+            _ => i64::MAX,
+        };
+        let millis = match duration.checked_mul(multiplier) {
+            Some(millis) if millis > 0 && millis <= max_duration => millis as u128,
+            _ => return Err("ERR invalid expire time in 'set' command".into()),
+        };
+
+        Ok(match option {
+            "EX" | "PX" => SetExpiry::After(millis),
+            _ => SetExpiry::At(millis),
+        })
+    }
+
+    fn timestamp(self) -> u128 {
+        match self {
+            SetExpiry::After(duration) => get_unix_ts_millis() + duration,
+            SetExpiry::At(ts) => ts,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Set {
+    key: Bytes,
+    val: Bytes,
+    expiry: Option<SetExpiry>,
+    /// Whether the key keeps the expiry it had, from `KEEPTTL`.
+    keep_ttl: bool,
+}
+
+impl Set {
+    fn new(key: Bytes, val: Bytes, expiry: Option<SetExpiry>, keep_ttl: bool) -> Set {
+        Set {
+            key,
+            val,
+            expiry,
+            keep_ttl,
+        }
+    }
+
+    /// Stores the value, returning the expiry the key ends up with.
+    fn execute(&self, shard: &mut Shard) -> Option<u128> {
+        let expiry = match self.keep_ttl {
+            true => shard.peek(&self.key).and_then(|entry| entry.expiry),
+            false => self.expiry.map(SetExpiry::timestamp),
+        };
+        shard.insert(self.key.clone(), Value::string(self.val.clone()), expiry);
+
+        expiry
+    }
+}
+
+impl CommandExec for Set {
+    fn parse(args: &CommandArgs) -> crate::Result<Set> {
+        let key = args.key(1)?;
+        let val = args.bytes(2)?;
+
+        let mut expiry = None;
+        let mut keep_ttl = false;
+
+        // At most one of the options setting the expiry.
+        let mut idx = 3;
+        while idx < args.len() {
+            let option = args.string(idx)?.to_uppercase();
+
+            match option.as_str() {
+                "KEEPTTL" if expiry.is_none() && !keep_ttl => keep_ttl = true,
+                "EX" | "PX" | "EXAT" | "PXAT" if expiry.is_none() && !keep_ttl && idx + 1 < args.len() => {
+                    expiry = Some(SetExpiry::parse(&option, args.integer(idx + 1)?)?);
+                    idx += 1;
+                },
+                _ => return Err(RedisError::Syntax),
+            }
+            idx += 1;
+        }
+
+        Ok(Set::new(
+            key,
+            val.clone(),
+            expiry,
+            keep_ttl,
+        ))
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let mut shard = ctx.db.get_db(ctx.client.db_index).lock(&self.key).await;
+            let expiry = self.execute(&mut shard);
+
+            // Replicas get the expiry as a timestamp, so the key expires at the
+            // same time everywhere.
+            let propagate = match (expiry, self.keep_ttl) {
+                (Some(ts), false) => Propagate::Rewrite(Frame::command([
+                    "SET".as_bytes(),
+                    &self.key,
+                    &self.val,
+                    "PXAT".as_bytes(),
+                    ts.to_string().as_bytes(),
+                ])),
+                _ => Propagate::Verbatim,
+            };
+
+            ctx.notify(EventClass::String, "set", &self.key);
+
+            debug!("Replicating SET command");
+            ctx.propagate(propagate).await?;
+            debug!("Done replicating SET command");
+            drop(shard);
+
+            ctx.reply(&Frame::simple("OK")).await?;
+
+            Ok(())
+        })
+    }
+
+    fn apply_replica(self: Box<Self>, ctx: &mut ReplicaContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let mut shard = ctx.db.get_db(ctx.db_index).lock(&self.key).await;
+
+            self.execute(&mut shard);
+            ctx.notify(EventClass::String, "set", &self.key);
+
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Get {
+    key: Bytes,
+}
+
+impl Get {
+    pub fn new(key: Bytes) -> Get {
+        Get { key }
+    }
+}
+
+impl CommandExec for Get {
+    fn parse(args: &CommandArgs) -> crate::Result<Get> {
+        Ok(Get::new(args.key(1)?))
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let frame = match ctx.db.get_db(ctx.client.db_index).read_string(&self.key, |val| Frame::Bulk(val.cloned())).await {
+                Ok(frame) => frame,
+                Err(err) => RedisError::from(err).to_frame(),
+            };
+
+            if matches!(frame, Frame::Bulk(None)) {
+                ctx.notify(EventClass::KeyMiss, "keymiss", &self.key);
+            }
+
+            ctx.reply(&frame).await?;
+
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Strlen {
+    key: Bytes,
+}
+
+impl Strlen {
+    pub fn new(key: Bytes) -> Strlen {
+        Strlen { key }
+    }
+}
+
+impl CommandExec for Strlen {
+    fn parse(args: &CommandArgs) -> crate::Result<Strlen> {
+        Ok(Strlen::new(args.key(1)?))
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let len = ctx.db.get_db(ctx.client.db_index).read_string(&self.key, |val| val.map(Bytes::len)).await?;
+
+            if len.is_none() {
+                ctx.notify(EventClass::KeyMiss, "keymiss", &self.key);
+            }
+
+            ctx.reply(&Frame::Integer(len.unwrap_or(0) as i64)).await?;
+
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct GetRange {
+    key: Bytes,
+    start: i64,
+    end: i64,
+}
+
+impl GetRange {
+    pub fn new(key: Bytes, start: i64, end: i64) -> GetRange {
+        GetRange { key, start, end }
+    }
+
+    /// The bytes from `start` to `end`, both included, counting from the
+    /// end for negative indexes and clamped to the string, like Redis.
+    fn substring(&self, val: &Bytes) -> Bytes {
+        let len = val.len() as i64;
+
+        // Both counting from the end, and in the wrong order, is empty even
+        // if both clamp to the first byte.
+        if self.start < 0 && self.end < 0 && self.start > self.end {
+            return Bytes::new();
+        }
+
+        let start = if self.start < 0 { (len + self.start).max(0) } else { self.start };
+        let end = if self.end < 0 { (len + self.end).max(0) } else { self.end.min(len - 1) };
+
+        match start <= end && len > 0 {
+            true => val.slice(start as usize..=end as usize),
+            false => Bytes::new(),
+        }
+    }
+}
+
+impl CommandExec for GetRange {
+    fn parse(args: &CommandArgs) -> crate::Result<GetRange> {
+        Ok(GetRange::new(args.key(1)?, args.integer(2)?, args.integer(3)?))
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let res = ctx.db.get_db(ctx.client.db_index).read_string(&self.key, |val| val.map(|val| self.substring(val))).await?;
+
+            if res.is_none() {
+                ctx.notify(EventClass::KeyMiss, "keymiss", &self.key);
+            }
+
+            // A missing key reads as an empty string, not nil.
+            ctx.reply(&Frame::Bulk(Some(res.unwrap_or_default()))).await?;
+
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct SetRange {
+    key: Bytes,
+    offset: usize,
+    val: Bytes,
+}
+
+impl SetRange {
+    pub fn new(key: Bytes, offset: usize, val: Bytes) -> SetRange {
+        SetRange { key, offset, val }
+    }
+
+    /// Overwrites the string, returning its new length.
+    fn execute(&self, shard: &mut Shard) -> crate::Result<usize> {
+        // Writing nothing doesn't create the key, nor pad it.
+        if self.val.is_empty() {
+            return Ok(shard.string_len(&self.key)?);
+        }
+
+        Ok(shard.modify_string(&self.key, |buf| {
+            let end = self.offset + self.val.len();
+            pad_string(buf, end);
+            buf[self.offset..end].copy_from_slice(&self.val);
+
+            buf.len()
+        })?)
+    }
+}
+
+impl CommandExec for SetRange {
+    fn parse(args: &CommandArgs) -> crate::Result<SetRange> {
+        let offset = match args.integer(2)? {
+            offset if offset < 0 => return Err("ERR offset is out of range".into()),
+            offset => usize::try_from(offset).map_err(|_| RedisError::StringTooLong)?,
+        };
+
+        Ok(SetRange::new(args.key(1)?, offset, args.bytes(3)?.clone()))
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            if !self.val.is_empty() {
+                check_string_length(self.offset as u64 + self.val.len() as u64)?;
+            }
+
+            let mut shard = ctx.db.get_db(ctx.client.db_index).lock(&self.key).await;
+            let len = self.execute(&mut shard)?;
+
+            if !self.val.is_empty() {
+                ctx.notify(EventClass::String, "setrange", &self.key);
+            }
+            ctx.propagate(match self.val.is_empty() {
+                true => Propagate::None,
+                false => Propagate::Verbatim,
+            }).await?;
+            drop(shard);
+
+            ctx.reply(&Frame::Integer(len as i64)).await?;
+
+            Ok(())
+        })
+    }
+
+    fn apply_replica(self: Box<Self>, ctx: &mut ReplicaContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let mut shard = ctx.db.get_db(ctx.db_index).lock(&self.key).await;
+            self.execute(&mut shard)?;
+            if !self.val.is_empty() {
+                ctx.notify(EventClass::String, "setrange", &self.key);
+            }
+
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Append {
+    key: Bytes,
+    val: Bytes,
+}
+
+impl Append {
+    pub fn new(key: Bytes, val: Bytes) -> Append {
+        Append { key, val }
+    }
+
+    /// Appends to the string, creating it if missing, and returns its new
+    /// length. The key keeps its expiry.
+    fn execute(&self, shard: &mut Shard) -> crate::Result<usize> {
+        Ok(shard.append_string(&self.key, &self.val)?)
+    }
+}
+
+impl CommandExec for Append {
+    fn parse(args: &CommandArgs) -> crate::Result<Append> {
+        Ok(Append::new(args.key(1)?, args.bytes(2)?.clone()))
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let mut shard = ctx.db.get_db(ctx.client.db_index).lock(&self.key).await;
+
+            let current_len = shard.string_len(&self.key)?;
+            check_string_length(current_len as u64 + self.val.len() as u64)?;
+            let len = self.execute(&mut shard)?;
+
+            ctx.notify(EventClass::String, "append", &self.key);
+            ctx.propagate(Propagate::Verbatim).await?;
+            drop(shard);
+
+            ctx.reply(&Frame::Integer(len as i64)).await?;
+
+            Ok(())
+        })
+    }
+
+    fn apply_replica(self: Box<Self>, ctx: &mut ReplicaContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let mut shard = ctx.db.get_db(ctx.db_index).lock(&self.key).await;
+            self.execute(&mut shard)?;
+            ctx.notify(EventClass::String, "append", &self.key);
+
+            Ok(())
+        })
+    }
+}
+
+/// `INCR`, `DECR`, `INCRBY` and `DECRBY`, adding `delta` to the integer a
+/// string holds.
+#[derive(Debug)]
+pub struct IncrBy {
+    key: Bytes,
+    delta: i64,
+}
+
+impl IncrBy {
+    pub fn new(key: Bytes, delta: i64) -> IncrBy {
+        IncrBy { key, delta }
+    }
+
+    /// Adds to the integer, a missing key counting as 0, and returns the
+    /// result. The key keeps its expiry.
+    fn execute(&self, shard: &mut Shard) -> crate::Result<i64> {
+        let current = match shard.get_string(&self.key)? {
+            Some(val) => value::parse_integer(&val).ok_or(RedisError::NotAnInteger)?,
+            None => 0,
+        };
+
+        let res = match current.checked_add(self.delta) {
+            Some(res) => res,
+            None => return Err("ERR increment or decrement would overflow".into()),
+        };
+        // Small results are the shared integer objects, like those of SET.
+        let val = value::shared_integer(res).unwrap_or_else(|| Bytes::from(res.to_string()));
+        shard.set_string(&self.key, val)?;
+
+        Ok(res)
+    }
+}
+
+impl CommandExec for IncrBy {
+    fn parse(args: &CommandArgs) -> crate::Result<IncrBy> {
+        let delta = match args.name() {
+            "incr" => 1,
+            "decr" => -1,
+            "incrby" => args.integer(2)?,
+            // Like Redis, decrementing by the smallest integer fails even if
+            // the result would fit.
+            _ => match args.integer(2)?.checked_neg() {
+                Some(delta) => delta,
+                None => return Err("ERR decrement would overflow".into()),
+            },
+        };
+
+        Ok(IncrBy::new(args.key(1)?, delta))
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let mut shard = ctx.db.get_db(ctx.client.db_index).lock(&self.key).await;
+            let res = self.execute(&mut shard)?;
+
+            ctx.notify(EventClass::String, "incrby", &self.key);
+            ctx.propagate(Propagate::Verbatim).await?;
+            drop(shard);
+
+            ctx.reply(&Frame::Integer(res)).await?;
+
+            Ok(())
+        })
+    }
+
+    fn apply_replica(self: Box<Self>, ctx: &mut ReplicaContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let mut shard = ctx.db.get_db(ctx.db_index).lock(&self.key).await;
+            self.execute(&mut shard)?;
+            ctx.notify(EventClass::String, "incrby", &self.key);
+
+            Ok(())
+        })
+    }
+}
+
+/// `INCRBYFLOAT`, adding `increment` to the number a string holds.
+#[derive(Debug)]
+pub struct IncrByFloat {
+    key: Bytes,
+    increment: f64,
+}
+
+impl IncrByFloat {
+    pub fn new(key: Bytes, increment: f64) -> IncrByFloat {
+        IncrByFloat { key, increment }
+    }
+
+    /// Adds to the number, a missing key counting as 0, and returns the
+    /// result as written back. The key keeps its expiry.
+    fn execute(&self, shard: &mut Shard) -> crate::Result<String> {
+        let current = match shard.get_string(&self.key)? {
+            Some(val) => parse_float(&val).ok_or(RedisError::NotAFloat)?,
+            None => 0.0,
+        };
+
+        let res = current + self.increment;
+        if !res.is_finite() {
+            return Err("ERR increment would produce NaN or Infinity".into());
+        }
+
+        // Written out in full, never in exponent form, with no trailing
+        // zeros, like redis-server does.
+        let res = res.to_string();
+        shard.modify_string(&self.key, |buf| {
+            buf.clear();
+            buf.extend_from_slice(res.as_bytes());
+        })?;
+
+        Ok(res)
+    }
+}
+
+impl CommandExec for IncrByFloat {
+    fn parse(args: &CommandArgs) -> crate::Result<IncrByFloat> {
+        let increment = parse_float(args.bytes(2)?).ok_or(RedisError::NotAFloat)?;
+
+        Ok(IncrByFloat::new(args.key(1)?, increment))
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let mut shard = ctx.db.get_db(ctx.client.db_index).lock(&self.key).await;
+            let res = self.execute(&mut shard)?;
+
+            // Adding the increment again on a replica could round differently,
+            // so it gets the result instead, like redis-server sends it.
+            ctx.notify(EventClass::String, "incrbyfloat", &self.key);
+            ctx.propagate(Propagate::Rewrite(Frame::command(["SET".as_bytes(), &self.key, res.as_bytes(), "KEEPTTL".as_bytes()]))).await?;
+            drop(shard);
+
+            ctx.reply(&Frame::bulk(res)).await?;
+
+            Ok(())
+        })
+    }
+
+    fn apply_replica(self: Box<Self>, ctx: &mut ReplicaContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let mut shard = ctx.db.get_db(ctx.db_index).lock(&self.key).await;
+            self.execute(&mut shard)?;
+            ctx.notify(EventClass::String, "incrbyfloat", &self.key);
+
+            Ok(())
+        })
+    }
+}
+
+/// The number `bytes` holds, as `INCRBYFLOAT` takes it. Infinities parse,
+/// but NaN doesn't.
+fn parse_float(bytes: &[u8]) -> Option<f64> {
+    std::str::from_utf8(bytes).ok()?.parse::<f64>().ok().filter(|float| !float.is_nan())
+}
+
+#[derive(Debug)]
+pub struct Lcs {
+    key1: Bytes,
+    key2: Bytes,
+    len: bool,
+    idx: bool,
+    min_match_len: usize,
+    with_match_len: bool,
+}
+
+impl Lcs {
+    pub fn new(key1: Bytes, key2: Bytes, len: bool, idx: bool, min_match_len: usize, with_match_len: bool) -> Lcs {
+        Lcs { key1, key2, len, idx, min_match_len, with_match_len }
+    }
+}
+
+impl CommandExec for Lcs {
+    fn parse(args: &CommandArgs) -> crate::Result<Lcs> {
+        let (mut len, mut idx_reply, mut min_match_len, mut with_match_len) = (false, false, 0, false);
+
+        let mut idx = 3;
+        while idx < args.len() {
+            match args.string(idx)?.to_uppercase().as_str() {
+                "LEN" => len = true,
+                "IDX" => idx_reply = true,
+                "WITHMATCHLEN" => with_match_len = true,
+                "MINMATCHLEN" if idx + 1 < args.len() => {
+                    // Negative lengths are the same as no minimum.
+                    min_match_len = args.integer(idx + 1)?.max(0) as usize;
+                    idx += 1;
+                },
+                _ => return Err(RedisError::Syntax),
+            }
+            idx += 1;
+        }
+
+        if len && idx_reply {
+            return Err("ERR If you want both the length and indexes, please just use IDX.".into());
+        }
+
+        Ok(Lcs::new(args.key(1)?, args.key(2)?, len, idx_reply, min_match_len, with_match_len))
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let (a, b) = {
+                let mut guards = ctx.db.get_db(ctx.client.db_index).lock_keys(&[&self.key1, &self.key2]).await;
+
+                let a = guards.get_mut(&self.key1).get_string(&self.key1)?.map(Cow::into_owned).unwrap_or_default();
+                let b = guards.get_mut(&self.key2).get_string(&self.key2)?.map(Cow::into_owned).unwrap_or_default();
+                (a, b)
+            };
+
+            // The table is filled in without holding any locks, but still bound
+            // its size so a pair of huge strings can't stall the server.
+            let max_cells = ctx.db.get_config().lcs_max_cells;
+            match lcs::table_cells(a.len(), b.len()) {
+                Some(cells) if cells as u64 <= max_cells => {},
+                _ => return Err("ERR Insufficient memory, transient memory for LCS exceeds lcs-max-cells".into()),
+            }
+
+            let res = lcs::lcs(&a, &b, &ctx.long_operation())?;
+
+            let frame = if self.idx {
+                let matches = res.matches.iter()
+                    .filter(|run| run.len() >= self.min_match_len)
+                    .map(|run| {
+                        let mut fields = vec![
+                            Frame::Array(vec![Frame::Integer(run.a.0 as i64), Frame::Integer(run.a.1 as i64)]),
+                            Frame::Array(vec![Frame::Integer(run.b.0 as i64), Frame::Integer(run.b.1 as i64)]),
+                        ];
+                        if self.with_match_len {
+                            fields.push(Frame::Integer(run.len() as i64));
+                        }
+
+                        Frame::Array(fields)
+                    })
+                    .collect();
+
+                Frame::Array(vec![
+                    Frame::bulk("matches"),
+                    Frame::Array(matches),
+                    Frame::bulk("len"),
+                    Frame::Integer(res.subsequence.len() as i64),
+                ])
+            } else if self.len {
+                Frame::Integer(res.subsequence.len() as i64)
+            } else {
+                Frame::bulk(res.subsequence)
+            };
+
+            ctx.reply(&frame).await?;
+
+            Ok(())
+        })
+    }
+}
+
+/// Fails if a command would grow a string past `proto-max-bulk-len`, which
+/// is checked before anything is allocated. Only clients are held to it: a
+/// replica applies whatever its master accepted.
+pub(super) fn check_string_length(len: u64) -> crate::Result<()> {
+    match len > ConnectionClass::Normal.max_bulk_len() as u64 {
+        true => Err(RedisError::StringTooLong),
+        false => Ok(()),
+    }
+}
@@ -0,0 +1,256 @@
+use bytes::Bytes;
+
+use crate::{get_unix_ts_millis, Frame, RedisError, SharedRedisState, Transaction};
+use super::{BoxFuture, CommandArgs, CommandContext, CommandExec, ReplicaContext, TransactionOutput};
+
+#[derive(Debug)]
+pub struct Multi {}
+
+impl Multi {
+    pub fn new() -> Multi {
+        Multi {}
+    }
+}
+
+impl CommandExec for Multi {
+    fn parse(_args: &CommandArgs) -> crate::Result<Multi> {
+        Ok(Multi::new())
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            if ctx.client.transaction.is_some() {
+                return Err("ERR MULTI calls can not be nested".into());
+            }
+
+            ctx.client.transaction = Some(Transaction::default());
+            ctx.reply(&Frame::simple("OK")).await?;
+
+            Ok(())
+        })
+    }
+
+    fn apply_replica(self: Box<Self>, ctx: &mut ReplicaContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            ctx.transaction = Some(vec![]);
+
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Exec {}
+
+impl Exec {
+    pub fn new() -> Exec {
+        Exec {}
+    }
+}
+
+impl CommandExec for Exec {
+    fn parse(_args: &CommandArgs) -> crate::Result<Exec> {
+        Ok(Exec::new())
+    }
+
+    /// Runs the queued commands with no other command in between, replying
+    /// with all their replies at once. Their writes reach the replicas as a
+    /// single `MULTI`/`EXEC` block.
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let transaction = match ctx.client.transaction.take() {
+                Some(transaction) => transaction,
+                None => return Err("ERR EXEC without MULTI".into()),
+            };
+
+            if transaction.failed {
+                ctx.db.get_watches().unwatch(ctx.client.id);
+                return Err("EXECABORT Transaction discarded because of previous errors.".into());
+            }
+
+            let db = ctx.db.clone();
+            let _guard = db.lock_for_transaction().await;
+
+            let aborted = watched_key_modified(&db, ctx.client.id).await;
+            db.get_watches().unwatch(ctx.client.id);
+            if aborted {
+                ctx.reply(&Frame::Null).await?;
+                return Ok(());
+            }
+
+            *ctx.transaction_output.lock().unwrap_or_else(|err| err.into_inner()) = Some(TransactionOutput::default());
+
+            // A failing command doesn't stop the others, its error is just
+            // the reply in its place. Only a lost connection does, with what
+            // was already written still propagated.
+            let mut res = Ok(());
+            for command in transaction.commands {
+                match command.run(ctx).await {
+                    Ok(()) => {},
+                    Err(err @ RedisError::Io(_)) => {
+                        res = Err(err);
+                        break;
+                    },
+                    Err(err) => ctx.reply(&err.to_frame()).await?,
+                }
+            }
+
+            let output = ctx.transaction_output.lock().unwrap_or_else(|err| err.into_inner()).take().unwrap_or_default();
+            db.get_replication_state().propagate_transaction(&ctx.conn_manager, &output.writes).await?;
+            res?;
+
+            ctx.reply(&Frame::Array(output.replies)).await?;
+
+            Ok(())
+        })
+    }
+
+    /// Applies the commands queued since `MULTI`, keeping clients of the
+    /// replica from seeing any of them before all are applied.
+    fn apply_replica(self: Box<Self>, ctx: &mut ReplicaContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let commands = match ctx.transaction.take() {
+                Some(commands) => commands,
+                None => return Err("Master sent EXEC without MULTI".into()),
+            };
+
+            let db = ctx.db.clone();
+            let _guard = db.lock_for_transaction().await;
+
+            for command in commands {
+                command.apply_replica(ctx).await?;
+            }
+
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Discard {}
+
+impl Discard {
+    pub fn new() -> Discard {
+        Discard {}
+    }
+}
+
+impl CommandExec for Discard {
+    fn parse(_args: &CommandArgs) -> crate::Result<Discard> {
+        Ok(Discard::new())
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            if ctx.client.transaction.take().is_none() {
+                return Err("ERR DISCARD without MULTI".into());
+            }
+            ctx.db.get_watches().unwatch(ctx.client.id);
+
+            ctx.reply(&Frame::simple("OK")).await?;
+
+            Ok(())
+        })
+    }
+
+    fn apply_replica(self: Box<Self>, ctx: &mut ReplicaContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            ctx.transaction = None;
+
+            Ok(())
+        })
+    }
+}
+
+/// Whether one of the keys client `id` watches was modified since, or has
+/// expired since, which fails its transaction.
+async fn watched_key_modified(db: &SharedRedisState, id: u64) -> bool {
+    let watches = db.get_watches();
+    if watches.is_dirty(id) {
+        return true;
+    }
+
+    let now = get_unix_ts_millis();
+    for (db_index, key) in watches.watched_keys(id) {
+        if db.get_db(db_index).read(&key).await.is_expired(&key, now) {
+            return true;
+        }
+    }
+
+    false
+}
+
+#[derive(Debug)]
+pub struct Watch {
+    keys: Vec<Bytes>,
+}
+
+impl Watch {
+    pub fn new(keys: Vec<Bytes>) -> Watch {
+        Watch { keys }
+    }
+}
+
+impl CommandExec for Watch {
+    fn parse(args: &CommandArgs) -> crate::Result<Watch> {
+        Ok(Watch::new(args.keys_from(1)?))
+    }
+
+    /// Watches the keys for the next `EXEC`, which fails if any of them is
+    /// modified until then, by this client or any other.
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            if ctx.client.transaction.is_some() {
+                return Err("ERR WATCH inside MULTI is not allowed".into());
+            }
+
+            let keyspace = ctx.db.get_db(ctx.client.db_index);
+            let watches = ctx.db.get_watches();
+            for key in self.keys.iter() {
+                // An expired key is gone for good, and doesn't count as
+                // modified once watched.
+                let mut shard = keyspace.lock(key).await;
+                if shard.is_expired(key, get_unix_ts_millis()) {
+                    shard.remove_expired(key);
+                }
+                watches.watch(ctx.client.id, ctx.client.db_index, key);
+            }
+
+            ctx.reply(&Frame::simple("OK")).await?;
+
+            Ok(())
+        })
+    }
+
+    fn apply_replica(self: Box<Self>, _ctx: &mut ReplicaContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+#[derive(Debug)]
+pub struct Unwatch {}
+
+impl Unwatch {
+    pub fn new() -> Unwatch {
+        Unwatch {}
+    }
+}
+
+impl CommandExec for Unwatch {
+    fn parse(_args: &CommandArgs) -> crate::Result<Unwatch> {
+        Ok(Unwatch::new())
+    }
+
+    fn apply(self: Box<Self>, ctx: &mut CommandContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            ctx.db.get_watches().unwatch(ctx.client.id);
+            ctx.reply(&Frame::simple("OK")).await?;
+
+            Ok(())
+        })
+    }
+
+    fn apply_replica(self: Box<Self>, _ctx: &mut ReplicaContext) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
+}
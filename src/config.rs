@@ -0,0 +1,335 @@
+use crate::acl::User;
+use crate::command_table;
+use crate::cron;
+use crate::log;
+use crate::notify;
+use crate::value::CompactLimits;
+
+/// Runtime configuration, settable from the command line (`--name value`) and
+/// through `CONFIG SET`.
+#[derive(Clone, Debug)]
+pub struct ServerConfig {
+    /// Commands taking at least this many microseconds are recorded in the
+    /// slow log. Zero logs every command, a negative value disables logging.
+    pub slowlog_log_slower_than: i64,
+    /// Maximum number of entries kept in the slow log.
+    pub slowlog_max_len: u64,
+    /// Number of logical databases. Can only be set at startup.
+    pub databases: usize,
+    /// Number of independently locked shards each database is split into.
+    /// Defaults to the number of cores, can only be set at startup.
+    pub keyspace_shards: usize,
+    /// How keys are picked for eviction. The LFU policies also switch access
+    /// tracking from idle time to access frequency.
+    pub maxmemory_policy: String,
+    /// How quickly the LFU counter saturates, higher is slower.
+    pub lfu_log_factor: u32,
+    /// Minutes after which an unaccessed key's LFU counter is decremented.
+    pub lfu_decay_time: u32,
+    /// Largest table `LCS` may fill in, as the product of the lengths of the
+    /// two strings (each plus one). The table takes 4 bytes per cell.
+    pub lcs_max_cells: u64,
+    /// Longest bulk string a client may send.
+    pub proto_max_bulk_len: u64,
+    /// Longest bulk string a replica accepts from its master, which sends
+    /// the RDB file as one, so it's well above `proto_max_bulk_len`.
+    pub repl_max_bulk_len: u64,
+    /// File the replication stream is traced to, see `ReplTrace`. Empty
+    /// for no tracing.
+    pub repl_trace: String,
+    /// Size at which the trace file is rotated.
+    pub repl_trace_max_size: u64,
+    /// Seconds a replica waits on its master while syncing: to connect, for
+    /// each reply of the handshake, and for more of the RDB file.
+    pub repl_syncio_timeout: u64,
+    /// Milliseconds a long operation may run before other clients get a
+    /// `BUSY` error instead of waiting for it. Zero or negative never does.
+    pub busy_reply_threshold: i64,
+    /// Most fields a hash may have and still be kept compact.
+    pub hash_max_listpack_entries: usize,
+    /// Longest field or value a compact hash may have.
+    pub hash_max_listpack_value: usize,
+    /// Most members a set of integers may have and still be kept as one.
+    pub set_max_intset_entries: usize,
+    pub set_max_listpack_entries: usize,
+    pub set_max_listpack_value: usize,
+    pub zset_max_listpack_entries: usize,
+    pub zset_max_listpack_value: usize,
+    /// Number of elements a streamed reply, like that of `HGETALL`, gathers
+    /// before writing them to the client.
+    pub reply_stream_chunk_size: usize,
+    /// Which keyspace notifications are published, see `notify`.
+    pub notify_keyspace_events: String,
+    /// Least severe lines logged, one of `log::LEVELS`.
+    pub loglevel: String,
+    /// Times a second background duties like active expiry run, see `Cron`.
+    pub hz: u64,
+    /// File of ACL user declarations, one `user <name> <rules>` per line.
+    /// Can only be set at startup.
+    pub aclfile: String,
+    /// User declarations given one by one, each `--user "<name> <rules>"`.
+    /// Can only be set at startup.
+    pub users: Vec<String>,
+    /// Commands given another name, or none to disable them, each with
+    /// `--rename-command <command> <new-name>`. Only set at startup, and
+    /// not a parameter of `CONFIG`, like in redis-server.
+    pub rename_commands: Vec<(String, String)>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            slowlog_log_slower_than: 10000,
+            slowlog_max_len: 128,
+            databases: 16,
+            keyspace_shards: std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+            maxmemory_policy: "noeviction".to_string(),
+            lfu_log_factor: 10,
+            lfu_decay_time: 1,
+            // 512MB, like the transient memory limit of LCS in Redis.
+            lcs_max_cells: 1 << 27,
+            proto_max_bulk_len: 512 * 1024 * 1024,
+            repl_max_bulk_len: 1 << 32,
+            repl_trace: String::new(),
+            repl_trace_max_size: 64 * 1024 * 1024,
+            repl_syncio_timeout: 60,
+            busy_reply_threshold: 5000,
+            hash_max_listpack_entries: 128,
+            hash_max_listpack_value: 64,
+            set_max_intset_entries: 512,
+            set_max_listpack_entries: 128,
+            set_max_listpack_value: 64,
+            zset_max_listpack_entries: 128,
+            zset_max_listpack_value: 64,
+            reply_stream_chunk_size: 1024,
+            notify_keyspace_events: String::new(),
+            loglevel: "notice".to_string(),
+            hz: 10,
+            aclfile: String::new(),
+            users: vec![],
+            rename_commands: vec![],
+        }
+    }
+}
+
+impl ServerConfig {
+    pub const PARAMETERS: &'static [&'static str] = &[
+        "slowlog-log-slower-than",
+        "slowlog-max-len",
+        "databases",
+        "keyspace-shards",
+        "maxmemory-policy",
+        "lfu-log-factor",
+        "lfu-decay-time",
+        "lcs-max-cells",
+        "proto-max-bulk-len",
+        "repl-max-bulk-len",
+        "repl-trace",
+        "repl-trace-max-size",
+        "repl-syncio-timeout",
+        "busy-reply-threshold",
+        "hash-max-listpack-entries",
+        "hash-max-listpack-value",
+        "set-max-intset-entries",
+        "set-max-listpack-entries",
+        "set-max-listpack-value",
+        "zset-max-listpack-entries",
+        "zset-max-listpack-value",
+        "reply-stream-chunk-size",
+        "notify-keyspace-events",
+        "loglevel",
+        "hz",
+        "aclfile",
+        "user",
+    ];
+
+    /// Accepted values of `maxmemory-policy`.
+    pub const MAXMEMORY_POLICIES: &'static [&'static str] = &[
+        "volatile-lru",
+        "volatile-lfu",
+        "volatile-random",
+        "volatile-ttl",
+        "allkeys-lru",
+        "allkeys-lfu",
+        "allkeys-random",
+        "noeviction",
+    ];
+
+    /// Parameters which can't be changed with `CONFIG SET`.
+    pub const IMMUTABLE_PARAMETERS: &'static [&'static str] = &[
+        "databases",
+        "keyspace-shards",
+        "aclfile",
+        "user",
+    ];
+
+    /// Returns the current value of the given parameter.
+    pub fn get(&self, name: &str) -> Option<String> {
+        match name.to_lowercase().as_str() {
+            "slowlog-log-slower-than" => Some(self.slowlog_log_slower_than.to_string()),
+            "slowlog-max-len" => Some(self.slowlog_max_len.to_string()),
+            "databases" => Some(self.databases.to_string()),
+            "keyspace-shards" => Some(self.keyspace_shards.to_string()),
+            "maxmemory-policy" => Some(self.maxmemory_policy.clone()),
+            "lfu-log-factor" => Some(self.lfu_log_factor.to_string()),
+            "lfu-decay-time" => Some(self.lfu_decay_time.to_string()),
+            "lcs-max-cells" => Some(self.lcs_max_cells.to_string()),
+            "proto-max-bulk-len" => Some(self.proto_max_bulk_len.to_string()),
+            "repl-max-bulk-len" => Some(self.repl_max_bulk_len.to_string()),
+            "repl-trace" => Some(self.repl_trace.clone()),
+            "repl-trace-max-size" => Some(self.repl_trace_max_size.to_string()),
+            "repl-syncio-timeout" => Some(self.repl_syncio_timeout.to_string()),
+            "busy-reply-threshold" => Some(self.busy_reply_threshold.to_string()),
+            "hash-max-listpack-entries" => Some(self.hash_max_listpack_entries.to_string()),
+            "hash-max-listpack-value" => Some(self.hash_max_listpack_value.to_string()),
+            "set-max-intset-entries" => Some(self.set_max_intset_entries.to_string()),
+            "set-max-listpack-entries" => Some(self.set_max_listpack_entries.to_string()),
+            "set-max-listpack-value" => Some(self.set_max_listpack_value.to_string()),
+            "zset-max-listpack-entries" => Some(self.zset_max_listpack_entries.to_string()),
+            "zset-max-listpack-value" => Some(self.zset_max_listpack_value.to_string()),
+            "reply-stream-chunk-size" => Some(self.reply_stream_chunk_size.to_string()),
+            "notify-keyspace-events" => Some(self.notify_keyspace_events.clone()),
+            "loglevel" => Some(self.loglevel.clone()),
+            "hz" => Some(self.hz.to_string()),
+            "aclfile" => Some(self.aclfile.clone()),
+            // Users are listed by `ACL LIST` instead.
+            "user" => None,
+            _ => None,
+        }
+    }
+
+    /// Returns all `(name, value)` pairs matching the given parameter name,
+    /// `*` matches every parameter.
+    pub fn get_matching(&self, pattern: &str) -> Vec<(String, String)> {
+        Self::PARAMETERS
+            .iter()
+            .filter(|name| pattern == "*" || name.eq_ignore_ascii_case(pattern))
+            .filter_map(|name| self.get(name).map(|value| (name.to_string(), value)))
+            .collect()
+    }
+
+    /// Sets a parameter. This doesn't check `IMMUTABLE_PARAMETERS`, since it
+    /// is also used to apply the command line arguments.
+    pub fn set(&mut self, name: &str, value: &str) -> crate::Result<()> {
+        match name.to_lowercase().as_str() {
+            "slowlog-log-slower-than" => self.slowlog_log_slower_than = parse_integer(name, value)?,
+            "slowlog-max-len" => self.slowlog_max_len = parse_integer(name, value)?,
+            "databases" => match parse_integer(name, value)? {
+                0 => return Err("ERR CONFIG SET failed (possibly related to argument 'databases') - argument must be between 1 and 2147483647 inclusive".into()),
+                databases => self.databases = databases,
+            },
+            "keyspace-shards" => match parse_integer(name, value)? {
+                shards if shards == 0 || shards > 1024 => return Err("ERR CONFIG SET failed (possibly related to argument 'keyspace-shards') - argument must be between 1 and 1024 inclusive".into()),
+                shards => self.keyspace_shards = shards,
+            },
+            "maxmemory-policy" => match Self::MAXMEMORY_POLICIES.iter().find(|policy| policy.eq_ignore_ascii_case(value)) {
+                Some(policy) => self.maxmemory_policy = policy.to_string(),
+                None => return Err(format!(
+                    "ERR CONFIG SET failed (possibly related to argument 'maxmemory-policy') - argument(s) must be one of the following: {}",
+                    Self::MAXMEMORY_POLICIES.join(", ")
+                ).into()),
+            },
+            "lfu-log-factor" => self.lfu_log_factor = parse_integer(name, value)?,
+            "lfu-decay-time" => self.lfu_decay_time = parse_integer(name, value)?,
+            "lcs-max-cells" => self.lcs_max_cells = parse_integer(name, value)?,
+            "proto-max-bulk-len" | "repl-max-bulk-len" => {
+                let len = match parse_integer(name, value)? {
+                    0 => return Err(format!("ERR CONFIG SET failed (possibly related to argument '{}') - argument must be at least 1", name).into()),
+                    len => len,
+                };
+
+                match name.to_lowercase().as_str() {
+                    "proto-max-bulk-len" => self.proto_max_bulk_len = len,
+                    _ => self.repl_max_bulk_len = len,
+                }
+            },
+            "repl-trace" => self.repl_trace = value.to_string(),
+            "repl-trace-max-size" => self.repl_trace_max_size = parse_integer(name, value)?,
+            "repl-syncio-timeout" => match parse_integer(name, value)? {
+                0 => return Err("ERR CONFIG SET failed (possibly related to argument 'repl-syncio-timeout') - argument must be at least 1".into()),
+                timeout => self.repl_syncio_timeout = timeout,
+            },
+            "busy-reply-threshold" => self.busy_reply_threshold = parse_integer(name, value)?,
+            "hash-max-listpack-entries" => self.hash_max_listpack_entries = parse_integer(name, value)?,
+            "hash-max-listpack-value" => self.hash_max_listpack_value = parse_integer(name, value)?,
+            "set-max-intset-entries" => self.set_max_intset_entries = parse_integer(name, value)?,
+            "set-max-listpack-entries" => self.set_max_listpack_entries = parse_integer(name, value)?,
+            "set-max-listpack-value" => self.set_max_listpack_value = parse_integer(name, value)?,
+            "zset-max-listpack-entries" => self.zset_max_listpack_entries = parse_integer(name, value)?,
+            "zset-max-listpack-value" => self.zset_max_listpack_value = parse_integer(name, value)?,
+            "reply-stream-chunk-size" => match parse_integer(name, value)? {
+                0 => return Err("ERR CONFIG SET failed (possibly related to argument 'reply-stream-chunk-size') - argument must be at least 1".into()),
+                size => self.reply_stream_chunk_size = size,
+            },
+            "notify-keyspace-events" => match notify::parse_flags(value) {
+                Some(flags) => self.notify_keyspace_events = notify::format_flags(flags),
+                None => return Err("ERR CONFIG SET failed (possibly related to argument 'notify-keyspace-events') - Invalid event class character. Use 'Ag$lshzxeKEtmdn'.".into()),
+            },
+            // Clamped rather than refused, like in redis-server.
+            "hz" => self.hz = parse_integer::<u64>(name, value)?.clamp(cron::MIN_HZ, cron::MAX_HZ),
+            "loglevel" => match log::LEVELS.iter().find(|level| level.eq_ignore_ascii_case(value)) {
+                Some(level) => self.loglevel = level.to_string(),
+                None => return Err(format!(
+                    "ERR CONFIG SET failed (possibly related to argument 'loglevel') - argument(s) must be one of the following: {}",
+                    log::LEVELS.join(", ")
+                ).into()),
+            },
+            "aclfile" => self.aclfile = value.to_string(),
+            "user" => {
+                User::parse(value)?;
+                self.users.push(value.to_string());
+            },
+            _ => return Err(format!("ERR Unknown option or number of arguments for CONFIG SET - '{}'", name).into()),
+        }
+
+        Ok(())
+    }
+
+    /// Renames `command` to `new_name`, or disables it if that's empty.
+    pub fn rename_command(&mut self, command: &str, new_name: &str) -> crate::Result<()> {
+        let spec = match command_table::lookup(&command.to_lowercase()) {
+            Some(spec) => spec,
+            None => return Err(format!("ERR No such command in rename-command: {}", command).into()),
+        };
+
+        let renamed_away = |name: &str| self.rename_commands.iter().any(|(renamed, _)| renamed.eq_ignore_ascii_case(name));
+        if renamed_away(spec.name) {
+            return Err(format!("ERR Command renamed twice in rename-command: {}", command).into());
+        }
+
+        // The new name may be that of another command renamed away, but
+        // never one still in use.
+        let taken = self.rename_commands.iter().any(|(_, other)| other.eq_ignore_ascii_case(new_name))
+            || command_table::lookup(&new_name.to_lowercase()).map_or(false, |other| !renamed_away(other.name));
+        if !new_name.is_empty() && taken {
+            return Err(format!("ERR Target command name already exists in rename-command: {}", new_name).into());
+        }
+
+        self.rename_commands.push((spec.name.to_string(), new_name.to_string()));
+
+        Ok(())
+    }
+
+    /// The thresholds under which collections are kept compact.
+    pub fn compact_limits(&self) -> CompactLimits {
+        CompactLimits {
+            hash_entries: self.hash_max_listpack_entries,
+            hash_value: self.hash_max_listpack_value,
+            set_intset_entries: self.set_max_intset_entries,
+            set_entries: self.set_max_listpack_entries,
+            set_value: self.set_max_listpack_value,
+            zset_entries: self.zset_max_listpack_entries,
+            zset_value: self.zset_max_listpack_value,
+        }
+    }
+}
+
+fn parse_integer<T: std::str::FromStr>(name: &str, value: &str) -> crate::Result<T> {
+    value.parse::<T>().map_err(|_| {
+        format!(
+            "ERR CONFIG SET failed (possibly related to argument '{}') - argument couldn't be parsed into an integer",
+            name
+        )
+        .into()
+    })
+}
@@ -1,29 +1,94 @@
 use std::collections::HashMap;
+use std::future::Future;
 use std::io::{self, Cursor};
-use std::sync::Arc;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{self, Arc};
+use std::time::Duration;
 
 use bytes::{Buf, BytesMut};
 use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
 use tokio::net::TcpStream;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncReadExt, AsyncWriteExt, BufWriter};
 use tokio::sync::Mutex;
 
 use crate::{debug, DELIM};
 use crate::frame::{self, Frame};
 
+static MAX_BULK_LEN: AtomicUsize = AtomicUsize::new(512 * 1024 * 1024);
+static MAX_MASTER_BULK_LEN: AtomicUsize = AtomicUsize::new(1 << 32);
+
+/// Applies the `proto-max-bulk-len` and `repl-max-bulk-len` settings to every
+/// connection.
+pub fn configure(max_bulk_len: u64, max_master_bulk_len: u64) {
+    MAX_BULK_LEN.store(usize::try_from(max_bulk_len).unwrap_or(usize::MAX), Ordering::Relaxed);
+    MAX_MASTER_BULK_LEN.store(usize::try_from(max_master_bulk_len).unwrap_or(usize::MAX), Ordering::Relaxed);
+}
+
+/// Who is on the other end of a connection, which decides how much it may
+/// send at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionClass {
+    /// A client of this server.
+    Normal,
+    /// A replica of this server, once it asked for a sync.
+    Replica,
+    /// This replica's link to its master, which sends the whole RDB file as
+    /// a single bulk string.
+    MasterLink,
+}
+
+impl ConnectionClass {
+    /// Longest bulk string accepted on the connection.
+    pub fn max_bulk_len(self) -> usize {
+        match self {
+            ConnectionClass::Normal | ConnectionClass::Replica => MAX_BULK_LEN.load(Ordering::Relaxed),
+            ConnectionClass::MasterLink => MAX_MASTER_BULK_LEN.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// A connection's class, shared by its reader and the `ConnectionManager`,
+/// which may change it while the reader waits for data.
+type SharedClass = Arc<sync::Mutex<ConnectionClass>>;
+
 pub struct ReadConnection {
     stream: OwnedReadHalf,
     buffer: BytesMut,
+    class: SharedClass,
+    /// Total size of the frames parsed so far, as they were sent.
+    consumed: u64,
+    /// How long a read may go without receiving anything before failing
+    /// with `TimedOut`. None waits for as long as it takes.
+    idle_timeout: Option<Duration>,
 }
 
 impl ReadConnection {
-    pub fn new(stream: OwnedReadHalf) -> ReadConnection {
+    pub fn new(stream: OwnedReadHalf, class: ConnectionClass) -> ReadConnection {
         ReadConnection {
             stream,
             buffer: BytesMut::with_capacity(4096),
+            class: Arc::new(sync::Mutex::new(class)),
+            consumed: 0,
+            idle_timeout: None,
         }
     }
 
+    /// Number of bytes the frames read so far took on the wire.
+    pub fn consumed(&self) -> u64 {
+        self.consumed
+    }
+
+    fn class(&self) -> ConnectionClass {
+        *self.class.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// See `idle_timeout`. The timer restarts whenever data arrives, so a
+    /// large frame can take as long as it needs while it keeps coming.
+    pub fn set_idle_timeout(&mut self, timeout: Option<Duration>) {
+        self.idle_timeout = timeout;
+    }
+
     /// Read a frame from the connection.
     /// 
     /// Returns `None` if EOF is read.
@@ -40,7 +105,16 @@ impl ReadConnection {
             // We don't have enough data to parse a frame.
             // Attempt to read more data from the socket to the buffer.
 
-            if 0 == self.stream.read_buf(&mut self.buffer).await? {
+            let read = self.stream.read_buf(&mut self.buffer);
+            let n = match self.idle_timeout {
+                Some(timeout) => match tokio::time::timeout(timeout, read).await {
+                    Ok(n) => n?,
+                    Err(_) => return Err(io::Error::new(io::ErrorKind::TimedOut, "Timed out waiting for data").into()),
+                },
+                None => read.await?,
+            };
+
+            if n == 0 {
                 // No more data was read from the buffer, meaning the remote end
                 // closed the connection. For this to have been a clean
                 // shutdown, there should be no data in the buffer, otherwise
@@ -49,23 +123,32 @@ impl ReadConnection {
                     debug!("read_frame(): Exit from empty");
                     return Ok(None);
                 } else {
-                    return Err("Connection reset by peer".into());
+                    return Err(io::Error::new(io::ErrorKind::ConnectionReset, "Connection reset by peer").into());
                 }
             }
             debug!("read_frame(): Continuing loop");
         }
     }
 
-    /// Parse a frame to the connection.
-    fn parse_frame(&mut self, expect_file: bool) -> crate::Result<Option<Frame>> {
+    /// Parse a frame out of the data already read, if it holds a whole one.
+    pub fn parse_frame(&mut self, expect_file: bool) -> crate::Result<Option<Frame>> {
         debug!("parse_frame(): Start");
         use frame::Error::Incomplete;
 
+        // Masters send bare newlines to keep the link alive while they
+        // prepare the RDB file. They aren't frames, so they don't count as
+        // consumed either.
+        let class = self.class();
+        if class == ConnectionClass::MasterLink {
+            let newlines = self.buffer.iter().take_while(|byte| **byte == b'\n').count();
+            self.buffer.advance(newlines);
+        }
+
         let mut buf = Cursor::new(&self.buffer[..]);
 
         debug!("parse_frame(): match");
 
-        match Frame::check(&mut buf, expect_file) {
+        match Frame::check(&mut buf, expect_file, class.max_bulk_len()) {
             Ok(_) => {
                 // Get the current position in the buffer.
                 let len = buf.position() as usize;
@@ -78,6 +161,7 @@ impl ReadConnection {
 
                 // Advance the buffer past this frame.
                 self.buffer.advance(len);
+                self.consumed += len as u64;
 
                 Ok(Some(frame))
             },
@@ -87,76 +171,106 @@ impl ReadConnection {
     }
 }
 
+/// The writing half of a connection. Frames are buffered, and only sent once
+/// the connection is flushed, so a batch of them goes out in a few syscalls
+/// instead of several per frame.
 pub struct WriteConnection {
-    stream: OwnedWriteHalf,
+    stream: BufWriter<OwnedWriteHalf>,
 }
 
 impl WriteConnection {
     pub fn new(stream: OwnedWriteHalf) -> WriteConnection {
         WriteConnection {
-            stream
+            stream: BufWriter::new(stream),
         }
     }
 
-    /// Write a frame to the connection.
+    /// Write a frame to the connection, sending it right away along with any
+    /// queued before it.
     pub async fn write_frame(&mut self, frame: &Frame) -> io::Result<()> {
-        match frame {
-            Frame::Array(val) => {
-                self.stream.write_u8(b'*').await?;
+        self.queue_frame(frame).await?;
+        self.flush().await
+    }
 
-                self.write_decimal(val.len() as u64).await?;
+    /// Buffer a frame, to be sent with the next flush.
+    pub async fn queue_frame(&mut self, frame: &Frame) -> io::Result<()> {
+        self.write_value(frame).await
+    }
 
-                for entry in &**val {
-                    self.write_value(entry).await?;
-                }
-            }
-            _ => self.write_value(frame).await?
-        }
+    /// Send the queued frames.
+    pub async fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush().await
+    }
 
-        Ok(())
+    /// Buffer the header of an array of `len` elements, which are then
+    /// queued one by one.
+    pub async fn queue_array_header(&mut self, len: usize) -> io::Result<()> {
+        self.stream.write_u8(b'*').await?;
+        self.write_decimal(len as u64).await
     }
 
-    async fn write_value(&mut self, frame: &Frame) -> io::Result<()> {
-        match frame {
-            Frame::Bulk(bytes) => {
-                if let Some(content) = bytes {
-                    let len = content.len();
+    // Arrays can be nested, so the recursive future needs to be boxed.
+    fn write_value<'a>(&'a mut self, frame: &'a Frame) -> Pin<Box<dyn Future<Output = io::Result<()>> + Send + 'a>> {
+        Box::pin(async move {
+            match frame {
+                Frame::Array(val) => {
+                    self.stream.write_u8(b'*').await?;
 
-                    self.stream.write_u8(b'$').await?;
-                    self.write_decimal(len as u64).await?;
+                    self.write_decimal(val.len() as u64).await?;
 
-                    self.stream.write_all(content).await?;
+                    for entry in val {
+                        self.write_value(entry).await?;
+                    }
+                },
+                Frame::Integer(val) => {
+                    self.stream.write_u8(b':').await?;
+
+                    self.stream.write_all(val.to_string().as_bytes()).await?;
                     self.stream.write_all(DELIM).await?;
-                } else {
-                    self.stream.write_u8(b'$').await?;
-                    self.stream.write_u8(b'-').await?;
-                    self.stream.write_u8(b'1').await?;
+                },
+                Frame::Null => {
+                    self.stream.write_all(b"$-1").await?;
                     self.stream.write_all(DELIM).await?;
-                }
-            },
-            Frame::Simple(val) => {
-                self.stream.write_u8(b'+').await?;
-
-                self.stream.write_all(val.as_bytes()).await?;
-                self.stream.write_all(DELIM).await?;
-            },
-            Frame::Error(val) => {
-                self.stream.write_u8(b'-').await?;
+                },
+                Frame::Bulk(bytes) => {
+                    if let Some(content) = bytes {
+                        let len = content.len();
+
+                        self.stream.write_u8(b'$').await?;
+                        self.write_decimal(len as u64).await?;
+
+                        self.stream.write_all(content).await?;
+                        self.stream.write_all(DELIM).await?;
+                    } else {
+                        self.stream.write_u8(b'$').await?;
+                        self.stream.write_u8(b'-').await?;
+                        self.stream.write_u8(b'1').await?;
+                        self.stream.write_all(DELIM).await?;
+                    }
+                },
+                Frame::Simple(val) => {
+                    self.stream.write_u8(b'+').await?;
+
+                    self.stream.write_all(val.as_bytes()).await?;
+                    self.stream.write_all(DELIM).await?;
+                },
+                Frame::Error(val) => {
+                    self.stream.write_u8(b'-').await?;
 
-                self.stream.write_all(val.as_bytes()).await?;
-                self.stream.write_all(DELIM).await?;
-            },
-            Frame::File(contents) => {
-                let len = contents.len();
-                self.stream.write_u8(b'$').await?;
-                self.write_decimal(len as u64).await?;
+                    self.stream.write_all(val.as_bytes()).await?;
+                    self.stream.write_all(DELIM).await?;
+                },
+                Frame::File(contents) => {
+                    let len = contents.len();
+                    self.stream.write_u8(b'$').await?;
+                    self.write_decimal(len as u64).await?;
 
-                self.stream.write_all(contents).await?;
-            },
-            _ => {}
-        }
+                    self.stream.write_all(contents).await?;
+                },
+            }
 
-        Ok(())
+            Ok(())
+        })
     }
 
     async fn write_decimal(&mut self, val: u64) -> io::Result<()> {
@@ -181,11 +295,15 @@ pub struct Connection {
 
 impl Connection {
     pub fn new(stream: TcpStream) -> Connection {
+        Connection::with_class(stream, ConnectionClass::Normal)
+    }
+
+    pub fn with_class(stream: TcpStream, class: ConnectionClass) -> Connection {
         let (r, w) = stream.into_split();
 
         Connection {
             w_conn: WriteConnection::new(w),
-            r_conn: ReadConnection::new(r),
+            r_conn: ReadConnection::new(r, class),
         }
     }
 
@@ -196,67 +314,86 @@ impl Connection {
     pub async fn write_frame(&mut self, frame: &Frame) -> io::Result<()> {
         self.w_conn.write_frame(frame).await
     }
+
+    /// See `ReadConnection::consumed`.
+    pub fn consumed(&self) -> u64 {
+        self.r_conn.consumed()
+    }
+
+    /// See `ReadConnection::set_idle_timeout`.
+    pub fn set_idle_timeout(&mut self, timeout: Option<Duration>) {
+        self.r_conn.set_idle_timeout(timeout);
+    }
+
+    pub async fn queue_frame(&mut self, frame: &Frame) -> io::Result<()> {
+        self.w_conn.queue_frame(frame).await
+    }
+
+    pub async fn flush(&mut self) -> io::Result<()> {
+        self.w_conn.flush().await
+    }
 }
 
+/// The writing halves of the clients' connections, by address, for anything
+/// to reply or push messages to a client. Reading is left to each
+/// connection's own reader, which `add` hands back.
+#[derive(Clone)]
 pub struct ConnectionManager {
-    read_connections: Arc<Mutex<HashMap<String, Arc<Mutex<ReadConnection>>>>>,
+    classes: Arc<Mutex<HashMap<String, SharedClass>>>,
     write_connections: Arc<Mutex<HashMap<String, Arc<Mutex<WriteConnection>>>>>
 }
 
 impl ConnectionManager {
     pub fn new() -> Self {
         ConnectionManager {
-            read_connections: Arc::new(Mutex::new(HashMap::new())),
+            classes: Arc::new(Mutex::new(HashMap::new())),
             write_connections: Arc::new(Mutex::new(HashMap::new()))
         }
     }
 
-    async fn get_read_conn(&self, addr: String) -> Option<Arc<Mutex<ReadConnection>>> {
-        let connections = self.read_connections.lock().await;
-
-        if let Some(conn) = connections.get(&addr) {
-            return Some(conn.clone());
-        }
-
-        None
-    }
-
-    async fn get_write_conn(&self, addr: String) -> Option<Arc<Mutex<WriteConnection>>> {
+    async fn get_write_conn(&self, addr: &str) -> Option<Arc<Mutex<WriteConnection>>> {
         let connections = self.write_connections.lock().await;
 
-        if let Some(conn) = connections.get(&addr) {
+        if let Some(conn) = connections.get(addr) {
             return Some(conn.clone());
         }
 
         None
     }
 
-    pub async fn add(&self, addr: String, stream: TcpStream) {
+    /// Adds the connection, returning its reading half for the caller to
+    /// read commands from.
+    pub async fn add(&self, addr: String, stream: TcpStream) -> ReadConnection {
         let (rconn, wconn) = stream.into_split();
 
-        let mut read_connections = self.read_connections.lock().await;
-        let rconn = Arc::new(Mutex::new(ReadConnection::new(rconn)));
-        read_connections.insert(addr.clone(), rconn.clone());
+        let rconn = ReadConnection::new(rconn, ConnectionClass::Normal);
+        self.classes.lock().await.insert(addr.clone(), rconn.class.clone());
 
         let mut write_connections = self.write_connections.lock().await;
         let wconn = Arc::new(Mutex::new(WriteConnection::new(wconn)));
         write_connections.insert(addr, wconn.clone());
-    }
 
-    pub async fn read_frame(&self, addr: String, expect_file: bool) -> crate::Result<Option<Frame>> {
-        let conn = self.get_read_conn(addr).await;
+        rconn
+    }
 
-        if let Some(conn) = conn {
-            debug!("Getting conn lock");
-            let mut conn = conn.lock().await;
-            debug!("Got conn lock");
-            conn.read_frame(expect_file).await
-        } else {
-            Err("Connection not found".into())
+    /// Changes the class of the connection, which applies from the next
+    /// frame its reader parses.
+    pub async fn set_class(&self, addr: &str, class: ConnectionClass) {
+        if let Some(shared) = self.classes.lock().await.get(addr) {
+            *shared.lock().unwrap_or_else(|err| err.into_inner()) = class;
         }
     }
 
-    pub async fn write_frame(&self, addr: String, frame: &Frame) -> io::Result<()> {
+    /// Forgets the connection. The socket is closed once its reader is
+    /// dropped and the last in-flight write on it completes.
+    pub async fn remove(&self, addr: &str) {
+        self.classes.lock().await.remove(addr);
+        self.write_connections.lock().await.remove(addr);
+    }
+
+    /// Writes a frame and flushes the connection; see
+    /// `WriteConnection::write_frame`.
+    pub async fn write_frame(&self, addr: &str, frame: &Frame) -> io::Result<()> {
         debug!("Writing to addr: {}", addr);
         let conn = self.get_write_conn(addr).await;
         debug!("Got conn");
@@ -271,10 +408,48 @@ impl ConnectionManager {
         }
     }
 
-    pub fn clone(&self) -> Self {
-        ConnectionManager {
-            read_connections: self.read_connections.clone(),
-            write_connections: self.write_connections.clone()
+    /// Buffers a frame, to be sent by the next flush or write of the
+    /// connection.
+    pub async fn queue_frame(&self, addr: &str, frame: &Frame) -> io::Result<()> {
+        match self.get_write_conn(addr).await {
+            Some(conn) => conn.lock().await.queue_frame(frame).await,
+            None => Err(io::Error::new(io::ErrorKind::NotFound, "Connection not found")),
         }
     }
+
+    /// Queues the header of an array whose elements are queued next; see
+    /// `WriteConnection::queue_array_header`.
+    pub async fn queue_array_header(&self, addr: &str, len: usize) -> io::Result<()> {
+        match self.get_write_conn(addr).await {
+            Some(conn) => conn.lock().await.queue_array_header(len).await,
+            None => Err(io::Error::new(io::ErrorKind::NotFound, "Connection not found")),
+        }
+    }
+
+    /// Queues several frames at once, taking the connection's lock once.
+    pub async fn queue_frames(&self, addr: &str, frames: &[Frame]) -> io::Result<()> {
+        match self.get_write_conn(addr).await {
+            Some(conn) => {
+                let mut conn = conn.lock().await;
+                for frame in frames {
+                    conn.queue_frame(frame).await?;
+                }
+                Ok(())
+            },
+            None => Err(io::Error::new(io::ErrorKind::NotFound, "Connection not found")),
+        }
+    }
+
+    pub async fn flush(&self, addr: &str) -> io::Result<()> {
+        match self.get_write_conn(addr).await {
+            Some(conn) => conn.lock().await.flush().await,
+            None => Err(io::Error::new(io::ErrorKind::NotFound, "Connection not found")),
+        }
+    }
+}
+
+impl Default for ConnectionManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
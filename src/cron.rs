@@ -0,0 +1,101 @@
+//! The server's periodic duties, run from a single task `hz` times a
+//! second, like the `serverCron` of redis-server.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::{evict, random, ConnectionManager, SharedRedisState};
+
+/// Fewest and most ticks a second. Values of `hz` outside are clamped, like
+/// in redis-server.
+pub const MIN_HZ: u64 = 1;
+pub const MAX_HZ: u64 = 500;
+
+/// How often the LRU clock is updated. Its resolution is a second, so more
+/// often than every tick is plenty at high `hz`.
+const LRU_CLOCK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Share of a tick active expiry may take, in percent, as in redis-server.
+const ACTIVE_EXPIRE_BUDGET_PERCENT: u32 = 25;
+
+/// Most a tick is moved off schedule, as a fraction of its period.
+const JITTER: f64 = 0.1;
+
+static HZ: AtomicU64 = AtomicU64::new(10);
+
+/// Applies the `hz` setting to the cron, from its next tick.
+pub fn configure(hz: u64) {
+    HZ.store(hz.clamp(MIN_HZ, MAX_HZ), Ordering::Relaxed);
+}
+
+/// The number of ticks a second.
+pub fn hz() -> u64 {
+    HZ.load(Ordering::Relaxed)
+}
+
+fn period() -> Duration {
+    Duration::from_secs(1) / hz() as u32
+}
+
+/// Runs the periodic duties of a server, each tick running those due:
+///
+/// - the LRU clock is updated every `LRU_CLOCK_INTERVAL`,
+/// - expired keys are removed every tick, for at most a quarter of it,
+///   carrying on from where the previous tick stopped. Replicas leave
+///   expired keys to their master, which propagates their deletion.
+/// - the deletions of keys expired since the last propagated write go out
+///   to the replicas every tick.
+///
+/// Ticks start at a random phase and are each moved by up to a tenth of
+/// their period, so servers started together on one machine don't do their
+/// work in lockstep. `tick` runs one on demand, e.g. to have keys expire at
+/// a known point.
+pub struct Cron {
+    db: SharedRedisState,
+    conn_manager: ConnectionManager,
+    /// Ticks run so far.
+    ticks: u64,
+}
+
+impl Cron {
+    pub fn new(db: SharedRedisState, conn_manager: ConnectionManager) -> Self {
+        Self { db, conn_manager, ticks: 0 }
+    }
+
+    /// Ticks `hz` times a second, forever.
+    pub async fn run(mut self) {
+        tokio::time::sleep(period().mul_f64(random::next_f64())).await;
+
+        loop {
+            let started = Instant::now();
+            self.tick().await;
+
+            let jitter = 1.0 + JITTER * (2.0 * random::next_f64() - 1.0);
+            tokio::time::sleep_until(started + period().mul_f64(jitter)).await;
+        }
+    }
+
+    /// Runs the duties due at this tick.
+    pub async fn tick(&mut self) {
+        if self.every(LRU_CLOCK_INTERVAL) {
+            evict::update_lru_clock();
+        }
+
+        if self.db.get_replication_state().get_role() == "master" {
+            self.db.remove_expired_keys(period() * ACTIVE_EXPIRE_BUDGET_PERCENT / 100).await;
+        }
+        let _ = self.db.get_replication_state().propagate_expired(&self.conn_manager).await;
+
+        self.ticks += 1;
+    }
+
+    /// Whether a duty run every `interval` is due this tick. Those more
+    /// frequent than ticks run on every one.
+    fn every(&self, interval: Duration) -> bool {
+        let ticks = (interval.as_micros() / period().as_micros()).max(1) as u64;
+
+        self.ticks % ticks == 0
+    }
+}
@@ -1,55 +1,912 @@
-use std::{collections::HashMap, sync::Arc};
+use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
+use std::hash::{Hash as _, Hasher};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, MutexGuard as StdMutexGuard};
+use std::time::{Duration, Instant};
 
-use tokio::sync::Mutex;
+use tokio::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
 
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
 
-use crate::ReplicationInfo;
+use crate::command_table::CommandNames;
+use crate::commands;
+use crate::connection;
+use crate::cron;
+use crate::dict::Dict;
+use crate::digest::{self, Digest};
+use crate::evict::{self, AccessStats};
+use crate::log;
+use crate::notify;
+use crate::rdb::{self, LoadedKey};
+use crate::value::{self, Hash, Set, SortedSet, Stream, Value, WrongType};
+use crate::{get_unix_ts_millis, Acl, Blocking, BusyOperations, ClientPause, ClientRegistry, CommandStats, ExpiredKeys, Loading, MonitorFeed, PubSub, ReplicationState, ServerConfig, ServerIdentity, SharedReplicationState, SlowLog, Watches};
 
-pub type SharedRedisState = Arc<Mutex<RedisState>>;
+pub type SharedRedisState = Arc<RedisState>;
+
+/// A key's value along with its expiry and access metadata.
+#[derive(Debug)]
+pub struct Entry {
+    pub value: Value,
+    // Unix timestamp in milliseconds.
+    pub expiry: Option<u128>,
+    pub access: AccessStats,
+}
+
+impl Entry {
+    /// Whether the key's expiry passed, or it's a hash all of whose fields
+    /// expired.
+    fn is_expired(&self, now: u128) -> bool {
+        matches!(self.expiry, Some(ts) if ts <= now) || matches!(&self.value, Value::Hash(hash) if hash.is_expired(now))
+    }
+}
+
+/// A subset of the keys of a database, behind its own lock.
+///
+/// Keys are only changed through the methods below, which signal every
+/// modification to the clients watching the key, see `Watches`. Those
+/// deleting keys or hash fields which expired record it in `ExpiredKeys`,
+/// for the replicas to delete them too.
+pub struct Shard {
+    db: Dict<Bytes, Entry>,
+    /// The database the shard is part of.
+    db_index: usize,
+    watches: Arc<Watches>,
+    expired: Arc<ExpiredKeys>,
+}
+
+impl Shard {
+    fn new(db_index: usize, watches: Arc<Watches>, expired: Arc<ExpiredKeys>) -> Self {
+        Self { db: Dict::new(), db_index, watches, expired }
+    }
+
+    /// Lets the clients watching `key` know it was modified.
+    fn signal_modified(&self, key: &[u8]) {
+        self.watches.touch(self.db_index, key);
+    }
+
+    /// Sets the value of `key`, which counts as an access to it.
+    pub fn insert(&mut self, key: Bytes, value: Value, expiry: Option<u128>) {
+        let entry = Entry { value, expiry, access: AccessStats::new() };
+
+        if let Some(old) = self.db.get(&key) {
+            entry.access.inherit(&old.access);
+        }
+        entry.access.touch();
+
+        self.signal_modified(&key);
+        self.db.insert(key, entry);
+    }
+
+    /// Returns the entry for `key`, including expired ones which haven't been
+    /// removed yet. Commands should prefer the typed getters below.
+    pub fn get(&self, key: &[u8]) -> Option<&Entry> {
+        self.db.get(key)
+    }
+
+    /// Returns the entry for `key` without counting as an access, treating
+    /// expired keys as missing. Meant for introspection like `OBJECT`.
+    pub fn peek(&self, key: &[u8]) -> Option<&Entry> {
+        self.db.get(key).filter(|entry| !entry.is_expired(get_unix_ts_millis()))
+    }
+
+    /// Returns the value at `key`, treating expired keys as missing.
+    pub fn get_value(&self, key: &[u8]) -> Option<&Value> {
+        let entry = self.peek(key)?;
+        entry.access.touch();
+
+        Some(&entry.value)
+    }
+
+    /// Mutable version of `get_value`, which also removes the key if it has
+    /// expired so callers can freely create a new value in its place. The
+    /// key counts as modified.
+    pub fn get_value_mut(&mut self, key: &[u8]) -> Option<&mut Value> {
+        self.get_typed_mut(key, |value: &mut Value| -> Result<&mut Value, WrongType> { Ok(value) }).unwrap_or_default()
+    }
+
+    /// `get_value_mut` for a value of the type `cast` picks. The key only
+    /// counts as modified if it has that type.
+    fn get_typed_mut<T>(&mut self, key: &[u8], cast: impl FnOnce(&mut Value) -> Result<&mut T, WrongType>) -> Result<Option<&mut T>, WrongType> {
+        if self.is_expired(key, get_unix_ts_millis()) {
+            self.remove_expired(key);
+        }
+
+        let entry = match self.db.get_mut(key) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        entry.access.touch();
+
+        let value = cast(&mut entry.value)?;
+        self.watches.touch(self.db_index, key);
+
+        Ok(Some(value))
+    }
+
+    pub fn get_string(&self, key: &[u8]) -> Result<Option<Cow<'_, Bytes>>, WrongType> {
+        self.get_value(key).map(Value::as_string).transpose()
+    }
+
+    /// Length of the string at `key`, 0 if it doesn't exist, which unlike
+    /// `get_string` never copies it.
+    pub fn string_len(&self, key: &[u8]) -> Result<usize, WrongType> {
+        match self.get_value(key) {
+            Some(Value::Appended(buf)) => Ok(buf.len()),
+            Some(value) => Ok(value.as_string()?.len()),
+            None => Ok(0),
+        }
+    }
+
+    /// Runs `f` on the contents of the string at `key`, which is created
+    /// empty if it doesn't exist, and stores the result back.
+    pub fn modify_string<R>(&mut self, key: &[u8], f: impl FnOnce(&mut Vec<u8>) -> R) -> Result<R, WrongType> {
+        if self.get_typed_mut(key, Value::as_string_mut)?.is_none() {
+            self.insert(Bytes::copy_from_slice(key), Value::String(Bytes::new()), None);
+        }
+
+        let string = self.db.get_mut(key).expect("key exists").value.as_string_mut()?;
+
+        // Strings are immutable `Bytes`, so edit a copy and swap it in.
+        let mut buf = Vec::from(std::mem::take(string));
+        let res = f(&mut buf);
+        *string = Bytes::from(buf);
+
+        Ok(res)
+    }
+
+    /// Appends `val` to the string at `key`, which is created if it doesn't
+    /// exist, and returns its new length. The string stays in a growable
+    /// buffer until something else reads or writes it, so a run of appends
+    /// only copies what's appended.
+    pub fn append_string(&mut self, key: &[u8], val: &[u8]) -> Result<usize, WrongType> {
+        match self.get_typed_mut(key, Value::as_string_buf)? {
+            Some(buf) => {
+                buf.extend_from_slice(val);
+                Ok(buf.len())
+            },
+            None => {
+                self.insert(Bytes::copy_from_slice(key), Value::Appended(BytesMut::from(val)), None);
+                Ok(val.len())
+            },
+        }
+    }
+
+    /// Sets the string at `key`, which is created if it doesn't exist. Unlike
+    /// `insert`, an existing key keeps its expiry.
+    pub fn set_string(&mut self, key: &[u8], val: Bytes) -> Result<(), WrongType> {
+        match self.get_typed_mut(key, Value::as_string_mut)? {
+            Some(string) => *string = val,
+            None => self.insert(Bytes::copy_from_slice(key), Value::String(val), None),
+        }
+
+        Ok(())
+    }
+
+    pub fn get_list(&self, key: &[u8]) -> Result<Option<&VecDeque<Bytes>>, WrongType> {
+        self.get_value(key).map(Value::as_list).transpose()
+    }
+
+    pub fn get_list_mut(&mut self, key: &[u8]) -> Result<Option<&mut VecDeque<Bytes>>, WrongType> {
+        self.get_typed_mut(key, Value::as_list_mut)
+    }
+
+    pub fn get_hash(&self, key: &[u8]) -> Result<Option<&Hash>, WrongType> {
+        self.get_value(key).map(Value::as_hash).transpose()
+    }
+
+    /// Returns the hash at `key` with its expired fields removed.
+    pub fn get_hash_mut(&mut self, key: &[u8]) -> Result<Option<&mut Hash>, WrongType> {
+        let now = get_unix_ts_millis();
+
+        // A hash which expired as a whole is deleted by `get_typed_mut`.
+        let entry = self.db.get_mut(key).filter(|entry| self.expired.deletes_expired() && !entry.is_expired(now));
+        if let Some(Entry { value: Value::Hash(hash), .. }) = entry {
+            let fields = hash.remove_expired(now);
+            if !fields.is_empty() {
+                self.expired.fields(self.db_index, key, &fields);
+            }
+        }
+
+        self.get_typed_mut(key, Value::as_hash_mut)
+    }
+
+    pub fn get_set(&self, key: &[u8]) -> Result<Option<&Set>, WrongType> {
+        self.get_value(key).map(Value::as_set).transpose()
+    }
+
+    pub fn get_set_mut(&mut self, key: &[u8]) -> Result<Option<&mut Set>, WrongType> {
+        self.get_typed_mut(key, Value::as_set_mut)
+    }
+
+    pub fn get_zset(&self, key: &[u8]) -> Result<Option<&SortedSet>, WrongType> {
+        self.get_value(key).map(Value::as_zset).transpose()
+    }
+
+    pub fn get_zset_mut(&mut self, key: &[u8]) -> Result<Option<&mut SortedSet>, WrongType> {
+        self.get_typed_mut(key, Value::as_zset_mut)
+    }
+
+    pub fn get_stream_mut(&mut self, key: &[u8]) -> Result<Option<&mut Stream>, WrongType> {
+        self.get_typed_mut(key, Value::as_stream_mut)
+    }
+
+    pub fn remove(&mut self, key: &[u8]) {
+        if self.db.remove(key).is_some() {
+            self.signal_modified(key);
+        }
+    }
+
+    /// Deletes `key`, which the caller found expired, letting the replicas
+    /// know. Replicas keep the key until their master deletes it.
+    pub fn remove_expired(&mut self, key: &[u8]) {
+        if !self.expired.deletes_expired() {
+            return;
+        }
+
+        if self.db.remove(key).is_some() {
+            self.signal_modified(key);
+            self.expired.key(self.db_index, key);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.db.len()
+    }
+
+    /// The keys which haven't expired, in no particular order.
+    pub fn keys(&self, now: u128) -> impl Iterator<Item = &Bytes> {
+        self.db.iter().filter(move |(_, entry)| !entry.is_expired(now)).map(|(key, _)| key)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.db.is_empty()
+    }
+
+    /// Number of keys with an expiry set.
+    pub fn expires(&self) -> usize {
+        self.db.values().filter(|entry| entry.expiry.is_some()).count()
+    }
+
+    pub fn clear(&mut self) {
+        self.watches.touch_matching(self.db_index, |key| self.db.contains_key(key));
+        self.db.clear();
+    }
+
+    /// Exchanges the keys of two shards, of different databases.
+    fn swap_keys(&mut self, other: &mut Shard) {
+        let exists = |key: &[u8]| self.db.contains_key(key) || other.db.contains_key(key);
+        self.watches.touch_matching(self.db_index, exists);
+        self.watches.touch_matching(other.db_index, exists);
+
+        std::mem::swap(&mut self.db, &mut other.db);
+    }
+
+    /// Whether `key` exists but its expiry has passed.
+    pub fn is_expired(&self, key: &[u8], now: u128) -> bool {
+        matches!(self.db.get(key), Some(entry) if entry.is_expired(now))
+    }
+
+    /// Removes expired keys, and the expired fields of hashes.
+    fn remove_expired_keys(&mut self, now: u128) {
+        let (watches, expired, db_index) = (&self.watches, &self.expired, self.db_index);
+
+        self.db.retain(|key, entry| {
+            if entry.is_expired(now) {
+                watches.touch(db_index, key);
+                expired.key(db_index, key);
+                return false;
+            }
+
+            if let Value::Hash(hash) = &mut entry.value {
+                let fields = hash.remove_expired(now);
+                if !fields.is_empty() {
+                    watches.touch(db_index, key);
+                    expired.fields(db_index, key, &fields);
+                }
+            }
+
+            true
+        });
+    }
+}
+
+/// The hash placing `key` in a shard.
+fn key_hash(key: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+
+    hasher.finish()
+}
+
+/// A single logical database, selected with `SELECT`.
+///
+/// Keys are spread over a fixed number of shards by hash, so commands on
+/// keys in different shards don't contend. Code locking more than one shard
+/// must do so in increasing shard index order (and increasing database index
+/// order across databases) to avoid deadlocks, which `lock_keys` and
+/// `lock_all` take care of.
+///
+/// Read-only commands should take `read` guards, so reads of a shard don't
+/// contend with each other. Lazily expiring a key found by a read goes
+/// through `remove_if_expired`, which only takes the write lock when there is
+/// something to delete.
+pub struct Keyspace {
+    shards: Vec<RwLock<Shard>>,
+}
+
+impl Keyspace {
+    pub fn new(num_shards: usize, db_index: usize, watches: &Arc<Watches>, expired: &Arc<ExpiredKeys>) -> Self {
+        Self {
+            shards: (0..num_shards.max(1)).map(|_| RwLock::new(Shard::new(db_index, watches.clone(), expired.clone()))).collect(),
+        }
+    }
+
+    fn shard_index(&self, key: &[u8]) -> usize {
+        (key_hash(key) % self.shards.len() as u64) as usize
+    }
+
+    /// Locks the shard holding `key` for reading.
+    pub async fn read(&self, key: &[u8]) -> RwLockReadGuard<'_, Shard> {
+        self.shards[self.shard_index(key)].read().await
+    }
+
+    /// Locks the shard holding `key` for writing.
+    pub async fn lock(&self, key: &[u8]) -> RwLockWriteGuard<'_, Shard> {
+        self.shards[self.shard_index(key)].write().await
+    }
+
+    /// Deletes `key` if it has expired. The expiry is checked again under the
+    /// write lock, since the key may have been overwritten after the caller
+    /// saw it expired under a read lock.
+    pub async fn remove_if_expired(&self, key: &[u8]) {
+        let mut shard = self.lock(key).await;
+
+        if shard.is_expired(key, get_unix_ts_millis()) {
+            shard.remove_expired(key);
+        }
+    }
+
+    /// Runs `f` on the string at `key`, None if it's missing or expired, with
+    /// its shard locked for reading. A key found expired is deleted first,
+    /// so reads expire keys lazily, and a string being appended to is frozen
+    /// first so it's read without copying it.
+    pub async fn read_string<R>(&self, key: &[u8], f: impl FnOnce(Option<&Bytes>) -> R) -> Result<R, WrongType> {
+        {
+            let shard = self.read(key).await;
+
+            match shard.db.get(key) {
+                Some(entry) if entry.is_expired(get_unix_ts_millis()) => {},
+                Some(Entry { value: Value::Appended(_), .. }) => {},
+                _ => return Ok(f(shard.get_string(key)?.as_deref())),
+            }
+        }
+
+        let mut shard = self.lock(key).await;
+        if shard.is_expired(key, get_unix_ts_millis()) {
+            shard.remove_expired(key);
+        }
+        if let Some(entry) = shard.db.get_mut(key) {
+            entry.value.freeze();
+        }
+
+        Ok(f(shard.get_string(key)?.as_deref()))
+    }
+
+    /// Locks the shards holding all of `keys`, for multi-key commands.
+    pub async fn lock_keys(&self, keys: &[&[u8]]) -> ShardGuards<'_> {
+        let mut indexes: Vec<usize> = keys.iter().map(|key| self.shard_index(key)).collect();
+        indexes.sort_unstable();
+        indexes.dedup();
+
+        self.lock_shards(indexes).await
+    }
+
+    /// Locks every shard, for commands which only find out which keys they
+    /// touch while running, like `SORT` with `BY` or `GET` patterns.
+    pub async fn lock_all_keys(&self) -> ShardGuards<'_> {
+        self.lock_shards((0..self.shards.len()).collect()).await
+    }
+
+    async fn lock_shards(&self, indexes: Vec<usize>) -> ShardGuards<'_> {
+        let mut guards = Vec::with_capacity(indexes.len());
+        for index in indexes {
+            guards.push((index, self.shards[index].write().await));
+        }
+
+        ShardGuards { keyspace: self, guards }
+    }
+
+    /// Locks every shard of the database for reading.
+    pub async fn read_all(&self) -> Vec<RwLockReadGuard<'_, Shard>> {
+        let mut guards = Vec::with_capacity(self.shards.len());
+        for shard in self.shards.iter() {
+            guards.push(shard.read().await);
+        }
+
+        guards
+    }
+
+    /// One step of `SCAN`: about `count` keys from where `cursor` points,
+    /// along with the cursor to carry on from, 0 once done. Only the keys
+    /// `filter` accepts are returned, but all count towards `count`, like
+    /// in redis-server.
+    ///
+    /// The cursor is the shard to scan, plus the cursor of the next bucket
+    /// of the shard's `Dict` times the number of shards. Each step visits
+    /// whole buckets, moving on to the next shard once the last bucket was
+    /// visited, so a scan returns every key there all along at least once,
+    /// and ends, whatever is written in between. See `Dict::scan`.
+    pub async fn scan(&self, cursor: u64, count: usize, filter: impl Fn(&Bytes, &Value) -> bool) -> (u64, Vec<Bytes>) {
+        let num_shards = self.shards.len() as u64;
+        let index = cursor % num_shards;
+        let mut bucket = cursor / num_shards;
+        let shard = self.shards[index as usize].read().await;
+        let now = get_unix_ts_millis();
+
+        let mut keys = vec![];
+        let mut visited = 0;
+        // Like redis-server, don't go over more than ten empty buckets per key
+        // asked for, so a sparse shard doesn't hold its lock for long.
+        for _ in 0..count.saturating_mul(10) {
+            bucket = shard.db.scan(bucket, |key, entry| {
+                if entry.is_expired(now) {
+                    return;
+                }
+
+                visited += 1;
+                if filter(key, &entry.value) {
+                    keys.push(key.clone());
+                }
+            });
+
+            if bucket == 0 || visited >= count {
+                break;
+            }
+        }
+
+        let next = match bucket {
+            0 if index + 1 < num_shards => index + 1,
+            0 => 0,
+            bucket => bucket * num_shards + index,
+        };
+
+        (next, keys)
+    }
+
+    /// Locks every shard of the database.
+    pub async fn lock_all(&self) -> Vec<RwLockWriteGuard<'_, Shard>> {
+        let mut guards = Vec::with_capacity(self.shards.len());
+        for shard in self.shards.iter() {
+            guards.push(shard.write().await);
+        }
+
+        guards
+    }
+
+    pub async fn len(&self) -> usize {
+        let mut len = 0;
+        for shard in self.shards.iter() {
+            len += shard.read().await.len();
+        }
+
+        len
+    }
+
+    pub async fn is_empty(&self) -> bool {
+        for shard in self.shards.iter() {
+            if !shard.read().await.is_empty() {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    pub async fn expires(&self) -> usize {
+        let mut expires = 0;
+        for shard in self.shards.iter() {
+            expires += shard.read().await.expires();
+        }
+
+        expires
+    }
+
+    /// Removes every key. The shards stay locked until the returned guards
+    /// are dropped, so the caller can propagate the flush first.
+    pub async fn flush(&self) -> Vec<RwLockWriteGuard<'_, Shard>> {
+        let mut guards = self.lock_all().await;
+        for shard in guards.iter_mut() {
+            shard.clear();
+        }
+
+        guards
+    }
+}
+
+/// The shards locked by `Keyspace::lock_keys`.
+pub struct ShardGuards<'a> {
+    keyspace: &'a Keyspace,
+    guards: Vec<(usize, RwLockWriteGuard<'a, Shard>)>,
+}
+
+impl<'a> ShardGuards<'a> {
+    /// Returns the shard holding `key`, which must be one of the keys the
+    /// guards were created for.
+    pub fn get_mut(&mut self, key: &[u8]) -> &mut Shard {
+        let index = self.keyspace.shard_index(key);
+
+        self.guards
+            .iter_mut()
+            .find(|(idx, _)| *idx == index)
+            .map(|(_, guard)| &mut **guard)
+            .expect("Key was not locked")
+    }
+}
 
 pub struct RedisState {
-    db: HashMap<String, (Bytes, Option<u128>)>,
-    replication_info: ReplicationInfo,
+    identity: ServerIdentity,
+    dbs: Vec<Keyspace>,
+    replication: SharedReplicationState,
+    config: std::sync::RwLock<ServerConfig>,
+    slowlog: std::sync::Mutex<SlowLog>,
+    monitor: MonitorFeed,
+    pubsub: Arc<PubSub>,
+    acl: Acl,
+    command_names: CommandNames,
+    clients: ClientRegistry,
+    blocking: Blocking,
+    watches: Arc<Watches>,
+    pause: ClientPause,
+    busy: BusyOperations,
+    loading: Loading,
+    command_stats: Arc<CommandStats>,
+    active_expire_enabled: AtomicBool,
+    /// Index of the shard active expiry goes on from, counting the shards of
+    /// every database in turn.
+    expire_cursor: AtomicUsize,
+    /// Connection tasks which panicked, and were cleaned up after.
+    task_panics: AtomicU64,
+    /// Held shared by every command reading or writing keys, and exclusively
+    /// while a transaction runs, so no one sees one half applied. Taken
+    /// before any shard lock.
+    transaction_lock: RwLock<()>,
 }
 
 impl RedisState {
-    pub fn new(replicaof: Option<String>, listening_port: String) -> Self {
+    pub fn new(replicaof: Option<String>, listening_port: u16, config: ServerConfig, acl: Acl) -> Self {
+        evict::configure(&config.maxmemory_policy, config.lfu_log_factor, config.lfu_decay_time);
+        connection::configure(config.proto_max_bulk_len, config.repl_max_bulk_len);
+        value::configure(&config.compact_limits());
+        notify::configure(&config.notify_keyspace_events);
+        commands::configure(config.reply_stream_chunk_size);
+        log::configure(&config.loglevel);
+        cron::configure(config.hz);
+
+        let pubsub = Arc::new(PubSub::new());
+        let expired = Arc::new(ExpiredKeys::new(pubsub.clone(), replicaof.is_some()));
+        let replication = ReplicationState::new(replicaof, listening_port.to_string(), expired.clone());
+        replication.get_trace().configure(&config.repl_trace, config.repl_trace_max_size);
+        let busy = BusyOperations::new(config.busy_reply_threshold);
+        let command_names = CommandNames::new(&config.rename_commands);
+        let watches = Arc::new(Watches::new());
+
         Self {
-            db: HashMap::new(),
-            replication_info: ReplicationInfo::new(replicaof, listening_port),
+            identity: ServerIdentity::new(listening_port),
+            dbs: (0..config.databases).map(|index| Keyspace::new(config.keyspace_shards, index, &watches, &expired)).collect(),
+            replication: Arc::new(replication),
+            config: std::sync::RwLock::new(config),
+            slowlog: std::sync::Mutex::new(SlowLog::new()),
+            monitor: MonitorFeed::new(),
+            pubsub,
+            acl,
+            command_names,
+            clients: ClientRegistry::new(),
+            blocking: Blocking::new(),
+            watches,
+            pause: ClientPause::new(),
+            busy,
+            loading: Loading::new(),
+            command_stats: Arc::new(CommandStats::new()),
+            active_expire_enabled: AtomicBool::new(true),
+            expire_cursor: AtomicUsize::new(0),
+            task_panics: AtomicU64::new(0),
+            transaction_lock: RwLock::new(()),
         }
     }
 
-    pub fn insert(&mut self, key: String, value: Bytes, expiry: Option<u128>) {
-        self.db.insert(key, (value, expiry));
+    pub fn num_dbs(&self) -> usize {
+        self.dbs.len()
     }
 
-    pub fn get(&self, key: &str) -> Option<&(Bytes, Option<u128>)> {
-        self.db.get(key)
+    /// Returns the database with the given index, which callers are expected
+    /// to have validated against `num_dbs()`.
+    pub fn get_db(&self, index: usize) -> &Keyspace {
+        &self.dbs[index]
+    }
+
+    /// Keeps transactions from running until the guard is dropped. Held by
+    /// commands accessing keys, for as long as they run.
+    pub async fn lock_for_command(&self) -> RwLockReadGuard<'_, ()> {
+        self.transaction_lock.read().await
+    }
+
+    /// Waits for the running commands to finish, and keeps any other from
+    /// accessing keys until the guard is dropped.
+    pub async fn lock_for_transaction(&self) -> RwLockWriteGuard<'_, ()> {
+        self.transaction_lock.write().await
+    }
+
+    /// Locks every shard of every database, e.g. to stall the server in
+    /// `DEBUG SLEEP`.
+    pub async fn lock_all(&self) -> Vec<RwLockWriteGuard<'_, Shard>> {
+        let mut guards = vec![];
+        for keyspace in self.dbs.iter() {
+            guards.extend(keyspace.lock_all().await);
+        }
+
+        guards
+    }
+
+    /// Atomically exchanges the contents of two databases. Both stay locked
+    /// until the returned guards are dropped.
+    pub async fn swap_dbs(&self, a: usize, b: usize) -> Vec<RwLockWriteGuard<'_, Shard>> {
+        if a == b {
+            return self.dbs[a].lock_all().await;
+        }
+
+        let mut guards = self.dbs[a.min(b)].lock_all().await;
+        guards.extend(self.dbs[a.max(b)].lock_all().await);
+
+        let (first, second) = guards.split_at_mut(self.dbs[a].shards.len());
+        for (a, b) in first.iter_mut().zip(second.iter_mut()) {
+            a.swap_keys(b);
+        }
+
+        guards
+    }
+
+    /// Removes every key from every database. All shards stay locked until
+    /// the returned guards are dropped.
+    pub async fn flush_all(&self) -> Vec<RwLockWriteGuard<'_, Shard>> {
+        let mut guards = self.lock_all().await;
+        for shard in guards.iter_mut() {
+            shard.clear();
+        }
+
+        guards
+    }
+
+    /// Serializes the whole dataset as an RDB file, given the guards of
+    /// `lock_all`. The dataset stays locked while it's written, so it's
+    /// consistent, at the cost of holding up every command meanwhile.
+    pub fn save_snapshot(&self, guards: &[RwLockWriteGuard<'_, Shard>]) -> Vec<u8> {
+        let shards = (guards.len() / self.dbs.len()).max(1);
+
+        rdb::save(guards.chunks(shards).enumerate().flat_map(|(db_index, shards)| {
+            shards.iter().flat_map(move |shard| shard.db.iter().map(move |(key, entry)| rdb::SavedKey {
+                db_index,
+                key,
+                value: &entry.value,
+                expiry: entry.expiry,
+            }))
+        }))
+    }
+
+    /// The digest of the whole dataset for `DEBUG DIGEST`, all zeros when
+    /// it's empty. Each database is read as of a single moment, but not
+    /// every database at the same one.
+    pub async fn digest(&self) -> Digest {
+        let now = get_unix_ts_millis();
+        let mut res = [0; 20];
+
+        for (db_index, keyspace) in self.dbs.iter().enumerate() {
+            let mut keys = None;
+            for shard in keyspace.read_all().await.iter() {
+                for (key, entry) in shard.db.iter().filter(|(_, entry)| !entry.is_expired(now)) {
+                    digest::xor(keys.get_or_insert([0; 20]), &digest::key_digest(key, &entry.value, entry.expiry.is_some(), now));
+                }
+            }
+
+            // Empty databases leave no trace, not even their index.
+            if let Some(keys) = keys {
+                digest::mix_digest(&mut res, &(db_index as u32).to_be_bytes());
+                digest::xor(&mut res, &keys);
+            }
+        }
+
+        res
+    }
+
+    /// Replaces the whole dataset with the keys of a snapshot. Every shard
+    /// stays locked while they're inserted, so no one sees it partly loaded.
+    ///
+    /// Keys which expired are left out unless `keep_expired` is set, which
+    /// replicas do as their master deletes the keys when it gets to them.
+    /// Those left out count as deleted on expiry, so replicas of this
+    /// server delete them too.
+    pub async fn load_snapshot(&self, keys: Vec<LoadedKey>, keep_expired: bool) -> crate::Result<()> {
+        let mut guards = self.lock_all().await;
+
+        self.replace_snapshot(&mut guards, keys, keep_expired)
+    }
+
+    /// `load_snapshot` for a caller already holding the guards of
+    /// `lock_all`, like `DEBUG RELOAD` which saves and loads under the same
+    /// locks. Nothing is touched if the snapshot doesn't fit the databases.
+    pub fn replace_snapshot(&self, guards: &mut [RwLockWriteGuard<'_, Shard>], keys: Vec<LoadedKey>, keep_expired: bool) -> crate::Result<()> {
+        if let Some(key) = keys.iter().find(|key| key.db_index >= self.dbs.len()) {
+            return Err(format!("ERR snapshot has keys in DB {}, which isn't configured", key.db_index).into());
+        }
+
+        for shard in guards.iter_mut() {
+            shard.clear();
+        }
+
+        let shards = (guards.len() / self.dbs.len()).max(1);
+        let now = get_unix_ts_millis();
+        for LoadedKey { db_index, key, value, expiry } in keys {
+            let shard = &mut guards[db_index * shards + self.dbs[db_index].shard_index(&key)];
+
+            if !keep_expired && matches!(expiry, Some(ts) if ts <= now) {
+                shard.expired.key(db_index, &key);
+                continue;
+            }
+
+            shard.insert(key, value, expiry);
+        }
+
+        // Any key may have changed, so clients blocked on one look again.
+        for db_index in 0..self.dbs.len() {
+            self.blocking.signal_db(db_index);
+        }
+
+        Ok(())
     }
 
-    pub fn remove(&mut self, key: &str) {
-        self.db.remove(key);
+    /// Renders the `keyspace` INFO section, listing every non-empty database.
+    pub async fn get_keyspace_info_bytes(&self) -> Bytes {
+        let mut res = "# Keyspace\n".to_string();
+
+        for (index, keyspace) in self.dbs.iter().enumerate() {
+            let keys = keyspace.len().await;
+
+            if keys > 0 {
+                res.push_str(&format!("db{}:keys={},expires={},avg_ttl=0\n", index, keys, keyspace.expires().await));
+            }
+        }
+
+        Bytes::from(res)
     }
 
-    pub fn get_replication_info(&self) -> ReplicationInfo {
-        self.replication_info.clone()
+    /// Removes the keys whose expiry has passed, unless active expiry was
+    /// turned off with `DEBUG SET-ACTIVE-EXPIRE 0`. Nothing expires while
+    /// clients are paused either, so the dataset stays put meanwhile.
+    ///
+    /// Shards are gone through one at a time, yielding in between, until
+    /// all were or `budget` is spent. The next call carries on from there.
+    pub async fn remove_expired_keys(&self, budget: Duration) {
+        if !self.active_expire_enabled.load(Ordering::Relaxed) || self.pause.is_paused() {
+            return;
+        }
+
+        let shards: Vec<&RwLock<Shard>> = self.dbs.iter().flat_map(|keyspace| keyspace.shards.iter()).collect();
+        let started = Instant::now();
+        let now = get_unix_ts_millis();
+
+        let mut cursor = self.expire_cursor.load(Ordering::Relaxed);
+        for _ in 0..shards.len() {
+            cursor %= shards.len();
+            shards[cursor].write().await.remove_expired_keys(now);
+            cursor += 1;
+
+            if started.elapsed() >= budget {
+                break;
+            }
+            tokio::task::yield_now().await;
+        }
+        self.expire_cursor.store(cursor, Ordering::Relaxed);
+    }
+
+    pub fn set_active_expire(&self, enabled: bool) {
+        self.active_expire_enabled.store(enabled, Ordering::Relaxed);
     }
-    
-    pub fn add_replica(&mut self, addr: String) {
-        self.replication_info.add_replica(addr);
+
+    pub fn get_identity(&self) -> &ServerIdentity {
+        &self.identity
     }
-    
-    pub fn get_replicas(&self) -> Vec<String> {
-        self.replication_info.get_replicas().clone()
+
+    pub fn get_replication_state(&self) -> SharedReplicationState {
+        self.replication.clone()
     }
 
-    pub fn get_replica_offset_bytes(&self) -> u64 {
-        self.replication_info.get_replica_offset_bytes()
+    pub fn get_config(&self) -> ServerConfig {
+        self.config.read().unwrap_or_else(|err| err.into_inner()).clone()
     }
 
-    pub fn add_replica_offset(&mut self, offset: u64) {
-        self.replication_info.add_replica_offset(offset);
+    pub fn set_config(&self, config: ServerConfig) {
+        evict::configure(&config.maxmemory_policy, config.lfu_log_factor, config.lfu_decay_time);
+        connection::configure(config.proto_max_bulk_len, config.repl_max_bulk_len);
+        self.replication.get_trace().configure(&config.repl_trace, config.repl_trace_max_size);
+        self.busy.configure(config.busy_reply_threshold);
+        value::configure(&config.compact_limits());
+        notify::configure(&config.notify_keyspace_events);
+        commands::configure(config.reply_stream_chunk_size);
+        log::configure(&config.loglevel);
+        cron::configure(config.hz);
+        *self.config.write().unwrap_or_else(|err| err.into_inner()) = config;
     }
-}
\ No newline at end of file
+
+    pub fn get_slowlog(&self) -> StdMutexGuard<'_, SlowLog> {
+        self.slowlog.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Adds the command to the slow log if it ran for longer than the
+    /// configured threshold.
+    pub fn record_slow_command(&self, args: &[Bytes], client_addr: &str, duration: Duration) {
+        let (threshold, max_len) = {
+            let config = self.config.read().unwrap_or_else(|err| err.into_inner());
+            (config.slowlog_log_slower_than, config.slowlog_max_len)
+        };
+
+        if threshold < 0 || (duration.as_micros() as i64) < threshold {
+            return;
+        }
+
+        self.slowlog.lock().unwrap_or_else(|err| err.into_inner()).push(args, client_addr, "", duration, max_len);
+    }
+
+    pub fn get_monitor_feed(&self) -> MonitorFeed {
+        self.monitor.clone()
+    }
+
+    pub fn get_pubsub(&self) -> &PubSub {
+        &self.pubsub
+    }
+
+    pub fn get_acl(&self) -> &Acl {
+        &self.acl
+    }
+
+    /// The names clients call commands by; see `CommandNames`.
+    pub fn get_command_names(&self) -> &CommandNames {
+        &self.command_names
+    }
+
+    pub fn get_clients(&self) -> &ClientRegistry {
+        &self.clients
+    }
+
+    pub fn get_blocking(&self) -> &Blocking {
+        &self.blocking
+    }
+
+    pub fn get_watches(&self) -> &Watches {
+        &self.watches
+    }
+
+    pub fn record_task_panic(&self) {
+        self.task_panics.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get_stats_info_bytes(&self) -> Bytes {
+        Bytes::from(format!("# Stats\ntask_panics:{}\n", self.task_panics.load(Ordering::Relaxed)))
+    }
+
+    pub fn get_pause(&self) -> &ClientPause {
+        &self.pause
+    }
+
+    pub fn get_busy(&self) -> &BusyOperations {
+        &self.busy
+    }
+
+    pub fn get_loading(&self) -> &Loading {
+        &self.loading
+    }
+
+    pub fn get_command_stats(&self) -> Arc<CommandStats> {
+        self.command_stats.clone()
+    }
+}
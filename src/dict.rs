@@ -0,0 +1,180 @@
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash, Hasher};
+
+/// Fewest buckets a table with anything in it has.
+const MIN_BUCKETS: usize = 4;
+
+/// A hash table chaining its entries in a power of two number of buckets,
+/// like `dict.c` in redis-server. Unlike with `HashMap`, which buckets a key
+/// lands in is known, so `scan` can walk the buckets with a cursor that
+/// stays valid while the table grows and shrinks.
+///
+/// The table doubles once it holds more entries than buckets, and halves
+/// as many times as needed once it's less than an eighth full, rehashing
+/// everything at once.
+pub struct Dict<K, V> {
+    buckets: Vec<Vec<(K, V)>>,
+    len: usize,
+    hasher: RandomState,
+}
+
+impl<K: Hash + Eq, V> Dict<K, V> {
+    pub fn new() -> Self {
+        Self { buckets: vec![], len: 0, hasher: RandomState::new() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn bucket_index<Q: Hash + ?Sized>(&self, key: &Q) -> usize {
+        let mut hasher = self.hasher.build_hasher();
+        key.hash(&mut hasher);
+
+        (hasher.finish() & (self.buckets.len() as u64 - 1)) as usize
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.buckets.is_empty() {
+            return None;
+        }
+
+        self.buckets[self.bucket_index(key)].iter().find(|(k, _)| k.borrow() == key).map(|(_, v)| v)
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.buckets.is_empty() {
+            return None;
+        }
+
+        let index = self.bucket_index(key);
+        self.buckets[index].iter_mut().find(|(k, _)| k.borrow() == key).map(|(_, v)| v)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Sets the value of `key`, returning the one it replaced.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        if let Some(old) = self.get_mut(&key) {
+            return Some(std::mem::replace(old, value));
+        }
+
+        if self.len >= self.buckets.len() {
+            self.resize((self.buckets.len() * 2).max(MIN_BUCKETS));
+        }
+
+        let index = self.bucket_index(&key);
+        self.buckets[index].push((key, value));
+        self.len += 1;
+
+        None
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.buckets.is_empty() {
+            return None;
+        }
+
+        let index = self.bucket_index(key);
+        let bucket = &mut self.buckets[index];
+        let pos = bucket.iter().position(|(k, _)| k.borrow() == key)?;
+        let (_, value) = bucket.swap_remove(pos);
+        self.len -= 1;
+        self.shrink();
+
+        Some(value)
+    }
+
+    /// Keeps only the entries `f` returns true for.
+    pub fn retain(&mut self, mut f: impl FnMut(&K, &mut V) -> bool) {
+        for bucket in self.buckets.iter_mut() {
+            bucket.retain_mut(|(key, value)| f(key, value));
+        }
+
+        self.len = self.buckets.iter().map(Vec::len).sum();
+        self.shrink();
+    }
+
+    pub fn clear(&mut self) {
+        self.buckets = vec![];
+        self.len = 0;
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.buckets.iter().flat_map(|bucket| bucket.iter().map(|(key, value)| (key, value)))
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, value)| value)
+    }
+
+    /// Calls `f` on the entries of the bucket `cursor` points to, returning
+    /// the cursor of the next bucket, 0 once every bucket was visited.
+    ///
+    /// The cursor is incremented from its high bits, within those the
+    /// table's size uses, rather than from its low bits. Doubling the table
+    /// splits bucket `i` into `i` and `i` plus the old size, and halving it
+    /// merges them back, and either way buckets that come later in this
+    /// order only hold entries of buckets not visited yet. So entries there
+    /// from start to end are visited at least once, and a scan ends, however
+    /// the table is resized in between. Entries may be visited twice after
+    /// it shrinks.
+    pub fn scan(&self, cursor: u64, mut f: impl FnMut(&K, &V)) -> u64 {
+        if self.buckets.is_empty() {
+            return 0;
+        }
+
+        let mask = self.buckets.len() as u64 - 1;
+        for (key, value) in self.buckets[(cursor & mask) as usize].iter() {
+            f(key, value);
+        }
+
+        // Setting the bits above the mask makes the carry run out past the
+        // top, wrapping around to 0 after the last bucket.
+        (cursor | !mask).reverse_bits().wrapping_add(1).reverse_bits()
+    }
+
+    fn shrink(&mut self) {
+        if self.buckets.len() > MIN_BUCKETS && self.len * 8 < self.buckets.len() {
+            self.resize(self.len.next_power_of_two().max(MIN_BUCKETS));
+        }
+    }
+
+    fn resize(&mut self, num_buckets: usize) {
+        let old = std::mem::replace(&mut self.buckets, (0..num_buckets).map(|_| vec![]).collect());
+
+        for (key, value) in old.into_iter().flatten() {
+            let index = self.bucket_index(&key);
+            self.buckets[index].push((key, value));
+        }
+    }
+}
+
+impl<K: Hash + Eq, V> Default for Dict<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,121 @@
+//! The digests of `DEBUG DIGEST` and `DEBUG DIGEST-VALUE`, built along the
+//! lines of redis-server's, so a master and its replicas can be compared
+//! cheaply. They aren't meant to match redis-server's own.
+//!
+//! Everything is SHA-1 based. Unordered collections xor the digests of their
+//! elements, so they digest the same however they're stored, and ordered ones
+//! mix each element into the digest so far.
+
+use bytes::Bytes;
+
+use crate::sha1::sha1;
+use crate::Value;
+
+pub type Digest = [u8; 20];
+
+/// Xors the SHA-1 of `data` into `digest`.
+pub fn xor_digest(digest: &mut Digest, data: &[u8]) {
+    xor(digest, &sha1(data));
+}
+
+/// Xors `other` into `digest`.
+pub fn xor(digest: &mut Digest, other: &Digest) {
+    for (byte, other) in digest.iter_mut().zip(other) {
+        *byte ^= other;
+    }
+}
+
+/// Mixes `data` into `digest`, so the result depends on the order data is
+/// mixed in.
+pub fn mix_digest(digest: &mut Digest, data: &[u8]) {
+    xor_digest(digest, data);
+    *digest = sha1(digest);
+}
+
+/// The digest of a value with or without an expiry, leaving out the key.
+pub fn value_digest(value: &Value, expires: bool, now: u128) -> Digest {
+    let mut digest = [0; 20];
+    mix_digest(&mut digest, &type_code(value).to_be_bytes());
+
+    match value {
+        Value::String(string) => mix_digest(&mut digest, string),
+        Value::Appended(string) => mix_digest(&mut digest, string),
+        Value::List(list) => {
+            for element in list {
+                mix_digest(&mut digest, element);
+            }
+        },
+        Value::Set(set) => {
+            for member in set.iter() {
+                xor_digest(&mut digest, &member);
+            }
+        },
+        Value::ZSet(zset) => {
+            for (member, score) in zset.iter() {
+                let mut element = [0; 20];
+                mix_digest(&mut element, member);
+                // The shortest form reading back as the same score.
+                mix_digest(&mut element, score.to_string().as_bytes());
+                xor_digest(&mut digest, &element);
+            }
+        },
+        Value::Hash(hash) => {
+            for (field, val, expiry) in hash.entries() {
+                if matches!(expiry, Some(ts) if ts <= now) {
+                    continue;
+                }
+
+                let mut element = [0; 20];
+                mix_digest(&mut element, field);
+                mix_digest(&mut element, val);
+                if expiry.is_some() {
+                    mix_digest(&mut element, b"!!hexpire!!");
+                }
+                xor_digest(&mut digest, &element);
+            }
+        },
+        Value::Stream(stream) => {
+            for (id, fields) in stream {
+                mix_digest(&mut digest, format!("{}-{}", id.ms, id.seq).as_bytes());
+                for (field, val) in fields {
+                    mix_digest(&mut digest, field);
+                    mix_digest(&mut digest, val);
+                }
+            }
+        },
+    }
+
+    // Only whether there's an expiry counts, as the exact time may differ
+    // by a millisecond between a master and its replicas.
+    if expires {
+        xor_digest(&mut digest, b"!!expire!!");
+    }
+
+    digest
+}
+
+/// The digest of a key and its value, to be xored into the dataset's.
+pub fn key_digest(key: &[u8], value: &Value, expires: bool, now: u128) -> Digest {
+    let mut digest = [0; 20];
+    mix_digest(&mut digest, key);
+    xor(&mut digest, &value_digest(value, expires, now));
+
+    digest
+}
+
+/// A digest as the 40 hex digits it's replied with.
+pub fn to_hex(digest: &Digest) -> Bytes {
+    Bytes::from(digest.iter().map(|byte| format!("{:02x}", byte)).collect::<String>())
+}
+
+/// The type numbers of redis-server's objects.
+fn type_code(value: &Value) -> u32 {
+    match value {
+        Value::String(_) | Value::Appended(_) => 0,
+        Value::List(_) => 1,
+        Value::Set(_) => 2,
+        Value::ZSet(_) => 3,
+        Value::Hash(_) => 4,
+        Value::Stream(_) => 6,
+    }
+}
@@ -0,0 +1,133 @@
+use std::io;
+use std::num::{ParseFloatError, ParseIntError};
+use std::string::FromUtf8Error;
+
+use crate::{frame, Frame, WrongType};
+
+/// Errors raised while reading, parsing or running a command. Apart from
+/// `Io` and `Protocol`, which close the connection, the error is sent to the
+/// client as a reply and the connection stays open.
+#[derive(Debug, thiserror::Error)]
+pub enum RedisError {
+    #[error("ERR wrong number of arguments for '{cmd}' command")]
+    WrongArity { cmd: String },
+
+    #[error("ERR value is not an integer or out of range")]
+    NotAnInteger,
+
+    #[error("ERR value is not a valid float")]
+    NotAFloat,
+
+    #[error("WRONGTYPE Operation against a key holding the wrong kind of value")]
+    WrongType,
+
+    #[error("ERR no such key")]
+    NoSuchKey,
+
+    #[error("ERR syntax error")]
+    Syntax,
+
+    #[error("NOAUTH Authentication required.")]
+    NoAuth,
+
+    #[error("WRONGPASS invalid username-password pair or user is disabled.")]
+    WrongPass,
+
+    #[error("ERR string exceeds maximum allowed size")]
+    StringTooLong,
+
+    #[error("BUSY Redis is busy running a long operation. You can only call SCRIPT KILL.")]
+    Busy,
+
+    #[error("LOADING Redis is loading the dataset in memory")]
+    Loading,
+
+    #[error("READONLY You can't write against a read only replica.")]
+    ReadOnly,
+
+    #[error("ERR unknown subcommand or wrong number of arguments for '{subcommand}'. Try {cmd} HELP.")]
+    UnknownSubcommand { cmd: String, subcommand: String },
+
+    /// The client sent something that isn't valid RESP.
+    #[error("ERR Protocol error: {0}")]
+    Protocol(String),
+
+    /// Any other error reply, including its `ERR`-style prefix.
+    #[error("{0}")]
+    Reply(String),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+impl RedisError {
+    pub fn wrong_arity(cmd: &str) -> Self {
+        RedisError::WrongArity { cmd: cmd.to_lowercase() }
+    }
+
+    pub fn unknown_subcommand(cmd: &str, subcommand: &str) -> Self {
+        RedisError::UnknownSubcommand { cmd: cmd.to_uppercase(), subcommand: subcommand.to_string() }
+    }
+
+    /// The error for an error reply received from a server, recognizing the
+    /// replies of the variants without fields.
+    pub fn from_reply(reply: String) -> Self {
+        [RedisError::NotAnInteger, RedisError::NotAFloat, RedisError::WrongType, RedisError::NoSuchKey, RedisError::Syntax, RedisError::NoAuth, RedisError::WrongPass, RedisError::StringTooLong, RedisError::Busy, RedisError::Loading, RedisError::ReadOnly]
+            .into_iter()
+            .find(|err| err.to_string() == reply)
+            .unwrap_or(RedisError::Reply(reply))
+    }
+
+    /// The error reply sent to the client.
+    pub fn to_frame(&self) -> Frame {
+        // Error replies are single lines.
+        Frame::Error(self.to_string().replace(['\r', '\n'], " "))
+    }
+
+    /// Whether the connection should be closed after the error.
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, RedisError::Io(_) | RedisError::Protocol(_))
+    }
+}
+
+impl From<&str> for RedisError {
+    fn from(src: &str) -> Self {
+        RedisError::Reply(src.to_string())
+    }
+}
+
+impl From<String> for RedisError {
+    fn from(src: String) -> Self {
+        RedisError::Reply(src)
+    }
+}
+
+impl From<WrongType> for RedisError {
+    fn from(_src: WrongType) -> Self {
+        RedisError::WrongType
+    }
+}
+
+impl From<FromUtf8Error> for RedisError {
+    fn from(_src: FromUtf8Error) -> Self {
+        RedisError::Reply("ERR invalid UTF-8 in argument".to_string())
+    }
+}
+
+impl From<ParseIntError> for RedisError {
+    fn from(_src: ParseIntError) -> Self {
+        RedisError::NotAnInteger
+    }
+}
+
+impl From<ParseFloatError> for RedisError {
+    fn from(_src: ParseFloatError) -> Self {
+        RedisError::NotAFloat
+    }
+}
+
+impl From<frame::Error> for RedisError {
+    fn from(src: frame::Error) -> Self {
+        RedisError::Protocol(src.to_string())
+    }
+}
@@ -0,0 +1,156 @@
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering};
+
+use crate::{get_unix_ts_millis, random};
+
+/// Resolution of the LRU clock in milliseconds.
+pub const LRU_CLOCK_RESOLUTION: u64 = 1000;
+
+/// The LRU clock wraps around at 24 bits, like in Redis.
+const LRU_CLOCK_MAX: u32 = (1 << 24) - 1;
+
+/// Initial LFU counter of new keys, so they aren't evicted right away.
+pub const LFU_INIT_VAL: u8 = 5;
+
+// Access tracking happens on every key lookup, so instead of threading the
+// configuration through every getter these are kept as atomics. The clock is
+// advanced by a background tick rather than reading the time on each access.
+static LRU_CLOCK: AtomicU32 = AtomicU32::new(0);
+static LFU_ENABLED: AtomicBool = AtomicBool::new(false);
+static LFU_LOG_FACTOR: AtomicU32 = AtomicU32::new(10);
+static LFU_DECAY_TIME: AtomicU32 = AtomicU32::new(1);
+
+fn unix_time() -> u64 {
+    get_unix_ts_millis() as u64
+}
+
+/// Advances the LRU clock, called periodically from a background task.
+pub fn update_lru_clock() {
+    let clock = (unix_time() / LRU_CLOCK_RESOLUTION) as u32 & LRU_CLOCK_MAX;
+    LRU_CLOCK.store(clock, Ordering::Relaxed);
+}
+
+pub fn lru_clock() -> u32 {
+    LRU_CLOCK.load(Ordering::Relaxed)
+}
+
+/// Applies the access tracking settings of the configuration.
+pub fn configure(maxmemory_policy: &str, lfu_log_factor: u32, lfu_decay_time: u32) {
+    LFU_ENABLED.store(maxmemory_policy.ends_with("-lfu"), Ordering::Relaxed);
+    LFU_LOG_FACTOR.store(lfu_log_factor, Ordering::Relaxed);
+    LFU_DECAY_TIME.store(lfu_decay_time, Ordering::Relaxed);
+}
+
+/// Whether an LFU maxmemory-policy is selected.
+pub fn lfu_enabled() -> bool {
+    LFU_ENABLED.load(Ordering::Relaxed)
+}
+
+/// Milliseconds since an object with the given LRU clock was last accessed.
+pub fn estimate_idle_time(lru: u32) -> u64 {
+    let clock = lru_clock();
+
+    let ticks = if clock >= lru {
+        clock - lru
+    } else {
+        (LRU_CLOCK_MAX - lru) + clock
+    };
+
+    ticks as u64 * LRU_CLOCK_RESOLUTION
+}
+
+/// Current time in minutes, truncated to 16 bits, for LFU decay.
+pub fn lfu_time_in_minutes() -> u16 {
+    ((unix_time() / 1000 / 60) & 0xffff) as u16
+}
+
+fn lfu_time_elapsed(ldt: u16) -> u64 {
+    let now = lfu_time_in_minutes();
+
+    if now >= ldt {
+        (now - ldt) as u64
+    } else {
+        (u16::MAX - ldt) as u64 + now as u64
+    }
+}
+
+/// Returns `counter` decremented by one for every `lfu-decay-time` minutes
+/// elapsed since `ldt`.
+pub fn lfu_decr(counter: u8, ldt: u16) -> u8 {
+    let decay_time = LFU_DECAY_TIME.load(Ordering::Relaxed) as u64;
+    if decay_time == 0 {
+        return counter;
+    }
+
+    let periods = lfu_time_elapsed(ldt) / decay_time;
+    counter.saturating_sub(periods.min(u8::MAX as u64) as u8)
+}
+
+/// Logarithmically increments the counter: the higher it already is, the
+/// less likely an access is to increment it further.
+pub fn lfu_log_incr(counter: u8) -> u8 {
+    if counter == u8::MAX {
+        return counter;
+    }
+
+    let base = counter.saturating_sub(LFU_INIT_VAL) as f64;
+    let p = 1.0 / (base * LFU_LOG_FACTOR.load(Ordering::Relaxed) as f64 + 1.0);
+
+    if random::next_f64() < p {
+        counter + 1
+    } else {
+        counter
+    }
+}
+
+/// Access metadata kept with every key. The fields are atomics so lookups
+/// can update them while only holding a read lock on the shard.
+#[derive(Debug)]
+pub struct AccessStats {
+    lru: AtomicU32,
+    lfu_counter: AtomicU8,
+    // Last decrement time, see `lfu_time_in_minutes`.
+    lfu_ldt: AtomicU32,
+}
+
+impl AccessStats {
+    pub fn new() -> Self {
+        Self {
+            lru: AtomicU32::new(lru_clock()),
+            lfu_counter: AtomicU8::new(LFU_INIT_VAL),
+            lfu_ldt: AtomicU32::new(lfu_time_in_minutes() as u32),
+        }
+    }
+
+    /// Records an access to the key.
+    pub fn touch(&self) {
+        if lfu_enabled() {
+            let counter = lfu_log_incr(self.frequency());
+            self.lfu_counter.store(counter, Ordering::Relaxed);
+            self.lfu_ldt.store(lfu_time_in_minutes() as u32, Ordering::Relaxed);
+        } else {
+            self.lru.store(lru_clock(), Ordering::Relaxed);
+        }
+    }
+
+    /// Milliseconds since the key was last accessed.
+    pub fn idle_time(&self) -> u64 {
+        estimate_idle_time(self.lru.load(Ordering::Relaxed))
+    }
+
+    /// The decayed LFU counter.
+    pub fn frequency(&self) -> u8 {
+        lfu_decr(self.lfu_counter.load(Ordering::Relaxed), self.lfu_ldt.load(Ordering::Relaxed) as u16)
+    }
+
+    /// Carries the LFU counter of an overwritten key over to its new value.
+    pub fn inherit(&self, old: &AccessStats) {
+        self.lfu_counter.store(old.lfu_counter.load(Ordering::Relaxed), Ordering::Relaxed);
+        self.lfu_ldt.store(old.lfu_ldt.load(Ordering::Relaxed), Ordering::Relaxed);
+    }
+}
+
+impl Default for AccessStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
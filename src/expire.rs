@@ -0,0 +1,62 @@
+use std::sync::{Arc, Mutex};
+
+use bytes::Bytes;
+
+use crate::notify;
+use crate::{EventClass, Frame, PubSub};
+
+/// Keys, and fields of hashes, deleted because they expired, which the
+/// replicas weren't sent the deletion of yet.
+///
+/// Replicas don't expire keys themselves, they wait for their master to
+/// delete them. Expiry happens deep in `Shard`, with no way to propagate
+/// anything, so the shard records the deletion here instead, publishing its
+/// `expired` event right away. The `DEL`s, or `HDEL`s of fields, go out
+/// ahead of whatever is propagated next, see `ReplicationState::propagate`,
+/// and at least once per cron tick.
+///
+/// A later write to the same key can only be propagated once its shard is
+/// unlocked, after the deletion was recorded, so replicas always get the
+/// deletion first.
+pub struct ExpiredKeys {
+    pubsub: Arc<PubSub>,
+    /// Deletions to propagate, each with the database it applies to.
+    pending: Mutex<Vec<(usize, Frame)>>,
+    /// Whether the server is a replica, which keeps expired keys around,
+    /// treated as missing, until its master deletes them.
+    replica: bool,
+}
+
+impl ExpiredKeys {
+    pub fn new(pubsub: Arc<PubSub>, replica: bool) -> Self {
+        Self { pubsub, pending: Mutex::new(vec![]), replica }
+    }
+
+    /// Whether expired keys are to be deleted here, rather than left to the
+    /// master. A replica deleting them on its own could apply a write of
+    /// the master to a key the master still had, if its clock is ahead.
+    pub fn deletes_expired(&self) -> bool {
+        !self.replica
+    }
+
+    /// Records that `key` of database `db_index` was deleted as it expired.
+    pub fn key(&self, db_index: usize, key: &[u8]) {
+        notify::keyspace_event(&self.pubsub, EventClass::Expired, "expired", key, db_index);
+
+        self.pending.lock().unwrap_or_else(|err| err.into_inner()).push((db_index, Frame::command(["DEL".as_bytes(), key])));
+    }
+
+    /// Records that `fields` of the hash at `key` of database `db_index`
+    /// were deleted as they expired. The hash itself is still there.
+    pub fn fields(&self, db_index: usize, key: &[u8], fields: &[Bytes]) {
+        notify::keyspace_event(&self.pubsub, EventClass::Hash, "hexpired", key, db_index);
+
+        let args = ["HDEL".as_bytes(), key].into_iter().chain(fields.iter().map(|field| field.as_ref()));
+        self.pending.lock().unwrap_or_else(|err| err.into_inner()).push((db_index, Frame::command(args)));
+    }
+
+    /// Takes the deletions recorded since the last call, in order.
+    pub fn take(&self) -> Vec<(usize, Frame)> {
+        std::mem::take(&mut *self.pending.lock().unwrap_or_else(|err| err.into_inner()))
+    }
+}
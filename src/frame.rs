@@ -9,7 +9,11 @@ use std::num::TryFromIntError;
 
 use crate::debug;
 
-#[derive(Debug)]
+/// How deeply arrays may nest, so a frame of nothing but array headers
+/// can't overflow the stack of the recursive `check` and `parse`.
+const MAX_NESTING: usize = 128;
+
+#[derive(Debug, Clone)]
 pub enum Frame {
     Simple(String),
     Error(String),
@@ -26,15 +30,55 @@ pub enum Error {
     Incomplete,
 
     /// Invalid message format
-    Other(crate::Error),
+    Other(String),
 }
 
 impl Frame {
-    /// Checks if the buffer has enough data to decode a frame.
-    pub fn check(src: &mut Cursor<&[u8]>, expect_file: bool) -> Result<(), Error> {
+    pub fn simple(val: impl Into<String>) -> Frame {
+        Frame::Simple(val.into())
+    }
+
+    pub fn integer(val: i64) -> Frame {
+        Frame::Integer(val)
+    }
+
+    /// A non-null bulk string.
+    pub fn bulk(val: impl Into<Bytes>) -> Frame {
+        Frame::Bulk(Some(val.into()))
+    }
+
+    /// A command as sent over the wire: an array of bulk strings.
+    pub fn command<I>(args: I) -> Frame
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        Frame::Array(args.into_iter().map(|arg| Frame::bulk(Bytes::copy_from_slice(arg.as_ref()))).collect())
+    }
+
+    /// Checks if the buffer has enough data to decode a frame. Bulk strings
+    /// longer than `max_bulk_len` are rejected as soon as their length is
+    /// read, rather than buffered.
+    ///
+    /// A frame this accepts is one `parse` parses, ending at the same
+    /// position, so malformed frames are rejected here already: those
+    /// nested too deeply, and simple strings, errors and inline commands
+    /// which aren't UTF-8.
+    pub fn check(src: &mut Cursor<&[u8]>, expect_file: bool, max_bulk_len: usize) -> Result<(), Error> {
+        Frame::check_nested(src, expect_file, max_bulk_len, 0)
+    }
+
+    fn check_nested(src: &mut Cursor<&[u8]>, expect_file: bool, max_bulk_len: usize, depth: usize) -> Result<(), Error> {
         match get_u8(src)? {
             b'$' => { // RESP string.
-                let len: usize = get_decimal(src)?.try_into()?;
+                let len = match get_length(src)? {
+                    Some(len) => len,
+                    None => return Ok(()),
+                };
+
+                if len > max_bulk_len {
+                    return Err(Error::Other("invalid bulk length".to_string()));
+                }
 
                 if expect_file {
                     skip(src, len)
@@ -43,16 +87,30 @@ impl Frame {
                 }
             }
             b'*' => { // RESP array.
-                let len: usize = get_decimal(src)?.try_into()?;
+                let len = get_length(src)?.unwrap_or(0);
+                if depth >= MAX_NESTING {
+                    return Err(nested_too_deeply());
+                }
 
+                // Only a frame as a whole may be a file.
                 for _ in 0..len {
-                    Frame::check(src, expect_file)?;
+                    Frame::check_nested(src, false, max_bulk_len, depth + 1)?;
                 }
 
                 Ok(())
             }
+            b'+' | b'-' => { // RESP simple string or error.
+                std::str::from_utf8(get_line(src)?).map_err(|_| invalid_format())?;
+
+                Ok(())
+            }
+            b':' => { // RESP integer.
+                get_integer(src)?;
+
+                Ok(())
+            }
             _inline => { // Inline space-separated command.
-                get_line(src)?;
+                std::str::from_utf8(get_inline(src)?).map_err(|_| invalid_format())?;
 
                 Ok(())
             },
@@ -61,16 +119,23 @@ impl Frame {
 
     /// Parses the buffer into a Frame.
     pub fn parse(src: &mut Cursor<&[u8]>, expect_file: bool) -> Result<Frame, Error> {
+        Frame::parse_nested(src, expect_file, 0)
+    }
+
+    fn parse_nested(src: &mut Cursor<&[u8]>, expect_file: bool, depth: usize) -> Result<Frame, Error> {
         debug!("Frame::parse(): Start");
         match get_u8(src)? {
             b'$' => { // RESP string.
                 debug!("Frame::parse(): Parsing RESP string");
-                let len: usize = get_decimal(src)?.try_into()?;
+                let len = match get_length(src)? {
+                    Some(len) => len,
+                    None => return Ok(Frame::Bulk(None)),
+                };
 
                 debug!("Parsing decimal string with length: {}", len);
 
                 let n = match expect_file {
-                    false => len + 2,
+                    false => len.saturating_add(2),
                     _ => len,
                 };
 
@@ -93,13 +158,22 @@ impl Frame {
             }
             b'*' => { // RESP array.
                 debug!("Frame::parse(): Parsing RESP array");
-                let len = get_decimal(src)?.try_into()?;
+                let len = match get_length(src)? {
+                    Some(len) => len,
+                    None => return Ok(Frame::Null),
+                };
+
+                if depth >= MAX_NESTING {
+                    return Err(nested_too_deeply());
+                }
+
+                // Every element takes a few bytes at least, so a length only
+                // the buffer can back is allocated for.
+                let mut result = Vec::with_capacity(len.min(src.remaining()));
 
-                let mut result = Vec::with_capacity(len);
-                
                 for i in 0..len {
                     debug!("Parsing array element: {}", i);
-                    let part = Frame::parse(src, false)?;
+                    let part = Frame::parse_nested(src, false, depth + 1)?;
                     result.push(part);
                 }
 
@@ -110,12 +184,17 @@ impl Frame {
                 let line = get_line(src)?;
                 Ok(Frame::Simple(String::from_utf8(line.to_vec())?))
             }
-            inline => {
+            b'-' => { // RESP error.
+                let line = get_line(src)?;
+                Ok(Frame::Error(String::from_utf8(line.to_vec())?))
+            }
+            b':' => { // RESP integer.
+                Ok(Frame::Integer(get_integer(src)?))
+            }
+            _inline => {
                 debug!("Frame::parse(): Parsing inline command");
 
-                let line = get_line(src)?.to_vec();
-
-                let line_str = format!("{}{}", String::from_utf8(vec![inline])?, String::from_utf8(line)?);
+                let line_str = String::from_utf8(get_inline(src)?.to_vec())?;
                 let parts: Vec<&str> = line_str.split(' ').collect();
 
                 let mut res = vec![];
@@ -129,6 +208,7 @@ impl Frame {
         }
     }
 
+    #[allow(clippy::len_without_is_empty)]
     pub fn len(&self) -> usize {
         match self {
             Frame::Simple(s) => s.len() + 3,
@@ -141,6 +221,21 @@ impl Frame {
             Frame::File(b) => b.len() + 1 + b.len().to_string().len(),
         }
     }
+
+    /// Returns the arguments of a command frame as raw bytes.
+    pub fn to_args(&self) -> Vec<Bytes> {
+        match self {
+            Frame::Array(parts) => parts
+                .iter()
+                .filter_map(|part| match part {
+                    Frame::Bulk(Some(bytes)) => Some(bytes.clone()),
+                    Frame::Simple(val) => Some(Bytes::from(val.clone())),
+                    _ => None,
+                })
+                .collect(),
+            _ => vec![],
+        }
+    }
 }
 
 /// Skip the given number of bytes, return an error if not possible.
@@ -155,42 +250,79 @@ fn skip(src: &mut Cursor<&[u8]>, n: usize) -> Result<(), Error> {
 
 /// Find a line
 fn get_line<'a>(src: &mut Cursor<&'a [u8]>) -> Result<&'a [u8], Error> {
-    // Scan the bytes directly
+    let buf: &'a [u8] = src.get_ref();
     let start = src.position() as usize;
-    // Scan to the second to last byte
-    let end = src.get_ref().len() - 1;
 
-    for i in start..end {
-        if src.get_ref()[i] == b'\r' && src.get_ref()[i + 1] == b'\n' {
+    // Scan the bytes directly, for a CRLF starting at or after `start`.
+    let rest = buf.get(start..).unwrap_or_default();
+    match rest.windows(2).position(|pair| pair == b"\r\n") {
+        Some(len) => {
             // We found a line, update the position to be *after* the \n
-            src.set_position((i + 2) as u64);
+            src.set_position((start + len + 2) as u64);
 
             // Return the line
-            return Ok(&src.get_ref()[start..i]);
-        }
+            Ok(&rest[..len])
+        },
+        None => Err(Error::Incomplete),
     }
+}
 
-    Err(Error::Incomplete)
+/// Find the line of an inline command, whose first byte was read already
+/// to tell it's one.
+fn get_inline<'a>(src: &mut Cursor<&'a [u8]>) -> Result<&'a [u8], Error> {
+    let buf: &'a [u8] = src.get_ref();
+    let start = src.position() as usize - 1;
+    let len = get_line(src)?.len();
+
+    Ok(&buf[start..start + 1 + len])
 }
 
 /// Read a new-line terminated decimal
 fn get_decimal(src: &mut Cursor<&[u8]>) -> Result<u64, Error> {
     let line = get_line(src)?;
 
-    debug!("Got line: {}", String::from_utf8(line.to_vec())?);
+    debug!("Got line: {}", String::from_utf8_lossy(line));
+
+    if line.is_empty() {
+        return Err(Error::Other("invalid decimal string".to_string()));
+    }
 
     let mut result = 0u64;
 
     for &b in line.iter() {
         if  !b.is_ascii_digit() {
-            return Err(Error::Other("Invalid decimal string".into())); 
+            return Err(Error::Other("invalid decimal string".to_string()));
         }
-        result = result * 10 + (b - b'0') as u64;
+        result = result
+            .checked_mul(10)
+            .and_then(|result| result.checked_add((b - b'0') as u64))
+            .ok_or_else(|| Error::Other("invalid decimal string".to_string()))?;
     }
 
     Ok(result)
 }
 
+/// Read the length of a string or array, `None` for the `-1` of null
+/// replies.
+fn get_length(src: &mut Cursor<&[u8]>) -> Result<Option<usize>, Error> {
+    if src.chunk().starts_with(b"-1\r\n") {
+        src.advance(4);
+        return Ok(None);
+    }
+
+    Ok(Some(get_decimal(src)?.try_into()?))
+}
+
+/// Read a new-line terminated, possibly negative, integer
+fn get_integer(src: &mut Cursor<&[u8]>) -> Result<i64, Error> {
+    let line = get_line(src)?;
+
+    std::str::from_utf8(line)
+        .ok()
+        .and_then(|line| line.parse().ok())
+        .ok_or_else(|| Error::Other("invalid integer".to_string()))
+}
+
 /// Read a u8
 fn get_u8(src: &mut Cursor<&[u8]>) -> Result<u8, Error> {
     debug!("get_u8(): Start");
@@ -203,9 +335,65 @@ fn get_u8(src: &mut Cursor<&[u8]>) -> Result<u8, Error> {
     Ok(src.get_u8())
 }
 
+/// Reads a reply as a string, from a simple or a bulk string.
+impl TryFrom<&Frame> for String {
+    type Error = Error;
+
+    fn try_from(frame: &Frame) -> Result<String, Error> {
+        match frame {
+            Frame::Simple(val) => Ok(val.clone()),
+            Frame::Bulk(Some(val)) => Ok(String::from_utf8(val.to_vec())?),
+            frame => Err(unexpected_reply(frame, "a string")),
+        }
+    }
+}
+
+/// Reads a reply as an integer, from an integer or a string holding one.
+impl TryFrom<&Frame> for i64 {
+    type Error = Error;
+
+    fn try_from(frame: &Frame) -> Result<i64, Error> {
+        match frame {
+            Frame::Integer(val) => Ok(*val),
+            Frame::Simple(_) | Frame::Bulk(Some(_)) => String::try_from(frame)?
+                .parse()
+                .map_err(|_| unexpected_reply(frame, "an integer")),
+            frame => Err(unexpected_reply(frame, "an integer")),
+        }
+    }
+}
+
+/// Reads a reply as raw bytes, from a simple or a bulk string.
+impl TryFrom<&Frame> for Bytes {
+    type Error = Error;
+
+    fn try_from(frame: &Frame) -> Result<Bytes, Error> {
+        match frame {
+            Frame::Simple(val) => Ok(Bytes::from(val.clone())),
+            Frame::Bulk(Some(val)) => Ok(val.clone()),
+            frame => Err(unexpected_reply(frame, "a string")),
+        }
+    }
+}
+
+fn invalid_format() -> Error {
+    "invalid frame format".into()
+}
+
+fn nested_too_deeply() -> Error {
+    "frame nested too deeply".into()
+}
+
+fn unexpected_reply(frame: &Frame, expected: &str) -> Error {
+    match frame {
+        Frame::Error(err) => Error::Other(err.clone()),
+        frame => Error::Other(format!("expected {}, got {:?}", expected, frame)),
+    }
+}
+
 impl From<String> for Error {
     fn from(src: String) -> Error {
-        Error::Other(src.into())
+        Error::Other(src)
     }
 }
 
@@ -217,13 +405,13 @@ impl From<&str> for Error {
 
 impl From<FromUtf8Error> for Error {
     fn from(_src: FromUtf8Error) -> Error {
-        "protocol error; invalid frame format".into()
+        "invalid frame format".into()
     }
 }
 
 impl From<TryFromIntError> for Error {
     fn from(_src: TryFromIntError) -> Error {
-        "protocol error; invalid frame format".into()
+        "invalid frame format".into()
     }
 }
 
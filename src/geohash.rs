@@ -0,0 +1,268 @@
+//! Geohashes as used by the GEO commands: coordinates are encoded as 52-bit
+//! integers, interleaving 26 bits of latitude and longitude, which are stored
+//! as the scores of a sorted set.
+
+/// Bits of precision per coordinate.
+pub const STEP_MAX: u32 = 26;
+
+pub const LONGITUDE_MIN: f64 = -180.0;
+pub const LONGITUDE_MAX: f64 = 180.0;
+// Limits of EPSG:900913 / EPSG:3785 / OSGEO:41001, like Redis.
+pub const LATITUDE_MIN: f64 = -85.05112878;
+pub const LATITUDE_MAX: f64 = 85.05112878;
+
+/// Earth's quadratic mean radius for WGS-84, used by Redis.
+const EARTH_RADIUS_IN_METERS: f64 = 6372797.560856;
+
+/// Whether a longitude/latitude pair can be encoded.
+pub fn is_valid(lon: f64, lat: f64) -> bool {
+    (LONGITUDE_MIN..=LONGITUDE_MAX).contains(&lon) && (LATITUDE_MIN..=LATITUDE_MAX).contains(&lat)
+}
+
+/// Spreads the low 32 bits of `x` to the even bits of the result.
+fn spread(x: u32) -> u64 {
+    let mut x = x as u64;
+
+    x = (x | (x << 16)) & 0x0000_ffff_0000_ffff;
+    x = (x | (x << 8)) & 0x00ff_00ff_00ff_00ff;
+    x = (x | (x << 4)) & 0x0f0f_0f0f_0f0f_0f0f;
+    x = (x | (x << 2)) & 0x3333_3333_3333_3333;
+    (x | (x << 1)) & 0x5555_5555_5555_5555
+}
+
+/// Gathers the even bits of `x` into the low 32 bits of the result.
+fn squash(x: u64) -> u32 {
+    let mut x = x & 0x5555_5555_5555_5555;
+
+    x = (x | (x >> 1)) & 0x3333_3333_3333_3333;
+    x = (x | (x >> 2)) & 0x0f0f_0f0f_0f0f_0f0f;
+    x = (x | (x >> 4)) & 0x00ff_00ff_00ff_00ff;
+    x = (x | (x >> 8)) & 0x0000_ffff_0000_ffff;
+    ((x | (x >> 16)) & 0x0000_0000_ffff_ffff) as u32
+}
+
+/// Encodes a position with `step` bits per coordinate. Latitude takes the
+/// even bits and longitude the odd ones.
+pub fn encode(lon: f64, lat: f64, step: u32) -> u64 {
+    let scale = (1u64 << step) as f64;
+
+    let lat_offset = ((lat - LATITUDE_MIN) / (LATITUDE_MAX - LATITUDE_MIN) * scale) as u32;
+    let lon_offset = ((lon - LONGITUDE_MIN) / (LONGITUDE_MAX - LONGITUDE_MIN) * scale) as u32;
+
+    spread(lat_offset) | (spread(lon_offset) << 1)
+}
+
+/// The area covered by a geohash.
+#[derive(Debug, Clone, Copy)]
+pub struct Area {
+    pub lon_min: f64,
+    pub lon_max: f64,
+    pub lat_min: f64,
+    pub lat_max: f64,
+}
+
+/// Decodes a geohash of `step` bits per coordinate into the area it covers.
+pub fn decode_area(hash: u64, step: u32) -> Area {
+    let scale = (1u64 << step) as f64;
+
+    let lat_offset = squash(hash) as f64;
+    let lon_offset = squash(hash >> 1) as f64;
+
+    let lat_range = LATITUDE_MAX - LATITUDE_MIN;
+    let lon_range = LONGITUDE_MAX - LONGITUDE_MIN;
+
+    Area {
+        lat_min: LATITUDE_MIN + lat_offset / scale * lat_range,
+        lat_max: LATITUDE_MIN + (lat_offset + 1.0) / scale * lat_range,
+        lon_min: LONGITUDE_MIN + lon_offset / scale * lon_range,
+        lon_max: LONGITUDE_MIN + (lon_offset + 1.0) / scale * lon_range,
+    }
+}
+
+/// Decodes a full precision geohash into the longitude and latitude at the
+/// center of its area.
+pub fn decode(hash: u64) -> (f64, f64) {
+    let area = decode_area(hash, STEP_MAX);
+
+    let lon = ((area.lon_min + area.lon_max) / 2.0).clamp(LONGITUDE_MIN, LONGITUDE_MAX);
+    let lat = ((area.lat_min + area.lat_max) / 2.0).clamp(LATITUDE_MIN, LATITUDE_MAX);
+
+    (lon, lat)
+}
+
+/// The sorted set score a position is stored with.
+pub fn score(lon: f64, lat: f64) -> f64 {
+    encode(lon, lat, STEP_MAX) as f64
+}
+
+/// The position a sorted set score stands for.
+pub fn position(score: f64) -> (f64, f64) {
+    decode(score as u64)
+}
+
+/// Distance in meters between two positions, using the haversine formula.
+pub fn distance(lon1: f64, lat1: f64, lon2: f64, lat2: f64) -> f64 {
+    let lat1 = lat1.to_radians();
+    let lat2 = lat2.to_radians();
+
+    let u = ((lat2 - lat1) / 2.0).sin();
+    let v = ((lon2.to_radians() - lon1.to_radians()) / 2.0).sin();
+
+    2.0 * EARTH_RADIUS_IN_METERS * (u * u + lat1.cos() * lat2.cos() * v * v).sqrt().asin()
+}
+
+/// Meters per distance unit, for the units GEO commands accept.
+pub fn unit_to_meters(unit: &str) -> Option<f64> {
+    match unit.to_lowercase().as_str() {
+        "m" => Some(1.0),
+        "km" => Some(1000.0),
+        "ft" => Some(0.3048),
+        "mi" => Some(1609.34),
+        _ => None,
+    }
+}
+
+/// Largest distance on the Mercator projection, in meters.
+const MERCATOR_MAX: f64 = 20037726.37;
+
+/// The area searched by `GEOSEARCH`, in meters.
+#[derive(Debug, Clone, Copy)]
+pub enum Shape {
+    Radius(f64),
+    Box { width: f64, height: f64 },
+}
+
+impl Shape {
+    /// Distance from the center of the shape to a point within `lon`/`lat`
+    /// of it, or `None` if the point is outside the shape.
+    pub fn distance_if_within(&self, center: (f64, f64), lon: f64, lat: f64) -> Option<f64> {
+        let (center_lon, center_lat) = center;
+
+        if let Shape::Box { width, height } = *self {
+            let lat_distance = EARTH_RADIUS_IN_METERS * (lat.to_radians() - center_lat.to_radians()).abs();
+            if lat_distance > height / 2.0 {
+                return None;
+            }
+
+            if distance(lon, lat, center_lon, lat) > width / 2.0 {
+                return None;
+            }
+        }
+
+        let distance = distance(center_lon, center_lat, lon, lat);
+        match *self {
+            Shape::Radius(radius) if distance > radius => None,
+            _ => Some(distance),
+        }
+    }
+
+    /// Radius of the smallest circle holding the shape.
+    fn radius(&self) -> f64 {
+        match *self {
+            Shape::Radius(radius) => radius,
+            Shape::Box { width, height } => ((width / 2.0).powi(2) + (height / 2.0).powi(2)).sqrt(),
+        }
+    }
+
+    /// The bounding box of the shape around `lon`/`lat`, as the longitude
+    /// and latitude of its south-west and north-east corners.
+    fn bounding_box(&self, lon: f64, lat: f64) -> (f64, f64, f64, f64) {
+        let (half_width, half_height) = match *self {
+            Shape::Radius(radius) => (radius, radius),
+            Shape::Box { width, height } => (width / 2.0, height / 2.0),
+        };
+
+        let lat_delta = (half_height / EARTH_RADIUS_IN_METERS).to_degrees();
+        let lon_delta_top = (half_width / EARTH_RADIUS_IN_METERS / (lat + lat_delta).to_radians().cos()).to_degrees();
+        let lon_delta_bottom = (half_width / EARTH_RADIUS_IN_METERS / (lat - lat_delta).to_radians().cos()).to_degrees();
+
+        // The widest edge is the one nearer the equator.
+        let lon_delta = if lat < 0.0 { lon_delta_bottom } else { lon_delta_top };
+
+        (lon - lon_delta, lat - lat_delta, lon + lon_delta, lat + lat_delta)
+    }
+}
+
+/// The number of bits per coordinate of geohash cells which, together with
+/// their neighbours, cover a radius of `radius` meters around `lat`.
+fn estimate_step(radius: f64, lat: f64) -> u32 {
+    if radius == 0.0 {
+        return STEP_MAX;
+    }
+
+    let mut radius = radius;
+    let mut step: i32 = 1;
+    while radius < MERCATOR_MAX {
+        radius *= 2.0;
+        step += 1;
+    }
+    // Make sure the range is included in most of the base cases.
+    step -= 2;
+
+    // Cells get narrower towards the poles.
+    if !(-66.0..=66.0).contains(&lat) {
+        step -= 1;
+        if !(-80.0..=80.0).contains(&lat) {
+            step -= 1;
+        }
+    }
+
+    step.clamp(1, STEP_MAX as i32) as u32
+}
+
+/// Moves a geohash of `step` bits per coordinate by whole cells, wrapping
+/// around at the edges.
+fn neighbour(hash: u64, step: u32, d_lon: i64, d_lat: i64) -> u64 {
+    let cells = 1i64 << step;
+
+    let lat = (squash(hash) as i64 + d_lat).rem_euclid(cells) as u32;
+    let lon = (squash(hash >> 1) as i64 + d_lon).rem_euclid(cells) as u32;
+
+    spread(lat) | (spread(lon) << 1)
+}
+
+/// The score ranges, each `min..max` with `max` exclusive, of the geohash
+/// cells covering `shape` around `lon`/`lat`.
+pub fn search_ranges(lon: f64, lat: f64, shape: Shape) -> Vec<(u64, u64)> {
+    let (min_lon, min_lat, max_lon, max_lat) = shape.bounding_box(lon, lat);
+    let mut step = estimate_step(shape.radius(), lat);
+
+    // Near the edges of the center cell, the neighbours may not reach far
+    // enough. Use bigger cells then.
+    let hash = encode(lon, lat, step);
+    let north = decode_area(neighbour(hash, step, 0, 1), step);
+    let south = decode_area(neighbour(hash, step, 0, -1), step);
+    let east = decode_area(neighbour(hash, step, 1, 0), step);
+    let west = decode_area(neighbour(hash, step, -1, 0), step);
+
+    if step > 1 && (north.lat_max < max_lat || south.lat_min > min_lat || east.lon_max < max_lon || west.lon_min > min_lon) {
+        step -= 1;
+    }
+
+    let hash = encode(lon, lat, step);
+    let area = decode_area(hash, step);
+
+    let mut cells = vec![];
+    for d_lat in -1..=1 {
+        for d_lon in -1..=1 {
+            // Skip neighbours the search area doesn't reach into.
+            if step >= 2 && ((d_lat == -1 && area.lat_min < min_lat)
+                || (d_lat == 1 && area.lat_max > max_lat)
+                || (d_lon == -1 && area.lon_min < min_lon)
+                || (d_lon == 1 && area.lon_max > max_lon))
+            {
+                continue;
+            }
+
+            let cell = neighbour(hash, step, d_lon, d_lat);
+            // With very large cells, neighbours can wrap around to the same
+            // cell.
+            if !cells.contains(&cell) {
+                cells.push(cell);
+            }
+        }
+    }
+
+    let shift = (STEP_MAX - step) * 2;
+    cells.into_iter().map(|cell| (cell << shift, (cell + 1) << shift)).collect()
+}
@@ -0,0 +1,111 @@
+use crate::warn;
+
+/// Most steps a single match may take before giving up on it. Matching takes
+/// at most pattern length times string length steps, so only huge keys or
+/// channels come near, and a match cut short counts as no match.
+const MAX_STEPS: u64 = 1 << 26;
+
+/// Matches `string` against a glob-style pattern, like `stringmatchlen` in
+/// redis-server: `*` matches any run of bytes, `?` any single byte, `[...]`
+/// a set of bytes (with `^` negating it and `a-z` ranges), and `\` escapes
+/// the byte after it.
+///
+/// Everything but `*` matches exactly one byte, so on a mismatch only the
+/// last star needs to take one more byte, never an earlier one: this keeps
+/// patterns like `a*a*a*a*b` from taking exponential time.
+pub fn matches(pattern: &[u8], string: &[u8]) -> bool {
+    let (mut p, mut s) = (0, 0);
+    // Where to carry on from after the last star, in the pattern and the
+    // string, if what follows it fails to match.
+    let mut star: Option<(usize, usize)> = None;
+    let mut steps = 0u64;
+
+    loop {
+        steps += 1;
+        if steps > MAX_STEPS {
+            warn!("Gave up matching a {} byte string against a {} byte pattern after {} steps", string.len(), pattern.len(), MAX_STEPS);
+            return false;
+        }
+
+        if p < pattern.len() {
+            if pattern[p] == b'*' {
+                // Consecutive stars match the same as one.
+                while p < pattern.len() && pattern[p] == b'*' {
+                    p += 1;
+                }
+                if p == pattern.len() {
+                    return true;
+                }
+                star = Some((p, s));
+                continue;
+            }
+
+            if s < string.len() {
+                if let Some(end) = match_one(pattern, p, string[s]) {
+                    p = end + 1;
+                    s += 1;
+                    continue;
+                }
+            }
+        } else if s == string.len() {
+            return true;
+        }
+
+        // Have the last star take one more byte, and try again after it.
+        match star {
+            Some((after, from)) if from < string.len() => {
+                star = Some((after, from + 1));
+                p = after;
+                s = from + 1;
+            },
+            _ => return false,
+        }
+    }
+}
+
+/// Matches `byte` against the part of the pattern at `p`, other than a star,
+/// returning the index of its last byte if it matched.
+fn match_one(pattern: &[u8], p: usize, byte: u8) -> Option<usize> {
+    let (matched, end) = match pattern[p] {
+        b'?' => (true, p),
+        b'[' => match_set(pattern, p + 1, byte),
+        b'\\' if p + 1 < pattern.len() => (pattern[p + 1] == byte, p + 1),
+        literal => (literal == byte, p),
+    };
+
+    matched.then_some(end)
+}
+
+/// Matches `byte` against the set starting at `start`, just past its `[`,
+/// returning whether it matched and the index of the closing `]`. An
+/// unterminated set runs to the end of the pattern.
+fn match_set(pattern: &[u8], start: usize, byte: u8) -> (bool, usize) {
+    let mut p = start;
+    let negate = p < pattern.len() && pattern[p] == b'^';
+    if negate {
+        p += 1;
+    }
+
+    let mut matched = false;
+    while p < pattern.len() && pattern[p] != b']' {
+        if pattern[p] == b'\\' && p + 1 < pattern.len() {
+            p += 1;
+            matched |= pattern[p] == byte;
+        } else if p + 2 < pattern.len() && pattern[p + 1] == b'-' && pattern[p + 2] != b']' {
+            let (low, high) = match pattern[p] <= pattern[p + 2] {
+                true => (pattern[p], pattern[p + 2]),
+                false => (pattern[p + 2], pattern[p]),
+            };
+            matched |= (low..=high).contains(&byte);
+            p += 2;
+        } else {
+            matched |= pattern[p] == byte;
+        }
+        p += 1;
+    }
+
+    // Past the end, so the caller's increment doesn't skip anything.
+    let end = p.min(pattern.len() - 1);
+
+    (matched != negate, end)
+}
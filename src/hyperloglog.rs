@@ -0,0 +1,226 @@
+//! HyperLogLog cardinality estimation, stored in string values using the
+//! dense encoding of Redis: a 16 byte header followed by 16384 registers of 6
+//! bits each. The sparse encoding isn't supported.
+
+/// Bits of the hash used to select a register.
+const P: u32 = 14;
+/// Bits of the hash left to count runs of zeros in.
+const Q: u32 = 64 - P;
+pub const REGISTERS: usize = 1 << P;
+const REGISTER_BITS: usize = 6;
+const REGISTER_MAX: u8 = (1 << REGISTER_BITS) - 1;
+
+const HEADER_SIZE: usize = 16;
+const DENSE_SIZE: usize = HEADER_SIZE + REGISTERS * REGISTER_BITS / 8;
+const MAGIC: &[u8] = b"HYLL";
+const ENCODING_DENSE: u8 = 0;
+
+/// Set in the last byte of the cached cardinality when it is out of date.
+const CACHE_INVALID: u8 = 1 << 7;
+
+const ALPHA_INF: f64 = 0.721_347_520_444_481_7;
+
+/// A new HyperLogLog with every register at zero.
+pub fn new_dense() -> Vec<u8> {
+    let mut buf = vec![0; DENSE_SIZE];
+    buf[..MAGIC.len()].copy_from_slice(MAGIC);
+    buf[4] = ENCODING_DENSE;
+
+    buf
+}
+
+/// Whether `buf` holds a HyperLogLog we can work with.
+pub fn is_valid(buf: &[u8]) -> bool {
+    buf.len() == DENSE_SIZE && buf.starts_with(MAGIC) && buf[4] == ENCODING_DENSE
+}
+
+fn get_register(registers: &[u8], idx: usize) -> u8 {
+    let byte = idx * REGISTER_BITS / 8;
+    let shift = idx * REGISTER_BITS % 8;
+
+    // Registers are packed least significant bit first, and may straddle two
+    // bytes.
+    let lo = registers[byte] as u16;
+    let hi = registers.get(byte + 1).copied().unwrap_or(0) as u16;
+
+    (((lo | (hi << 8)) >> shift) as u8) & REGISTER_MAX
+}
+
+fn set_register(registers: &mut [u8], idx: usize, val: u8) {
+    let byte = idx * REGISTER_BITS / 8;
+    let shift = idx * REGISTER_BITS % 8;
+
+    registers[byte] &= !(REGISTER_MAX << shift);
+    registers[byte] |= val << shift;
+
+    if shift + REGISTER_BITS > 8 {
+        let shift = 8 - shift;
+        registers[byte + 1] &= !(REGISTER_MAX >> shift);
+        registers[byte + 1] |= val >> shift;
+    }
+}
+
+/// MurmurHash64A, which Redis hashes elements with.
+fn murmur_hash64a(key: &[u8], seed: u64) -> u64 {
+    const M: u64 = 0xc6a4_a793_5bd1_e995;
+    const R: u32 = 47;
+
+    let mut h = seed ^ (key.len() as u64).wrapping_mul(M);
+
+    let mut chunks = key.chunks_exact(8);
+    for chunk in chunks.by_ref() {
+        let mut k = u64::from_le_bytes(chunk.try_into().unwrap());
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+
+        h ^= k;
+        h = h.wrapping_mul(M);
+    }
+
+    let rest = chunks.remainder();
+    if !rest.is_empty() {
+        for (idx, byte) in rest.iter().enumerate() {
+            h ^= (*byte as u64) << (8 * idx);
+        }
+        h = h.wrapping_mul(M);
+    }
+
+    h ^= h >> R;
+    h = h.wrapping_mul(M);
+    h ^= h >> R;
+
+    h
+}
+
+/// The register an element maps to, and the length of the run of zeros
+/// (plus one) in the rest of its hash.
+fn register_and_count(element: &[u8]) -> (usize, u8) {
+    let hash = murmur_hash64a(element, 0xadc8_3b19);
+    let idx = (hash & (REGISTERS as u64 - 1)) as usize;
+
+    // Make sure the loop terminates and the count is at most Q + 1.
+    let hash = (hash >> P) | (1 << Q);
+
+    (idx, hash.trailing_zeros() as u8 + 1)
+}
+
+/// Adds `element` to the HyperLogLog in `buf`, returning whether a register
+/// changed.
+pub fn add(buf: &mut [u8], element: &[u8]) -> bool {
+    let (idx, count) = register_and_count(element);
+    let registers = &mut buf[HEADER_SIZE..];
+
+    if get_register(registers, idx) >= count {
+        return false;
+    }
+
+    set_register(registers, idx, count);
+    invalidate_cache(buf);
+
+    true
+}
+
+pub fn invalidate_cache(buf: &mut [u8]) {
+    buf[15] |= CACHE_INVALID;
+}
+
+pub fn cached_count(buf: &[u8]) -> Option<u64> {
+    if buf[15] & CACHE_INVALID != 0 {
+        return None;
+    }
+
+    Some(u64::from_le_bytes(buf[8..16].try_into().unwrap()))
+}
+
+pub fn set_cached_count(buf: &mut [u8], count: u64) {
+    buf[8..16].copy_from_slice(&count.to_le_bytes());
+}
+
+/// The registers of the HyperLogLog in `buf`, one per byte.
+pub fn registers(buf: &[u8]) -> Vec<u8> {
+    let registers = &buf[HEADER_SIZE..];
+    (0..REGISTERS).map(|idx| get_register(registers, idx)).collect()
+}
+
+/// Merges the HyperLogLog in `buf` into `max`, which holds one register per
+/// byte, keeping the larger value of every register.
+pub fn merge(max: &mut [u8], buf: &[u8]) {
+    let registers = &buf[HEADER_SIZE..];
+
+    for (idx, reg) in max.iter_mut().enumerate() {
+        *reg = (*reg).max(get_register(registers, idx));
+    }
+}
+
+/// Packs registers held one per byte into a new HyperLogLog.
+pub fn from_registers(max: &[u8]) -> Vec<u8> {
+    let mut buf = new_dense();
+
+    for (idx, reg) in max.iter().enumerate() {
+        set_register(&mut buf[HEADER_SIZE..], idx, *reg);
+    }
+    invalidate_cache(&mut buf);
+
+    buf
+}
+
+/// Estimates the cardinality from registers held one per byte, using the
+/// estimator from Otmar Ertl's "New cardinality estimation algorithms for
+/// HyperLogLog sketches", like Redis.
+pub fn estimate(registers: &[u8]) -> u64 {
+    let m = REGISTERS as f64;
+
+    let mut histogram = [0u32; Q as usize + 2];
+    for reg in registers {
+        histogram[*reg as usize] += 1;
+    }
+
+    let q = Q as usize;
+    let mut z = m * tau((m - histogram[q + 1] as f64) / m);
+    for count in histogram[1..=q].iter().rev() {
+        z += *count as f64;
+        z *= 0.5;
+    }
+    z += m * sigma(histogram[0] as f64 / m);
+
+    (ALPHA_INF * m * m / z).round() as u64
+}
+
+fn sigma(mut x: f64) -> f64 {
+    if x == 1.0 {
+        return f64::INFINITY;
+    }
+
+    let mut y = 1.0;
+    let mut z = x;
+    loop {
+        x *= x;
+        let prev = z;
+        z += x * y;
+        y += y;
+
+        if prev == z {
+            return z;
+        }
+    }
+}
+
+fn tau(mut x: f64) -> f64 {
+    if x == 0.0 || x == 1.0 {
+        return 0.0;
+    }
+
+    let mut y = 1.0;
+    let mut z = 1.0 - x;
+    loop {
+        x = x.sqrt();
+        let prev = z;
+        y *= 0.5;
+        z -= (1.0 - x).powi(2) * y;
+
+        if prev == z {
+            return z / 3.0;
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+
+use crate::{cron, get_unix_ts_millis};
+
+/// The redis-server version this server answers as, e.g. in `HELLO`.
+pub const REDIS_VERSION: &str = "7.2.0";
+
+/// What identifies this server process, fixed for as long as it runs.
+#[derive(Debug)]
+pub struct ServerIdentity {
+    /// Random ID of this process, which unlike the replication ID never
+    /// changes while it runs.
+    pub run_id: String,
+    pub process_id: u32,
+    pub tcp_port: u16,
+    started_at: Instant,
+}
+
+impl ServerIdentity {
+    pub fn new(tcp_port: u16) -> Self {
+        Self {
+            run_id: generate_id(),
+            process_id: std::process::id(),
+            tcp_port,
+            started_at: Instant::now(),
+        }
+    }
+
+    pub fn uptime(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+
+    pub fn get_info_bytes(&self) -> Bytes {
+        let uptime = self.uptime().as_secs();
+
+        Bytes::from(format!(
+            "# Server\nredis_version:{}\nprocess_id:{}\nrun_id:{}\ntcp_port:{}\nuptime_in_seconds:{}\nuptime_in_days:{}\nhz:{}\nconfigured_hz:{}\n",
+            REDIS_VERSION,
+            self.process_id,
+            self.run_id,
+            self.tcp_port,
+            uptime,
+            uptime / (24 * 60 * 60),
+            cron::hz(),
+            cron::hz(),
+        ))
+    }
+}
+
+/// A random 40 character hex string, like the IDs redis-server generates.
+/// Each `RandomState` is seeded from the OS's randomness, so hashing the
+/// same input with fresh ones gives unpredictable numbers.
+pub fn generate_id() -> String {
+    let mut id = String::with_capacity(48);
+
+    while id.len() < 40 {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u128(get_unix_ts_millis());
+        hasher.write_u32(std::process::id());
+        id.push_str(&format!("{:016x}", hasher.finish()));
+    }
+    id.truncate(40);
+
+    id
+}
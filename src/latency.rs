@@ -0,0 +1,198 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use bytes::Bytes;
+
+use crate::{get_unix_ts_millis, Frame};
+
+/// Number of power-of-two microsecond buckets, the last one is open-ended.
+const HISTOGRAM_BUCKETS: usize = 24;
+
+/// Number of per-second samples kept for `LATENCY HISTORY`.
+const HISTORY_LEN: usize = 160;
+
+/// Latency statistics for a single command.
+pub struct CommandStat {
+    calls: AtomicU64,
+    usec: AtomicU64,
+    histogram: [AtomicU64; HISTOGRAM_BUCKETS],
+    // (unix seconds, max latency in milliseconds seen during that second)
+    history: Mutex<VecDeque<(u64, u64)>>,
+}
+
+impl CommandStat {
+    fn new() -> Self {
+        Self {
+            calls: AtomicU64::new(0),
+            usec: AtomicU64::new(0),
+            histogram: Default::default(),
+            history: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    fn record(&self, duration: Duration) {
+        let usec = duration.as_micros() as u64;
+
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        self.usec.fetch_add(usec, Ordering::Relaxed);
+
+        let bucket = (64 - usec.leading_zeros() as usize).min(HISTOGRAM_BUCKETS - 1);
+        self.histogram[bucket].fetch_add(1, Ordering::Relaxed);
+
+        let now = (get_unix_ts_millis() / 1000) as u64;
+        let latency_ms = usec / 1000;
+        let mut history = self.history.lock().unwrap_or_else(|err| err.into_inner());
+
+        match history.back_mut() {
+            Some((ts, max)) if *ts == now => *max = (*max).max(latency_ms),
+            _ => {
+                history.push_back((now, latency_ms));
+                if history.len() > HISTORY_LEN {
+                    history.pop_front();
+                }
+            }
+        }
+    }
+
+    fn info_line(&self, name: &str) -> String {
+        let calls = self.calls.load(Ordering::Relaxed);
+        let usec = self.usec.load(Ordering::Relaxed);
+        let usec_per_call = if calls == 0 { 0.0 } else { usec as f64 / calls as f64 };
+
+        format!("cmdstat_{}:calls={},usec={},usec_per_call={:.2}\n", name, calls, usec, usec_per_call)
+    }
+
+    fn history_frame(&self) -> Frame {
+        let history = self.history.lock().unwrap_or_else(|err| err.into_inner());
+
+        Frame::Array(history.iter().map(|(ts, latency)| Frame::Array(vec![
+            Frame::Integer(*ts as i64),
+            Frame::Integer(*latency as i64),
+        ])).collect())
+    }
+
+    fn histogram_frame(&self) -> Frame {
+        let mut buckets = vec![];
+        let mut cumulative = 0;
+
+        for (idx, count) in self.histogram.iter().enumerate() {
+            let count = count.load(Ordering::Relaxed);
+            if count == 0 {
+                continue;
+            }
+
+            cumulative += count;
+            buckets.push(Frame::Integer(1i64 << idx));
+            buckets.push(Frame::Integer(cumulative as i64));
+        }
+
+        Frame::Array(vec![
+            Frame::bulk("calls"),
+            Frame::Integer(self.calls.load(Ordering::Relaxed) as i64),
+            Frame::bulk("histogram_usec"),
+            Frame::Array(buckets),
+        ])
+    }
+}
+
+/// Per-command latency statistics. Entries are created the first time a
+/// command runs, after which recording only touches that command's atomics.
+#[derive(Default)]
+pub struct CommandStats {
+    commands: RwLock<HashMap<String, Arc<CommandStat>>>,
+}
+
+impl CommandStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, name: &str, duration: Duration) {
+        let stat = self.commands.read().unwrap_or_else(|err| err.into_inner()).get(name).cloned();
+
+        let stat = match stat {
+            Some(stat) => stat,
+            None => self.commands.write().unwrap_or_else(|err| err.into_inner())
+                .entry(name.to_string())
+                .or_insert_with(|| Arc::new(CommandStat::new()))
+                .clone(),
+        };
+
+        stat.record(duration);
+    }
+
+    /// Calls and total microseconds of every command run so far, sorted by
+    /// name.
+    pub fn totals(&self) -> Vec<(String, u64, u64)> {
+        let commands = self.commands.read().unwrap_or_else(|err| err.into_inner());
+
+        let mut totals: Vec<(String, u64, u64)> = commands.iter()
+            .map(|(name, stat)| (name.clone(), stat.calls.load(Ordering::Relaxed), stat.usec.load(Ordering::Relaxed)))
+            .collect();
+        totals.sort();
+
+        totals
+    }
+
+    /// Renders the `commandstats` INFO section.
+    pub fn get_info_bytes(&self) -> Bytes {
+        let commands = self.commands.read().unwrap_or_else(|err| err.into_inner());
+
+        let mut names: Vec<&String> = commands.keys().collect();
+        names.sort();
+
+        let mut res = "# Commandstats\n".to_string();
+        for name in names {
+            res.push_str(&commands[name].info_line(name));
+        }
+
+        Bytes::from(res)
+    }
+
+    pub fn history(&self, name: &str) -> Frame {
+        match self.commands.read().unwrap_or_else(|err| err.into_inner()).get(&name.to_lowercase()) {
+            Some(stat) => stat.history_frame(),
+            None => Frame::Array(vec![]),
+        }
+    }
+
+    /// Histograms for the given commands, or for every command that has run
+    /// when `names` is empty.
+    pub fn histogram(&self, names: &[String]) -> Frame {
+        let commands = self.commands.read().unwrap_or_else(|err| err.into_inner());
+
+        let mut names: Vec<String> = if names.is_empty() {
+            commands.keys().cloned().collect()
+        } else {
+            names.iter().map(|name| name.to_lowercase()).collect()
+        };
+        names.sort();
+        names.dedup();
+
+        let mut res = vec![];
+        for name in names {
+            if let Some(stat) = commands.get(&name) {
+                res.push(Frame::bulk(name));
+                res.push(stat.histogram_frame());
+            }
+        }
+
+        Frame::Array(res)
+    }
+
+    /// Forgets the statistics of the given commands, or of every command when
+    /// `names` is empty. Returns the number of commands reset.
+    pub fn reset(&self, names: &[String]) -> usize {
+        let mut commands = self.commands.write().unwrap_or_else(|err| err.into_inner());
+
+        if names.is_empty() {
+            let count = commands.len();
+            commands.clear();
+            return count;
+        }
+
+        names.iter().filter(|name| commands.remove(&name.to_lowercase()).is_some()).count()
+    }
+}
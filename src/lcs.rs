@@ -0,0 +1,95 @@
+//! Longest common subsequence of two strings, as used by `LCS`.
+
+use crate::LongOperation;
+
+/// A run of bytes found consecutively in both strings, as inclusive ranges of
+/// indexes into each.
+#[derive(Debug, Clone, Copy)]
+pub struct Match {
+    pub a: (usize, usize),
+    pub b: (usize, usize),
+}
+
+impl Match {
+    pub fn len(&self) -> usize {
+        self.a.1 - self.a.0 + 1
+    }
+}
+
+#[derive(Debug)]
+pub struct Lcs {
+    pub subsequence: Vec<u8>,
+    /// The runs making up the subsequence, last one first like Redis
+    /// reports them.
+    pub matches: Vec<Match>,
+}
+
+/// Number of cells in the table `lcs` fills in for strings of these lengths,
+/// which takes 4 bytes each.
+pub fn table_cells(a_len: usize, b_len: usize) -> Option<usize> {
+    (a_len + 1).checked_mul(b_len + 1)
+}
+
+/// Finds the longest common subsequence of `a` and `b`, picking the same one
+/// as Redis when there are several. Checks `operation` after each row of the
+/// table.
+pub fn lcs(a: &[u8], b: &[u8], operation: &LongOperation) -> crate::Result<Lcs> {
+    let width = b.len() + 1;
+
+    // table[i * width + j] is the length of the LCS of a[..i] and b[..j].
+    let mut table = vec![0u32; (a.len() + 1) * width];
+    for i in 1..=a.len() {
+        operation.checkpoint()?;
+        for j in 1..=b.len() {
+            table[i * width + j] = if a[i - 1] == b[j - 1] {
+                table[(i - 1) * width + j - 1] + 1
+            } else {
+                table[(i - 1) * width + j].max(table[i * width + j - 1])
+            };
+        }
+    }
+
+    // Walk back from the end, collecting the subsequence and the runs.
+    let mut subsequence = Vec::with_capacity(table[a.len() * width + b.len()] as usize);
+    let mut matches = vec![];
+    let mut current: Option<Match> = None;
+
+    let (mut i, mut j) = (a.len(), b.len());
+    while i > 0 && j > 0 {
+        if a[i - 1] == b[j - 1] {
+            subsequence.push(a[i - 1]);
+
+            match current.as_mut() {
+                Some(run) if run.a.0 == i && run.b.0 == j => {
+                    run.a.0 -= 1;
+                    run.b.0 -= 1;
+                },
+                Some(run) => {
+                    matches.push(*run);
+                    current = Some(Match { a: (i - 1, i - 1), b: (j - 1, j - 1) });
+                },
+                None => current = Some(Match { a: (i - 1, i - 1), b: (j - 1, j - 1) }),
+            }
+
+            i -= 1;
+            j -= 1;
+        } else {
+            if table[(i - 1) * width + j] > table[i * width + j - 1] {
+                i -= 1;
+            } else {
+                j -= 1;
+            }
+
+            if let Some(run) = current.take() {
+                matches.push(run);
+            }
+        }
+    }
+
+    if let Some(run) = current {
+        matches.push(run);
+    }
+    subsequence.reverse();
+
+    Ok(Lcs { subsequence, matches })
+}
@@ -1,36 +1,138 @@
-mod log;
+pub mod log;
 
 mod connection;
-use std::time::{SystemTime, UNIX_EPOCH};
 
-pub use connection::{Connection, ConnectionManager};
+pub use connection::{Connection, ConnectionClass, ConnectionManager};
 
 pub mod frame;
 pub use frame::Frame;
 
 mod commands;
-pub use commands::Command;
+pub use commands::{Command, CommandContext, Propagate};
+
+pub mod command_table;
+
+mod value;
+pub use value::{Hash, Set, SortedSet, Value, WrongType};
+
+pub mod evict;
+
+pub mod random;
+
+mod bitops;
+
+mod bitfield;
+
+mod hyperloglog;
+
+mod geohash;
+
+mod lcs;
+
+mod sha1;
+
+mod digest;
+
+mod listpack;
+
+mod glob;
+
+mod rdb;
+
+pub mod clock;
+
+mod dict;
 
 mod db;
 pub use db::SharedRedisState;
 pub use db::RedisState;
+pub use db::Keyspace;
+
+mod client_state;
+pub use client_state::{ClientState, Transaction};
+
+pub mod client;
+pub use client::Client;
 
 mod replication;
 pub use replication::*;
 
-pub type Error = Box<dyn std::error::Error + Send + Sync>;
+mod config;
+pub use config::ServerConfig;
+
+mod identity;
+pub use identity::ServerIdentity;
+
+mod server;
+pub use server::{Server, ServerBuilder, ServerHandle};
+
+mod slowlog;
+pub use slowlog::SlowLog;
+
+mod monitor;
+pub use monitor::MonitorFeed;
+
+mod repltrace;
+pub use repltrace::{ReplTrace, TraceDirection};
+
+mod acl;
+pub use acl::Acl;
+
+mod blocking;
+pub use blocking::{BlockedClient, Blocking, Wakeup};
+
+mod watch;
+pub use watch::Watches;
+
+mod expire;
+pub use expire::ExpiredKeys;
+
+pub mod cron;
+pub use cron::Cron;
+
+mod clients;
+pub use clients::{ClientRegistry, ClientType, RegisteredClient};
+
+mod pause;
+pub use pause::{ClientPause, PauseMode};
+
+mod busy;
+pub use busy::{BusyOperations, LongOperation};
+
+mod loading;
+pub use loading::{Loading, LoadingGuard};
+
+mod pubsub;
+pub use pubsub::{ChannelKind, PubSub};
+
+mod notify;
+pub use notify::EventClass;
+
+mod latency;
+pub use latency::CommandStats;
+
+mod metrics;
+
+mod error;
+pub use error::RedisError;
+
+pub type Error = RedisError;
 
 /// This is defined as a convenience.
 pub type Result<T> = std::result::Result<T, Error>;
 
 pub const DELIM: &[u8; 2] = b"\r\n";
 
+/// Commands a connection's reader queues ahead of the one running, before it
+/// stops reading until they've run.
 pub const PIPELINE_MAX_COMMANDS: usize = 500;
 
+/// Milliseconds since the Unix epoch, from `clock::current()`. Unless a clock
+/// was installed, never goes backwards, even when the system clock does; see
+/// `clock::MonotonicClock`.
+///
+/// Everything deciding whether a key expired reads the time here, and a key
+/// counts as expired from the millisecond its expiry is at: `now >= expiry`.
 pub fn get_unix_ts_millis() -> u128 {
-    let start = SystemTime::now();
-
-    start
-        .duration_since(UNIX_EPOCH)
-        .expect("Time went backwards").as_millis()
+    clock::current().now_millis()
 }
\ No newline at end of file
@@ -0,0 +1,195 @@
+//! Decoding of the compact blobs redis-server keeps small collections in,
+//! and writes to RDB files as they are: listpacks, the ziplists they
+//! replaced and intsets. Integer entries are turned into their decimal
+//! strings, the way redis-server hands them out.
+//!
+//! Every decoder returns None for a corrupt blob rather than panicking, as
+//! the data comes from the network.
+
+use bytes::Bytes;
+
+const LISTPACK_EOF: u8 = 0xff;
+const ZIPLIST_EOF: u8 = 0xff;
+
+/// The entries of a listpack.
+pub fn listpack_entries(blob: &[u8]) -> Option<Vec<Bytes>> {
+    // Total bytes and number of entries, which is only a hint past 65534.
+    let header = blob.get(..6)?;
+    let total = u32::from_le_bytes(header[..4].try_into().ok()?) as usize;
+    if total != blob.len() {
+        return None;
+    }
+
+    let mut entries = vec![];
+    let mut pos = 6;
+
+    loop {
+        let encoding = *blob.get(pos)?;
+        if encoding == LISTPACK_EOF {
+            break;
+        }
+
+        let start = pos;
+        let entry = match encoding {
+            // 7 bit unsigned integer.
+            0x00..=0x7f => {
+                pos += 1;
+                Bytes::from(encoding.to_string())
+            },
+            // 6 bit string length.
+            0x80..=0xbf => {
+                let len = (encoding & 0x3f) as usize;
+                let string = blob.get(pos + 1..pos + 1 + len)?;
+                pos += 1 + len;
+                Bytes::copy_from_slice(string)
+            },
+            // 13 bit signed integer.
+            0xc0..=0xdf => {
+                let unsigned = (((encoding & 0x1f) as i64) << 8) | *blob.get(pos + 1)? as i64;
+                pos += 2;
+                Bytes::from(sign_extend(unsigned, 13).to_string())
+            },
+            // 12 bit string length.
+            0xe0..=0xef => {
+                let len = (((encoding & 0x0f) as usize) << 8) | *blob.get(pos + 1)? as usize;
+                let string = blob.get(pos + 2..pos + 2 + len)?;
+                pos += 2 + len;
+                Bytes::copy_from_slice(string)
+            },
+            // 32 bit string length.
+            0xf0 => {
+                let len = u32::from_le_bytes(blob.get(pos + 1..pos + 5)?.try_into().ok()?) as usize;
+                let string = blob.get(pos + 5..pos.checked_add(5 + len)?)?;
+                pos += 5 + len;
+                Bytes::copy_from_slice(string)
+            },
+            // 16, 24, 32 and 64 bit signed integers.
+            0xf1..=0xf4 => {
+                let size = [2, 3, 4, 8][(encoding - 0xf1) as usize];
+                let int = read_int_le(blob.get(pos + 1..pos + 1 + size)?);
+                pos += 1 + size;
+                Bytes::from(int.to_string())
+            },
+            _ => return None,
+        };
+
+        // Each entry ends in its own length, for walking backwards.
+        pos += backlen_size(pos - start);
+        entries.push(entry);
+    }
+
+    match pos + 1 == blob.len() {
+        true => Some(entries),
+        false => None,
+    }
+}
+
+/// Size of the length ending a listpack entry `len` bytes long.
+fn backlen_size(len: usize) -> usize {
+    match len {
+        0..=127 => 1,
+        128..=16382 => 2,
+        16383..=2097150 => 3,
+        2097151..=268435454 => 4,
+        _ => 5,
+    }
+}
+
+/// The entries of a ziplist, the encoding of small collections before
+/// Redis 7.0.
+pub fn ziplist_entries(blob: &[u8]) -> Option<Vec<Bytes>> {
+    // Total bytes, offset of the last entry and number of entries.
+    let header = blob.get(..10)?;
+    let total = u32::from_le_bytes(header[..4].try_into().ok()?) as usize;
+    if total != blob.len() {
+        return None;
+    }
+
+    let mut entries = vec![];
+    let mut pos = 10;
+
+    loop {
+        // The length of the previous entry, for walking backwards.
+        match *blob.get(pos)? {
+            ZIPLIST_EOF => break,
+            0xfe => pos += 5,
+            _ => pos += 1,
+        }
+
+        let encoding = *blob.get(pos)?;
+        let entry = match encoding >> 6 {
+            // 6 bit string length.
+            0 => {
+                let len = (encoding & 0x3f) as usize;
+                let string = blob.get(pos + 1..pos + 1 + len)?;
+                pos += 1 + len;
+                Bytes::copy_from_slice(string)
+            },
+            // 14 bit string length, big endian unlike everything else.
+            1 => {
+                let len = (((encoding & 0x3f) as usize) << 8) | *blob.get(pos + 1)? as usize;
+                let string = blob.get(pos + 2..pos + 2 + len)?;
+                pos += 2 + len;
+                Bytes::copy_from_slice(string)
+            },
+            // 32 bit string length.
+            2 => {
+                let len = u32::from_be_bytes(blob.get(pos + 1..pos + 5)?.try_into().ok()?) as usize;
+                let string = blob.get(pos + 5..pos.checked_add(5 + len)?)?;
+                pos += 5 + len;
+                Bytes::copy_from_slice(string)
+            },
+            _ => {
+                let (size, int) = match encoding {
+                    0xc0 => (2, None),
+                    0xd0 => (4, None),
+                    0xe0 => (8, None),
+                    0xf0 => (3, None),
+                    0xfe => (1, None),
+                    // Integers 0 to 12 held in the encoding itself.
+                    0xf1..=0xfd => (0, Some((encoding & 0x0f) as i64 - 1)),
+                    _ => return None,
+                };
+                let int = match int {
+                    Some(int) => int,
+                    None => read_int_le(blob.get(pos + 1..pos + 1 + size)?),
+                };
+                pos += 1 + size;
+                Bytes::from(int.to_string())
+            },
+        };
+
+        entries.push(entry);
+    }
+
+    match pos + 1 == blob.len() {
+        true => Some(entries),
+        false => None,
+    }
+}
+
+/// The members of an intset, sorted integers of 2, 4 or 8 bytes each.
+pub fn intset_entries(blob: &[u8]) -> Option<Vec<Bytes>> {
+    let size = u32::from_le_bytes(blob.get(..4)?.try_into().ok()?) as usize;
+    let len = u32::from_le_bytes(blob.get(4..8)?.try_into().ok()?) as usize;
+
+    if !matches!(size, 2 | 4 | 8) || blob.len() != len.checked_mul(size)?.checked_add(8)? {
+        return None;
+    }
+
+    Some(blob[8..].chunks(size).map(|int| Bytes::from(read_int_le(int).to_string())).collect())
+}
+
+/// Reads a little endian signed integer of 1 to 8 bytes.
+fn read_int_le(bytes: &[u8]) -> i64 {
+    let unsigned = bytes.iter().rev().fold(0u64, |int, byte| (int << 8) | *byte as u64);
+
+    sign_extend(unsigned as i64, bytes.len() as u32 * 8)
+}
+
+/// Sign extends the lowest `bits` bits of `int`.
+fn sign_extend(int: i64, bits: u32) -> i64 {
+    let shift = 64 - bits;
+
+    (int << shift) >> shift
+}
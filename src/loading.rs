@@ -0,0 +1,91 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use bytes::Bytes;
+
+use crate::get_unix_ts_millis;
+
+/// Whether the server is loading a dataset, as a replica does with its
+/// master's snapshot after a full resync, and how far along it is.
+///
+/// While it is, commands not flagged `loading` in the command table get a
+/// `LOADING` error, rather than waiting for the locks the load holds or
+/// seeing the keyspace as it was before. Dispatch checks this on every
+/// command, so it's a plain atomic, without any lock.
+#[derive(Default)]
+pub struct Loading {
+    active: AtomicBool,
+    /// Unix time in milliseconds the load started at.
+    started: AtomicU64,
+    total_bytes: AtomicU64,
+    loaded_bytes: AtomicU64,
+}
+
+/// A load in progress, until dropped, whether it succeeded or not.
+pub struct LoadingGuard<'a> {
+    loading: &'a Loading,
+}
+
+impl Loading {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts loading a file of `total_bytes`.
+    pub fn start(&self, total_bytes: usize) -> LoadingGuard<'_> {
+        self.started.store(get_unix_ts_millis() as u64, Ordering::Relaxed);
+        self.total_bytes.store(total_bytes as u64, Ordering::Relaxed);
+        self.loaded_bytes.store(0, Ordering::Relaxed);
+        self.active.store(true, Ordering::Release);
+
+        LoadingGuard { loading: self }
+    }
+
+    pub fn is_loading(&self) -> bool {
+        self.active.load(Ordering::Acquire)
+    }
+
+    /// Records that the first `loaded_bytes` of the file were loaded.
+    pub fn progress(&self, loaded_bytes: usize) {
+        self.loaded_bytes.store(loaded_bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Renders the `persistence` INFO section. There's no persistence to
+    /// speak of, only the progress of a load.
+    pub fn get_info_bytes(&self) -> Bytes {
+        let mut res = format!("# Persistence\nloading:{}\nasync_loading:0\n", self.is_loading() as u8);
+
+        if self.is_loading() {
+            let started = self.started.load(Ordering::Relaxed);
+            let total = self.total_bytes.load(Ordering::Relaxed);
+            let loaded = self.loaded_bytes.load(Ordering::Relaxed);
+            let elapsed = (get_unix_ts_millis() as u64).saturating_sub(started);
+
+            let perc = match total {
+                0 => 0.0,
+                total => loaded as f64 * 100.0 / total as f64,
+            };
+            // Assumes the rest loads as fast as what was loaded so far.
+            let eta = match loaded {
+                0 => 1,
+                loaded => (total - loaded.min(total)) * elapsed / loaded / 1000,
+            };
+
+            res.push_str(&format!(
+                "loading_start_time:{}\nloading_total_bytes:{}\nloading_loaded_bytes:{}\nloading_loaded_perc:{:.2}\nloading_eta_seconds:{}\n",
+                started / 1000,
+                total,
+                loaded,
+                perc,
+                eta,
+            ));
+        }
+
+        Bytes::from(res)
+    }
+}
+
+impl Drop for LoadingGuard<'_> {
+    fn drop(&mut self) {
+        self.loading.active.store(false, Ordering::Release);
+    }
+}
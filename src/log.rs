@@ -1,56 +1,131 @@
-#[macro_export]
-macro_rules! debug {
-    ($($arg:tt)*) => {{
-        use std::time::SystemTime;
+//! Logging to stdout, one line per message: `[LEVEL][timestamp][tag] message`.
+//!
+//! Lines below the `loglevel` setting aren't even formatted, so the debug
+//! lines of the command path cost nothing unless asked for.
+//!
+//! The tag names the process, the port it listens on unless `--log-tag`
+//! says otherwise, so the output of several servers piped to the same
+//! terminal can be told apart.
+
+use std::fmt;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+// `Mutex::new` isn't const on the Rust the server is built with, so the tag
+// is created on first use, like the system clock.
+static TAG: AtomicPtr<Mutex<String>> = AtomicPtr::new(ptr::null_mut());
 
-        let now = SystemTime::now();
-        let timestamp = match now.duration_since(SystemTime::UNIX_EPOCH) {
-            Ok(duration) => duration.as_secs(),
-            Err(_) => panic!("SystemTime before UNIX EPOCH!"),
-        };
-        println!("[DEBUG][{}] {}", timestamp, format_args!($($arg)*));
-    }};
+fn tag() -> &'static Mutex<String> {
+    let tag = TAG.load(Ordering::Acquire);
+    if !tag.is_null() {
+        // SAFETY: Once set, the pointer refers to a leaked box which is never
+        // freed.
+        return unsafe { &*tag };
+    }
+
+    let new = Box::into_raw(Box::new(Mutex::new(String::new())));
+    match TAG.compare_exchange(ptr::null_mut(), new, Ordering::AcqRel, Ordering::Acquire) {
+        // SAFETY: `new` was just leaked and is never freed.
+        Ok(_) => unsafe { &*new },
+        Err(existing) => {
+            // Another thread got there first.
+            // SAFETY: `new` was never shared.
+            drop(unsafe { Box::from_raw(new) });
+            // SAFETY: As above, `existing` is never freed.
+            unsafe { &*existing }
+        }
+    }
 }
 
+/// Values of `loglevel`, from the most verbose, like in redis-server.
+/// `nothing` turns logging off.
+pub const LEVELS: &[&str] = &["debug", "verbose", "notice", "warning", "nothing"];
+
+/// Levels of the macros below, as indexes into `LEVELS`.
+#[doc(hidden)]
+pub const DEBUG: usize = 0;
+#[doc(hidden)]
+pub const NOTICE: usize = 2;
+#[doc(hidden)]
+pub const WARNING: usize = 3;
+
+static LEVEL: AtomicUsize = AtomicUsize::new(NOTICE);
+
+/// Applies the `loglevel` setting, one of `LEVELS`.
+pub fn configure(level: &str) {
+    if let Some(level) = LEVELS.iter().position(|name| name.eq_ignore_ascii_case(level)) {
+        LEVEL.store(level, Ordering::Relaxed);
+    }
+}
+
+/// Whether lines of `level` are written.
+#[doc(hidden)]
+pub fn enabled(level: usize) -> bool {
+    level >= LEVEL.load(Ordering::Relaxed)
+}
+
+/// Sets the tag printed on every line.
+pub fn set_tag(tag: &str) {
+    *self::tag().lock().unwrap_or_else(|err| err.into_inner()) = tag.to_string();
+}
+
+/// Sets the tag unless one was set already.
+pub fn set_default_tag(tag: &str) {
+    let mut current = self::tag().lock().unwrap_or_else(|err| err.into_inner());
+    if current.is_empty() {
+        *current = tag.to_string();
+    }
+}
+
+/// Writes a line, for the macros below.
+#[doc(hidden)]
+pub fn write(level: &str, args: fmt::Arguments) {
+    let timestamp = match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs(),
+        Err(_) => panic!("SystemTime before UNIX EPOCH!"),
+    };
+
+    let tag = tag().lock().unwrap_or_else(|err| err.into_inner());
+    match tag.is_empty() {
+        true => println!("[{}][{}] {}", level, timestamp, args),
+        false => println!("[{}][{}][{}] {}", level, timestamp, tag, args),
+    }
+}
 
 #[macro_export]
-macro_rules! info {
-    ($($arg:tt)*) => {{
-        use std::time::SystemTime;
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::DEBUG) {
+            $crate::log::write("DEBUG", format_args!($($arg)*))
+        }
+    };
+}
 
-        let now = SystemTime::now();
-        let timestamp = match now.duration_since(SystemTime::UNIX_EPOCH) {
-            Ok(duration) => duration.as_secs(),
-            Err(_) => panic!("SystemTime before UNIX EPOCH!"),
-        };
-        println!("[INFO ][{}] {}", timestamp, format_args!($($arg)*));
-    }};
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::NOTICE) {
+            $crate::log::write("INFO ", format_args!($($arg)*))
+        }
+    };
 }
 
 #[macro_export]
 macro_rules! warn {
-    ($($arg:tt)*) => {{
-        use std::time::SystemTime;
-
-        let now = SystemTime::now();
-        let timestamp = match now.duration_since(SystemTime::UNIX_EPOCH) {
-            Ok(duration) => duration.as_secs(),
-            Err(_) => panic!("SystemTime before UNIX EPOCH!"),
-        };
-        println!("[WARN ][{}] {}", timestamp, format_args!($($arg)*));
-    }};
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::WARNING) {
+            $crate::log::write("WARN ", format_args!($($arg)*))
+        }
+    };
 }
 
 #[macro_export]
 macro_rules! error {
-    ($($arg:tt)*) => {{
-        use std::time::SystemTime;
-
-        let now = SystemTime::now();
-        let timestamp = match now.duration_since(SystemTime::UNIX_EPOCH) {
-            Ok(duration) => duration.as_secs(),
-            Err(_) => panic!("SystemTime before UNIX EPOCH!"),
-        };
-        println!("[ERROR][{}] {}", timestamp, format_args!($($arg)*));
-    }};
-}
\ No newline at end of file
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::WARNING) {
+            $crate::log::write("ERROR", format_args!($($arg)*))
+        }
+    };
+}
@@ -1,24 +1,22 @@
 use std::env;
-use std::sync::Arc;
 
-use redis_starter_rust::{Command, ConnectionManager, Frame, RedisState, ReplicationWorker, SharedRedisState};
-
-use tokio::net::TcpListener;
-use tokio::sync::Mutex;
-
-mod log;
+use redis_starter_rust::{info, log, Server, ServerConfig};
 
 struct RedisArgs {
     port: String,
     replicaof: Option<String>,
+    metrics_port: Option<String>,
+    log_tag: Option<String>,
+    random_seed: Option<String>,
+    config: ServerConfig,
 }
 
 impl RedisArgs {
     pub fn new() -> Self {
         let args: Vec<String> = env::args().collect();
-        let port_number_idx = args.iter().position(|r| r == "--port").or_else(|| Some(args.len())).unwrap() + 1;
+        let port_number_idx = args.iter().position(|r| r == "--port").unwrap_or(args.len()) + 1;
 
-        let port: String = match args.get(port_number_idx.clone()) {
+        let port: String = match args.get(port_number_idx) {
             Some(port) => port.clone(),
             None => "6379".to_owned()
         };
@@ -32,9 +30,46 @@ impl RedisArgs {
             _ => None
         };
 
+        let metrics_port = args.iter().position(|r| r == "--metrics-port").and_then(|idx| args.get(idx + 1).cloned());
+
+        let log_tag = args.iter().position(|r| r == "--log-tag").and_then(|idx| args.get(idx + 1).cloned());
+
+        let random_seed = args.iter().position(|r| r == "--random-seed").and_then(|idx| args.get(idx + 1).cloned());
+
+        // Any other `--name value` pair naming a config parameter.
+        let mut config = ServerConfig::default();
+        for (idx, arg) in args.iter().enumerate() {
+            let name = match arg.strip_prefix("--") {
+                Some(name) if ServerConfig::PARAMETERS.contains(&name) => name,
+                _ => continue,
+            };
+
+            match args.get(idx + 1) {
+                Some(value) => config.set(name, value).expect("Invalid config value"),
+                None => panic!("Missing value for --{}", name),
+            }
+        }
+
+        // `--rename-command <command> <new-name>`, an empty name disabling
+        // the command, as often as needed.
+        for (idx, arg) in args.iter().enumerate() {
+            if arg != "--rename-command" {
+                continue;
+            }
+
+            match (args.get(idx + 1), args.get(idx + 2)) {
+                (Some(command), Some(new_name)) => config.rename_command(command, new_name).expect("Invalid rename-command"),
+                _ => panic!("Missing values for --rename-command"),
+            }
+        }
+
         Self{
             port,
             replicaof,
+            metrics_port,
+            log_tag,
+            random_seed,
+            config,
         }
     }
 }
@@ -42,72 +77,23 @@ impl RedisArgs {
 
 #[tokio::main]
 async fn main() {
-    info!("Logs from your program will appear here!");
-
     // Get port number from the command line arguments, with default of 6379.
     let args = RedisArgs::new();
-    let listener = TcpListener::bind(format!("127.0.0.1:{}", args.port)).await.unwrap();
+    log::set_tag(args.log_tag.as_deref().unwrap_or(&args.port));
 
-    info!("Listening on port: {}", args.port);
-
-    let connection_manager = ConnectionManager::new();
-    let shared_db = Arc::new(
-        Mutex::new(RedisState::new(args.replicaof.clone(), args.port)));
-
-    if args.replicaof.is_some() {
-        let replicaof = args.replicaof.as_ref().unwrap();
-        info!("Replicating to: {}", replicaof);
-
-        let replication_info = shared_db.lock().await.get_replication_info().clone();
-        let mut replication_worker = ReplicationWorker::new(replication_info, shared_db.clone());
+    info!("Logs from your program will appear here!");
+    let port = args.port.parse().expect("Invalid port");
 
-        tokio::spawn(async move {
-            replication_worker.start().await.expect("Exited!");
-        });
+    let mut server = Server::builder().port(port).config(args.config);
+    if let Some(replicaof) = args.replicaof {
+        server = server.replicaof(replicaof);
     }
-
-    loop {
-        let (socket, addr) = listener.accept().await.unwrap();
-        info!("Accepted connection");
-
-        let db = shared_db.clone();
-        let conn_manager = connection_manager.clone();
-        conn_manager.add(addr.to_string(), socket).await;
-
-        tokio::spawn(
-            async move {
-                let res = handle_conn(addr.to_string(), db, &conn_manager).await;
-                if res.is_err() {
-                    error!("Error reading frame! {:?} ", res.err());
-                }
-            }
-        );
+    if let Some(metrics_port) = args.metrics_port {
+        server = server.metrics_port(metrics_port.parse().expect("Invalid metrics port"));
     }
-}
-
-
-
-// Request lifecyle (all within this function):
-// 1. Read a frame from the connection.
-// 2. Parse the frame into a command.
-// 3. Apply the command to the database.
-// 4. Write the result of the command to the connection.
-
-// For replication, we need to refactor request lifecycle to an async loop
-// 1. Accept connection and add to a list of connections
-// 2. For each accepted connection, launch a new task to handle the connection
-// 3. Repeat current request lifecycle in the new task
-async fn handle_conn(addr: String, db: SharedRedisState, conn_manager: &ConnectionManager) -> redis_starter_rust::Result<()> {
-    debug!("Start handling conn: {}", addr);
-    while let Some(frame) = conn_manager.clone().read_frame(addr.clone(), false).await? {
-        debug!("Got frame: {:?}, len: {}", frame, frame.len());
-
-        match Command::from_frame(frame) {
-            Ok(cmd) => cmd.apply(addr.clone(), db.clone(), conn_manager.clone()).await?,
-            Err(err) => conn_manager.write_frame(addr.clone(), &Frame::Error(err.to_string())).await?
-        }
+    if let Some(random_seed) = args.random_seed {
+        server = server.random_seed(random_seed.parse().expect("Invalid random seed"));
     }
-    debug!("Done handling conn: {}", addr);
 
-    Ok(())
+    server.spawn().await.unwrap().wait().await;
 }
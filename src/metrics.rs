@@ -0,0 +1,139 @@
+//! A Prometheus endpoint for the statistics `INFO` reports, served over a
+//! hand-rolled HTTP/1.0 on its own port.
+//!
+//! Rendering only reads counters and the small registries beside the
+//! keyspace, never a shard lock, so scraping can't hold up commands and a
+//! stalled server can still be scraped.
+
+use std::fmt::Write;
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinSet;
+
+use crate::{debug, error, RedisState, SharedRedisState};
+
+/// Longest request accepted, headers included.
+const MAX_REQUEST_LEN: usize = 8 * 1024;
+
+/// How long a scraper has to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Serves the metrics until the task is aborted.
+pub async fn serve(listener: TcpListener, db: SharedRedisState) {
+    let mut tasks = JoinSet::new();
+
+    loop {
+        let socket = tokio::select! {
+            res = listener.accept() => match res {
+                Ok((socket, _)) => socket,
+                Err(err) => {
+                    error!("Error accepting metrics connection: {:?}", err);
+                    continue;
+                },
+            },
+            Some(_) = tasks.join_next() => continue,
+        };
+
+        let db = db.clone();
+        tasks.spawn(async move {
+            if let Err(err) = handle_conn(socket, &db).await {
+                debug!("Error serving metrics: {:?}", err);
+            }
+        });
+    }
+}
+
+/// Answers a single request, then closes the connection like HTTP/1.0 does.
+async fn handle_conn(mut socket: TcpStream, db: &RedisState) -> std::io::Result<()> {
+    let request = match tokio::time::timeout(REQUEST_TIMEOUT, read_request(&mut socket)).await {
+        Ok(request) => request?,
+        Err(_) => return Ok(()),
+    };
+
+    let request_line = request.lines().next().unwrap_or_default();
+    let mut parts = request_line.split(' ');
+
+    let response = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => response("200 OK", &render(db)),
+        (Some("GET"), Some(_)) => response("404 Not Found", "Not Found\n"),
+        _ => response("405 Method Not Allowed", "Method Not Allowed\n"),
+    };
+
+    socket.write_all(response.as_bytes()).await?;
+    socket.shutdown().await
+}
+
+/// Reads the request up to the blank line ending its headers. Requests to
+/// this endpoint have no body.
+async fn read_request(socket: &mut TcpStream) -> std::io::Result<String> {
+    let mut request = vec![];
+    let mut buf = [0u8; 1024];
+
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") && request.len() < MAX_REQUEST_LEN {
+        let n = socket.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+
+    Ok(String::from_utf8_lossy(&request).to_string())
+}
+
+fn response(status: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.0 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body,
+    )
+}
+
+/// Renders every metric in the Prometheus text exposition format.
+pub fn render(db: &RedisState) -> String {
+    let mut out = String::new();
+
+    let replication = db.get_replication_state();
+    let offset = match replication.get_role() {
+        "master" => replication.get_replication_offset(),
+        _ => replication.get_replica_offset_bytes(),
+    };
+
+    metric(&mut out, "redis_uptime_in_seconds", "gauge", "Seconds since the server started.", db.get_identity().uptime().as_secs());
+    metric(&mut out, "redis_connected_clients", "gauge", "Number of client connections.", db.get_clients().len());
+    metric(&mut out, "redis_blocked_clients", "gauge", "Number of clients waiting on a blocking command.", db.get_blocking().len());
+    metric(&mut out, "redis_connected_slaves", "gauge", "Number of connected replicas.", replication.get_replicas().len());
+    metric(&mut out, "redis_replication_offset", "gauge", "Replication offset of this server, as a master or a replica.", offset);
+
+    let commands = db.get_command_stats().totals();
+    let processed: u64 = commands.iter().map(|(_, calls, _)| calls).sum();
+    metric(&mut out, "redis_commands_processed_total", "counter", "Total number of commands processed.", processed);
+
+    let _ = writeln!(out, "# HELP redis_commands_total Number of calls per command.");
+    let _ = writeln!(out, "# TYPE redis_commands_total counter");
+    for (name, calls, _) in commands.iter() {
+        let _ = writeln!(out, "redis_commands_total{{cmd=\"{}\"}} {}", escape_label(name), calls);
+    }
+
+    let _ = writeln!(out, "# HELP redis_commands_duration_seconds_total Time spent running each command.");
+    let _ = writeln!(out, "# TYPE redis_commands_duration_seconds_total counter");
+    for (name, _, usec) in commands.iter() {
+        let _ = writeln!(out, "redis_commands_duration_seconds_total{{cmd=\"{}\"}} {}", escape_label(name), *usec as f64 / 1e6);
+    }
+
+    out
+}
+
+fn metric(out: &mut String, name: &str, kind: &str, help: &str, value: impl std::fmt::Display) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+/// Escapes a label value. Unknown commands are recorded under whatever
+/// name the client sent, so it can be anything.
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
@@ -0,0 +1,79 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+use tokio::sync::broadcast;
+
+/// Number of lines a MONITOR client may fall behind before it is disconnected.
+const MONITOR_BACKLOG: usize = 1024;
+
+/// Broadcasts every processed command to the connections in MONITOR mode.
+#[derive(Clone)]
+pub struct MonitorFeed {
+    tx: broadcast::Sender<String>,
+}
+
+impl MonitorFeed {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(MONITOR_BACKLOG);
+
+        Self { tx }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<String> {
+        self.tx.subscribe()
+    }
+
+    /// Sends the command to all monitors, if there are any.
+    pub fn feed(&self, db_index: usize, client_addr: &str, args: &[Bytes]) {
+        if self.tx.receiver_count() == 0 {
+            return;
+        }
+
+        // Failing to send only means the last monitor just went away.
+        let _ = self.tx.send(format_line(db_index, client_addr, args));
+    }
+}
+
+impl Default for MonitorFeed {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Formats a command the way `MONITOR` prints it:
+/// `1339518083.107412 [0 127.0.0.1:60866] "SET" "key" "value"`
+fn format_line(db_index: usize, client_addr: &str, args: &[Bytes]) -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+
+    let mut line = format!("{}.{:06} [{} {}]", now.as_secs(), now.subsec_micros(), db_index, client_addr);
+
+    for arg in args {
+        line.push(' ');
+        line.push_str(&quote(arg));
+    }
+
+    line
+}
+
+/// Quotes a binary argument, escaping anything that isn't printable ASCII.
+pub(crate) fn quote(arg: &[u8]) -> String {
+    let mut res = String::with_capacity(arg.len() + 2);
+    res.push('"');
+
+    for &b in arg {
+        match b {
+            b'\\' => res.push_str("\\\\"),
+            b'"' => res.push_str("\\\""),
+            b'\n' => res.push_str("\\n"),
+            b'\r' => res.push_str("\\r"),
+            b'\t' => res.push_str("\\t"),
+            0x07 => res.push_str("\\a"),
+            0x08 => res.push_str("\\b"),
+            b if b.is_ascii_graphic() || b == b' ' => res.push(b as char),
+            b => res.push_str(&format!("\\x{:02x}", b)),
+        }
+    }
+
+    res.push('"');
+    res
+}
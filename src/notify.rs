@@ -0,0 +1,141 @@
+//! Keyspace notifications: messages published about writes to keys, to
+//! `__keyspace@<db>__:<key>` with the event as the message and to
+//! `__keyevent@<db>__:<event>` with the key as the message.
+//!
+//! Which of them are published is set by `notify-keyspace-events`, in the
+//! same letters as redis-server uses. Nothing is published by default.
+
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use bytes::Bytes;
+
+use crate::{ChannelKind, PubSub};
+
+const KEYSPACE: u32 = 1 << 0;
+const KEYEVENT: u32 = 1 << 1;
+const GENERIC: u32 = 1 << 2;
+const STRING: u32 = 1 << 3;
+const LIST: u32 = 1 << 4;
+const SET: u32 = 1 << 5;
+const HASH: u32 = 1 << 6;
+const ZSET: u32 = 1 << 7;
+const EXPIRED: u32 = 1 << 8;
+const EVICTED: u32 = 1 << 9;
+const STREAM: u32 = 1 << 10;
+const KEY_MISS: u32 = 1 << 11;
+const MODULE: u32 = 1 << 12;
+const NEW: u32 = 1 << 13;
+/// The classes `A` stands for.
+const ALL: u32 = GENERIC | STRING | LIST | SET | HASH | ZSET | EXPIRED | EVICTED | STREAM | MODULE;
+
+static FLAGS: AtomicU32 = AtomicU32::new(0);
+
+/// What an event is about, for the classes to be enabled one by one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventClass {
+    /// Commands working on keys of any type, like `DEL` and `EXPIRE`.
+    Generic,
+    String,
+    List,
+    Set,
+    Hash,
+    Zset,
+    Stream,
+    /// Keys removed as they expired.
+    Expired,
+    /// Keys removed to free memory.
+    Evicted,
+    /// Reads of keys that don't exist. Not part of `A`.
+    KeyMiss,
+}
+
+impl EventClass {
+    fn flag(self) -> u32 {
+        match self {
+            EventClass::Generic => GENERIC,
+            EventClass::String => STRING,
+            EventClass::List => LIST,
+            EventClass::Set => SET,
+            EventClass::Hash => HASH,
+            EventClass::Zset => ZSET,
+            EventClass::Stream => STREAM,
+            EventClass::Expired => EXPIRED,
+            EventClass::Evicted => EVICTED,
+            EventClass::KeyMiss => KEY_MISS,
+        }
+    }
+}
+
+/// Parses the letters of `notify-keyspace-events`, None if there's one it
+/// doesn't know.
+pub fn parse_flags(letters: &str) -> Option<u32> {
+    letters.chars().try_fold(0, |flags, letter| {
+        let flag = match letter {
+            'A' => ALL,
+            'g' => GENERIC,
+            '$' => STRING,
+            'l' => LIST,
+            's' => SET,
+            'h' => HASH,
+            'z' => ZSET,
+            'x' => EXPIRED,
+            'e' => EVICTED,
+            't' => STREAM,
+            'm' => KEY_MISS,
+            'd' => MODULE,
+            'n' => NEW,
+            'K' => KEYSPACE,
+            'E' => KEYEVENT,
+            _ => return None,
+        };
+        Some(flags | flag)
+    })
+}
+
+/// The letters `CONFIG GET` shows for `flags`, in redis-server's order.
+pub fn format_flags(flags: u32) -> String {
+    let mut letters = String::new();
+
+    if flags & ALL == ALL {
+        letters.push('A');
+    } else {
+        for (flag, letter) in [(GENERIC, 'g'), (STRING, '$'), (LIST, 'l'), (SET, 's'), (HASH, 'h'), (ZSET, 'z'), (EXPIRED, 'x'), (EVICTED, 'e'), (STREAM, 't'), (MODULE, 'd')] {
+            if flags & flag != 0 {
+                letters.push(letter);
+            }
+        }
+    }
+    for (flag, letter) in [(KEYSPACE, 'K'), (KEYEVENT, 'E'), (KEY_MISS, 'm'), (NEW, 'n')] {
+        if flags & flag != 0 {
+            letters.push(letter);
+        }
+    }
+
+    letters
+}
+
+/// Applies the `notify-keyspace-events` setting, which was validated when
+/// set.
+pub fn configure(letters: &str) {
+    FLAGS.store(parse_flags(letters).unwrap_or(0), Ordering::Relaxed);
+}
+
+/// Publishes `event` about `key` of database `db_index`, if its class is
+/// enabled.
+pub fn keyspace_event(pubsub: &PubSub, class: EventClass, event: &str, key: &[u8], db_index: usize) {
+    let flags = FLAGS.load(Ordering::Relaxed);
+    if flags & class.flag() == 0 {
+        return;
+    }
+
+    if flags & KEYSPACE != 0 {
+        let mut channel = format!("__keyspace@{}__:", db_index).into_bytes();
+        channel.extend_from_slice(key);
+        let channel = Bytes::from(channel);
+        pubsub.publish(ChannelKind::Global, &channel, &Bytes::from(event.to_string()));
+    }
+    if flags & KEYEVENT != 0 {
+        let channel = Bytes::from(format!("__keyevent@{}__:{}", db_index, event));
+        pubsub.publish(ChannelKind::Global, &channel, &Bytes::copy_from_slice(key));
+    }
+}
@@ -0,0 +1,80 @@
+use tokio::sync::watch;
+use tokio::time::Instant;
+
+/// Which commands `CLIENT PAUSE` holds up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PauseMode {
+    /// Only commands that may write.
+    Write,
+    All,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Pause {
+    mode: PauseMode,
+    until: Instant,
+}
+
+/// The pause of `CLIENT PAUSE`, which holds up the commands of normal
+/// clients until it's lifted or its deadline passes. The commands wait
+/// before they run, and don't read anything in the meantime, so later ones
+/// stay in the client's read buffer.
+///
+/// Waiting commands watch the pause, rather than each sleeping for a while
+/// and checking again, so they go ahead as soon as it's lifted.
+pub struct ClientPause {
+    state: watch::Sender<Option<Pause>>,
+}
+
+impl ClientPause {
+    pub fn new() -> Self {
+        Self { state: watch::channel(None).0 }
+    }
+
+    /// Pauses clients until `until`. A pause already in effect is only ever
+    /// extended, both in mode and in duration, like redis-server does.
+    pub fn pause(&self, mode: PauseMode, until: Instant) {
+        self.state.send_modify(|state| {
+            *state = match *state {
+                Some(pause) if pause.until > Instant::now() => Some(Pause {
+                    mode: pause.mode.max(mode),
+                    until: pause.until.max(until),
+                }),
+                _ => Some(Pause { mode, until }),
+            };
+        });
+    }
+
+    pub fn unpause(&self) {
+        self.state.send_replace(None);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        matches!(*self.state.borrow(), Some(pause) if pause.until > Instant::now())
+    }
+
+    /// Waits until a command may run, `write` telling whether it may write.
+    pub async fn wait(&self, write: bool) {
+        let mut state = self.state.subscribe();
+
+        loop {
+            let pause = match *state.borrow_and_update() {
+                Some(pause) if pause.until > Instant::now() && (write || pause.mode == PauseMode::All) => pause,
+                _ => return,
+            };
+
+            tokio::select! {
+                res = state.changed() => if res.is_err() {
+                    return;
+                },
+                _ = tokio::time::sleep_until(pause.until) => return,
+            }
+        }
+    }
+}
+
+impl Default for ClientPause {
+    fn default() -> Self {
+        Self::new()
+    }
+}
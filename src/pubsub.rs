@@ -0,0 +1,283 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use bytes::Bytes;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::task::JoinHandle;
+
+use crate::{warn, ConnectionManager, Frame};
+
+/// Number of messages a subscriber may have waiting to be written before it
+/// is disconnected, standing in for the pubsub output buffer limit of
+/// redis-server.
+const SUBSCRIBER_BACKLOG: usize = 1024;
+
+/// How long shutting down waits for subscribers to be told they were
+/// unsubscribed.
+const SHUTDOWN_FLUSH_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Regular channels, or the shard channels of `SSUBSCRIBE` and `SPUBLISH`.
+/// With a single node the two behave the same, but they are separate
+/// namespaces with replies of their own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChannelKind {
+    Global,
+    Shard,
+}
+
+impl ChannelKind {
+    /// Prefix of the reply verbs, as in `ssubscribe` and `smessage`.
+    pub fn prefix(self) -> &'static str {
+        match self {
+            ChannelKind::Global => "",
+            ChannelKind::Shard => "s",
+        }
+    }
+}
+
+type Channel = (ChannelKind, Bytes);
+
+/// The channels clients are subscribed to.
+///
+/// Publishing never writes to a subscriber's socket itself. Messages go to
+/// a bounded queue per subscriber, drained by a writer task of its own, so
+/// a slow subscriber neither holds up the publisher nor the other
+/// subscribers. Each subscriber gets messages in the order they were
+/// published.
+#[derive(Default)]
+pub struct PubSub {
+    registry: Mutex<Registry>,
+}
+
+#[derive(Default)]
+struct Registry {
+    /// Addresses of the clients subscribed to each channel.
+    channels: HashMap<Channel, HashSet<String>>,
+    subscribers: HashMap<String, Subscriber>,
+}
+
+struct Subscriber {
+    queue: mpsc::Sender<Frame>,
+    writer: JoinHandle<()>,
+    conn_manager: ConnectionManager,
+    channels: HashSet<Channel>,
+}
+
+impl Subscriber {
+    fn count(&self, kind: ChannelKind) -> usize {
+        self.channels.iter().filter(|(other, _)| *other == kind).count()
+    }
+}
+
+impl PubSub {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Subscribes the client at `addr` to `channel`, returning the number of
+    /// channels of that kind it is subscribed to. The first subscription
+    /// starts the writer delivering the client's messages.
+    pub fn subscribe(&self, conn_manager: &ConnectionManager, addr: &str, kind: ChannelKind, channel: Bytes) -> usize {
+        let mut registry = self.registry.lock().unwrap_or_else(|err| err.into_inner());
+
+        let subscriber = registry.subscribers.entry(addr.to_string()).or_insert_with(|| {
+            let (queue, rx) = mpsc::channel(SUBSCRIBER_BACKLOG);
+            let writer = tokio::spawn(write_messages(conn_manager.clone(), addr.to_string(), rx));
+
+            Subscriber { queue, writer, conn_manager: conn_manager.clone(), channels: HashSet::new() }
+        });
+        subscriber.channels.insert((kind, channel.clone()));
+        let count = subscriber.count(kind);
+
+        registry.channels.entry((kind, channel)).or_default().insert(addr.to_string());
+
+        count
+    }
+
+    /// Unsubscribes the client at `addr` from `channel`, returning the number
+    /// of channels of that kind it is still subscribed to.
+    pub fn unsubscribe(&self, addr: &str, kind: ChannelKind, channel: Bytes) -> usize {
+        let mut registry = self.registry.lock().unwrap_or_else(|err| err.into_inner());
+        let channel = (kind, channel);
+
+        let count = match registry.subscribers.get_mut(addr) {
+            Some(subscriber) => {
+                subscriber.channels.remove(&channel);
+                subscriber.count(kind)
+            },
+            None => 0,
+        };
+        registry.remove_from_channel(&channel, addr);
+
+        count
+    }
+
+    pub fn is_subscribed(&self, addr: &str, kind: ChannelKind, channel: &Bytes) -> bool {
+        let registry = self.registry.lock().unwrap_or_else(|err| err.into_inner());
+
+        matches!(registry.channels.get(&(kind, channel.clone())), Some(addrs) if addrs.contains(addr))
+    }
+
+    /// The channels of the given kind the client at `addr` is subscribed to.
+    pub fn channels_of(&self, addr: &str, kind: ChannelKind) -> Vec<Bytes> {
+        let registry = self.registry.lock().unwrap_or_else(|err| err.into_inner());
+
+        registry.subscribers.get(addr)
+            .map(|subscriber| subscriber.channels.iter()
+                .filter(|(other, _)| *other == kind)
+                .map(|(_, channel)| channel.clone())
+                .collect())
+            .unwrap_or_default()
+    }
+
+    /// The channels of the given kind with at least one subscriber.
+    pub fn channels(&self, kind: ChannelKind) -> Vec<Bytes> {
+        let registry = self.registry.lock().unwrap_or_else(|err| err.into_inner());
+
+        registry.channels.keys()
+            .filter(|(other, _)| *other == kind)
+            .map(|(_, channel)| channel.clone())
+            .collect()
+    }
+
+    pub fn num_subscribers(&self, kind: ChannelKind, channel: &Bytes) -> usize {
+        let registry = self.registry.lock().unwrap_or_else(|err| err.into_inner());
+
+        registry.channels.get(&(kind, channel.clone())).map(HashSet::len).unwrap_or(0)
+    }
+
+    /// Queues the message for every subscriber of the channel, returning how
+    /// many got it. Subscribers whose queue is full are disconnected.
+    pub fn publish(&self, kind: ChannelKind, channel: &Bytes, message: &Bytes) -> usize {
+        let mut registry = self.registry.lock().unwrap_or_else(|err| err.into_inner());
+
+        let addrs = match registry.channels.get(&(kind, channel.clone())) {
+            Some(addrs) => addrs.iter().cloned().collect::<Vec<_>>(),
+            None => return 0,
+        };
+
+        let frame = Frame::Array(vec![
+            Frame::bulk(format!("{}message", kind.prefix())),
+            Frame::Bulk(Some(channel.clone())),
+            Frame::Bulk(Some(message.clone())),
+        ]);
+
+        let mut receivers = 0;
+        for addr in addrs {
+            let subscriber = &registry.subscribers[&addr];
+
+            match subscriber.queue.try_send(frame.clone()) {
+                Ok(()) => receivers += 1,
+                Err(TrySendError::Full(_)) => {
+                    warn!("Disconnecting subscriber {} which fell {} messages behind", addr, SUBSCRIBER_BACKLOG);
+
+                    // The writer is likely stuck on a write the client isn't
+                    // reading, so it's stopped rather than left to drain.
+                    subscriber.writer.abort();
+                    let (conn_manager, conn_addr) = (subscriber.conn_manager.clone(), addr.clone());
+                    tokio::spawn(async move { conn_manager.remove(&conn_addr).await });
+
+                    registry.remove_subscriber(&addr);
+                },
+                // The writer stopped, since the connection is gone.
+                Err(TrySendError::Closed(_)) => registry.remove_subscriber(&addr),
+            }
+        }
+
+        receivers
+    }
+
+    /// Forgets the client at `addr`, e.g. once its connection is closed.
+    pub fn remove(&self, addr: &str) {
+        self.registry.lock().unwrap_or_else(|err| err.into_inner()).remove_subscriber(addr);
+    }
+
+    /// Unsubscribes every client from all of its channels as the server
+    /// shuts down, so clients don't wait on subscriptions that are gone.
+    /// Each gets an `unsubscribe` push per channel, after the messages
+    /// published before, and this waits for them to be written, for
+    /// `SHUTDOWN_FLUSH_TIMEOUT` at most.
+    pub async fn shutdown(&self) {
+        let subscribers: Vec<(String, Subscriber)> = {
+            let mut registry = self.registry.lock().unwrap_or_else(|err| err.into_inner());
+            registry.channels.clear();
+            registry.subscribers.drain().collect()
+        };
+
+        let mut writers = Vec::with_capacity(subscribers.len());
+        for (addr, subscriber) in subscribers {
+            for kind in [ChannelKind::Global, ChannelKind::Shard] {
+                let mut count = subscriber.count(kind);
+
+                for (_, channel) in subscriber.channels.iter().filter(|(other, _)| *other == kind) {
+                    count -= 1;
+                    let frame = Frame::Array(vec![
+                        Frame::bulk(format!("{}unsubscribe", kind.prefix())),
+                        Frame::Bulk(Some(channel.clone())),
+                        Frame::Integer(count as i64),
+                    ]);
+
+                    if subscriber.queue.try_send(frame).is_err() {
+                        warn!("Subscriber {} can't be told it was unsubscribed", addr);
+                    }
+                }
+            }
+
+            // Dropping the queue lets the writer stop once it's drained.
+            writers.push(subscriber.writer);
+        }
+
+        let flushed = tokio::time::timeout(SHUTDOWN_FLUSH_TIMEOUT, async {
+            for writer in writers.iter_mut() {
+                let _ = writer.await;
+            }
+        }).await;
+        if flushed.is_err() {
+            for writer in writers {
+                writer.abort();
+            }
+        }
+    }
+}
+
+impl Registry {
+    fn remove_from_channel(&mut self, channel: &Channel, addr: &str) {
+        if let Some(addrs) = self.channels.get_mut(channel) {
+            addrs.remove(addr);
+            if addrs.is_empty() {
+                self.channels.remove(channel);
+            }
+        }
+    }
+
+    /// Drops the subscriber along with its queue, which stops its writer.
+    fn remove_subscriber(&mut self, addr: &str) {
+        if let Some(subscriber) = self.subscribers.remove(addr) {
+            for channel in subscriber.channels.iter() {
+                self.remove_from_channel(channel, addr);
+            }
+        }
+    }
+}
+
+/// Writes the messages queued for a subscriber, flushing once the queue is
+/// drained. Runs until the subscriber is dropped from the registry or its
+/// connection fails.
+async fn write_messages(conn_manager: ConnectionManager, addr: String, mut queue: mpsc::Receiver<Frame>) {
+    while let Some(frame) = queue.recv().await {
+        let mut frames = vec![frame];
+        while let Ok(frame) = queue.try_recv() {
+            frames.push(frame);
+        }
+
+        for frame in frames.iter() {
+            if conn_manager.queue_frame(&addr, frame).await.is_err() {
+                return;
+            }
+        }
+        if conn_manager.flush(&addr).await.is_err() {
+            return;
+        }
+    }
+}
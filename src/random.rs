@@ -0,0 +1,50 @@
+//! The server's random numbers, from one generator seeded at startup. Given
+//! the same seed, a client sending the same commands gets the same results,
+//! as long as no other client draws numbers in between.
+//!
+//! It's process-wide, like the LRU clock, since it's used on every key
+//! access when LFU eviction is on.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The increment of splitmix64, which is what the generator is.
+const GAMMA: u64 = 0x9e3779b97f4a7c15;
+
+static SEED: AtomicU64 = AtomicU64::new(0);
+static STATE: AtomicU64 = AtomicU64::new(0);
+
+/// Restarts the sequence of random numbers from `seed`.
+pub fn seed(seed: u64) {
+    SEED.store(seed, Ordering::Relaxed);
+    STATE.store(seed, Ordering::Relaxed);
+}
+
+/// The seed the sequence was last restarted from.
+pub fn current_seed() -> u64 {
+    SEED.load(Ordering::Relaxed)
+}
+
+/// A seed from the OS's randomness, for when none was given.
+pub fn entropy_seed() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u32(std::process::id());
+
+    hasher.finish()
+}
+
+/// The next number of the sequence. Each call takes a step of its own, even
+/// when threads race for it.
+pub fn next_u64() -> u64 {
+    let mut z = STATE.fetch_add(GAMMA, Ordering::Relaxed).wrapping_add(GAMMA);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+
+    z ^ (z >> 31)
+}
+
+/// A number in `[0, 1)`.
+pub fn next_f64() -> f64 {
+    (next_u64() >> 11) as f64 / (1u64 << 53) as f64
+}
@@ -0,0 +1,709 @@
+//! The RDB encoding of values, shared by `DUMP`/`RESTORE` and the snapshots
+//! a master sends its replicas, which are whole RDB files.
+//!
+//! Values are written in the simple encodings every redis-server still
+//! reads: lists as plain lists of strings, sets and hashes as hash tables
+//! and sorted sets with binary scores. Only hashes with field expiries take
+//! the Redis 7.4 encoding, there being no older one to keep them in. Streams
+//! can't be encoded yet.
+//!
+//! Reading also takes the compact encodings redis-server writes small
+//! collections in, see `listpack`: quicklists, listpacks, ziplists and
+//! intsets. Streams, modules and the zipmaps of Redis 2.4 aren't read.
+
+use std::collections::{HashMap, VecDeque};
+
+use bytes::Bytes;
+
+use crate::listpack;
+use crate::{Hash, Set, SortedSet, Value};
+
+/// Version of the RDB format we write, that of Redis 7.2.
+pub const RDB_VERSION: u16 = 11;
+
+/// Newest version of RDB files we read, that of Redis 7.4, which only adds
+/// cluster slot information we skip.
+const RDB_LOAD_VERSION: u16 = 12;
+
+/// Opcodes of RDB files, which take the place of a value type.
+const OPCODE_SLOT_INFO: u8 = 0xf4;
+const OPCODE_IDLE: u8 = 0xf8;
+const OPCODE_FREQ: u8 = 0xf9;
+const OPCODE_AUX: u8 = 0xfa;
+const OPCODE_RESIZEDB: u8 = 0xfb;
+const OPCODE_EXPIRETIME_MS: u8 = 0xfc;
+const OPCODE_EXPIRETIME: u8 = 0xfd;
+const OPCODE_SELECTDB: u8 = 0xfe;
+const OPCODE_EOF: u8 = 0xff;
+
+const TYPE_STRING: u8 = 0;
+const TYPE_LIST: u8 = 1;
+const TYPE_SET: u8 = 2;
+const TYPE_ZSET: u8 = 3;
+const TYPE_HASH: u8 = 4;
+const TYPE_ZSET_2: u8 = 5;
+const TYPE_LIST_ZIPLIST: u8 = 10;
+const TYPE_SET_INTSET: u8 = 11;
+const TYPE_ZSET_ZIPLIST: u8 = 12;
+const TYPE_HASH_ZIPLIST: u8 = 13;
+const TYPE_LIST_QUICKLIST: u8 = 14;
+const TYPE_HASH_LISTPACK: u8 = 16;
+const TYPE_ZSET_LISTPACK: u8 = 17;
+const TYPE_LIST_QUICKLIST_2: u8 = 18;
+const TYPE_SET_LISTPACK: u8 = 20;
+/// A hash with field expiries, from Redis 7.4.
+const TYPE_HASH_METADATA: u8 = 24;
+/// A listpack of fields, values and expiries, from Redis 7.4.
+const TYPE_HASH_LISTPACK_EX: u8 = 25;
+
+/// Nodes of a `TYPE_LIST_QUICKLIST_2` list, either a single element or a
+/// listpack of them.
+const QUICKLIST_NODE_PLAIN: u64 = 1;
+const QUICKLIST_NODE_PACKED: u64 = 2;
+
+/// Special encodings of strings, flagged by the top two bits of a length.
+const ENC_INT8: u8 = 0;
+const ENC_INT16: u8 = 1;
+const ENC_INT32: u8 = 2;
+const ENC_LZF: u8 = 3;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("unexpected end of data")]
+    Eof,
+
+    #[error("invalid {0} encoding")]
+    Invalid(&'static str),
+
+    #[error("unsupported value type {0}")]
+    UnsupportedType(u8),
+
+    #[error("{0} values can't be encoded")]
+    UnsupportedValue(&'static str),
+
+    #[error("version or checksum are wrong")]
+    Checksum,
+}
+
+pub fn write_length(buf: &mut Vec<u8>, len: u64) {
+    if len < 1 << 6 {
+        buf.push(len as u8);
+    } else if len < 1 << 14 {
+        buf.extend([0x40 | (len >> 8) as u8, len as u8]);
+    } else if len <= u32::MAX as u64 {
+        buf.push(0x80);
+        buf.extend((len as u32).to_be_bytes());
+    } else {
+        buf.push(0x81);
+        buf.extend(len.to_be_bytes());
+    }
+}
+
+/// Writes a string, as an integer when it is the canonical form of one that
+/// fits in 32 bits, like Redis.
+pub fn write_string(buf: &mut Vec<u8>, val: &[u8]) {
+    let int = std::str::from_utf8(val)
+        .ok()
+        .and_then(|val| val.parse::<i32>().ok().filter(|int| int.to_string() == val));
+
+    match int {
+        Some(int) if i8::try_from(int).is_ok() => buf.extend([0xc0 | ENC_INT8, int as u8]),
+        Some(int) if i16::try_from(int).is_ok() => {
+            buf.push(0xc0 | ENC_INT16);
+            buf.extend((int as i16).to_le_bytes());
+        },
+        Some(int) => {
+            buf.push(0xc0 | ENC_INT32);
+            buf.extend(int.to_le_bytes());
+        },
+        None => {
+            write_length(buf, val.len() as u64);
+            buf.extend_from_slice(val);
+        },
+    }
+}
+
+/// Writes the type of `value` followed by the value itself.
+pub fn write_object(buf: &mut Vec<u8>, value: &Value) -> Result<(), Error> {
+    match value {
+        Value::String(val) => {
+            buf.push(TYPE_STRING);
+            write_string(buf, val);
+        },
+        Value::Appended(val) => {
+            buf.push(TYPE_STRING);
+            write_string(buf, val);
+        },
+        Value::List(list) => {
+            buf.push(TYPE_LIST);
+            write_length(buf, list.len() as u64);
+            for val in list.iter() {
+                write_string(buf, val);
+            }
+        },
+        Value::Set(set) => {
+            buf.push(TYPE_SET);
+            write_length(buf, set.len() as u64);
+            for val in set.iter() {
+                write_string(buf, &val);
+            }
+        },
+        Value::ZSet(zset) => {
+            buf.push(TYPE_ZSET_2);
+            write_length(buf, zset.len() as u64);
+            for (member, score) in zset.iter() {
+                write_string(buf, member);
+                buf.extend(score.to_le_bytes());
+            }
+        },
+        Value::Hash(hash) => match hash.entries().filter_map(|(_, _, expiry)| expiry).min() {
+            None => {
+                buf.push(TYPE_HASH);
+                write_length(buf, hash.entries().count() as u64);
+                for (field, val, _) in hash.entries() {
+                    write_string(buf, field);
+                    write_string(buf, val);
+                }
+            },
+            // Like Redis, the earliest expiry comes first and each field's is
+            // relative to it, plus one so zero can mean none.
+            Some(min_expiry) => {
+                buf.push(TYPE_HASH_METADATA);
+                buf.extend((min_expiry as u64).to_le_bytes());
+                write_length(buf, hash.entries().count() as u64);
+                for (field, val, expiry) in hash.entries() {
+                    write_length(buf, expiry.map(|expiry| (expiry - min_expiry) as u64 + 1).unwrap_or(0));
+                    write_string(buf, field);
+                    write_string(buf, val);
+                }
+            },
+        },
+        Value::Stream(_) => return Err(Error::UnsupportedValue("stream")),
+    }
+
+    Ok(())
+}
+
+/// A length, or the special encoding a string uses instead.
+enum Length {
+    Len(u64),
+    Encoded(u8),
+}
+
+/// Reads RDB encoded data from a buffer.
+pub struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos == self.buf.len()
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, Error> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let end = self.pos.checked_add(len).filter(|end| *end <= self.buf.len()).ok_or(Error::Eof)?;
+        let bytes = &self.buf[self.pos..end];
+        self.pos = end;
+
+        Ok(bytes)
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        Ok(self.read_bytes(N)?.try_into().expect("read N bytes"))
+    }
+
+    fn read_length_or_encoding(&mut self) -> Result<Length, Error> {
+        let first = self.read_u8()?;
+
+        match first >> 6 {
+            0 => Ok(Length::Len((first & 0x3f) as u64)),
+            1 => Ok(Length::Len((((first & 0x3f) as u64) << 8) | self.read_u8()? as u64)),
+            2 if first == 0x80 => Ok(Length::Len(u32::from_be_bytes(self.read_array()?) as u64)),
+            2 if first == 0x81 => Ok(Length::Len(u64::from_be_bytes(self.read_array()?))),
+            2 => Err(Error::Invalid("length")),
+            _ => Ok(Length::Encoded(first & 0x3f)),
+        }
+    }
+
+    pub fn read_length(&mut self) -> Result<u64, Error> {
+        match self.read_length_or_encoding()? {
+            Length::Len(len) => Ok(len),
+            Length::Encoded(_) => Err(Error::Invalid("length")),
+        }
+    }
+
+    /// Reads a length to be used as a number of items or bytes still to be
+    /// read, so a corrupt length can't make the caller allocate more memory
+    /// than the data could possibly hold.
+    fn read_count(&mut self) -> Result<usize, Error> {
+        let len = self.read_length()?;
+
+        match usize::try_from(len) {
+            Ok(len) if len <= self.buf.len() - self.pos => Ok(len),
+            _ => Err(Error::Eof),
+        }
+    }
+
+    /// Reads the number of items of a collection, which can't be empty.
+    fn read_collection_len(&mut self) -> Result<usize, Error> {
+        match self.read_count()? {
+            0 => Err(Error::Invalid("empty collection")),
+            len => Ok(len),
+        }
+    }
+
+    pub fn read_string(&mut self) -> Result<Bytes, Error> {
+        let len = match self.read_length_or_encoding()? {
+            Length::Len(len) => len,
+            Length::Encoded(ENC_INT8) => return Ok(Bytes::from((self.read_u8()? as i8).to_string())),
+            Length::Encoded(ENC_INT16) => return Ok(Bytes::from(i16::from_le_bytes(self.read_array()?).to_string())),
+            Length::Encoded(ENC_INT32) => return Ok(Bytes::from(i32::from_le_bytes(self.read_array()?).to_string())),
+            Length::Encoded(ENC_LZF) => {
+                let compressed_len = self.read_count()?;
+                let len = self.read_length()?;
+                let compressed = self.read_bytes(compressed_len)?;
+
+                return lzf_decompress(compressed, len).map(Bytes::from).ok_or(Error::Invalid("LZF string"));
+            },
+            Length::Encoded(_) => return Err(Error::Invalid("string")),
+        };
+
+        let len = usize::try_from(len).map_err(|_| Error::Eof)?;
+        Ok(Bytes::copy_from_slice(self.read_bytes(len)?))
+    }
+
+    /// Reads a score of the original sorted set encoding, written as a
+    /// string of its own.
+    fn read_string_score(&mut self) -> Result<f64, Error> {
+        match self.read_u8()? {
+            253 => Ok(f64::NAN),
+            254 => Ok(f64::INFINITY),
+            255 => Ok(f64::NEG_INFINITY),
+            len => std::str::from_utf8(self.read_bytes(len as usize)?)
+                .ok()
+                .and_then(|score| score.parse().ok())
+                .ok_or(Error::Invalid("score")),
+        }
+    }
+
+    /// Reads a value preceded by its type, as written by `write_object`.
+    pub fn read_object(&mut self) -> Result<Value, Error> {
+        let value_type = self.read_u8()?;
+
+        self.read_value(value_type)
+    }
+
+    fn read_value(&mut self, value_type: u8) -> Result<Value, Error> {
+        let value = match value_type {
+            TYPE_STRING => Value::string(self.read_string()?),
+            TYPE_LIST => {
+                let len = self.read_collection_len()?;
+                let mut list = VecDeque::with_capacity(len);
+                for _ in 0..len {
+                    list.push_back(self.read_string()?);
+                }
+
+                Value::List(list)
+            },
+            TYPE_SET => {
+                let len = self.read_collection_len()?;
+                let mut set = Set::new();
+                for _ in 0..len {
+                    set.insert(self.read_string()?);
+                }
+
+                Value::Set(set)
+            },
+            TYPE_ZSET | TYPE_ZSET_2 => {
+                let len = self.read_collection_len()?;
+                let mut zset = SortedSet::new();
+                for _ in 0..len {
+                    let member = self.read_string()?;
+                    let score = if value_type == TYPE_ZSET {
+                        self.read_string_score()?
+                    } else {
+                        f64::from_le_bytes(self.read_array()?)
+                    };
+
+                    if score.is_nan() {
+                        return Err(Error::Invalid("score"));
+                    }
+                    zset.insert(member, score);
+                }
+
+                Value::ZSet(zset)
+            },
+            TYPE_HASH => {
+                let len = self.read_collection_len()?;
+                let mut fields = HashMap::with_capacity(len);
+                for _ in 0..len {
+                    let field = self.read_string()?;
+                    fields.insert(field, self.read_string()?);
+                }
+
+                Value::Hash(fields.into_iter().collect())
+            },
+            TYPE_HASH_METADATA => {
+                let min_expiry = u64::from_le_bytes(self.read_array()?) as u128;
+                let len = self.read_collection_len()?;
+                let mut hash = Hash::new();
+                for _ in 0..len {
+                    let expiry = self.read_length()?;
+                    let field = self.read_string()?;
+                    hash.insert(field.clone(), self.read_string()?);
+                    if expiry > 0 {
+                        hash.set_expiry(&field, min_expiry + expiry as u128 - 1);
+                    }
+                }
+
+                Value::Hash(hash)
+            },
+            TYPE_LIST_ZIPLIST => Value::List(self.read_blob(listpack::ziplist_entries, "ziplist")?.into()),
+            TYPE_LIST_QUICKLIST | TYPE_LIST_QUICKLIST_2 => {
+                let nodes = self.read_collection_len()?;
+                let mut list = VecDeque::new();
+                for _ in 0..nodes {
+                    let container = match value_type {
+                        TYPE_LIST_QUICKLIST => QUICKLIST_NODE_PACKED,
+                        _ => self.read_length()?,
+                    };
+
+                    match (value_type, container) {
+                        (_, QUICKLIST_NODE_PLAIN) => list.push_back(self.read_string()?),
+                        (TYPE_LIST_QUICKLIST, _) => list.extend(self.read_blob(listpack::ziplist_entries, "ziplist")?),
+                        (_, QUICKLIST_NODE_PACKED) => list.extend(self.read_blob(listpack::listpack_entries, "listpack")?),
+                        _ => return Err(Error::Invalid("quicklist")),
+                    }
+                }
+
+                Value::List(list)
+            },
+            TYPE_SET_INTSET => Value::Set(self.read_blob(listpack::intset_entries, "intset")?.into_iter().collect()),
+            TYPE_SET_LISTPACK => Value::Set(self.read_blob(listpack::listpack_entries, "listpack")?.into_iter().collect()),
+            TYPE_ZSET_ZIPLIST | TYPE_ZSET_LISTPACK => {
+                let entries = match value_type {
+                    TYPE_ZSET_ZIPLIST => self.read_blob(listpack::ziplist_entries, "ziplist")?,
+                    _ => self.read_blob(listpack::listpack_entries, "listpack")?,
+                };
+                if entries.len() % 2 != 0 {
+                    return Err(Error::Invalid("sorted set"));
+                }
+
+                let mut zset = SortedSet::new();
+                for pair in entries.chunks(2) {
+                    let score = std::str::from_utf8(&pair[1])
+                        .ok()
+                        .and_then(|score| score.parse::<f64>().ok())
+                        .filter(|score| !score.is_nan())
+                        .ok_or(Error::Invalid("score"))?;
+                    zset.insert(pair[0].clone(), score);
+                }
+
+                Value::ZSet(zset)
+            },
+            TYPE_HASH_ZIPLIST | TYPE_HASH_LISTPACK => {
+                let entries = match value_type {
+                    TYPE_HASH_ZIPLIST => self.read_blob(listpack::ziplist_entries, "ziplist")?,
+                    _ => self.read_blob(listpack::listpack_entries, "listpack")?,
+                };
+                if entries.len() % 2 != 0 {
+                    return Err(Error::Invalid("hash"));
+                }
+
+                Value::Hash(entries.chunks(2).map(|pair| (pair[0].clone(), pair[1].clone())).collect())
+            },
+            TYPE_HASH_LISTPACK_EX => {
+                // The earliest expiry, which the entries don't depend on.
+                self.read_array::<8>()?;
+                let entries = self.read_blob(listpack::listpack_entries, "listpack")?;
+                if entries.len() % 3 != 0 {
+                    return Err(Error::Invalid("hash"));
+                }
+
+                let mut hash = Hash::new();
+                for triple in entries.chunks(3) {
+                    let expiry = std::str::from_utf8(&triple[2])
+                        .ok()
+                        .and_then(|expiry| expiry.parse::<u64>().ok())
+                        .ok_or(Error::Invalid("field expiry"))?;
+
+                    hash.insert(triple[0].clone(), triple[1].clone());
+                    // Fields without an expiry have zero.
+                    if expiry > 0 {
+                        hash.set_expiry(&triple[0], expiry as u128);
+                    }
+                }
+
+                Value::Hash(hash)
+            },
+            _ => return Err(Error::UnsupportedType(value_type)),
+        };
+
+        Ok(value)
+    }
+
+    /// Reads a compact blob written as a string, returning its entries,
+    /// which can't be none.
+    fn read_blob(&mut self, decode: fn(&[u8]) -> Option<Vec<Bytes>>, encoding: &'static str) -> Result<Vec<Bytes>, Error> {
+        match decode(&self.read_string()?) {
+            Some(entries) if !entries.is_empty() => Ok(entries),
+            Some(_) => Err(Error::Invalid("empty collection")),
+            None => Err(Error::Invalid(encoding)),
+        }
+    }
+}
+
+/// Decompresses LZF data, which must decompress to exactly `len` bytes.
+fn lzf_decompress(input: &[u8], len: u64) -> Option<Vec<u8>> {
+    let len = usize::try_from(len).ok()?;
+    // LZF can't expand data by more than a factor of about 256, which bounds
+    // what a corrupt length makes us allocate.
+    let mut out = Vec::with_capacity(len.min(input.len().saturating_mul(256)));
+
+    let mut idx = 0;
+    while idx < input.len() {
+        let ctrl = input[idx] as usize;
+        idx += 1;
+
+        if ctrl < 1 << 5 {
+            // A run of ctrl + 1 literal bytes.
+            let literal = input.get(idx..idx + ctrl + 1)?;
+            out.extend_from_slice(literal);
+            idx += ctrl + 1;
+        } else {
+            // A back reference.
+            let mut run = ctrl >> 5;
+            if run == 7 {
+                run += *input.get(idx)? as usize;
+                idx += 1;
+            }
+
+            let distance = ((ctrl & 0x1f) << 8) + *input.get(idx)? as usize + 1;
+            idx += 1;
+
+            let start = out.len().checked_sub(distance)?;
+            for offset in 0..run + 2 {
+                out.push(out[start + offset]);
+            }
+        }
+
+        if out.len() > len {
+            return None;
+        }
+    }
+
+    if out.len() != len {
+        return None;
+    }
+
+    Some(out)
+}
+
+/// Table for the Jones CRC-64 Redis checksums with, processing the bits in
+/// reflected order.
+const CRC64_TABLE: [u64; 256] = {
+    const POLY: u64 = 0x95ac_9329_ac4b_c9b5;
+
+    let mut table = [0; 256];
+    let mut idx = 0;
+    while idx < 256 {
+        let mut crc = idx as u64;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ POLY } else { crc >> 1 };
+            bit += 1;
+        }
+
+        table[idx] = crc;
+        idx += 1;
+    }
+
+    table
+};
+
+pub fn crc64(crc: u64, buf: &[u8]) -> u64 {
+    buf.iter().fold(crc, |crc, byte| CRC64_TABLE[((crc ^ *byte as u64) & 0xff) as usize] ^ (crc >> 8))
+}
+
+/// Serializes a value for `DUMP`: the object, followed by the RDB version and
+/// a CRC-64 of everything before it, both little endian.
+pub fn dump(value: &Value) -> Result<Vec<u8>, Error> {
+    let mut buf = vec![];
+    write_object(&mut buf, value)?;
+
+    buf.extend(RDB_VERSION.to_le_bytes());
+    let crc = crc64(0, &buf);
+    buf.extend(crc.to_le_bytes());
+
+    Ok(buf)
+}
+
+/// Deserializes a value serialized by `dump`, checking its footer first.
+pub fn restore(payload: &[u8]) -> Result<Value, Error> {
+    if payload.len() < 10 {
+        return Err(Error::Checksum);
+    }
+
+    let (body, crc) = payload.split_at(payload.len() - 8);
+    let version = u16::from_le_bytes([body[body.len() - 2], body[body.len() - 1]]);
+
+    if version > RDB_VERSION || crc64(0, body).to_le_bytes() != crc {
+        return Err(Error::Checksum);
+    }
+
+    let mut reader = Reader::new(&body[..body.len() - 2]);
+    let value = reader.read_object()?;
+
+    if !reader.is_empty() {
+        return Err(Error::Invalid("payload"));
+    }
+
+    Ok(value)
+}
+
+/// A key to write to an RDB file.
+pub struct SavedKey<'a> {
+    pub db_index: usize,
+    pub key: &'a [u8],
+    pub value: &'a Value,
+    /// Unix time in milliseconds the key expires at.
+    pub expiry: Option<u128>,
+}
+
+/// Writes a whole RDB file holding `keys`, which must come grouped by
+/// database. Keys whose value can't be encoded are left out.
+pub fn save<'a>(keys: impl IntoIterator<Item = SavedKey<'a>>) -> Vec<u8> {
+    let mut buf = format!("REDIS{:04}", RDB_VERSION).into_bytes();
+
+    for (name, val) in [("redis-ver", crate::identity::REDIS_VERSION), ("redis-bits", "64")] {
+        buf.push(OPCODE_AUX);
+        write_string(&mut buf, name.as_bytes());
+        write_string(&mut buf, val.as_bytes());
+    }
+    buf.push(OPCODE_AUX);
+    write_string(&mut buf, b"ctime");
+    write_string(&mut buf, (crate::get_unix_ts_millis() / 1000).to_string().as_bytes());
+
+    let mut db_index = None;
+    for key in keys {
+        let mut object = vec![];
+        if let Err(err) = write_object(&mut object, key.value) {
+            crate::warn!("Leaving key {} out of the RDB file: {}", String::from_utf8_lossy(key.key), err);
+            continue;
+        }
+
+        if db_index != Some(key.db_index) {
+            buf.push(OPCODE_SELECTDB);
+            write_length(&mut buf, key.db_index as u64);
+            db_index = Some(key.db_index);
+        }
+        if let Some(expiry) = key.expiry {
+            buf.push(OPCODE_EXPIRETIME_MS);
+            buf.extend((expiry as u64).to_le_bytes());
+        }
+
+        // The type comes before the key, the value after it.
+        buf.push(object[0]);
+        write_string(&mut buf, key.key);
+        buf.extend_from_slice(&object[1..]);
+    }
+
+    buf.push(OPCODE_EOF);
+    let crc = crc64(0, &buf);
+    buf.extend(crc.to_le_bytes());
+
+    buf
+}
+
+/// A key read from an RDB file.
+#[derive(Debug)]
+pub struct LoadedKey {
+    pub db_index: usize,
+    pub key: Bytes,
+    pub value: Value,
+    /// Unix time in milliseconds the key expires at.
+    pub expiry: Option<u128>,
+}
+
+/// Reads the keys of a whole RDB file, calling `progress` with how many
+/// bytes of it were read after each key. Metadata such as the auxiliary
+/// fields and the LRU/LFU info of keys is skipped.
+pub fn load(file: &[u8], mut progress: impl FnMut(usize)) -> Result<Vec<LoadedKey>, Error> {
+    if file.len() < 9 || &file[..5] != b"REDIS" {
+        return Err(Error::Invalid("RDB header"));
+    }
+
+    let version = std::str::from_utf8(&file[5..9])
+        .ok()
+        .and_then(|version| version.parse::<u16>().ok())
+        .ok_or(Error::Invalid("RDB header"))?;
+    if version == 0 || version > RDB_LOAD_VERSION {
+        return Err(Error::Checksum);
+    }
+
+    let mut reader = Reader::new(&file[9..]);
+    let mut keys = vec![];
+    let mut db_index = 0;
+    let mut expiry = None;
+
+    loop {
+        match reader.read_u8()? {
+            OPCODE_EOF => break,
+            OPCODE_SELECTDB => db_index = usize::try_from(reader.read_length()?).map_err(|_| Error::Invalid("database index"))?,
+            OPCODE_RESIZEDB => {
+                reader.read_length()?;
+                reader.read_length()?;
+            },
+            OPCODE_AUX => {
+                reader.read_string()?;
+                reader.read_string()?;
+            },
+            OPCODE_SLOT_INFO => {
+                for _ in 0..3 {
+                    reader.read_length()?;
+                }
+            },
+            OPCODE_EXPIRETIME_MS => expiry = Some(u64::from_le_bytes(reader.read_array()?) as u128),
+            OPCODE_EXPIRETIME => expiry = Some(u32::from_le_bytes(reader.read_array()?) as u128 * 1000),
+            OPCODE_IDLE => {
+                reader.read_length()?;
+            },
+            OPCODE_FREQ => {
+                reader.read_u8()?;
+            },
+            value_type => {
+                let key = reader.read_string()?;
+                let value = reader.read_value(value_type)?;
+
+                keys.push(LoadedKey { db_index, key, value, expiry: expiry.take() });
+                progress(9 + reader.pos);
+            },
+        }
+    }
+
+    // Files since version 5 end in a CRC-64 of everything before it, which
+    // is zero if checksums were turned off.
+    if version >= 5 {
+        let end = 9 + reader.pos;
+        let crc: [u8; 8] = reader.read_array()?;
+
+        if crc != [0; 8] && crc64(0, &file[..end]).to_le_bytes() != crc {
+            return Err(Error::Checksum);
+        }
+    }
+
+    if !reader.is_empty() {
+        return Err(Error::Invalid("RDB file"));
+    }
+
+    Ok(keys)
+}
@@ -1,7 +1,18 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
 use bytes::Bytes;
 use tokio::net::TcpStream;
+use tokio::sync::{Mutex, Notify};
+
+use crate::command_table::CommandNames;
+use crate::commands::ReplicaContext;
+use crate::rdb;
+use crate::{debug, info, warn, Client, Command, Connection, ConnectionClass, ConnectionManager, ExpiredKeys, Frame, RedisError, ReplTrace, SharedRedisState, TraceDirection};
 
-use crate::{debug, info, Command, Connection, Frame, SharedRedisState};
+/// How long a replica waits before connecting to its master again.
+const RECONNECT_INTERVAL_MILLIS: u64 = 1000;
 
 pub const EMPTY_RDB_FILE_BYTES: &[u8] = &[
     0x52,0x45,0x44,0x49,0x53,0x30,0x30,0x31,0x31,0xfa,0x09,0x72,0x65,0x64,0x69,0x73,
@@ -12,13 +23,29 @@ pub const EMPTY_RDB_FILE_BYTES: &[u8] = &[
     0xf0,0x6e,0x3b,0xfe,0xc0,0xff,0x5a,0xa2,
 ];
 
+/// A replica attached to this master.
+struct Replica {
+    addr: String,
+    // Commands propagated while the replica is still receiving its initial
+    // snapshot, sent once the snapshot is through.
+    pending: Option<Vec<Frame>>,
+    // Bytes propagated to the replica since its snapshot, which is the
+    // offset the replica itself counts.
+    offset: u64,
+    // The offset the replica last acknowledged with `REPLCONF ACK`.
+    ack_offset: u64,
+    // The offset the replica last reported its AOF fsynced, for those that
+    // run one and say so with `FACK`.
+    aof_offset: Option<u64>,
+}
 
-#[derive(Clone)]
-pub struct ReplicationInfo {
+/// Replication state shared by the connection handlers, the propagation path
+/// and the replication worker. Everything that changes at runtime is
+/// interior-mutable, so readers such as INFO never see a stale copy.
+pub struct ReplicationState {
     role: String,
-    connected_slaves: u64,
-    master_repl_offset: u64,
     master_replication_id: String,
+    master_repl_offset: AtomicU64,
     second_repl_offset: i64,
     repl_backlog_active: bool,
     repl_backlog_size: u64,
@@ -26,12 +53,26 @@ pub struct ReplicationInfo {
     repl_backlog_histlen: u64,
     reaplicaof_addr: Option<String>,
     listening_port: String,
-    replicas: Vec<String>,
-    replica_offset_bytes: u64,
+    replicas: RwLock<Vec<Replica>>,
+    // Bytes of the replication stream processed so far, on a replica.
+    replica_offset_bytes: AtomicU64,
+    // Whether a replica's connection to its master is established.
+    master_link_up: AtomicBool,
+    // Database of the last command sent to replicas. Held for the duration of
+    // `propagate`, which also keeps propagated commands in order.
+    last_propagated_db: Mutex<Option<usize>>,
+    // Notified whenever a replica acknowledges an offset, for `WAIT`.
+    acked: Notify,
+    trace: ReplTrace,
+    // Keys deleted on expiry, whose deletion goes out ahead of the next
+    // propagated write.
+    expired: Arc<ExpiredKeys>,
 }
 
-impl ReplicationInfo {
-    pub fn new(replicaof: Option<String>, listening_port: String) -> Self {
+pub type SharedReplicationState = Arc<ReplicationState>;
+
+impl ReplicationState {
+    pub fn new(replicaof: Option<String>, listening_port: String, expired: Arc<ExpiredKeys>) -> Self {
         let role = match replicaof {
             Some(_) => "slave".to_string(),
             None => "master".to_string(),
@@ -42,34 +83,56 @@ impl ReplicationInfo {
 
         Self {
             role,
-            connected_slaves: 0,
-            master_repl_offset: 0,
             master_replication_id: replication_id.to_string(),
+            master_repl_offset: AtomicU64::new(0),
             second_repl_offset: 0,
             repl_backlog_active: false,
             repl_backlog_size: 0,
             repl_backlog_first_byte_offset: 0,
             repl_backlog_histlen: 0,
             reaplicaof_addr: replicaof,
-            listening_port: listening_port,
-            replicas: vec![],
-            replica_offset_bytes: 0,
+            listening_port,
+            replicas: RwLock::new(vec![]),
+            replica_offset_bytes: AtomicU64::new(0),
+            master_link_up: AtomicBool::new(false),
+            last_propagated_db: Mutex::new(None),
+            acked: Notify::new(),
+            trace: ReplTrace::new(),
+            expired,
         }
     }
-    
+
     pub fn get_info_bytes(&self) -> Bytes {
-        Bytes::from(format!(
-            "# Replication\nrole:{}\nconnected_slaves:{}\nmaster_repl_offset:{}\nmaster_replid:{}\nsecond_repl_offset:{}\nrepl_backlog_active:{}\nrepl_backlog_size:{}\nrepl_backlog_first_byte_offset:{}\nrepl_backlog_histlen:{}\n",
-            self.role,
-            self.connected_slaves,
-            self.master_repl_offset,
+        let mut res = format!("# Replication\nrole:{}\n", self.role);
+
+        if let Some((host, port)) = self.reaplicaof_addr.as_ref().and_then(|addr| addr.rsplit_once(':')) {
+            res.push_str(&format!(
+                "master_host:{}\nmaster_port:{}\nmaster_link_status:{}\nslave_repl_offset:{}\n",
+                host,
+                port,
+                if self.master_link_up.load(Ordering::Relaxed) { "up" } else { "down" },
+                self.get_replica_offset_bytes(),
+            ));
+        }
+
+        res.push_str(&format!(
+            "connected_slaves:{}\nmaster_repl_offset:{}\nmaster_replid:{}\nsecond_repl_offset:{}\nrepl_backlog_active:{}\nrepl_backlog_size:{}\nrepl_backlog_first_byte_offset:{}\nrepl_backlog_histlen:{}\n",
+            self.replicas.read().unwrap_or_else(|err| err.into_inner()).len(),
+            self.get_replication_offset(),
             self.master_replication_id,
             self.second_repl_offset,
             self.repl_backlog_active,
             self.repl_backlog_size,
             self.repl_backlog_first_byte_offset,
             self.repl_backlog_histlen
-        ))
+        ));
+
+        Bytes::from(res)
+    }
+
+    /// `master`, or `slave` when replicating another server.
+    pub fn get_role(&self) -> &str {
+        &self.role
     }
 
     pub fn get_replication_id(&self) -> String {
@@ -77,147 +140,444 @@ impl ReplicationInfo {
     }
 
     pub fn get_replication_offset(&self) -> u64 {
-        self.master_repl_offset
+        self.master_repl_offset.load(Ordering::Relaxed)
     }
 
-    pub fn add_replica(&mut self, addr: String) {
+    /// Registers a replica starting a full resynchronization and returns the
+    /// replication offset its snapshot corresponds to. Commands propagated
+    /// from then on are buffered until `finish_full_resync`, so they reach the
+    /// replica after the snapshot.
+    ///
+    /// Callers must hold the locks of every shard while taking the snapshot
+    /// and registering the replica, so no write falls in between.
+    pub async fn add_replica(&self, addr: String) -> u64 {
         assert!(self.role == "master");
-        self.replicas.push(addr);
-        self.connected_slaves += 1;
+
+        // Make sure the new replica is told which database comes next.
+        let mut last_propagated_db = self.last_propagated_db.lock().await;
+        *last_propagated_db = None;
+
+        self.replicas.write().unwrap_or_else(|err| err.into_inner()).push(Replica { addr, pending: Some(vec![]), offset: 0, ack_offset: 0, aof_offset: None });
+
+        self.get_replication_offset()
+    }
+
+    /// Sends a replica the commands buffered while it received its snapshot,
+    /// and propagates to it directly from then on.
+    pub async fn finish_full_resync(&self, conn_manager: &ConnectionManager, addr: &str) -> crate::Result<()> {
+        let _last_propagated_db = self.last_propagated_db.lock().await;
+
+        let pending = self.replicas.write().unwrap_or_else(|err| err.into_inner())
+            .iter_mut()
+            .find(|replica| replica.addr == addr)
+            .and_then(|replica| replica.pending.take())
+            .unwrap_or_default();
+
+        for frame in pending.iter() {
+            conn_manager.queue_frame(addr, frame).await?;
+        }
+        conn_manager.flush(addr).await?;
+
+        Ok(())
+    }
+
+    pub async fn remove_replica(&self, addr: &str) {
+        let _last_propagated_db = self.last_propagated_db.lock().await;
+        self.replicas.write().unwrap_or_else(|err| err.into_inner()).retain(|replica| replica.addr != addr);
     }
 
     pub fn get_replicas(&self) -> Vec<String> {
-        self.replicas.clone()
+        self.replicas.read().unwrap_or_else(|err| err.into_inner()).iter().map(|replica| replica.addr.clone()).collect()
+    }
+
+    /// The offset each replica has been sent up to so far. Once a replica
+    /// acknowledges its offset, it has applied every write propagated
+    /// before this call.
+    pub fn get_replica_offsets(&self) -> Vec<(String, u64)> {
+        self.replicas.read().unwrap_or_else(|err| err.into_inner()).iter().map(|replica| (replica.addr.clone(), replica.offset)).collect()
+    }
+
+    /// Records the offsets replica `addr` acknowledged, the one it applied
+    /// and, if it runs an AOF, the one it fsynced.
+    pub fn record_ack(&self, addr: &str, offset: u64, aof_offset: Option<u64>) {
+        if let Some(replica) = self.replicas.write().unwrap_or_else(|err| err.into_inner()).iter_mut().find(|replica| replica.addr == addr) {
+            replica.ack_offset = replica.ack_offset.max(offset);
+            if let Some(aof_offset) = aof_offset {
+                replica.aof_offset = Some(replica.aof_offset.unwrap_or_default().max(aof_offset));
+            }
+        }
+
+        self.acked.notify_waiters();
+    }
+
+    /// Number of the replicas in `targets` that acknowledged at least the
+    /// offset given for them, either applied or, with `aof`, fsynced.
+    /// Replicas that went away since don't count.
+    pub fn count_acked(&self, targets: &[(String, u64)], aof: bool) -> usize {
+        let replicas = self.replicas.read().unwrap_or_else(|err| err.into_inner());
+
+        targets.iter()
+            .filter(|(addr, target)| replicas.iter().any(|replica| {
+                let acked = match aof {
+                    true => replica.aof_offset,
+                    false => Some(replica.ack_offset),
+                };
+                replica.addr == *addr && matches!(acked, Some(acked) if acked >= *target)
+            }))
+            .count()
+    }
+
+    /// Waits for the next acknowledgement from any replica. The future only
+    /// misses acknowledgements recorded before it was created, so create it
+    /// before looking at what was acknowledged so far.
+    pub fn acked(&self) -> tokio::sync::futures::Notified<'_> {
+        self.acked.notified()
+    }
+
+    /// Asks every replica to acknowledge its offset.
+    pub async fn request_acks(&self, conn_manager: &ConnectionManager) {
+        let _last_propagated_db = self.last_propagated_db.lock().await;
+
+        self.send_to_replicas(conn_manager, &[Frame::command(["REPLCONF", "GETACK", "*"])]).await;
     }
 
     pub fn get_replica_offset_bytes(&self) -> u64 {
-        self.replica_offset_bytes
+        self.replica_offset_bytes.load(Ordering::Relaxed)
+    }
+
+    pub fn add_replica_offset(&self, offset: u64) {
+        self.replica_offset_bytes.fetch_add(offset, Ordering::Relaxed);
+    }
+
+    pub fn reset_replica_offset(&self) {
+        self.replica_offset_bytes.store(0, Ordering::Relaxed);
+    }
+
+    pub fn set_master_link_up(&self, up: bool) {
+        self.master_link_up.store(up, Ordering::Relaxed);
+    }
+
+    pub fn get_trace(&self) -> &ReplTrace {
+        &self.trace
+    }
+
+    /// Sends a write command executed against database `db_index` to every
+    /// replica, preceded by a `SELECT` whenever the previous propagated command
+    /// targeted a different database.
+    ///
+    /// Callers must still hold the locks of the shards the command touched, so
+    /// that commands on the same keys reach replicas in the order they were
+    /// applied. Keys deleted on expiry since the last call go out first, see
+    /// `ExpiredKeys`.
+    ///
+    /// A replica that can't be written to is dropped, along with its
+    /// connection, rather than failing the command being propagated.
+    pub async fn propagate(&self, conn_manager: &ConnectionManager, db_index: usize, frame: &Frame) -> crate::Result<()> {
+        self.propagate_all(conn_manager, &[(db_index, frame)], false).await
     }
 
-    pub fn add_replica_offset(&mut self, offset: u64) {
-        self.replica_offset_bytes += offset;
+    /// Sends the writes of a transaction, each with the database it applied
+    /// to, wrapped in `MULTI` and `EXEC` so replicas apply them all at once.
+    /// Transactions that wrote nothing aren't propagated at all.
+    ///
+    /// Callers must still hold the transaction lock.
+    pub async fn propagate_transaction(&self, conn_manager: &ConnectionManager, writes: &[(usize, Frame)]) -> crate::Result<()> {
+        if writes.is_empty() {
+            return Ok(());
+        }
+
+        let writes: Vec<(usize, &Frame)> = writes.iter().map(|(db_index, frame)| (*db_index, frame)).collect();
+        self.propagate_all(conn_manager, &writes, true).await
+    }
+
+    /// Sends the deletions of the keys expired since the last propagated
+    /// write, which would otherwise wait for the next one. Run every cron
+    /// tick.
+    pub async fn propagate_expired(&self, conn_manager: &ConnectionManager) -> crate::Result<()> {
+        self.propagate_all(conn_manager, &[], false).await
+    }
+
+    async fn propagate_all(&self, conn_manager: &ConnectionManager, writes: &[(usize, &Frame)], transaction: bool) -> crate::Result<()> {
+        let mut last_propagated_db = self.last_propagated_db.lock().await;
+        // Taken even without replicas, so they don't pile up.
+        let expired = self.expired.take();
+
+        if self.replicas.read().unwrap_or_else(|err| err.into_inner()).is_empty() {
+            return Ok(());
+        }
+
+        let mut frames = vec![];
+        let mut push = |db_index: usize, frame: &Frame, frames: &mut Vec<Frame>| {
+            if *last_propagated_db != Some(db_index) {
+                *last_propagated_db = Some(db_index);
+                frames.push(Frame::command(["SELECT", &db_index.to_string()]));
+            }
+            frames.push(frame.clone());
+        };
+
+        for (db_index, frame) in expired.iter() {
+            push(*db_index, frame, &mut frames);
+        }
+        if transaction {
+            frames.push(Frame::command(["MULTI"]));
+        }
+        for (db_index, frame) in writes.iter() {
+            push(*db_index, frame, &mut frames);
+        }
+        if transaction {
+            frames.push(Frame::command(["EXEC"]));
+        }
+
+        if !frames.is_empty() {
+            self.send_to_replicas(conn_manager, &frames).await;
+        }
+
+        Ok(())
+    }
+
+    /// Sends `frames` to every replica, buffering them for those still
+    /// receiving their snapshot, and counts them toward each replica's
+    /// offset. Callers must hold `last_propagated_db`.
+    async fn send_to_replicas(&self, conn_manager: &ConnectionManager, frames: &[Frame]) {
+        // Buffer the commands for replicas still receiving their snapshot.
+        let mut replicas = vec![];
+        for replica in self.replicas.write().unwrap_or_else(|err| err.into_inner()).iter_mut() {
+            for frame in frames.iter() {
+                self.trace.record(TraceDirection::Propagated, &replica.addr, replica.offset, &frame.to_args());
+                replica.offset += frame.len() as u64;
+            }
+
+            match replica.pending.as_mut() {
+                Some(pending) => pending.extend(frames.iter().cloned()),
+                None => replicas.push(replica.addr.clone()),
+            }
+        }
+
+        for replica in replicas {
+            debug!("Replicating to replica: {}", replica);
+
+            let mut res = Ok(());
+            for frame in frames.iter() {
+                if res.is_ok() {
+                    res = conn_manager.queue_frame(&replica, frame).await;
+                }
+            }
+            if res.is_ok() {
+                res = conn_manager.flush(&replica).await;
+            }
+
+            if let Err(err) = res {
+                warn!("Dropping replica {} which can't be written to: {}", replica, err);
+                self.replicas.write().unwrap_or_else(|err| err.into_inner()).retain(|other| other.addr != replica);
+                conn_manager.remove(&replica).await;
+            }
+        }
     }
 }
 
 // ReplicationWorker is responsible for managing the replication behaviour of the server.
 pub struct ReplicationWorker {
-    replication_info: ReplicationInfo,
+    replication: SharedReplicationState,
     db: SharedRedisState,
-    connection: Option<Connection>,
+    client: Option<Client>,
 }
 
 impl ReplicationWorker {
-    pub fn new(replication_info: ReplicationInfo, db: SharedRedisState) -> Self {
-        Self { replication_info, db, connection: None }
+    pub fn new(replication: SharedReplicationState, db: SharedRedisState) -> Self {
+        Self { replication, db, client: None }
+    }
+
+    /// Replicates the master for as long as the server runs, connecting again
+    /// after a failed sync or a lost connection.
+    pub async fn run(&mut self) {
+        loop {
+            match self.start().await {
+                Ok(()) => warn!("Connection with master lost"),
+                Err(err) => warn!("Replication with master failed: {}", err),
+            }
+            self.replication.set_master_link_up(false);
+            self.client = None;
+
+            tokio::time::sleep(Duration::from_millis(RECONNECT_INTERVAL_MILLIS)).await;
+            info!("Connecting to master again");
+        }
     }
 
     // Start the replication worker as a background tokio task.
     pub async fn start(&mut self) -> crate::Result<()> {
         info!("Starting replication worker");
-        self.connection = Some(self.connect().await?);
+        let timeout = Duration::from_secs(self.db.get_config().repl_syncio_timeout);
+        let addr = self.replication.reaplicaof_addr.clone().unwrap();
+
+        let stream = match tokio::time::timeout(timeout, TcpStream::connect(&addr)).await {
+            Ok(stream) => stream?,
+            Err(_) => return Err(format!("ERR Timeout connecting to the master at {}", addr).into()),
+        };
+        self.client = Some(Client::new(Connection::with_class(stream, ConnectionClass::MasterLink)));
 
-        self.handshake().await?;
+        self.handshake(timeout).await?;
+        self.replication.set_master_link_up(true);
 
-        let conn = self.connection.as_mut().unwrap();
+        // From here on the master streams its writes without being asked.
+        let mut ctx = ReplicaContext::new(self.db.clone(), self.client.take().unwrap().into_connection());
+        let master_addr = self.replication.reaplicaof_addr.clone().unwrap_or_default();
+        let monitor = self.db.get_monitor_feed();
 
+        // Every byte after the RDB file counts toward the offset, measured as
+        // received rather than re-encoded. A command is counted before it's
+        // applied, with the offset before it kept for `REPLCONF GETACK`.
         debug!("Start waiting for frames");
-        while let Some(frame) = conn.read_frame(false).await? {
+        let mut consumed = ctx.master.consumed();
+        while let Some(frame) = ctx.master.read_frame(false).await? {
             debug!("Got frame: {:?}", &frame);
-            let frame_len = frame.len() as u64;
+            let frame_len = ctx.master.consumed() - consumed;
+            consumed = ctx.master.consumed();
+            monitor.feed(ctx.db_index, &master_addr, &frame.to_args());
 
-            match Command::from_frame(frame) {
-                Ok(Command::Set(cmd)) => {
-                    cmd.apply_replica(self.db.clone()).await?;
-                }
-                Ok(Command::ReplConf(cmd)) => {
-                    cmd.apply_replica(conn, self.db.clone()).await?;
-                },
-                Ok(Command::Ping(_)) => {},
-                e => {
-                    debug!("Encountered error while replaying replicated command: {:?}", e)
-                }, // TODO: Error handling?
-            }
+            ctx.offset = self.replication.get_replica_offset_bytes();
+            self.replication.trace.record(TraceDirection::Applied, &master_addr, ctx.offset, &frame.to_args());
             debug!("Adding replica offset: {}", frame_len);
-            self.db.lock().await.add_replica_offset(frame_len);
+            self.replication.add_replica_offset(frame_len);
+
+            // The master propagates by the original names, whatever this
+            // server calls the commands.
+            match Command::from_frame(frame, &CommandNames::default()) {
+                Ok(cmd) => cmd.apply_replica(&mut ctx).await?,
+                Err(err) => debug!("Encountered error while replaying replicated command: {:?}", err),
+            }
         }
 
-        Ok(())
-    }
+        self.replication.set_master_link_up(false);
 
-    async fn connect(&mut self) -> crate::Result<Connection> {
-        let stream = TcpStream::connect(self.replication_info.reaplicaof_addr.as_ref().unwrap()).await?;
-        return Ok(Connection::new(stream));
+        Ok(())
     }
 
-    async fn handshake(&mut self) -> crate::Result<()> {
-        let conn = self.connection.as_mut().unwrap();
-
-        // Send the first ping.
-        conn.write_frame(&Frame::Array(vec![
-            Frame::Bulk(Some(Bytes::from("PING"))),
-        ])).await?;
-
-        if let Some(pong) = conn.read_frame(false).await? {
-            if let Frame::Simple(pong) = pong {
-                assert!(pong.to_lowercase() == "pong");
-                info!("Received response: {}", pong);
-            } else {
-                return Err("Did not get PONG response from master".into());
-            }
+    /// Runs the handshake and loads the master's snapshot. Each reply must
+    /// arrive within `timeout`, and the RDB file may go that long without
+    /// any of it arriving. A master that goes silent fails the sync, to be
+    /// retried like any other failure.
+    async fn handshake(&mut self, timeout: Duration) -> crate::Result<()> {
+        let client = self.client.as_mut().unwrap();
+
+        let mut handshake = Handshake::new(&self.replication.listening_port);
+        while let Some(cmd) = handshake.command() {
+            let reply = match tokio::time::timeout(timeout, client.request(&cmd)).await {
+                Ok(reply) => reply?,
+                Err(_) => return Err(format!("ERR Timeout waiting for the reply to {} from master", cmd.join(" ")).into()),
+            };
+            handshake.on_reply(reply)?;
         }
 
-        conn.write_frame(&Frame::Array(vec![
-            Frame::Bulk(Some(Bytes::from("REPLCONF"))),
-            Frame::Bulk(Some(Bytes::from("listening-port"))),
-            Frame::Bulk(Some(Bytes::from(self.replication_info.listening_port.clone()))),
-        ])).await?;
+        let rdb = match client.read_file(Some(timeout)).await {
+            Err(RedisError::Io(err)) if err.kind() == std::io::ErrorKind::TimedOut => {
+                return Err("ERR Timeout receiving the RDB file from master".into());
+            },
+            res => res?,
+        };
+        info!("Received RDB file of size: {:?}", rdb.len());
+
+        // A full resync replaces the whole dataset, so nothing the replica
+        // had before may outlive it. A corrupt snapshot fails the sync before
+        // anything is touched.
+        //
+        // Clients get a LOADING error meanwhile, rather than the dataset from
+        // before the sync, until the new one is all in place. The file is
+        // parsed on a blocking thread so they're still answered meanwhile.
+        let loading = self.db.get_loading().start(rdb.len());
+        let db = self.db.clone();
+        let keys = tokio::task::spawn_blocking(move || rdb::load(&rdb, |loaded| db.get_loading().progress(loaded)))
+            .await
+            .map_err(|err| format!("ERR Failed loading the RDB file from master: {}", err))?
+            .map_err(|err| format!("ERR Bad RDB file from master: {}", err))?;
+        info!("Loaded {} keys from the master's snapshot", keys.len());
+        self.db.load_snapshot(keys, true).await?;
+        drop(loading);
+
+        // The handshake and the RDB file don't count toward the offset.
+        self.replication.reset_replica_offset();
 
-        if let Some(ok) = conn.read_frame(false).await? {
-            if let Frame::Simple(ok) = ok {
-                assert!(ok.to_lowercase() == "ok");
-                info!("Received response: {}", ok);
-            } else {
-                return Err("Did not get OK response from master".into());
-            }
-        }
+        Ok(())
+    }
+}
 
-        conn.write_frame(&Frame::Array(vec![
-            Frame::Bulk(Some(Bytes::from("REPLCONF"))),
-            Frame::Bulk(Some(Bytes::from("capa"))),
-            Frame::Bulk(Some(Bytes::from("psync2"))),
-        ])).await?;
+/// A step of the handshake with the master.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HandshakeStep {
+    Ping,
+    ListeningPort,
+    Capa,
+    Psync,
+    /// The master agreed to a full resync, and sends the RDB file next.
+    Done,
+}
 
-        if let Some(ok) = conn.read_frame(false).await? {
-            if let Frame::Simple(ok) = ok {
-                assert!(ok.to_lowercase() == "ok");
-                info!("Received response: {}", ok);
-            } else {
-                return Err("Did not get OK response from master".into());
-            }
-        }
+/// The replies the replica expects during the handshake, one step per
+/// command. Error replies to the `REPLCONF`s only mean the master doesn't
+/// know the option, so the replica goes on without it like redis-server
+/// does. Any other unexpected reply fails the sync, with the master's
+/// reply in the error.
+struct Handshake {
+    step: HandshakeStep,
+    listening_port: String,
+}
 
-        conn.write_frame(&Frame::Array(vec![
-            Frame::Bulk(Some(Bytes::from("PSYNC"))),
-            Frame::Bulk(Some(Bytes::from("?"))),
-            Frame::Bulk(Some(Bytes::from("-1"))),
-        ])).await?;
+impl Handshake {
+    fn new(listening_port: &str) -> Self {
+        Self { step: HandshakeStep::Ping, listening_port: listening_port.to_string() }
+    }
 
-        if let Some(resync) = conn.read_frame(false).await? {
-            if let Frame::Simple(resync) = resync {
-                info!("Received response: {}", resync);
-            } else {
-                return Err("Did not get OK response from master".into());
-            }
+    /// The command to send next, None once the handshake is done.
+    fn command(&self) -> Option<Vec<&str>> {
+        match self.step {
+            HandshakeStep::Ping => Some(vec!["PING"]),
+            HandshakeStep::ListeningPort => Some(vec!["REPLCONF", "listening-port", &self.listening_port]),
+            HandshakeStep::Capa => Some(vec!["REPLCONF", "capa", "psync2"]),
+            HandshakeStep::Psync => Some(vec!["PSYNC", "?", "-1"]),
+            HandshakeStep::Done => None,
         }
+    }
 
-        if let Some(rdb) = conn.read_frame(true).await? {
-            if let Frame::File(rdb) = rdb {
-                info!("Received RDB file of size: {:?}", rdb.len());
-            } else {
-                return Err("Did not get RDB file from master".into());
-            }
-        }
+    /// Checks the master's reply to the last command, moving on to the next
+    /// step.
+    fn on_reply(&mut self, reply: Frame) -> crate::Result<()> {
+        self.step = match (self.step, &reply) {
+            (HandshakeStep::Ping, Frame::Simple(pong)) if pong.trim().eq_ignore_ascii_case("pong") => HandshakeStep::ListeningPort,
+            (HandshakeStep::ListeningPort, Frame::Simple(ok)) if ok.trim().eq_ignore_ascii_case("ok") => HandshakeStep::Capa,
+            (HandshakeStep::ListeningPort, Frame::Error(err)) => {
+                warn!("(Non critical) Master does not understand REPLCONF listening-port: {}", err);
+                HandshakeStep::Capa
+            },
+            (HandshakeStep::Capa, Frame::Simple(ok)) if ok.trim().eq_ignore_ascii_case("ok") => HandshakeStep::Psync,
+            (HandshakeStep::Capa, Frame::Error(err)) => {
+                warn!("(Non critical) Master does not understand REPLCONF capa: {}", err);
+                HandshakeStep::Psync
+            },
+            (HandshakeStep::Psync, Frame::Simple(resync)) if is_full_resync(resync) => {
+                info!("Full resync from master: {}", resync.trim());
+                HandshakeStep::Done
+            },
+            (_, reply) => {
+                let cmd = self.command().unwrap_or_default().join(" ");
+                return Err(format!("ERR Unexpected reply to {} from master: {}", cmd, describe_reply(reply)).into());
+            },
+        };
 
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// Whether the reply to `PSYNC` is `FULLRESYNC <replid> <offset>`.
+fn is_full_resync(reply: &str) -> bool {
+    let parts: Vec<&str> = reply.split_whitespace().collect();
+
+    matches!(parts.as_slice(), [resync, _, offset] if resync.eq_ignore_ascii_case("fullresync") && offset.parse::<i64>().is_ok())
+}
+
+/// A reply the way the master sent it, for error messages.
+fn describe_reply(reply: &Frame) -> String {
+    match reply {
+        Frame::Simple(reply) => format!("'+{}'", reply),
+        Frame::Error(err) => format!("'-{}'", err),
+        reply => format!("{:?}", reply),
+    }
+}
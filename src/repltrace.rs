@@ -0,0 +1,129 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+
+use crate::monitor::quote;
+use crate::warn;
+
+/// Which end of the replication stream a traced command was seen at.
+#[derive(Debug, Clone, Copy)]
+pub enum TraceDirection {
+    /// Sent by this master to a replica.
+    Propagated,
+    /// Received from the master and applied by this replica.
+    Applied,
+}
+
+impl TraceDirection {
+    fn name(self) -> &'static str {
+        match self {
+            TraceDirection::Propagated => "propagated replica",
+            TraceDirection::Applied => "applied master",
+        }
+    }
+}
+
+/// Trace of the replication stream, for finding out where a replica drifted
+/// from its master. Each command goes on a line of its own, along with the
+/// replication offset before it, so the traces of both ends can be compared:
+///
+/// `1339518083.107412 propagated replica=127.0.0.1:60866 offset=51 "SET" "key" "value"`
+///
+/// Once the file reaches `repl-trace-max-size` it's moved to `<path>.1`,
+/// replacing the previous one, and a new file is started.
+#[derive(Default)]
+pub struct ReplTrace {
+    enabled: AtomicBool,
+    file: Mutex<TraceFile>,
+}
+
+#[derive(Default)]
+struct TraceFile {
+    path: String,
+    max_size: u64,
+    file: Option<File>,
+    size: u64,
+}
+
+impl ReplTrace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Applies the `repl-trace` and `repl-trace-max-size` settings. Tracing
+    /// starts whenever a new path is set, and stops for an empty one.
+    pub fn configure(&self, path: &str, max_size: u64) {
+        let mut trace = self.file.lock().unwrap_or_else(|err| err.into_inner());
+        trace.max_size = max_size;
+
+        if trace.path != path {
+            trace.path = path.to_string();
+            trace.file = None;
+            self.enabled.store(!path.is_empty(), Ordering::Relaxed);
+        }
+    }
+
+    /// Pauses or resumes tracing, e.g. through `DEBUG REPL-TRACE`.
+    pub fn set_enabled(&self, enabled: bool) -> crate::Result<()> {
+        if enabled && self.file.lock().unwrap_or_else(|err| err.into_inner()).path.is_empty() {
+            return Err("ERR repl-trace is not configured".into());
+        }
+        self.enabled.store(enabled, Ordering::Relaxed);
+
+        Ok(())
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Appends a command to the trace, if tracing is on. A trace that can't be
+    /// written to is turned off, rather than failing replication.
+    pub fn record(&self, direction: TraceDirection, peer: &str, offset: u64, args: &[Bytes]) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let mut line = format!("{}.{:06} {}={} offset={}", now.as_secs(), now.subsec_micros(), direction.name(), peer, offset);
+        for arg in args {
+            line.push(' ');
+            line.push_str(&quote(arg));
+        }
+        line.push('\n');
+
+        let mut trace = self.file.lock().unwrap_or_else(|err| err.into_inner());
+        if let Err(err) = trace.write(line.as_bytes()) {
+            warn!("Turning off repl-trace, which can't be written to {}: {}", trace.path, err);
+            trace.file = None;
+            self.enabled.store(false, Ordering::Relaxed);
+        }
+    }
+}
+
+impl TraceFile {
+    fn write(&mut self, line: &[u8]) -> std::io::Result<()> {
+        if self.file.is_some() && self.size + line.len() as u64 > self.max_size {
+            self.file = None;
+            fs::rename(&self.path, format!("{}.1", self.path))?;
+        }
+
+        let file = match self.file.as_mut() {
+            Some(file) => file,
+            None => {
+                let file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+                self.size = file.metadata()?.len();
+                self.file.insert(file)
+            },
+        };
+
+        file.write_all(line)?;
+        self.size += line.len() as u64;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,422 @@
+use std::any::Any;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use tokio::net::TcpListener;
+use tokio::sync::mpsc::{self, error::TryRecvError};
+use tokio::sync::oneshot;
+use tokio::task::{JoinHandle, JoinSet};
+
+use crate::identity::REDIS_VERSION;
+use crate::{debug, error, evict, info, log, metrics, random, slowlog, PIPELINE_MAX_COMMANDS};
+use crate::commands::replication::handle_replica_frame;
+use crate::connection::ReadConnection;
+use crate::{Acl, Command, CommandContext, ConnectionManager, Cron, Frame, RedisError, RedisState, ReplicationWorker, ServerConfig, SharedRedisState};
+
+/// The whole server: the accept loop, the connections it serves and the
+/// background tasks. The binary runs it the same way as in-process users,
+/// which can have it pick a free port and shut it down again.
+pub struct Server;
+
+impl Server {
+    pub fn builder() -> ServerBuilder {
+        ServerBuilder {
+            port: 6379,
+            replicaof: None,
+            metrics_port: None,
+            random_seed: None,
+            config: ServerConfig::default(),
+        }
+    }
+}
+
+pub struct ServerBuilder {
+    port: u16,
+    replicaof: Option<String>,
+    metrics_port: Option<u16>,
+    random_seed: Option<u64>,
+    config: ServerConfig,
+}
+
+impl ServerBuilder {
+    /// Port to listen on, 0 for any free one.
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// Replicates the master at `addr`, given as `host:port`.
+    pub fn replicaof(mut self, addr: impl Into<String>) -> Self {
+        self.replicaof = Some(addr.into());
+        self
+    }
+
+    /// Serves Prometheus metrics over HTTP on this port, 0 for any free one.
+    /// Without it there's no metrics listener at all.
+    pub fn metrics_port(mut self, port: u16) -> Self {
+        self.metrics_port = Some(port);
+        self
+    }
+
+    /// Seeds the random numbers of randomized commands and eviction, which
+    /// are otherwise seeded from the OS. As they're process-wide, this also
+    /// reseeds them for any other server of the process.
+    pub fn random_seed(mut self, seed: u64) -> Self {
+        self.random_seed = Some(seed);
+        self
+    }
+
+    pub fn config(mut self, config: ServerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Binds the port and starts serving in the background.
+    pub async fn spawn(self) -> crate::Result<ServerHandle> {
+        let acl = Acl::from_config(&self.config)?;
+
+        let listener = TcpListener::bind(("127.0.0.1", self.port)).await?;
+        let addr = listener.local_addr()?;
+        log::set_default_tag(&addr.port().to_string());
+        info!("Listening on port: {}", addr.port());
+
+        let metrics_listener = match self.metrics_port {
+            Some(port) => Some(TcpListener::bind(("127.0.0.1", port)).await?),
+            None => None,
+        };
+        let metrics_addr = match &metrics_listener {
+            Some(listener) => Some(listener.local_addr()?),
+            None => None,
+        };
+        if let Some(metrics_addr) = metrics_addr {
+            info!("Serving metrics on port: {}", metrics_addr.port());
+        }
+
+        let random_seed = self.random_seed.unwrap_or_else(random::entropy_seed);
+        random::seed(random_seed);
+
+        // Replicas tell their master the port they actually listen on.
+        let db = Arc::new(RedisState::new(self.replicaof.clone(), addr.port(), self.config, acl));
+        let identity = db.get_identity();
+        info!(
+            "Server initialized version={} port={} metrics_port={} role={} pid={} run_id={} random_seed={}",
+            REDIS_VERSION,
+            addr.port(),
+            metrics_addr.map(|addr| addr.port().to_string()).unwrap_or_else(|| "none".to_string()),
+            db.get_replication_state().get_role(),
+            identity.process_id,
+            identity.run_id,
+            random_seed,
+        );
+
+        let (shutdown, shutdown_rx) = oneshot::channel();
+        let task = tokio::spawn(serve(listener, metrics_listener, db.clone(), self.replicaof, shutdown_rx));
+
+        Ok(ServerHandle { addr, metrics_addr, db, shutdown, task })
+    }
+}
+
+/// A running server.
+pub struct ServerHandle {
+    addr: SocketAddr,
+    metrics_addr: Option<SocketAddr>,
+    db: SharedRedisState,
+    shutdown: oneshot::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+impl ServerHandle {
+    /// The address the server listens on.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// The address metrics are served on, if they are.
+    pub fn metrics_addr(&self) -> Option<SocketAddr> {
+        self.metrics_addr
+    }
+
+    pub fn db(&self) -> &SharedRedisState {
+        &self.db
+    }
+
+    /// Stops accepting connections, then closes the open ones and stops the
+    /// background tasks.
+    pub async fn shutdown(self) {
+        let _ = self.shutdown.send(());
+        let _ = self.task.await;
+    }
+
+    /// Serves until the server stops on its own, which it never does unless
+    /// the listener fails.
+    pub async fn wait(self) {
+        let _ = self.task.await;
+    }
+}
+
+/// The accept loop. Every task it spawns, connections included, lives in
+/// `tasks`, so they're all aborted when it returns.
+async fn serve(listener: TcpListener, metrics_listener: Option<TcpListener>, db: SharedRedisState, replicaof: Option<String>, mut shutdown: oneshot::Receiver<()>) {
+    let mut tasks = JoinSet::new();
+    let conn_manager = ConnectionManager::new();
+
+    if let Some(metrics_listener) = metrics_listener {
+        tasks.spawn(metrics::serve(metrics_listener, db.clone()));
+    }
+
+    evict::update_lru_clock();
+    tasks.spawn(Cron::new(db.clone(), conn_manager.clone()).run());
+
+    if let Some(replicaof) = &replicaof {
+        info!("Replicating to: {}", replicaof);
+
+        let mut replication_worker = ReplicationWorker::new(db.get_replication_state(), db.clone());
+
+        tasks.spawn(async move {
+            replication_worker.run().await;
+        });
+    }
+
+    loop {
+        let (socket, addr) = tokio::select! {
+            res = listener.accept() => match res {
+                Ok(accepted) => accepted,
+                Err(err) => {
+                    error!("Error accepting connection: {:?}", err);
+                    continue;
+                },
+            },
+            // Reap finished connections.
+            Some(_) = tasks.join_next() => continue,
+            _ = &mut shutdown => {
+                db.get_pubsub().shutdown().await;
+                break;
+            },
+        };
+        info!("Accepted connection");
+
+        let db = db.clone();
+        let conn_manager = conn_manager.clone();
+        let rconn = conn_manager.add(addr.to_string(), socket).await;
+
+        tasks.spawn(async move {
+            // A panic only takes down its connection, which is cleaned up
+            // after like any other. Whatever the task held, from its client
+            // entry to its locks, was released as it unwound.
+            let mut last_command = String::new();
+            match CatchUnwind(Box::pin(handle_conn(addr.to_string(), db.clone(), &conn_manager, rconn, &mut last_command))).await {
+                Ok(Ok(())) => {},
+                Ok(Err(err)) => error!("Error reading frame! {:?} ", err),
+                Err(message) => {
+                    db.record_task_panic();
+                    error!("Connection {} panicked running '{}': {}", addr, last_command, message);
+                },
+            }
+
+            // Nothing may write to the connection once it's gone, be it as a
+            // replica, a subscriber or a monitor.
+            db.get_replication_state().remove_replica(&addr.to_string()).await;
+            db.get_pubsub().remove(&addr.to_string());
+            conn_manager.remove(&addr.to_string()).await;
+        });
+    }
+
+    tasks.shutdown().await;
+}
+
+// Request lifecyle:
+// 1. The connection's reader task reads frames off the socket, and queues
+//    them for the connection's task.
+// 2. The connection's task parses each frame into a command.
+// 3. Apply the command to the database.
+// 4. Write the result of the command to the connection.
+//
+// Reading runs ahead of the commands, so the next ones of a pipeline are
+// parsed while a large reply is still being written, until the queue fills
+// up and the reader stops reading, leaving the client to wait.
+async fn handle_conn(addr: String, db: SharedRedisState, conn_manager: &ConnectionManager, rconn: ReadConnection, last_command: &mut String) -> crate::Result<()> {
+    debug!("Start handling conn: {}", addr);
+    let (queue, mut frames) = mpsc::channel(PIPELINE_MAX_COMMANDS);
+    let _reader = Reader(tokio::spawn(read_frames(rconn, queue)));
+
+    let monitor = db.get_monitor_feed();
+    let command_stats = db.get_command_stats();
+
+    let clients = db.get_clients();
+    let mut client = clients.register(&addr);
+
+    let mut ctx = CommandContext::new(addr.clone(), db.clone(), conn_manager.clone());
+    ctx.client.id = client.id;
+    clients.update(client.id, &ctx.client);
+    let _watching = Watching(&db, client.id);
+
+    // A killed client is dropped before its next command.
+    while !client.is_killed() {
+        // Replies are queued, and sent once every command already received
+        // has run, so a pipeline's replies go out together.
+        let frame = match frames.try_recv() {
+            Ok(frame) => Some(frame),
+            Err(TryRecvError::Empty) => {
+                conn_manager.flush(&addr).await?;
+
+                tokio::select! {
+                    frame = frames.recv() => frame,
+                    _ = client.killed() => break,
+                }
+            },
+            Err(TryRecvError::Disconnected) => None,
+        };
+
+        let frame = match frame {
+            Some(Ok(frame)) => frame,
+            None => break,
+            Some(Err(err)) => {
+                // Let the client know what was wrong with its request before
+                // closing the connection, like redis-server does. A replica
+                // only ever gets its replication stream.
+                if let (RedisError::Protocol(_), false) = (&err, ctx.client.replica) {
+                    conn_manager.write_frame(&addr, &err.to_frame()).await?;
+                }
+                return Err(err);
+            }
+        };
+        debug!("Got frame: {:?}, len: {}", frame, frame.len());
+
+        // Once synced, a replica's connection carries its replication
+        // stream, which replies would corrupt, so what it sends doesn't
+        // go through the commands.
+        if ctx.client.replica {
+            handle_replica_frame(&ctx, &frame);
+            continue;
+        }
+
+        // Like redis-server, silently skip empty commands (`*0\r\n`), which
+        // some clients send as keepalives.
+        if matches!(&frame, Frame::Array(parts) if parts.is_empty()) {
+            continue;
+        }
+
+        let args = frame.to_args();
+        let name = args.first().map(|name| String::from_utf8_lossy(name).to_lowercase()).unwrap_or_default();
+
+        // Passwords are never shown to monitors, nor kept in the slow log,
+        // whether or not they were right.
+        let secret = matches!(db.get_command_names().lookup(&name), Some(spec) if spec.has_secret_args());
+        if !secret {
+            monitor.feed(ctx.client.db_index, &addr, &args);
+        }
+
+        clients.start_command(client.id, &name);
+        last_command.clone_from(&name);
+
+        match Command::from_frame(frame, db.get_command_names()) {
+            Ok(cmd) => {
+                // Only the command itself is timed, not reading it off the socket.
+                let start = Instant::now();
+                let res = cmd.apply(&mut ctx).await;
+                let duration = start.elapsed();
+
+                match res {
+                    // Failing to sync drops the replica, which starts over.
+                    Err(err) if ctx.client.replica => return Err(err),
+                    Err(err) => reply_error(&addr, conn_manager, err).await?,
+                    Ok(()) => {},
+                }
+
+                command_stats.record(&name, duration);
+                match secret {
+                    true => db.record_slow_command(&slowlog::redact(&args), &addr, duration),
+                    false => db.record_slow_command(&args, &addr, duration),
+                }
+            },
+            Err(err) => {
+                ctx.client.fail_transaction();
+                reply_error(&addr, conn_manager, err).await?
+            },
+        }
+        clients.update(client.id, &ctx.client);
+    }
+    debug!("Done handling conn: {}", addr);
+
+    Ok(())
+}
+
+/// A connection's reader task, stopped along with the connection.
+struct Reader(JoinHandle<()>);
+
+impl Drop for Reader {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Polls a future, turning a panic into an `Err` with the panic's message.
+struct CatchUnwind<F>(Pin<Box<F>>);
+
+impl<F: Future> Future for CatchUnwind<F> {
+    type Output = Result<F::Output, String>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match panic::catch_unwind(AssertUnwindSafe(|| self.0.as_mut().poll(cx))) {
+            Ok(Poll::Ready(output)) => Poll::Ready(Ok(output)),
+            Ok(Poll::Pending) => Poll::Pending,
+            Err(payload) => Poll::Ready(Err(panic_message(payload.as_ref()))),
+        }
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    match (payload.downcast_ref::<&str>(), payload.downcast_ref::<String>()) {
+        (Some(message), _) => message.to_string(),
+        (_, Some(message)) => message.clone(),
+        _ => "unknown panic".to_string(),
+    }
+}
+
+/// Forgets the keys a client watches once its connection is gone.
+struct Watching<'a>(&'a SharedRedisState, u64);
+
+impl Drop for Watching<'_> {
+    fn drop(&mut self) {
+        self.0.get_watches().unwatch(self.1);
+    }
+}
+
+/// Reads frames off a client's connection and queues them, until it's
+/// closed or sends something that isn't RESP, whose error is queued last.
+async fn read_frames(mut rconn: ReadConnection, queue: mpsc::Sender<crate::Result<Frame>>) {
+    loop {
+        let (frame, done) = match rconn.read_frame(false).await {
+            Ok(Some(frame)) => (Ok(frame), false),
+            Ok(None) => return,
+            Err(err) => (Err(err), true),
+        };
+
+        // The connection's task stopped taking them.
+        if queue.send(frame).await.is_err() || done {
+            return;
+        }
+    }
+}
+
+/// Sends a failed command's error to the client. Errors which mean the
+/// connection can't be used anymore are returned instead, after replying if
+/// possible.
+async fn reply_error(addr: &str, conn_manager: &ConnectionManager, err: RedisError) -> crate::Result<()> {
+    if let RedisError::Io(_) = err {
+        return Err(err);
+    }
+
+    conn_manager.write_frame(addr, &err.to_frame()).await?;
+
+    if err.is_fatal() {
+        return Err(err);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,116 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use bytes::Bytes;
+
+use crate::{get_unix_ts_millis, Frame};
+
+/// Maximum number of arguments stored per entry.
+const SLOWLOG_ENTRY_MAX_ARGC: usize = 32;
+
+/// Maximum number of bytes stored per argument.
+const SLOWLOG_ENTRY_MAX_STRING: usize = 128;
+
+#[derive(Debug)]
+pub struct SlowLogEntry {
+    id: u64,
+    timestamp: u64,
+    duration_micros: u64,
+    args: Vec<Bytes>,
+    client_addr: String,
+    client_name: String,
+}
+
+impl SlowLogEntry {
+    fn to_frame(&self) -> Frame {
+        Frame::Array(vec![
+            Frame::Integer(self.id as i64),
+            Frame::Integer(self.timestamp as i64),
+            Frame::Integer(self.duration_micros as i64),
+            Frame::Array(self.args.iter().map(|arg| Frame::Bulk(Some(arg.clone()))).collect()),
+            Frame::bulk(self.client_addr.clone()),
+            Frame::bulk(self.client_name.clone()),
+        ])
+    }
+}
+
+/// Ring buffer of the most recent commands which exceeded the
+/// `slowlog-log-slower-than` threshold, newest first.
+#[derive(Debug, Default)]
+pub struct SlowLog {
+    entries: VecDeque<SlowLogEntry>,
+    next_id: u64,
+}
+
+impl SlowLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, args: &[Bytes], client_addr: &str, client_name: &str, duration: Duration, max_len: u64) {
+        let entry = SlowLogEntry {
+            id: self.next_id,
+            timestamp: (get_unix_ts_millis() / 1000) as u64,
+            duration_micros: duration.as_micros() as u64,
+            args: truncate_args(args),
+            client_addr: client_addr.to_string(),
+            client_name: client_name.to_string(),
+        };
+
+        self.next_id += 1;
+        self.entries.push_front(entry);
+        self.entries.truncate(max_len as usize);
+    }
+
+    /// Returns up to `count` of the most recent entries, or all of them when
+    /// `count` is `None`.
+    pub fn get(&self, count: Option<usize>) -> Frame {
+        let count = count.unwrap_or(self.entries.len());
+
+        Frame::Array(self.entries.iter().take(count).map(|entry| entry.to_frame()).collect())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn reset(&mut self) {
+        self.entries.clear();
+    }
+}
+
+/// The arguments of a command with secrets in them, as the slow log keeps
+/// them: its name, then `(redacted)` for every other argument.
+pub fn redact(args: &[Bytes]) -> Vec<Bytes> {
+    args.iter().enumerate()
+        .map(|(idx, arg)| match idx {
+            0 => arg.clone(),
+            _ => Bytes::from_static(b"(redacted)"),
+        })
+        .collect()
+}
+
+fn truncate_args(args: &[Bytes]) -> Vec<Bytes> {
+    let mut res = Vec::with_capacity(args.len().min(SLOWLOG_ENTRY_MAX_ARGC));
+
+    for (idx, arg) in args.iter().enumerate() {
+        if idx == SLOWLOG_ENTRY_MAX_ARGC - 1 && args.len() > SLOWLOG_ENTRY_MAX_ARGC {
+            res.push(Bytes::from(format!("... ({} more arguments)", args.len() - idx)));
+            break;
+        }
+
+        if arg.len() > SLOWLOG_ENTRY_MAX_STRING {
+            let mut truncated = arg[..SLOWLOG_ENTRY_MAX_STRING].to_vec();
+            truncated.extend_from_slice(format!("... ({} more bytes)", arg.len() - SLOWLOG_ENTRY_MAX_STRING).as_bytes());
+            res.push(Bytes::from(truncated));
+        } else {
+            res.push(arg.clone());
+        }
+    }
+
+    res
+}
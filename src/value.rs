@@ -0,0 +1,877 @@
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::ops::Bound;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+
+use bytes::{Bytes, BytesMut};
+
+/// Returned when a command is used against a key holding a different type
+/// than it operates on.
+#[derive(Debug, thiserror::Error)]
+#[error("WRONGTYPE Operation against a key holding the wrong kind of value")]
+pub struct WrongType;
+
+/// A stream entry ID, `<milliseconds>-<sequence>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+/// Stream entries in ID order, each a list of field-value pairs.
+pub type Stream = BTreeMap<StreamId, Vec<(Bytes, Bytes)>>;
+
+/// A score ordered by `f64::total_cmp`, so it can be used in ordered
+/// collections.
+#[derive(Debug, Clone, Copy)]
+struct Score(f64);
+
+impl PartialEq for Score {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Score {}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// Largest collections kept in a compact encoding, like the listpacks and
+/// intsets of Redis. Collections growing past them switch to a hash table
+/// (or skiplist) for good, even if they shrink again later.
+#[derive(Debug, Clone, Copy)]
+pub struct CompactLimits {
+    pub hash_entries: usize,
+    /// Longest field or value of a compact hash.
+    pub hash_value: usize,
+    /// Most members of a set of integers.
+    pub set_intset_entries: usize,
+    pub set_entries: usize,
+    pub set_value: usize,
+    pub zset_entries: usize,
+    pub zset_value: usize,
+}
+
+static HASH_MAX_ENTRIES: AtomicUsize = AtomicUsize::new(128);
+static HASH_MAX_VALUE: AtomicUsize = AtomicUsize::new(64);
+static SET_MAX_INTSET_ENTRIES: AtomicUsize = AtomicUsize::new(512);
+static SET_MAX_ENTRIES: AtomicUsize = AtomicUsize::new(128);
+static SET_MAX_VALUE: AtomicUsize = AtomicUsize::new(64);
+static ZSET_MAX_ENTRIES: AtomicUsize = AtomicUsize::new(128);
+static ZSET_MAX_VALUE: AtomicUsize = AtomicUsize::new(64);
+
+/// Applies the `*-max-listpack-*` and `set-max-intset-entries` settings.
+/// Collections already converted stay as they are.
+pub fn configure(limits: &CompactLimits) {
+    HASH_MAX_ENTRIES.store(limits.hash_entries, AtomicOrdering::Relaxed);
+    HASH_MAX_VALUE.store(limits.hash_value, AtomicOrdering::Relaxed);
+    SET_MAX_INTSET_ENTRIES.store(limits.set_intset_entries, AtomicOrdering::Relaxed);
+    SET_MAX_ENTRIES.store(limits.set_entries, AtomicOrdering::Relaxed);
+    SET_MAX_VALUE.store(limits.set_value, AtomicOrdering::Relaxed);
+    ZSET_MAX_ENTRIES.store(limits.zset_entries, AtomicOrdering::Relaxed);
+    ZSET_MAX_VALUE.store(limits.zset_value, AtomicOrdering::Relaxed);
+}
+
+/// Heap size of a hash table holding `capacity` entries of type `T`, going
+/// by the buckets and control bytes of the standard library's.
+fn table_size<T>(capacity: usize) -> usize {
+    if capacity == 0 {
+        return 0;
+    }
+
+    let buckets = (capacity * 8 / 7).next_power_of_two();
+    buckets * (std::mem::size_of::<T>() + 1)
+}
+
+/// Either of two iterators, for types iterating their compact and full
+/// encodings differently.
+enum Either<A, B> {
+    Left(A),
+    Right(B),
+}
+
+impl<A: Iterator, B: Iterator<Item = A::Item>> Iterator for Either<A, B> {
+    type Item = A::Item;
+
+    fn next(&mut self) -> Option<A::Item> {
+        match self {
+            Either::Left(iter) => iter.next(),
+            Either::Right(iter) => iter.next(),
+        }
+    }
+}
+
+impl<A: DoubleEndedIterator, B: DoubleEndedIterator<Item = A::Item>> DoubleEndedIterator for Either<A, B> {
+    fn next_back(&mut self) -> Option<A::Item> {
+        match self {
+            Either::Left(iter) => iter.next_back(),
+            Either::Right(iter) => iter.next_back(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum ZSetMembers {
+    /// Ordered by score and then member, looked up by member with a scan.
+    Compact(Vec<(Score, Bytes)>),
+    Table {
+        scores: HashMap<Bytes, f64>,
+        ordered: BTreeSet<(Score, Bytes)>,
+    },
+}
+
+/// Members with scores, ordered by score and then by member like in Redis.
+/// Small ones are kept in a single sorted vector, which `OBJECT ENCODING`
+/// reports as `listpack`.
+#[derive(Debug, Clone)]
+pub struct SortedSet {
+    members: ZSetMembers,
+}
+
+impl Default for SortedSet {
+    fn default() -> Self {
+        Self { members: ZSetMembers::Compact(vec![]) }
+    }
+}
+
+impl SortedSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        match &self.members {
+            ZSetMembers::Compact(members) => members.len(),
+            ZSetMembers::Table { scores, .. } => scores.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The name `OBJECT ENCODING` reports.
+    pub fn encoding(&self) -> &'static str {
+        match &self.members {
+            ZSetMembers::Compact(_) => "listpack",
+            ZSetMembers::Table { .. } => "skiplist",
+        }
+    }
+
+    pub fn score(&self, member: &[u8]) -> Option<f64> {
+        match &self.members {
+            ZSetMembers::Compact(members) => members.iter().find(|(_, other)| other == member).map(|(score, _)| score.0),
+            ZSetMembers::Table { scores, .. } => scores.get(member).copied(),
+        }
+    }
+
+    /// Sets the score of `member`, returning its previous score.
+    pub fn insert(&mut self, member: Bytes, score: f64) -> Option<f64> {
+        if let ZSetMembers::Compact(members) = &self.members {
+            let grows = !members.iter().any(|(_, other)| *other == member);
+            if (grows && members.len() >= ZSET_MAX_ENTRIES.load(AtomicOrdering::Relaxed)) || member.len() > ZSET_MAX_VALUE.load(AtomicOrdering::Relaxed) {
+                self.convert();
+            }
+        }
+
+        match &mut self.members {
+            ZSetMembers::Compact(members) => {
+                let old = members.iter().position(|(_, other)| *other == member).map(|idx| members.remove(idx).0 .0);
+                let entry = (Score(score), member);
+                let idx = members.binary_search(&entry).unwrap_or_else(|idx| idx);
+                members.insert(idx, entry);
+
+                old
+            },
+            ZSetMembers::Table { scores, ordered } => {
+                let old = scores.insert(member.clone(), score);
+                if let Some(old) = old {
+                    ordered.remove(&(Score(old), member.clone()));
+                }
+                ordered.insert((Score(score), member));
+
+                old
+            },
+        }
+    }
+
+    /// Removes `member`, returning its score.
+    pub fn remove(&mut self, member: &[u8]) -> Option<f64> {
+        match &mut self.members {
+            ZSetMembers::Compact(members) => {
+                let idx = members.iter().position(|(_, other)| other == member)?;
+                Some(members.remove(idx).0 .0)
+            },
+            ZSetMembers::Table { scores, ordered } => {
+                let (member, score) = scores.remove_entry(member)?;
+                ordered.remove(&(Score(score), member));
+
+                Some(score)
+            },
+        }
+    }
+
+    /// Members in order.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&Bytes, f64)> {
+        match &self.members {
+            ZSetMembers::Compact(members) => Either::Left(members.iter().map(|(score, member)| (member, score.0))),
+            ZSetMembers::Table { ordered, .. } => Either::Right(ordered.iter().map(|(score, member)| (member, score.0))),
+        }
+    }
+
+    /// Members with a score within `min..=max`, in order.
+    pub fn range_by_score(&self, min: f64, max: f64) -> impl Iterator<Item = (&Bytes, f64)> {
+        let members = match &self.members {
+            ZSetMembers::Compact(members) => {
+                let start = members.partition_point(|(score, _)| score.0.total_cmp(&min) == Ordering::Less);
+                Either::Left(members[start..].iter())
+            },
+            ZSetMembers::Table { ordered, .. } => Either::Right(ordered.range((Bound::Included((Score(min), Bytes::new())), Bound::Unbounded))),
+        };
+
+        members.take_while(move |(score, _)| score.0 <= max).map(|(score, member)| (member, score.0))
+    }
+
+    fn convert(&mut self) {
+        if let ZSetMembers::Compact(members) = &mut self.members {
+            let members = std::mem::take(members);
+            let scores = members.iter().map(|(score, member)| (member.clone(), score.0)).collect();
+            self.members = ZSetMembers::Table { scores, ordered: members.into_iter().collect() };
+        }
+    }
+
+    fn memory_usage(&self) -> usize {
+        let members: usize = self.iter().map(|(member, _)| member.len()).sum();
+
+        members + match &self.members {
+            ZSetMembers::Compact(members) => members.capacity() * std::mem::size_of::<(Score, Bytes)>(),
+            // A B-tree node holds up to 11 elements.
+            ZSetMembers::Table { scores, ordered } => table_size::<(Bytes, f64)>(scores.capacity())
+                + ordered.len() * std::mem::size_of::<(Score, Bytes)>() * 12 / 11,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum SetMembers {
+    /// Sorted integers, for sets of nothing but integers.
+    Ints(Vec<i64>),
+    /// Sorted members.
+    Compact(Vec<Bytes>),
+    Table(HashSet<Bytes>),
+}
+
+/// Unordered members. Small sets are kept sorted in a single vector, of
+/// integers if they all are, which `OBJECT ENCODING` reports as `intset`
+/// and `listpack` like in Redis.
+#[derive(Debug, Clone)]
+pub struct Set {
+    members: SetMembers,
+}
+
+impl Default for Set {
+    fn default() -> Self {
+        Self { members: SetMembers::Ints(vec![]) }
+    }
+}
+
+/// The integer `bytes` is the canonical form of, if any. Like `string2ll`
+/// in redis-server, that rules out a `+` sign, leading zeros, `-0` and
+/// surrounding spaces, so an integer reads back the same once written.
+pub fn parse_integer(bytes: &[u8]) -> Option<i64> {
+    let int = std::str::from_utf8(bytes).ok()?.parse::<i64>().ok()?;
+
+    match int.to_string().as_bytes() == bytes {
+        true => Some(int),
+        false => None,
+    }
+}
+
+/// `parse_integer` for unsigned 64-bit integers, like `SCAN` cursors, which
+/// go past `i64::MAX`.
+pub fn parse_unsigned(bytes: &[u8]) -> Option<u64> {
+    let int = std::str::from_utf8(bytes).ok()?.parse::<u64>().ok()?;
+
+    match int.to_string().as_bytes() == bytes {
+        true => Some(int),
+        false => None,
+    }
+}
+
+impl Set {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        match &self.members {
+            SetMembers::Ints(members) => members.len(),
+            SetMembers::Compact(members) => members.len(),
+            SetMembers::Table(members) => members.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The name `OBJECT ENCODING` reports.
+    pub fn encoding(&self) -> &'static str {
+        match &self.members {
+            SetMembers::Ints(_) => "intset",
+            SetMembers::Compact(_) => "listpack",
+            SetMembers::Table(_) => "hashtable",
+        }
+    }
+
+    pub fn contains(&self, member: &[u8]) -> bool {
+        match &self.members {
+            SetMembers::Ints(members) => matches!(parse_integer(member), Some(int) if members.binary_search(&int).is_ok()),
+            SetMembers::Compact(members) => members.binary_search_by(|other| other.as_ref().cmp(member)).is_ok(),
+            SetMembers::Table(members) => members.contains(member),
+        }
+    }
+
+    /// Adds `member`, returning whether it's new.
+    pub fn insert(&mut self, member: Bytes) -> bool {
+        if self.contains(&member) {
+            return false;
+        }
+
+        let len = self.len() + 1;
+        let (max_ints, max_entries, max_value) = (
+            SET_MAX_INTSET_ENTRIES.load(AtomicOrdering::Relaxed),
+            SET_MAX_ENTRIES.load(AtomicOrdering::Relaxed),
+            SET_MAX_VALUE.load(AtomicOrdering::Relaxed),
+        );
+
+        if let SetMembers::Ints(ints) = &self.members {
+            if parse_integer(&member).is_none() || len > max_ints {
+                let fits = len <= max_entries && member.len() <= max_value;
+                let members = ints.iter().map(|int| Bytes::from(int.to_string()));
+                self.members = match fits {
+                    true => SetMembers::Compact(members.collect()),
+                    false => SetMembers::Table(members.collect()),
+                };
+            }
+        }
+        if let SetMembers::Compact(members) = &self.members {
+            if len > max_entries || member.len() > max_value {
+                self.members = SetMembers::Table(members.iter().cloned().collect());
+            }
+        }
+
+        match &mut self.members {
+            SetMembers::Ints(members) => {
+                let int = parse_integer(&member).expect("checked above");
+                let idx = members.binary_search(&int).unwrap_or_else(|idx| idx);
+                members.insert(idx, int);
+            },
+            SetMembers::Compact(members) => {
+                let idx = members.binary_search(&member).unwrap_or_else(|idx| idx);
+                members.insert(idx, member);
+            },
+            SetMembers::Table(members) => {
+                members.insert(member);
+            },
+        }
+
+        true
+    }
+
+    /// The members, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = Bytes> + '_ {
+        match &self.members {
+            SetMembers::Ints(members) => Either::Left(members.iter().map(|int| Bytes::from(int.to_string()))),
+            SetMembers::Compact(members) => Either::Right(Either::Left(members.iter().cloned())),
+            SetMembers::Table(members) => Either::Right(Either::Right(members.iter().cloned())),
+        }
+    }
+
+    fn memory_usage(&self) -> usize {
+        match &self.members {
+            SetMembers::Ints(members) => members.capacity() * std::mem::size_of::<i64>(),
+            SetMembers::Compact(members) => members.capacity() * std::mem::size_of::<Bytes>() + members.iter().map(Bytes::len).sum::<usize>(),
+            SetMembers::Table(members) => table_size::<Bytes>(members.capacity()) + members.iter().map(Bytes::len).sum::<usize>(),
+        }
+    }
+}
+
+impl FromIterator<Bytes> for Set {
+    fn from_iter<I: IntoIterator<Item = Bytes>>(iter: I) -> Self {
+        let mut set = Set::new();
+        for member in iter {
+            set.insert(member);
+        }
+
+        set
+    }
+}
+
+#[derive(Debug, Clone)]
+enum HashFields {
+    /// Sorted by field.
+    Compact(Vec<(Bytes, Bytes)>),
+    Table(HashMap<Bytes, Bytes>),
+}
+
+/// Fields and their values, each field with an optional expiry like in
+/// Redis 7.4. Small hashes are kept sorted in a single vector, which `OBJECT
+/// ENCODING` reports as `listpack` (`listpackex` with expiries).
+///
+/// Expired fields are absent to the getters, which take the current time,
+/// and only removed by `remove_expired`. The other mutations expect that to
+/// have run first. Fields without expiries keep to `fields`, whose lookups
+/// are all a hash without any pays for.
+#[derive(Debug, Clone)]
+pub struct Hash {
+    fields: HashFields,
+    /// Unix timestamps in milliseconds of the fields that expire. Empty,
+    /// and so unallocated, until a field gets an expiry.
+    expires: HashMap<Bytes, u128>,
+}
+
+impl Default for Hash {
+    fn default() -> Self {
+        Self { fields: HashFields::Compact(vec![]), expires: HashMap::new() }
+    }
+}
+
+impl HashFields {
+    fn get_key_value(&self, field: &[u8]) -> Option<(&Bytes, &Bytes)> {
+        match self {
+            HashFields::Compact(fields) => fields.binary_search_by(|(other, _)| other.as_ref().cmp(field)).ok()
+                .map(|idx| (&fields[idx].0, &fields[idx].1)),
+            HashFields::Table(fields) => fields.get_key_value(field),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            HashFields::Compact(fields) => fields.len(),
+            HashFields::Table(fields) => fields.len(),
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (&Bytes, &Bytes)> {
+        match self {
+            HashFields::Compact(fields) => Either::Left(fields.iter().map(|(field, val)| (field, val))),
+            HashFields::Table(fields) => Either::Right(fields.iter()),
+        }
+    }
+
+    /// Sets `field`, returning whether it's new.
+    fn insert(&mut self, field: Bytes, val: Bytes) -> bool {
+        if let HashFields::Compact(fields) = self {
+            let grows = fields.binary_search_by(|(other, _)| other.cmp(&field)).is_err();
+            let max_value = HASH_MAX_VALUE.load(AtomicOrdering::Relaxed);
+
+            if (grows && fields.len() >= HASH_MAX_ENTRIES.load(AtomicOrdering::Relaxed)) || field.len() > max_value || val.len() > max_value {
+                *self = HashFields::Table(std::mem::take(fields).into_iter().collect());
+            }
+        }
+
+        match self {
+            HashFields::Compact(fields) => match fields.binary_search_by(|(other, _)| other.cmp(&field)) {
+                Ok(idx) => {
+                    fields[idx].1 = val;
+                    false
+                },
+                Err(idx) => {
+                    fields.insert(idx, (field, val));
+                    true
+                },
+            },
+            HashFields::Table(fields) => fields.insert(field, val).is_none(),
+        }
+    }
+
+    fn remove(&mut self, field: &[u8]) -> bool {
+        match self {
+            HashFields::Compact(fields) => match fields.binary_search_by(|(other, _)| other.as_ref().cmp(field)) {
+                Ok(idx) => {
+                    fields.remove(idx);
+                    true
+                },
+                Err(_) => false,
+            },
+            HashFields::Table(fields) => fields.remove(field).is_some(),
+        }
+    }
+}
+
+impl Hash {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The name `OBJECT ENCODING` reports.
+    pub fn encoding(&self) -> &'static str {
+        match (&self.fields, self.expires.is_empty()) {
+            (HashFields::Compact(_), true) => "listpack",
+            (HashFields::Compact(_), false) => "listpackex",
+            (HashFields::Table(_), _) => "hashtable",
+        }
+    }
+
+    fn is_live(&self, field: &[u8], now: u128) -> bool {
+        self.expires.is_empty() || !matches!(self.expires.get(field), Some(ts) if *ts <= now)
+    }
+
+    pub fn get(&self, field: &[u8], now: u128) -> Option<&Bytes> {
+        self.fields.get_key_value(field).map(|(_, val)| val).filter(|_| self.is_live(field, now))
+    }
+
+    /// Number of fields which haven't expired.
+    pub fn len(&self, now: u128) -> usize {
+        match self.expires.is_empty() {
+            true => self.fields.len(),
+            false => self.fields.len() - self.expires.values().filter(|ts| **ts <= now).count(),
+        }
+    }
+
+    /// Whether the hash has no fields at all, expired or not.
+    pub fn is_empty(&self) -> bool {
+        self.fields.len() == 0
+    }
+
+    /// Whether every field has expired, which makes the whole key count as
+    /// expired.
+    pub fn is_expired(&self, now: u128) -> bool {
+        !self.expires.is_empty() && self.len(now) == 0
+    }
+
+    /// Fields which haven't expired, with their values.
+    pub fn iter(&self, now: u128) -> impl Iterator<Item = (&Bytes, &Bytes)> {
+        self.fields.iter().filter(move |(field, _)| self.is_live(field, now))
+    }
+
+    /// Every field with its value and expiry, expired ones included.
+    pub fn entries(&self) -> impl Iterator<Item = (&Bytes, &Bytes, Option<u128>)> {
+        self.fields.iter().map(|(field, val)| (field, val, self.expires.get(field).copied()))
+    }
+
+    /// The expiry of `field`: None if there is no such field, Some(None) if
+    /// it doesn't expire.
+    pub fn expiry(&self, field: &[u8], now: u128) -> Option<Option<u128>> {
+        self.get(field, now)?;
+
+        Some(self.expires.get(field).copied())
+    }
+
+    /// Sets `field`, dropping any expiry it had like `HSET` does. Returns
+    /// whether it's a new field.
+    pub fn insert(&mut self, field: Bytes, val: Bytes) -> bool {
+        if !self.expires.is_empty() {
+            self.expires.remove(&field);
+        }
+
+        self.fields.insert(field, val)
+    }
+
+    /// Removes `field`, returning whether it existed.
+    pub fn remove(&mut self, field: &[u8]) -> bool {
+        if !self.expires.is_empty() {
+            self.expires.remove(field);
+        }
+
+        self.fields.remove(field)
+    }
+
+    /// Sets when `field`, which must exist, expires.
+    pub fn set_expiry(&mut self, field: &[u8], ts: u128) {
+        if let Some((field, _)) = self.fields.get_key_value(field) {
+            self.expires.insert(field.clone(), ts);
+        }
+    }
+
+    /// Removes the expiry of `field`, returning whether it had one.
+    pub fn persist(&mut self, field: &[u8]) -> bool {
+        self.expires.remove(field).is_some()
+    }
+
+    /// Removes the fields whose expiry has passed, returning them.
+    pub fn remove_expired(&mut self, now: u128) -> Vec<Bytes> {
+        if self.expires.is_empty() {
+            return vec![];
+        }
+
+        let expired: Vec<Bytes> = self.expires.iter()
+            .filter(|(_, ts)| **ts <= now)
+            .map(|(field, _)| field.clone())
+            .collect();
+        for field in expired.iter() {
+            self.expires.remove(field);
+            self.fields.remove(field);
+        }
+
+        expired
+    }
+
+    fn memory_usage(&self) -> usize {
+        let data: usize = self.fields.iter().map(|(field, val)| field.len() + val.len()).sum();
+        let fields = match &self.fields {
+            HashFields::Compact(fields) => fields.capacity() * std::mem::size_of::<(Bytes, Bytes)>(),
+            HashFields::Table(fields) => table_size::<(Bytes, Bytes)>(fields.capacity()),
+        };
+
+        data + fields + table_size::<(Bytes, u128)>(self.expires.capacity())
+    }
+}
+
+impl FromIterator<(Bytes, Bytes)> for Hash {
+    fn from_iter<I: IntoIterator<Item = (Bytes, Bytes)>>(iter: I) -> Self {
+        let mut hash = Hash::new();
+        for (field, val) in iter {
+            hash.insert(field, val);
+        }
+
+        hash
+    }
+}
+
+/// The value stored at a key.
+#[derive(Debug, Clone)]
+pub enum Value {
+    String(Bytes),
+    /// A string `APPEND` is growing, kept with room to spare so appending
+    /// to it again doesn't copy it. It's frozen back into a `String` by the
+    /// next access which isn't an append, see `Value::freeze`.
+    Appended(BytesMut),
+    List(VecDeque<Bytes>),
+    Hash(Hash),
+    Set(Set),
+    ZSet(SortedSet),
+    Stream(Stream),
+}
+
+impl Value {
+    /// A string value, sharing the object of small integers like
+    /// redis-server does.
+    pub fn string(val: Bytes) -> Value {
+        let shared = std::str::from_utf8(&val).ok()
+            .and_then(|val| val.parse::<i64>().ok())
+            .and_then(shared_integer)
+            .filter(|shared| *shared == val);
+
+        Value::String(shared.unwrap_or(val))
+    }
+
+    /// Whether the value is one of the shared integer objects.
+    pub fn is_shared(&self) -> bool {
+        let digits = SHARED_INTEGER_DIGITS.as_ptr_range();
+
+        matches!(self, Value::String(val) if digits.contains(&val.as_ptr()))
+    }
+
+    /// The name `TYPE` reports for the value.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) | Value::Appended(_) => "string",
+            Value::List(_) => "list",
+            Value::Hash(_) => "hash",
+            Value::Set(_) => "set",
+            Value::ZSet(_) => "zset",
+            Value::Stream(_) => "stream",
+        }
+    }
+
+    /// Roughly how many bytes the value takes on the heap.
+    pub fn memory_usage(&self) -> usize {
+        match self {
+            Value::String(_) if self.is_shared() => 0,
+            Value::String(val) => val.len(),
+            Value::Appended(buf) => buf.capacity(),
+            Value::List(list) => list.capacity() * std::mem::size_of::<Bytes>() + list.iter().map(Bytes::len).sum::<usize>(),
+            Value::Hash(hash) => hash.memory_usage(),
+            Value::Set(set) => set.memory_usage(),
+            Value::ZSet(zset) => zset.memory_usage(),
+            Value::Stream(stream) => stream.values()
+                .map(|fields| std::mem::size_of::<(StreamId, Vec<(Bytes, Bytes)>)>()
+                    + fields.capacity() * std::mem::size_of::<(Bytes, Bytes)>()
+                    + fields.iter().map(|(field, val)| field.len() + val.len()).sum::<usize>())
+                .sum(),
+        }
+    }
+
+    /// The string, copied out of the buffer if it's being appended to.
+    pub fn as_string(&self) -> Result<Cow<'_, Bytes>, WrongType> {
+        match self {
+            Value::String(val) => Ok(Cow::Borrowed(val)),
+            Value::Appended(buf) => Ok(Cow::Owned(Bytes::copy_from_slice(buf))),
+            _ => Err(WrongType),
+        }
+    }
+
+    pub fn as_string_mut(&mut self) -> Result<&mut Bytes, WrongType> {
+        self.freeze();
+
+        match self {
+            Value::String(val) => Ok(val),
+            _ => Err(WrongType),
+        }
+    }
+
+    /// The string as a buffer to append to, which it's moved into the first
+    /// time.
+    pub fn as_string_buf(&mut self) -> Result<&mut BytesMut, WrongType> {
+        if let Value::String(val) = self {
+            *self = Value::Appended(BytesMut::from(&val[..]));
+        }
+
+        match self {
+            Value::Appended(buf) => Ok(buf),
+            _ => Err(WrongType),
+        }
+    }
+
+    /// Turns a string being appended to back into an immutable one, which
+    /// can be shared without copying it.
+    pub fn freeze(&mut self) {
+        if let Value::Appended(buf) = self {
+            *self = Value::String(std::mem::take(buf).freeze());
+        }
+    }
+
+    pub fn as_list(&self) -> Result<&VecDeque<Bytes>, WrongType> {
+        match self {
+            Value::List(list) => Ok(list),
+            _ => Err(WrongType),
+        }
+    }
+
+    pub fn as_list_mut(&mut self) -> Result<&mut VecDeque<Bytes>, WrongType> {
+        match self {
+            Value::List(list) => Ok(list),
+            _ => Err(WrongType),
+        }
+    }
+
+    pub fn as_hash(&self) -> Result<&Hash, WrongType> {
+        match self {
+            Value::Hash(hash) => Ok(hash),
+            _ => Err(WrongType),
+        }
+    }
+
+    pub fn as_hash_mut(&mut self) -> Result<&mut Hash, WrongType> {
+        match self {
+            Value::Hash(hash) => Ok(hash),
+            _ => Err(WrongType),
+        }
+    }
+
+    pub fn as_set(&self) -> Result<&Set, WrongType> {
+        match self {
+            Value::Set(set) => Ok(set),
+            _ => Err(WrongType),
+        }
+    }
+
+    pub fn as_set_mut(&mut self) -> Result<&mut Set, WrongType> {
+        match self {
+            Value::Set(set) => Ok(set),
+            _ => Err(WrongType),
+        }
+    }
+
+    pub fn as_zset(&self) -> Result<&SortedSet, WrongType> {
+        match self {
+            Value::ZSet(zset) => Ok(zset),
+            _ => Err(WrongType),
+        }
+    }
+
+    pub fn as_zset_mut(&mut self) -> Result<&mut SortedSet, WrongType> {
+        match self {
+            Value::ZSet(zset) => Ok(zset),
+            _ => Err(WrongType),
+        }
+    }
+
+    pub fn as_stream_mut(&mut self) -> Result<&mut Stream, WrongType> {
+        match self {
+            Value::Stream(stream) => Ok(stream),
+            _ => Err(WrongType),
+        }
+    }
+}
+
+/// Grows a string to `len` bytes, padding it with zeros. Done in a single
+/// allocation, however far past the end `len` is.
+pub fn pad_string(buf: &mut Vec<u8>, len: usize) {
+    if buf.is_empty() {
+        *buf = vec![0; len];
+    } else if buf.len() < len {
+        buf.reserve_exact(len - buf.len());
+        buf.resize(len, 0);
+    }
+}
+
+/// Strings holding integers from 0 up to this share one object each,
+/// instead of every key holding them allocating its own.
+pub const SHARED_INTEGERS: i64 = 10000;
+
+const SHARED_INTEGER_DIGITS_LEN: usize = 10 + 90 * 2 + 900 * 3 + 9000 * 4;
+
+/// The shared integers written out back to back, which the shared objects
+/// point into.
+static SHARED_INTEGER_DIGITS: [u8; SHARED_INTEGER_DIGITS_LEN] = shared_integer_digits();
+
+const fn shared_integer_digits() -> [u8; SHARED_INTEGER_DIGITS_LEN] {
+    let mut digits = [0; SHARED_INTEGER_DIGITS_LEN];
+    let (mut val, mut pos) = (0, 0);
+
+    while val < SHARED_INTEGERS as usize {
+        let len = shared_integer_len(val);
+
+        let (mut rest, mut idx) = (val, len);
+        while idx > 0 {
+            idx -= 1;
+            digits[pos + idx] = b'0' + (rest % 10) as u8;
+            rest /= 10;
+        }
+
+        pos += len;
+        val += 1;
+    }
+
+    digits
+}
+
+const fn shared_integer_len(val: usize) -> usize {
+    match val {
+        0..=9 => 1,
+        10..=99 => 2,
+        100..=999 => 3,
+        _ => 4,
+    }
+}
+
+/// The shared object of `val`, if it's small enough to have one.
+pub fn shared_integer(val: i64) -> Option<Bytes> {
+    if !(0..SHARED_INTEGERS).contains(&val) {
+        return None;
+    }
+
+    let val = val as usize;
+    let start = match shared_integer_len(val) {
+        1 => val,
+        2 => 10 + (val - 10) * 2,
+        3 => 10 + 90 * 2 + (val - 100) * 3,
+        _ => 10 + 90 * 2 + 900 * 3 + (val - 1000) * 4,
+    };
+
+    Some(Bytes::from_static(&SHARED_INTEGER_DIGITS[start..start + shared_integer_len(val)]))
+}
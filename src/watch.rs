@@ -0,0 +1,139 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use bytes::Bytes;
+
+type WatchedKey = (usize, Bytes);
+
+/// The keys clients `WATCH`, by database and key, and which of those clients
+/// saw one of their keys modified since, failing their next `EXEC`.
+///
+/// Every change to a key goes through the mutation helpers of `Shard`, like
+/// `insert`, `remove` or the `get_*_mut` getters, which call `touch` for it.
+/// Writes by clients, writes applied from the master, expiry, `RESTORE` and
+/// flushes are all caught that way, without commands having to remember.
+/// Getting a value for writing counts as modifying it, so a write which
+/// turns out to change nothing, like `SREM` of a missing member, still
+/// fails the transactions watching the key. Like redis-server, a key being
+/// overwritten with the same value does too.
+///
+/// Writes check an atomic count of watching clients first, so nothing is
+/// locked on the write path unless some client watches a key.
+#[derive(Default)]
+pub struct Watches {
+    watchers: AtomicUsize,
+    registry: Mutex<Registry>,
+}
+
+#[derive(Default)]
+struct Registry {
+    /// Ids of the clients watching each key.
+    keys: HashMap<WatchedKey, HashSet<u64>>,
+    clients: HashMap<u64, Watcher>,
+}
+
+#[derive(Default)]
+struct Watcher {
+    keys: HashSet<WatchedKey>,
+    /// Whether one of the keys was modified since it was watched.
+    dirty: bool,
+}
+
+impl Watches {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Has client `id` watch `key` of database `db_index`, on top of the
+    /// keys it watches already.
+    pub fn watch(&self, id: u64, db_index: usize, key: &[u8]) {
+        let mut registry = self.registry.lock().unwrap_or_else(|err| err.into_inner());
+        let key = (db_index, Bytes::copy_from_slice(key));
+
+        registry.keys.entry(key.clone()).or_default().insert(id);
+        registry.clients.entry(id).or_default().keys.insert(key);
+        self.watchers.store(registry.clients.len(), Ordering::Relaxed);
+    }
+
+    /// Forgets every key client `id` watches, along with whether one was
+    /// modified, as `EXEC`, `DISCARD` and `UNWATCH` do.
+    pub fn unwatch(&self, id: u64) {
+        let mut registry = self.registry.lock().unwrap_or_else(|err| err.into_inner());
+
+        if let Some(watcher) = registry.clients.remove(&id) {
+            for key in watcher.keys.iter() {
+                if let Some(ids) = registry.keys.get_mut(key) {
+                    ids.remove(&id);
+                    if ids.is_empty() {
+                        registry.keys.remove(key);
+                    }
+                }
+            }
+        }
+        self.watchers.store(registry.clients.len(), Ordering::Relaxed);
+    }
+
+    /// Marks the clients watching `key` of database `db_index` as dirty.
+    pub fn touch(&self, db_index: usize, key: &[u8]) {
+        if self.watchers.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+
+        let mut registry = self.registry.lock().unwrap_or_else(|err| err.into_inner());
+        let ids: Vec<u64> = match registry.keys.get(&(db_index, Bytes::copy_from_slice(key))) {
+            Some(ids) => ids.iter().copied().collect(),
+            None => return,
+        };
+
+        for id in ids {
+            if let Some(watcher) = registry.clients.get_mut(&id) {
+                watcher.dirty = true;
+            }
+        }
+    }
+
+    /// Marks the clients watching keys of database `db_index` for which
+    /// `modified` holds as dirty, for writes to many keys at once, like
+    /// flushes.
+    pub fn touch_matching(&self, db_index: usize, modified: impl Fn(&[u8]) -> bool) {
+        if self.watchers.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+
+        let mut registry = self.registry.lock().unwrap_or_else(|err| err.into_inner());
+        let ids: Vec<u64> = registry.keys.iter()
+            .filter(|((key_db, key), _)| *key_db == db_index && modified(key))
+            .flat_map(|(_, ids)| ids.iter().copied())
+            .collect();
+
+        for id in ids {
+            if let Some(watcher) = registry.clients.get_mut(&id) {
+                watcher.dirty = true;
+            }
+        }
+    }
+
+    /// Whether one of the keys client `id` watches was modified.
+    pub fn is_dirty(&self, id: u64) -> bool {
+        self.registry.lock().unwrap_or_else(|err| err.into_inner()).clients.get(&id).map_or(false, |watcher| watcher.dirty)
+    }
+
+    /// The keys client `id` watches, as database and key.
+    pub fn watched_keys(&self, id: u64) -> Vec<(usize, Bytes)> {
+        match self.registry.lock().unwrap_or_else(|err| err.into_inner()).clients.get(&id) {
+            Some(watcher) => watcher.keys.iter().cloned().collect(),
+            None => vec![],
+        }
+    }
+
+    /// Number of clients watching keys.
+    pub fn watching_clients(&self) -> usize {
+        self.watchers.load(Ordering::Relaxed)
+    }
+
+    /// Number of distinct keys watched.
+    pub fn watched_keys_count(&self) -> usize {
+        self.registry.lock().unwrap_or_else(|err| err.into_inner()).keys.len()
+    }
+}
@@ -0,0 +1,44 @@
+//! Replays the RESP parser's corpus, so regressions the fuzzer once found
+//! are caught without running it; see `examples/fuzz_frame.rs`.
+
+use std::io::Cursor;
+
+use redis_starter_rust::frame::{Error, Frame};
+
+mod frame_fuzz;
+use frame_fuzz::{check_input, deeply_nested, escape, CORPUS, MAX_BULK_LEN};
+
+fn assert_holds(inputs: &[&[u8]]) {
+    let failures: Vec<String> = inputs.iter()
+        .filter_map(|input| check_input(input).err().map(|failure| format!("{}: {}", failure, escape(input))))
+        .collect();
+
+    assert!(failures.is_empty(), "{} failures:\n{}", failures.len(), failures.join("\n"));
+}
+
+#[test]
+fn corpus() {
+    assert_holds(CORPUS);
+}
+
+#[test]
+fn deeply_nested_array() {
+    assert_holds(&[&deeply_nested()]);
+}
+
+/// Every proper prefix of a well-formed frame is incomplete, however it's
+/// cut, rather than misread.
+#[test]
+fn truncated_frames() {
+    let frames: &[&[u8]] = &[b"*2\r\n$3\r\nGET\r\n$3\r\nkey\r\n", b"*1\r\n*1\r\n*1\r\n:1\r\n", b"-ERR wrong\r\n"];
+
+    for frame in frames {
+        let prefixes: Vec<&[u8]> = (0..frame.len()).map(|len| &frame[..len]).collect();
+        assert_holds(&prefixes);
+
+        for prefix in prefixes {
+            let res = Frame::check(&mut Cursor::new(prefix), false, MAX_BULK_LEN);
+            assert!(matches!(res, Err(Error::Incomplete)), "{} isn't incomplete: {:?}", escape(prefix), res);
+        }
+    }
+}
@@ -0,0 +1,107 @@
+//! The RESP parser's corpus and the invariants every input must hold,
+//! shared by `tests/frame_corpus.rs`, which replays the corpus on every
+//! `cargo test`, and `examples/fuzz_frame.rs`, which mutates it.
+
+use std::io::Cursor;
+use std::panic;
+
+use redis_starter_rust::Frame;
+
+pub const MAX_BULK_LEN: usize = 1 << 20;
+
+/// Inputs worth replaying on every run: well-formed frames, and the
+/// truncated and malformed ones which found bugs before. A failing input
+/// the fuzzer finds goes here once fixed.
+pub const CORPUS: &[&[u8]] = &[
+    b"",
+    b"\r",
+    b"\n",
+    b"\r\n",
+    b"+OK\r\n",
+    b"-ERR wrong\r\n",
+    b":-42\r\n",
+    b":9223372036854775808\r\n",
+    b"$3\r\nfoo\r\n",
+    b"$3\r\nfo",
+    b"$-1\r\n",
+    b"$0\r\n\r\n",
+    b"$\r\n\r\n",
+    b"$99999999999999999999999\r\n",
+    b"$18446744073709551615\r\n",
+    b"$1048577\r\n",
+    b"*-1\r\n",
+    b"*0\r\n",
+    b"*2\r\n$3\r\nGET\r\n$3\r\nkey\r\n",
+    b"*2\r\n$3\r\nGET\r\n$3\r\nke",
+    b"*1\r\n*1\r\n*1\r\n:1\r\n",
+    b"*99999999999\r\n",
+    b"*18446744073709551615\r\n:1\r\n",
+    b"*1\r\n$3\r\nfoo\r\n",
+    b"PING\r\n",
+    b"SET key value\r\n",
+    b"\xc3\xa9t\xc3\xa9\r\n",
+    b"+\xff\xfe\r\n",
+    b"-\xff\r\n",
+    b"\xff\r\n",
+    b"+OK\r",
+    b"+OK\n",
+    b"+OK",
+];
+
+/// An array nested far deeper than the parser allows, which used to
+/// overflow the stack.
+pub fn deeply_nested() -> Vec<u8> {
+    let mut input = b"*1\r\n".repeat(100000);
+    input.extend_from_slice(b":1\r\n");
+
+    input
+}
+
+/// Checks and parses `input` the way a connection reads frames, with and
+/// without `expect_file`, returning what went wrong, if anything: neither
+/// may panic, neither may move the cursor past the end of the buffer, and
+/// whatever `check` accepts `parse` must parse too, ending at the same
+/// position, as that's how much of the buffer the connection consumes.
+pub fn check_input(input: &[u8]) -> Result<(), String> {
+    for expect_file in [false, true] {
+        let res = panic::catch_unwind(|| {
+            let mut src = Cursor::new(input);
+            let checked = Frame::check(&mut src, expect_file, MAX_BULK_LEN);
+            let checked_len = src.position();
+            if checked_len > input.len() as u64 {
+                return Err(format!("check read past the end, to {}", checked_len));
+            }
+
+            // Parse on its own too, as clients can't always check first.
+            let mut src = Cursor::new(input);
+            let parsed = Frame::parse(&mut src, expect_file);
+            if src.position() > input.len() as u64 {
+                return Err(format!("parse read past the end, to {}", src.position()));
+            }
+
+            match (checked, parsed) {
+                (Ok(()), Err(err)) => Err(format!("checked, but failed to parse: {:?}", err)),
+                (Ok(()), Ok(_)) if src.position() != checked_len => Err(format!("checked {} bytes, but parsed {}", checked_len, src.position())),
+                _ => Ok(()),
+            }
+        });
+
+        match res {
+            Ok(Ok(())) => {},
+            Ok(Err(failure)) => return Err(format!("expect_file={}: {}", expect_file, failure)),
+            Err(_) => return Err(format!("expect_file={}: panicked", expect_file)),
+        }
+    }
+
+    Ok(())
+}
+
+/// `input` as a byte string literal, cut short if it's long.
+pub fn escape(input: &[u8]) -> String {
+    let shown: String = input.iter().take(200).flat_map(|byte| std::ascii::escape_default(*byte)).map(char::from).collect();
+
+    match input.len() > 200 {
+        true => format!("b\"{}\"... ({} bytes)", shown, input.len()),
+        false => format!("b\"{}\"", shown),
+    }
+}
@@ -0,0 +1,672 @@
+//! Runs servers in-process, on ports of their own, and talks to them with
+//! the crate's client.
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use bytes::Bytes;
+use redis_starter_rust::client::Expiry;
+use redis_starter_rust::{Client, Frame, RedisError, Server, ServerHandle};
+
+async fn spawn_master() -> ServerHandle {
+    Server::builder().port(0).spawn().await.expect("master starts")
+}
+
+async fn spawn_replica(master: &ServerHandle) -> ServerHandle {
+    let replica = Server::builder().port(0).replicaof(master.addr().to_string()).spawn().await.expect("replica starts");
+
+    let mut client = Client::connect(replica.addr()).await.unwrap();
+    poll(&mut client, &["INFO", "replication"], |reply| bulk_contains(reply, "master_link_status:up")).await;
+
+    replica
+}
+
+/// Sends `cmd` until its reply is `done`, failing the test after a few
+/// seconds.
+async fn poll(client: &mut Client, cmd: &[&str], done: impl Fn(&Frame) -> bool) {
+    for _ in 0..100 {
+        let reply = client.command(cmd).await.unwrap();
+        if done(&reply) {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    panic!("timed out waiting on {:?}", cmd);
+}
+
+fn bulk_contains(reply: &Frame, needle: &str) -> bool {
+    match reply {
+        Frame::Bulk(Some(bytes)) => String::from_utf8_lossy(bytes).contains(needle),
+        reply => panic!("unexpected reply {:?}", reply),
+    }
+}
+
+/// Every key of the current database with its `DUMP` payload, in order,
+/// followed by `DEBUG DIGEST` of the whole dataset, which covers expiries
+/// too. Two snapshots are equal only if no key changed in between.
+async fn keyspace_snapshot(client: &mut Client) -> Vec<(Bytes, Bytes)> {
+    let bulk = |reply: Frame| match reply {
+        Frame::Bulk(Some(bytes)) => bytes,
+        Frame::Simple(string) => Bytes::from(string),
+        reply => panic!("unexpected reply {:?}", reply),
+    };
+
+    let mut keys: Vec<Bytes> = match client.command(["KEYS", "*"]).await.unwrap() {
+        Frame::Array(keys) => keys.into_iter().map(bulk).collect(),
+        reply => panic!("unexpected reply {:?}", reply),
+    };
+    keys.sort();
+
+    let mut snapshot = vec![];
+    for key in keys {
+        let payload = bulk(client.command([&b"DUMP"[..], &key]).await.unwrap());
+        snapshot.push((key, payload));
+    }
+    snapshot.push((Bytes::from("DEBUG DIGEST"), bulk(client.command(["DEBUG", "DIGEST"]).await.unwrap())));
+
+    snapshot
+}
+
+#[tokio::test]
+async fn set_then_get() {
+    let server = spawn_master().await;
+    let mut client = Client::connect(server.addr()).await.unwrap();
+
+    assert_eq!(client.get("key").await.unwrap(), None);
+    client.set("key", b"value", None).await.unwrap();
+    assert_eq!(client.get("key").await.unwrap(), Some(Bytes::from("value")));
+
+    server.shutdown().await;
+}
+
+#[tokio::test]
+async fn keys_expire() {
+    let server = spawn_master().await;
+    let mut client = Client::connect(server.addr()).await.unwrap();
+
+    client.set("key", b"value", Some(Expiry::Px(100))).await.unwrap();
+    assert_eq!(client.get("key").await.unwrap(), Some(Bytes::from("value")));
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(client.get("key").await.unwrap(), None);
+
+    server.shutdown().await;
+}
+
+#[tokio::test]
+async fn replica_applies_writes_of_its_master() {
+    let master = spawn_master().await;
+    let replica = spawn_replica(&master).await;
+
+    let mut client = Client::connect(master.addr()).await.unwrap();
+    client.set("key", b"value", None).await.unwrap();
+    assert_eq!(client.incr("counter").await.unwrap(), 1);
+    assert!(matches!(client.command(["WAIT", "1", "5000"]).await.unwrap(), Frame::Integer(1)));
+
+    let mut client = Client::connect(replica.addr()).await.unwrap();
+    assert_eq!(client.get("key").await.unwrap(), Some(Bytes::from("value")));
+    assert_eq!(client.get("counter").await.unwrap(), Some(Bytes::from("1")));
+
+    replica.shutdown().await;
+    master.shutdown().await;
+}
+
+#[tokio::test]
+async fn replica_gets_the_result_of_incrbyfloat() {
+    let master = spawn_master().await;
+    let replica = spawn_replica(&master).await;
+
+    let mut subscriber = Client::connect(replica.addr()).await.unwrap();
+    subscriber.command(["CONFIG", "SET", "notify-keyspace-events", "K$"]).await.unwrap();
+    subscriber.command(["SUBSCRIBE", "__keyspace@0__:price"]).await.unwrap();
+    let mut subscriber = subscriber.into_connection();
+
+    let mut client = Client::connect(master.addr()).await.unwrap();
+    client.set("price", b"10.5", Some(Expiry::Ex(100))).await.unwrap();
+    assert!(matches!(client.command(["INCRBYFLOAT", "price", "0.1"]).await.unwrap(), Frame::Bulk(Some(res)) if res == "10.6"));
+    assert!(matches!(client.command(["WAIT", "1", "5000"]).await.unwrap(), Frame::Integer(1)));
+
+    // The replica sets the result rather than adding the increment again.
+    for _ in 0..2 {
+        match subscriber.read_frame(false).await.unwrap() {
+            Some(Frame::Array(message)) => assert!(matches!(&message[2], Frame::Bulk(Some(event)) if event == "set"), "{:?}", message),
+            frame => panic!("unexpected frame {:?}", frame),
+        }
+    }
+
+    let mut client = Client::connect(replica.addr()).await.unwrap();
+    assert_eq!(client.get("price").await.unwrap(), Some(Bytes::from("10.6")));
+    assert!(bulk_contains(&client.command(["INFO", "keyspace"]).await.unwrap(), "db0:keys=1,expires=1"));
+
+    replica.shutdown().await;
+    master.shutdown().await;
+}
+
+#[tokio::test]
+async fn replica_refuses_writes_of_its_clients() {
+    let master = spawn_master().await;
+    let replica = spawn_replica(&master).await;
+
+    let mut client = Client::connect(master.addr()).await.unwrap();
+    client.set("key", b"value", None).await.unwrap();
+    assert!(matches!(client.command(["WAIT", "1", "5000"]).await.unwrap(), Frame::Integer(1)));
+
+    let mut client = Client::connect(replica.addr()).await.unwrap();
+    for cmd in [&["SET", "key", "other"][..], &["DEL", "key"], &["INCR", "counter"]] {
+        match client.command(cmd).await {
+            Err(RedisError::ReadOnly) => {},
+            res => panic!("{:?} wasn't refused: {:?}", cmd, res),
+        }
+    }
+    assert_eq!(client.get("key").await.unwrap(), Some(Bytes::from("value")));
+    assert_eq!(client.get("counter").await.unwrap(), None);
+
+    // A refused write fails the transaction it was queued in.
+    client.command(["MULTI"]).await.unwrap();
+    assert!(matches!(client.command(["SET", "key", "other"]).await, Err(RedisError::ReadOnly)));
+    assert!(client.command(["EXEC"]).await.is_err());
+    assert_eq!(client.get("key").await.unwrap(), Some(Bytes::from("value")));
+
+    replica.shutdown().await;
+    master.shutdown().await;
+}
+
+#[tokio::test]
+async fn replica_deletes_keys_its_master_expires() {
+    let master = spawn_master().await;
+    let replica = spawn_replica(&master).await;
+
+    let mut client = Client::connect(master.addr()).await.unwrap();
+    client.set("key", b"value", Some(Expiry::Px(500))).await.unwrap();
+    assert!(matches!(client.command(["WAIT", "1", "5000"]).await.unwrap(), Frame::Integer(1)));
+
+    let mut client = Client::connect(replica.addr()).await.unwrap();
+    assert!(bulk_contains(&client.command(["INFO", "keyspace"]).await.unwrap(), "db0:keys=1"));
+
+    // The replica doesn't expire the key itself, its master's active expiry
+    // deletes it.
+    poll(&mut client, &["INFO", "keyspace"], |reply| !bulk_contains(reply, "db0:")).await;
+    assert_eq!(client.get("key").await.unwrap(), None);
+
+    replica.shutdown().await;
+    master.shutdown().await;
+}
+
+#[tokio::test]
+async fn passwords_stay_out_of_monitor_and_slowlog() {
+    let server = spawn_master().await;
+
+    let mut monitor = Client::connect(server.addr()).await.unwrap();
+    assert!(matches!(monitor.command(["MONITOR"]).await.unwrap(), Frame::Simple(ok) if ok == "OK"));
+    let mut monitor = monitor.into_connection();
+
+    let mut client = Client::connect(server.addr()).await.unwrap();
+    client.command(["CONFIG", "SET", "slowlog-log-slower-than", "0"]).await.unwrap();
+    assert!(client.command(["AUTH", "hunter2"]).await.is_err());
+    client.command(["HELLO", "2", "AUTH", "default", "hunter2"]).await.unwrap();
+    client.set("key", b"value", None).await.unwrap();
+
+    // The monitor gets the commands around them, but neither AUTH nor HELLO.
+    for expected in [r#""CONFIG" "SET""#, r#""SET" "key" "value""#] {
+        match monitor.read_frame(false).await.unwrap() {
+            Some(Frame::Simple(line)) => assert!(line.contains(expected), "{} doesn't contain {}", line, expected),
+            frame => panic!("unexpected frame {:?}", frame),
+        }
+    }
+
+    let entries = match client.command(["SLOWLOG", "GET"]).await.unwrap() {
+        Frame::Array(entries) => entries,
+        reply => panic!("unexpected reply {:?}", reply),
+    };
+    let logged: Vec<Vec<String>> = entries.iter()
+        .map(|entry| match entry {
+            Frame::Array(fields) => match &fields[3] {
+                Frame::Array(args) => args.iter().map(|arg| match arg {
+                    Frame::Bulk(Some(arg)) => String::from_utf8_lossy(arg).to_string(),
+                    arg => panic!("unexpected argument {:?}", arg),
+                }).collect(),
+                args => panic!("unexpected arguments {:?}", args),
+            },
+            entry => panic!("unexpected entry {:?}", entry),
+        })
+        .collect();
+
+    assert!(logged.contains(&vec!["AUTH".to_string(), "(redacted)".to_string()]), "{:?}", logged);
+    assert!(logged.contains(&["HELLO", "(redacted)", "(redacted)", "(redacted)", "(redacted)"].map(String::from).to_vec()), "{:?}", logged);
+    assert!(!logged.concat().contains(&"hunter2".to_string()), "{:?}", logged);
+
+    server.shutdown().await;
+}
+
+/// Scans while another client keeps adding and deleting thousands of keys,
+/// growing and shrinking the tables under the cursor.
+#[tokio::test]
+async fn scan_returns_every_key_despite_writes() {
+    let server = spawn_master().await;
+    let mut client = Client::connect(server.addr()).await.unwrap();
+
+    let mut pipeline = client.pipeline();
+    for idx in 0..1000 {
+        pipeline.command(["SET".to_string(), format!("stable:{}", idx), "value".to_string()]);
+    }
+    pipeline.execute().await.unwrap();
+
+    let mut writer = Client::connect(server.addr()).await.unwrap();
+    let writes = tokio::spawn(async move {
+        for round in 0..10 {
+            for cmd in ["SET", "DEL"] {
+                let mut pipeline = writer.pipeline();
+                for idx in 0..5000 {
+                    match cmd {
+                        "SET" => pipeline.command([cmd.to_string(), format!("churn:{}:{}", round, idx), "value".to_string()]),
+                        _ => pipeline.command([cmd.to_string(), format!("churn:{}:{}", round, idx)]),
+                    };
+                }
+                pipeline.execute().await.unwrap();
+            }
+        }
+    });
+
+    let mut seen = HashSet::new();
+    let mut cursor = "0".to_string();
+    for steps in 0.. {
+        assert!(steps < 100000, "scan didn't end");
+
+        let reply = client.command(["SCAN", &cursor, "COUNT", "10"]).await.unwrap();
+        let (next, keys) = match reply {
+            Frame::Array(mut parts) if parts.len() == 2 => match (parts.remove(0), parts.remove(0)) {
+                (Frame::Bulk(Some(next)), Frame::Array(keys)) => (String::from_utf8(next.to_vec()).unwrap(), keys),
+                parts => panic!("unexpected reply {:?}", parts),
+            },
+            reply => panic!("unexpected reply {:?}", reply),
+        };
+        for key in keys {
+            match key {
+                Frame::Bulk(Some(key)) => seen.insert(key),
+                key => panic!("unexpected key {:?}", key),
+            };
+        }
+
+        cursor = next;
+        if cursor == "0" {
+            break;
+        }
+    }
+    writes.await.unwrap();
+
+    let missing: Vec<String> = (0..1000).map(|idx| format!("stable:{}", idx)).filter(|key| !seen.contains(key.as_bytes())).collect();
+    assert!(missing.is_empty(), "missing {:?}", missing);
+
+    server.shutdown().await;
+}
+
+#[tokio::test]
+async fn small_integers_are_shared() {
+    let server = spawn_master().await;
+    let mut client = Client::connect(server.addr()).await.unwrap();
+
+    let refcount = |reply: Frame| match reply {
+        Frame::Integer(refcount) => refcount,
+        reply => panic!("unexpected reply {:?}", reply),
+    };
+
+    client.set("small", b"100", None).await.unwrap();
+    client.set("big", b"10000", None).await.unwrap();
+    client.set("padded", b"0100", None).await.unwrap();
+    assert_eq!(refcount(client.command(["OBJECT", "REFCOUNT", "small"]).await.unwrap()), i32::MAX as i64);
+    assert_eq!(refcount(client.command(["OBJECT", "REFCOUNT", "big"]).await.unwrap()), 1);
+    assert_eq!(refcount(client.command(["OBJECT", "REFCOUNT", "padded"]).await.unwrap()), 1);
+
+    // INCR and DECR results are shared too, and the key keeps its expiry.
+    client.set("counter", b"9998", Some(Expiry::Ex(100))).await.unwrap();
+    assert_eq!(client.incr("counter").await.unwrap(), 9999);
+    assert_eq!(refcount(client.command(["OBJECT", "REFCOUNT", "counter"]).await.unwrap()), i32::MAX as i64);
+    assert_eq!(client.incr("counter").await.unwrap(), 10000);
+    assert_eq!(refcount(client.command(["OBJECT", "REFCOUNT", "counter"]).await.unwrap()), 1);
+    assert!(matches!(client.command(["DECRBY", "counter", "5000"]).await.unwrap(), Frame::Integer(5000)));
+    assert_eq!(refcount(client.command(["OBJECT", "REFCOUNT", "counter"]).await.unwrap()), i32::MAX as i64);
+    assert!(bulk_contains(&client.command(["INFO", "keyspace"]).await.unwrap(), "expires=1"));
+
+    assert_eq!(client.incr("fresh").await.unwrap(), 1);
+    assert_eq!(refcount(client.command(["OBJECT", "REFCOUNT", "fresh"]).await.unwrap()), i32::MAX as i64);
+
+    server.shutdown().await;
+}
+
+#[tokio::test]
+async fn append_creates_and_grows_strings() {
+    let server = spawn_master().await;
+    let mut client = Client::connect(server.addr()).await.unwrap();
+
+    assert!(matches!(client.command(["APPEND", "log", "start"]).await.unwrap(), Frame::Integer(5)));
+    assert_eq!(client.get("log").await.unwrap(), Some(Bytes::from("start")));
+
+    // A long run of appends grows the same buffer.
+    let chunk = "x".repeat(100);
+    for _ in 0..10 {
+        let mut pipeline = client.pipeline();
+        for _ in 0..2000 {
+            pipeline.command(["APPEND", "log", &chunk]);
+        }
+        pipeline.execute().await.unwrap();
+    }
+    assert!(matches!(client.command(["STRLEN", "log"]).await.unwrap(), Frame::Integer(2_000_005)));
+
+    // Reading or writing it some other way sees every append.
+    client.command(["SETRANGE", "log", "0", "START"]).await.unwrap();
+    assert!(matches!(client.command(["APPEND", "log", "end"]).await.unwrap(), Frame::Integer(2_000_008)));
+    let log = client.get("log").await.unwrap().unwrap();
+    assert!(log.starts_with(b"STARTxxx") && log.ends_with(b"xxxend"));
+
+    // The key keeps its expiry.
+    client.set("counter", b"1", Some(Expiry::Ex(100))).await.unwrap();
+    assert!(matches!(client.command(["APPEND", "counter", "0"]).await.unwrap(), Frame::Integer(2)));
+    assert_eq!(client.incr("counter").await.unwrap(), 11);
+    assert!(bulk_contains(&client.command(["INFO", "keyspace"]).await.unwrap(), "expires=1"));
+
+    client.command(["RPUSH", "list", "a"]).await.unwrap();
+    assert!(matches!(client.command(["APPEND", "list", "b"]).await, Err(RedisError::WrongType)));
+    assert!(matches!(client.command(["LRANGE", "list", "0", "-1"]).await.unwrap(), Frame::Array(items) if items.len() == 1));
+
+    server.shutdown().await;
+}
+
+#[tokio::test]
+async fn full_resync_replaces_the_replica_dataset() {
+    let master = spawn_master().await;
+    let addr = master.addr();
+    let replica = spawn_replica(&master).await;
+
+    let mut client = Client::connect(addr).await.unwrap();
+    client.set("old", b"value", None).await.unwrap();
+    assert!(matches!(client.command(["WAIT", "1", "5000"]).await.unwrap(), Frame::Integer(1)));
+    master.shutdown().await;
+
+    // A master started over in its place has a new replication ID, so the
+    // replica resyncs from scratch once it reconnects.
+    let master = Server::builder().port(addr.port()).spawn().await.expect("master restarts");
+    let mut client = Client::connect(addr).await.unwrap();
+    client.set("new", b"value", None).await.unwrap();
+    client.set("newer", b"value", None).await.unwrap();
+
+    // INFO is answered while the snapshot loads, unlike reads.
+    let mut client = Client::connect(replica.addr()).await.unwrap();
+    poll(&mut client, &["INFO", "keyspace"], |reply| bulk_contains(reply, "db0:keys=2,")).await;
+    assert_eq!(client.get("old").await.unwrap(), None);
+    assert_eq!(client.get("new").await.unwrap(), Some(Bytes::from("value")));
+
+    replica.shutdown().await;
+    master.shutdown().await;
+}
+
+#[tokio::test]
+async fn failing_commands_leave_the_keyspace_unchanged() {
+    let server = spawn_master().await;
+    let mut client = Client::connect(server.addr()).await.unwrap();
+
+    client.set("str", b"hello", Some(Expiry::Ex(100))).await.unwrap();
+    client.set("num", b"10", None).await.unwrap();
+    client.command(["RPUSH", "list", "b", "a"]).await.unwrap();
+    client.command(["HSET", "hash", "field", "value"]).await.unwrap();
+    client.command(["PFADD", "hll", "a", "b"]).await.unwrap();
+    let payload = match client.command(["DUMP", "num"]).await.unwrap() {
+        Frame::Bulk(Some(payload)) => payload,
+        reply => panic!("unexpected reply {:?}", reply),
+    };
+
+    let before = keyspace_snapshot(&mut client).await;
+    let cmds: &[&[&str]] = &[
+        &["SET", "str", "other", "EX", "0"],
+        &["SET", "str", "other", "NX", "XX"],
+        &["SET", "fresh", "other", "KEEPTTL", "EX", "10"],
+        &["INCR", "str"],
+        &["INCRBY", "num", "9223372036854775807"],
+        &["INCRBYFLOAT", "str", "1"],
+        &["APPEND", "list", "x"],
+        &["SETRANGE", "str", "536870912", "x"],
+        &["SETRANGE", "list", "0", "x"],
+        &["SETBIT", "hash", "0", "1"],
+        &["BITFIELD", "list", "SET", "u8", "0", "1"],
+        &["BITOP", "AND", "dest", "str", "list"],
+        &["BITOP", "OR", "str", "num", "hash"],
+        &["PFADD", "str", "c"],
+        &["PFMERGE", "str", "hll"],
+        &["PFMERGE", "hll", "str"],
+        &["HSET", "list", "field", "value"],
+        &["HEXPIRE", "list", "10", "FIELDS", "1", "field"],
+        &["LPUSH", "hash", "x"],
+        &["LPOP", "str"],
+        &["GEOADD", "list", "0", "0", "member"],
+        &["SORT", "list", "STORE", "str"],
+        &["SORT", "hash", "STORE", "dest"],
+        &["RESTORE", "fresh", "0", "garbage"],
+    ];
+    for cmd in cmds {
+        assert!(client.command(*cmd).await.is_err(), "{:?} didn't fail", cmd);
+        assert_eq!(keyspace_snapshot(&mut client).await, before, "{:?} changed the keyspace", cmd);
+    }
+
+    assert!(client.command([&b"RESTORE"[..], b"str", b"0", &payload]).await.is_err());
+    assert_eq!(keyspace_snapshot(&mut client).await, before);
+
+    server.shutdown().await;
+}
+
+#[tokio::test]
+async fn a_panicking_client_leaves_the_others_working() {
+    let server = spawn_master().await;
+    let mut client = Client::connect(server.addr()).await.unwrap();
+    client.set("key", b"value", None).await.unwrap();
+
+    // The panic poisons the slow log's lock on its way out.
+    let mut panicking = Client::connect(server.addr()).await.unwrap();
+    assert!(panicking.command(["DEBUG", "PANIC"]).await.is_err());
+
+    client.command(["CONFIG", "SET", "slowlog-log-slower-than", "0"]).await.unwrap();
+    assert_eq!(client.get("key").await.unwrap(), Some(Bytes::from("value")));
+    assert!(matches!(client.command(["SLOWLOG", "LEN"]).await.unwrap(), Frame::Integer(len) if len > 0));
+    client.command(["MULTI"]).await.unwrap();
+    client.command(["GET", "key"]).await.unwrap();
+    assert!(matches!(client.command(["EXEC"]).await.unwrap(), Frame::Array(replies) if replies.len() == 1));
+    assert!(bulk_contains(&client.command(["INFO", "stats"]).await.unwrap(), "task_panics:1"));
+
+    let mut client = Client::connect(server.addr()).await.unwrap();
+    assert_eq!(client.get("key").await.unwrap(), Some(Bytes::from("value")));
+
+    server.shutdown().await;
+}
+
+#[tokio::test]
+async fn getrange_clamps_its_indexes() {
+    let server = spawn_master().await;
+    let mut client = Client::connect(server.addr()).await.unwrap();
+    client.set("str", b"This is a string", None).await.unwrap();
+    client.set("empty", b"", None).await.unwrap();
+
+    let cases: &[(&str, &str, &str, &str)] = &[
+        ("str", "0", "3", "This"),
+        ("str", "0", "-1", "This is a string"),
+        ("str", "-3", "-1", "ing"),
+        ("str", "-100", "3", "This"),
+        ("str", "10", "100", "string"),
+        ("str", "5", "3", ""),
+        ("str", "-1", "-5", ""),
+        ("str", "100", "200", ""),
+        ("empty", "0", "-1", ""),
+        ("empty", "-1", "0", ""),
+        ("missing", "0", "-1", ""),
+    ];
+    for (key, start, end, expected) in cases {
+        match client.command(["GETRANGE", key, start, end]).await.unwrap() {
+            Frame::Bulk(Some(range)) => assert_eq!(range, expected.as_bytes(), "GETRANGE {} {} {}", key, start, end),
+            reply => panic!("unexpected reply {:?}", reply),
+        }
+    }
+
+    assert!(client.command(["GETRANGE", "str", "a", "1"]).await.is_err());
+
+    server.shutdown().await;
+}
+
+#[tokio::test]
+async fn empty_store_results_delete_the_destination_on_replicas() {
+    let master = spawn_master().await;
+    let replica = spawn_replica(&master).await;
+
+    let mut client = Client::connect(master.addr()).await.unwrap();
+    let mut replica_client = Client::connect(replica.addr()).await.unwrap();
+    for dest in ["bitop-dest", "sort-dest"] {
+        client.set(dest, b"old", None).await.unwrap();
+    }
+    client.command(["RPUSH", "list", "2", "1"]).await.unwrap();
+    assert!(matches!(client.command(["WAIT", "1", "5000"]).await.unwrap(), Frame::Integer(1)));
+    assert_eq!(replica_client.get("sort-dest").await.unwrap(), Some(Bytes::from("old")));
+
+    assert!(matches!(client.command(["BITOP", "AND", "bitop-dest", "missing", "other"]).await.unwrap(), Frame::Integer(0)));
+    assert!(matches!(client.command(["SORT", "missing", "STORE", "sort-dest"]).await.unwrap(), Frame::Integer(0)));
+    assert!(matches!(client.command(["SORT", "list", "STORE", "sorted"]).await.unwrap(), Frame::Integer(2)));
+    assert!(matches!(client.command(["WAIT", "1", "5000"]).await.unwrap(), Frame::Integer(1)));
+
+    for client in [&mut client, &mut replica_client] {
+        assert_eq!(client.get("bitop-dest").await.unwrap(), None);
+        assert_eq!(client.get("sort-dest").await.unwrap(), None);
+        assert!(matches!(client.command(["LRANGE", "sorted", "0", "-1"]).await.unwrap(), Frame::Array(items) if items.len() == 2));
+    }
+
+    replica.shutdown().await;
+    master.shutdown().await;
+}
+
+#[tokio::test]
+async fn strlen_counts_bytes_of_live_strings() {
+    let server = spawn_master().await;
+    let mut client = Client::connect(server.addr()).await.unwrap();
+    let strlen = |reply: Frame| match reply {
+        Frame::Integer(len) => len,
+        reply => panic!("unexpected reply {:?}", reply),
+    };
+
+    client.set("key", "héllo".as_bytes(), None).await.unwrap();
+    assert_eq!(strlen(client.command(["STRLEN", "key"]).await.unwrap()), 6);
+    assert_eq!(strlen(client.command(["STRLEN", "missing"]).await.unwrap()), 0);
+
+    // Left to expire lazily, which STRLEN does like GET.
+    client.command(["DEBUG", "SET-ACTIVE-EXPIRE", "0"]).await.unwrap();
+    client.set("expiring", b"value", Some(Expiry::Px(100))).await.unwrap();
+    assert_eq!(strlen(client.command(["STRLEN", "expiring"]).await.unwrap()), 5);
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(strlen(client.command(["STRLEN", "expiring"]).await.unwrap()), 0);
+    assert!(bulk_contains(&client.command(["INFO", "keyspace"]).await.unwrap(), "db0:keys=1,"));
+
+    client.command(["RPUSH", "list", "a"]).await.unwrap();
+    assert!(matches!(client.command(["STRLEN", "list"]).await, Err(RedisError::WrongType)));
+    for cmd in [&["STRLEN"][..], &["STRLEN", "key", "other"]] {
+        match client.command(cmd).await {
+            Err(RedisError::Reply(err)) if err.contains("wrong number of arguments for 'strlen'") => {},
+            res => panic!("{:?} wasn't refused: {:?}", cmd, res),
+        }
+    }
+
+    server.shutdown().await;
+}
+
+#[tokio::test]
+async fn hz_is_configurable() {
+    let server = spawn_master().await;
+    let mut client = Client::connect(server.addr()).await.unwrap();
+    let hz = |reply: Frame| match reply {
+        Frame::Array(pair) => match &pair[..] {
+            [_, Frame::Bulk(Some(hz))] => String::from_utf8_lossy(hz).into_owned(),
+            _ => panic!("unexpected reply {:?}", pair),
+        },
+        reply => panic!("unexpected reply {:?}", reply),
+    };
+
+    // Every server in the process shares the cron, including those of other
+    // tests, so hz is only raised, and put back after.
+    assert_eq!(hz(client.command(["CONFIG", "GET", "hz"]).await.unwrap()), "10");
+    client.command(["CONFIG", "SET", "hz", "9999"]).await.unwrap();
+    assert_eq!(hz(client.command(["CONFIG", "GET", "hz"]).await.unwrap()), "500");
+    assert!(bulk_contains(&client.command(["INFO", "server"]).await.unwrap(), "\nhz:500\n"));
+    assert!(client.command(["CONFIG", "SET", "hz", "fast"]).await.is_err());
+    assert_eq!(hz(client.command(["CONFIG", "GET", "hz"]).await.unwrap()), "500");
+
+    // Active expiry runs from the cron, with nothing reading the key.
+    client.set("key", b"value", Some(Expiry::Px(100))).await.unwrap();
+    poll(&mut client, &["INFO", "keyspace"], |reply| !bulk_contains(reply, "db0:")).await;
+
+    client.command(["CONFIG", "SET", "hz", "10"]).await.unwrap();
+    server.shutdown().await;
+}
+
+#[tokio::test]
+async fn replica_serves_keys_its_master_had_before_it_attached() {
+    let master = spawn_master().await;
+    let mut client = Client::connect(master.addr()).await.unwrap();
+    client.set("key", b"value", None).await.unwrap();
+    client.set("expiring", b"soon", Some(Expiry::Ex(100))).await.unwrap();
+    client.command(["RPUSH", "list", "a", "b"]).await.unwrap();
+    client.command(["SELECT", "2"]).await.unwrap();
+    client.set("other", b"db", None).await.unwrap();
+
+    let replica = spawn_replica(&master).await;
+    let mut client = Client::connect(replica.addr()).await.unwrap();
+    assert_eq!(client.get("key").await.unwrap(), Some(Bytes::from("value")));
+    assert_eq!(client.get("expiring").await.unwrap(), Some(Bytes::from("soon")));
+    assert!(matches!(client.command(["LRANGE", "list", "0", "-1"]).await.unwrap(), Frame::Array(items) if items.len() == 2));
+    assert!(bulk_contains(&client.command(["INFO", "keyspace"]).await.unwrap(), "db0:keys=3,expires=1"));
+    client.command(["SELECT", "2"]).await.unwrap();
+    assert_eq!(client.get("other").await.unwrap(), Some(Bytes::from("db")));
+
+    replica.shutdown().await;
+    master.shutdown().await;
+}
+
+#[tokio::test]
+async fn exists_counts_every_live_key_named() {
+    let server = spawn_master().await;
+    let mut client = Client::connect(server.addr()).await.unwrap();
+    let count = |reply: Frame| match reply {
+        Frame::Integer(count) => count,
+        reply => panic!("unexpected reply {:?}", reply),
+    };
+
+    client.set("key", b"value", None).await.unwrap();
+    client.command(["RPUSH", "+5", "a"]).await.unwrap();
+    assert_eq!(count(client.command(["EXISTS", "key"]).await.unwrap()), 1);
+    assert_eq!(count(client.command(["EXISTS", "key", "key", "missing"]).await.unwrap()), 2);
+    // Arguments are keys, whatever they look like.
+    assert_eq!(count(client.command(["EXISTS", "+5", "5"]).await.unwrap()), 1);
+
+    client.command(["DEBUG", "SET-ACTIVE-EXPIRE", "0"]).await.unwrap();
+    client.set("expiring", b"value", Some(Expiry::Px(100))).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(count(client.command(["EXISTS", "expiring", "key"]).await.unwrap()), 1);
+    assert!(bulk_contains(&client.command(["INFO", "keyspace"]).await.unwrap(), "db0:keys=2,"));
+
+    match client.command(["EXISTS"]).await {
+        Err(RedisError::Reply(err)) if err.contains("wrong number of arguments for 'exists'") => {},
+        res => panic!("EXISTS without keys wasn't refused: {:?}", res),
+    }
+
+    server.shutdown().await;
+}
+
+#[tokio::test]
+async fn scan_refuses_cursors_not_written_canonically() {
+    let server = spawn_master().await;
+    let mut client = Client::connect(server.addr()).await.unwrap();
+
+    for cursor in ["+5", "05", "-1", " 5", "5 ", "", "18446744073709551616", "cursor"] {
+        match client.command(["SCAN", cursor]).await {
+            Err(RedisError::Reply(err)) if err == "ERR invalid cursor" => {},
+            res => panic!("cursor {:?} wasn't refused: {:?}", cursor, res),
+        }
+    }
+    for cursor in ["0", "5", "18446744073709551615"] {
+        assert!(matches!(client.command(["SCAN", cursor]).await.unwrap(), Frame::Array(parts) if parts.len() == 2));
+    }
+
+    server.shutdown().await;
+}